@@ -0,0 +1,48 @@
+//! Benchmarks generating a pack from a synthetic emoji directory.
+
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use fedimoji::{scan, PackConfig};
+use image::{Rgba, RgbaImage};
+
+/// Number of emoji in the synthetic directory.
+const NUM_EMOJI: u32 = 256;
+
+/// Writes a directory of distinct 128x128 emoji, returning its path.
+fn synthetic_emoji_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fedimoji-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for index in 0..NUM_EMOJI {
+        let image = RgbaImage::from_fn(128, 128, |x, y| {
+            Rgba([
+                (x + index) as u8,
+                (y * 2) as u8,
+                index as u8,
+                ((x ^ y) * 2) as u8,
+            ])
+        });
+        image.save(dir.join(format!("emoji_{index}.png"))).unwrap();
+    }
+    dir
+}
+
+fn generate(c: &mut Criterion) {
+    let dir = synthetic_emoji_dir();
+    let config = PackConfig::default();
+
+    c.bench_function("generate", |b| {
+        b.iter(|| {
+            let sources = scan::scan_emoji_dir(&dir, &config).unwrap();
+            let pack =
+                fedimoji::generate_pack_from_iter(scan::read_emoji(&sources, &config), &config)
+                    .unwrap();
+            pack.encode_atlas().unwrap()
+        })
+    });
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(benches, generate);
+criterion_main!(benches);
@@ -0,0 +1,23 @@
+//! Records the git commit fedimoji is built from, if there is one, so packs can be traced to the exact build.
+
+use std::process::Command;
+
+fn main() {
+    let version = env!("CARGO_PKG_VERSION");
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .filter(|hash| !hash.is_empty());
+
+    match git_hash {
+        Some(hash) => {
+            println!("cargo:rustc-env=FEDIMOJI_GIT_HASH={hash}");
+            println!("cargo:rustc-env=FEDIMOJI_VERSION={version} ({hash})");
+        }
+        None => println!("cargo:rustc-env=FEDIMOJI_VERSION={version}"),
+    }
+}
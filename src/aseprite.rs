@@ -0,0 +1,227 @@
+//! Reading emoji from a sprite sheet exported by Aseprite, named by its JSON sidecar.
+
+use std::{collections::BTreeMap, path::Path};
+
+use image::DynamicImage;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::{scan, PackConfig, PackError};
+
+/// The JSON sidecar Aseprite writes alongside an exported sprite sheet.
+#[derive(Debug, Deserialize)]
+struct Export {
+    frames: Frames,
+    meta: Meta,
+}
+
+/// Aseprite can write the frames either as a map of name -> frame, or as an array.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Frames {
+    Hash(BTreeMap<String, Frame>),
+    Array(Vec<NamedFrame>),
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedFrame {
+    filename: String,
+    #[serde(flatten)]
+    frame: Frame,
+}
+
+#[derive(Debug, Deserialize)]
+struct Frame {
+    frame: Rect,
+}
+
+#[derive(Debug, Deserialize)]
+struct Meta {
+    image: String,
+    #[serde(default)]
+    slices: Vec<Slice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Slice {
+    name: String,
+    keys: Vec<SliceKey>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SliceKey {
+    bounds: Rect,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct Rect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+/// Reads the emoji from an Aseprite export, given the path to its JSON sidecar.
+///
+/// If the export has slices, each slice is an emoji named after it (using its first key's bounds). Otherwise, each
+/// frame is an emoji named after its file name, without the extension. Frames which don't fit in the sheet are
+/// skipped with a warning.
+pub fn read_aseprite_export(
+    json_path: &Path,
+    config: &PackConfig,
+) -> Result<Vec<(String, DynamicImage)>, PackError> {
+    let contents = std::fs::read_to_string(json_path)?;
+    let export: Export = serde_json::from_str(&contents).map_err(PackError::AsepriteParse)?;
+
+    // the sheet's path is relative to the sidecar
+    let sheet_path = json_path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(&export.meta.image);
+    let sheet = scan::decode_image(&sheet_path, config).map_err(|source| PackError::ImageRead {
+        path: sheet_path.clone(),
+        source,
+    })?;
+
+    let regions = if export.meta.slices.is_empty() {
+        let frames = match export.frames {
+            Frames::Hash(frames) => frames.into_iter().collect::<Vec<_>>(),
+            Frames::Array(frames) => frames
+                .into_iter()
+                .map(|frame| (frame.filename, frame.frame))
+                .collect(),
+        };
+        frames
+            .into_iter()
+            .map(|(filename, frame)| {
+                // Aseprite names frames like "sheet 0.aseprite" or "neofox.png"
+                let name = match filename.rsplit_once('.') {
+                    Some((stem, _)) if !stem.is_empty() => stem.to_string(),
+                    _ => filename,
+                };
+                (name, frame.frame)
+            })
+            .collect::<Vec<_>>()
+    } else {
+        export
+            .meta
+            .slices
+            .into_iter()
+            .filter_map(|slice| match slice.keys.first() {
+                Some(key) => Some((slice.name, key.bounds)),
+                None => {
+                    warn!(
+                        kind = "skipped",
+                        "slice \"{}\" has no bounds (skipping it)", slice.name
+                    );
+                    None
+                }
+            })
+            .collect()
+    };
+
+    let mut emoji = regions
+        .into_iter()
+        .filter_map(|(name, rect)| {
+            let fits = rect.w > 0
+                && rect.h > 0
+                && rect
+                    .x
+                    .checked_add(rect.w)
+                    .is_some_and(|x| x <= sheet.width())
+                && rect
+                    .y
+                    .checked_add(rect.h)
+                    .is_some_and(|y| y <= sheet.height());
+            if !fits {
+                let Rect { x, y, w, h } = rect;
+                warn!(
+                    kind = "skipped",
+                    "frame \"{name}\" at ({x}, {y}) of {w}x{h} isn't within the sheet (skipping it)"
+                );
+                return None;
+            }
+            if name.is_empty() {
+                warn!(
+                    kind = "skipped",
+                    "frame at ({}, {}) has no name (skipping it)", rect.x, rect.y
+                );
+                return None;
+            }
+            let image = sheet.crop_imm(rect.x, rect.y, rect.w, rect.h);
+            Some((config.normalize_name(&name), image))
+        })
+        .collect::<Vec<_>>();
+
+    config.shorten_names(emoji.iter_mut().map(|(name, _)| name))?;
+    info!(
+        "read {} emoji from \"{}\"",
+        emoji.len(),
+        sheet_path.display()
+    );
+    Ok(emoji)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    use super::*;
+
+    /// Writes a 32x16 sheet, red on the left and blue on the right, with a sidecar.
+    fn export(sidecar: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        RgbaImage::from_fn(32, 16, |x, _| {
+            if x < 16 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        })
+        .save(dir.path().join("sheet.png"))
+        .unwrap();
+        std::fs::write(dir.path().join("sheet.json"), sidecar).unwrap();
+        dir
+    }
+
+    #[test]
+    fn frames_are_named_after_their_files() {
+        let dir = export(
+            r#"{
+              "frames": {
+                "Blobcat.png": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 } },
+                "neofox.png": { "frame": { "x": 16, "y": 0, "w": 16, "h": 16 } },
+                "outside.png": { "frame": { "x": 24, "y": 0, "w": 16, "h": 16 } }
+              },
+              "meta": { "image": "sheet.png" }
+            }"#,
+        );
+        let emoji =
+            read_aseprite_export(&dir.path().join("sheet.json"), &PackConfig::default()).unwrap();
+        let names = emoji
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["blobcat", "neofox"]);
+        assert_eq!(emoji[1].1.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn slices_take_precedence_over_frames() {
+        let dir = export(
+            r#"{
+              "frames": [{ "filename": "sheet 0.aseprite", "frame": { "x": 0, "y": 0, "w": 32, "h": 16 } }],
+              "meta": {
+                "image": "sheet.png",
+                "slices": [{ "name": "right", "keys": [{ "bounds": { "x": 16, "y": 0, "w": 16, "h": 16 } }] }]
+              }
+            }"#,
+        );
+        let emoji =
+            read_aseprite_export(&dir.path().join("sheet.json"), &PackConfig::default()).unwrap();
+        assert_eq!(emoji.len(), 1);
+        assert_eq!(emoji[0].0, "right");
+        assert_eq!(emoji[0].1.dimensions(), (16, 16));
+        assert_eq!(emoji[0].1.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+    }
+}
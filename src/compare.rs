@@ -0,0 +1,176 @@
+//! Comparing generated atlases.
+
+use std::path::Path;
+
+use image::{Rgba, RgbaImage};
+use tracing::{debug, error, info};
+
+/// Compares two atlas images pixel by pixel, returning whether they match within the tolerance.
+pub fn compare_atlases(expected: &Path, actual: &Path, tolerance: u8) -> bool {
+    let (expected, actual) = match (image::open(expected), image::open(actual)) {
+        (Err(err), _) => {
+            error!("failed to read \"{}\": {}", expected.display(), err);
+            return false;
+        }
+        (_, Err(err)) => {
+            error!("failed to read \"{}\": {}", actual.display(), err);
+            return false;
+        }
+        (Ok(expected), Ok(actual)) => (expected.into_rgba8(), actual.into_rgba8()),
+    };
+
+    if expected.dimensions() != actual.dimensions() {
+        error!(
+            "atlas dimensions differ: expected {}x{}, got {}x{}",
+            expected.width(),
+            expected.height(),
+            actual.width(),
+            actual.height()
+        );
+        return false;
+    }
+
+    // pixels where any channel differs by more than the tolerance
+    let differing = expected
+        .enumerate_pixels()
+        .zip(actual.pixels())
+        .filter(|((_, _, a), b)| a.0.iter().zip(b.0).any(|(a, b)| a.abs_diff(b) > tolerance))
+        .map(|((x, y, _), _)| (x, y))
+        .collect::<Vec<_>>();
+
+    if differing.is_empty() {
+        info!("atlases match (tolerance {tolerance})");
+        return true;
+    }
+
+    for (x, y) in &differing {
+        debug!("pixel ({x}, {y}) differs");
+    }
+
+    let (min_x, max_x) = differing.iter().fold((u32::MAX, 0), |(min, max), (x, _)| {
+        (min.min(*x), max.max(*x))
+    });
+    let (min_y, max_y) = differing.iter().fold((u32::MAX, 0), |(min, max), (_, y)| {
+        (min.min(*y), max.max(*y))
+    });
+    error!(
+        "{} pixels differ by more than {tolerance}, within ({min_x}, {min_y})..=({max_x}, {max_y})",
+        differing.len()
+    );
+    false
+}
+
+/// Color of the pixels which changed in a diff image.
+const CHANGED: Rgba<u8> = Rgba([255, 0, 255, 255]);
+
+/// Writes an image highlighting where two atlases of the same size differ by more than the tolerance: changed pixels
+/// are bright magenta, over a faded grayscale copy of the new atlas. Returns whether the image was written.
+pub fn diff_atlases(old: &Path, new: &Path, out: &Path, tolerance: u8) -> bool {
+    let (old_atlas, new_atlas) = match (image::open(old), image::open(new)) {
+        (Err(err), _) => {
+            error!("failed to read \"{}\": {}", old.display(), err);
+            return false;
+        }
+        (_, Err(err)) => {
+            error!("failed to read \"{}\": {}", new.display(), err);
+            return false;
+        }
+        (Ok(old), Ok(new)) => (old.into_rgba8(), new.into_rgba8()),
+    };
+
+    if old_atlas.dimensions() != new_atlas.dimensions() {
+        error!(
+            "atlas dimensions differ ({}x{} and {}x{}), so they can't be diffed pixel by pixel; \
+             use `fedimoji compare` to check whether they match",
+            old_atlas.width(),
+            old_atlas.height(),
+            new_atlas.width(),
+            new_atlas.height()
+        );
+        return false;
+    }
+
+    let mut changed = 0;
+    let diff = RgbaImage::from_fn(new_atlas.width(), new_atlas.height(), |x, y| {
+        let (a, b) = (old_atlas.get_pixel(x, y), new_atlas.get_pixel(x, y));
+        if a.0.iter().zip(b.0).any(|(a, b)| a.abs_diff(b) > tolerance) {
+            changed += 1;
+            return CHANGED;
+        }
+        let luma =
+            ((u32::from(b[0]) * 299 + u32::from(b[1]) * 587 + u32::from(b[2]) * 114) / 1000) as u8;
+        Rgba([luma, luma, luma, b[3] / 4])
+    });
+
+    if let Err(err) = diff.save(out) {
+        error!("failed to write \"{}\": {}", out.display(), err);
+        return false;
+    }
+    info!(
+        "{changed} pixels differ by more than {tolerance}; wrote the diff to \"{}\"",
+        out.display()
+    );
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atlas(dir: &Path, name: &str, width: u32, shade: u8) -> std::path::PathBuf {
+        let path = dir.join(name);
+        RgbaImage::from_pixel(width, 16, Rgba([shade, 0, 0, 255]))
+            .save(&path)
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn atlases_match_within_the_tolerance() {
+        let dir = tempfile::tempdir().unwrap();
+        let golden = atlas(dir.path(), "golden.png", 16, 100);
+        let close = atlas(dir.path(), "close.png", 16, 102);
+        let far = atlas(dir.path(), "far.png", 16, 110);
+        let wider = atlas(dir.path(), "wider.png", 32, 100);
+
+        assert!(compare_atlases(&golden, &golden, 0));
+        assert!(compare_atlases(&golden, &close, 2));
+        assert!(!compare_atlases(&golden, &close, 1));
+        assert!(!compare_atlases(&golden, &far, 2));
+        assert!(!compare_atlases(&golden, &wider, 255));
+        assert!(!compare_atlases(
+            &golden,
+            &dir.path().join("missing.png"),
+            0
+        ));
+    }
+
+    #[test]
+    fn diffs_highlight_the_changed_cell() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = atlas(dir.path(), "old.png", 32, 100);
+        let new = dir.path().join("new.png");
+        // the second 16px cell changed
+        RgbaImage::from_fn(32, 16, |x, _| {
+            Rgba([if x < 16 { 100 } else { 200 }, 0, 0, 255])
+        })
+        .save(&new)
+        .unwrap();
+        let out = dir.path().join("diff.png");
+
+        assert!(diff_atlases(&old, &new, &out, 0));
+        let diff = image::open(&out).unwrap().into_rgba8();
+        assert!(diff
+            .enumerate_pixels()
+            .all(|(x, _, pixel)| (*pixel == CHANGED) == (x >= 16)));
+
+        let wider = atlas(dir.path(), "wider.png", 48, 100);
+        assert!(!diff_atlases(
+            &old,
+            &wider,
+            &dir.path().join("wider-diff.png"),
+            0
+        ));
+        assert!(!dir.path().join("wider-diff.png").exists());
+    }
+}
@@ -0,0 +1,141 @@
+//! Rendering a line of credits (like the pack's author and license) into glyphs of its own, so they can be shown in
+//! chat alongside the emoji, with a small pixel font which also labels the printable preview.
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView, Rgba, RgbaImage};
+use tracing::warn;
+
+/// Prefix of the names given to the credits glyphs, followed by each glyph's index.
+pub const CREDITS_PREFIX: &str = "_credits_";
+
+/// Side of each credits glyph before it's scaled up, in pixels. Each holds two characters.
+const TILE_SIZE: u32 = 8;
+
+/// Width of a character, with the column of space after it.
+pub const ADVANCE: u32 = 4;
+
+/// Height of the characters, in pixels.
+pub const FONT_HEIGHT: u32 = 5;
+
+/// Rows of each character of the 3x5 font, with the leftmost pixel as the highest of the 3 bits. Lowercase letters
+/// are drawn as uppercase.
+const FONT: &[(char, [u8; 5])] = &[
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b110, 0b001, 0b010, 0b100, 0b111]),
+    ('3', [0b110, 0b001, 0b010, 0b001, 0b110]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b110, 0b001, 0b110]),
+    ('6', [0b011, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b110]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('?', [0b110, 0b001, 0b010, 0b000, 0b010]),
+    ('\'', [0b010, 0b010, 0b000, 0b000, 0b000]),
+    ('&', [0b010, 0b101, 0b010, 0b101, 0b011]),
+    ('@', [0b010, 0b101, 0b111, 0b100, 0b011]),
+];
+
+/// Renders credits text in white into glyphs of `size`x`size` pixels, two characters to each, named
+/// [`CREDITS_PREFIX`] followed by their index. Characters the font lacks are drawn as `?`, with a warning.
+pub fn render_credits(text: &str, size: u32) -> Vec<(String, DynamicImage)> {
+    let text = text.trim().replace('©', "(C)");
+    let line = render_text(&text, Rgba([255, 255, 255, 255]));
+
+    let scale = (size / TILE_SIZE).max(1);
+    (0..line.width().div_ceil(TILE_SIZE))
+        .map(|index| {
+            // leave a row above the characters, and room below for descending punctuation
+            let mut tile = RgbaImage::new(TILE_SIZE, TILE_SIZE);
+            let x = index * TILE_SIZE;
+            let width = TILE_SIZE.min(line.width() - x);
+            image::imageops::replace(&mut tile, &*line.view(x, 0, width, FONT_HEIGHT), 0, 1);
+
+            // scale up by whole pixels, so the font stays crisp whatever filter resizes the emoji
+            let tile = image::imageops::resize(
+                &tile,
+                TILE_SIZE * scale,
+                TILE_SIZE * scale,
+                FilterType::Nearest,
+            );
+            (
+                format!("{CREDITS_PREFIX}{index}"),
+                DynamicImage::ImageRgba8(tile),
+            )
+        })
+        .collect()
+}
+
+/// Renders a line of text in the small pixel font, [`FONT_HEIGHT`] pixels tall, with a column of space after each
+/// character. Characters the font lacks are drawn as `?`, with a warning.
+pub fn render_text(text: &str, color: Rgba<u8>) -> RgbaImage {
+    let glyph = |c: char| {
+        FONT.iter()
+            .find(|(font_char, _)| *font_char == c.to_ascii_uppercase())
+            .map(|(_, rows)| rows)
+    };
+
+    let unsupported = text
+        .chars()
+        .filter(|c| glyph(*c).is_none())
+        .collect::<String>();
+    if !unsupported.is_empty() {
+        warn!("the pixel font has no \"{unsupported}\", so they're drawn as \"?\"");
+    }
+
+    let mut line = RgbaImage::new(text.chars().count() as u32 * ADVANCE, FONT_HEIGHT);
+    for (position, c) in text.chars().enumerate() {
+        let rows = glyph(c).or_else(|| glyph('?')).unwrap();
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..3 {
+                if row & (0b100 >> x) != 0 {
+                    line.put_pixel(position as u32 * ADVANCE + x, y as u32, color);
+                }
+            }
+        }
+    }
+    line
+}
+
+/// Codepoints for `count` credits glyphs, at the very end of the private use plane so they stay put as emoji are
+/// added.
+pub fn credits_codepoints(count: usize) -> impl Iterator<Item = char> {
+    let first = 0xFFFFE - count as u32;
+    (first..=0xFFFFD).filter_map(char::from_u32)
+}
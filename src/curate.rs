@@ -0,0 +1,417 @@
+//! Decisions made while curating a pack (pinned codepoints, renames and exclusions), which are saved to a JSON file
+//! and applied to the scanned emoji before the pack is generated.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::{name_key, scan::EmojiSource, PackConfig, PackError};
+
+/// Curation decisions, each by the emoji's name as scanned.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Curation {
+    /// Codepoints pinned to emoji, taking precedence over their imported codepoints. They're written in hex, like
+    /// `U+F0010`, but read in any [`crate::CodepointFormat`].
+    #[serde(with = "pin_codepoints")]
+    pub pins: BTreeMap<String, char>,
+
+    /// New names of renamed emoji. Their imported codepoints move with them.
+    pub renames: BTreeMap<String, String>,
+
+    /// Emoji to leave out of the pack.
+    pub excluded: BTreeSet<String>,
+}
+
+/// Reads curation decisions, as written by the interactive curation mode.
+pub fn read_curation(path: &Path) -> Result<Curation, PackError> {
+    if !path.is_file() {
+        return Err(PackError::ImportMissing(path.to_path_buf()));
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+impl Curation {
+    /// Leaves out the excluded emoji and renames the renamed ones, then pins codepoints by adding them to the existing
+    /// mappings.
+    ///
+    /// As curation files can be edited by hand, fails if a pin is outside the private use planes, or would give an
+    /// emoji a codepoint another emoji is imported with or pinned to.
+    pub fn apply(
+        &self,
+        sources: &mut Vec<EmojiSource>,
+        config: &mut PackConfig,
+    ) -> Result<(), PackError> {
+        sources.retain(|source| {
+            let excluded = self.excluded.contains(&source.name);
+            if excluded {
+                debug!("leaving out \"{}\", as it's excluded", source.name);
+            }
+            !excluded
+        });
+
+        let mut renames = HashMap::new();
+        let mut pins = Vec::new();
+        for source in sources.iter_mut() {
+            let pin = self.pins.get(&source.name).copied();
+            if let Some(new_name) = self.renames.get(&source.name) {
+                let new_name = config.normalize_name(new_name);
+                debug!("renaming \"{}\" to \"{new_name}\"", source.name);
+                renames.insert(name_key(&source.name), name_key(&new_name));
+                source.name = new_name;
+            }
+            if let Some(codepoint) = pin {
+                pins.push((name_key(&source.name), codepoint));
+            }
+        }
+
+        crate::rename_mappings(&mut config.existing_mappings, &renames);
+
+        // each pinned codepoint has to be free for the emoji it's pinned to
+        let mut problems = Vec::new();
+        for (index, (name, codepoint)) in pins.iter().enumerate() {
+            let codepoint_name = format!("U+{:04X}", *codepoint as u32);
+            if !crate::is_private_use(*codepoint) {
+                problems.push(format!(
+                    "\"{name}\" is pinned to {codepoint_name}, outside the private use planes"
+                ));
+            }
+            let mut others = config
+                .existing_mappings
+                .iter()
+                .filter(|(other, other_codepoint)| *other != name && *other_codepoint == codepoint)
+                .map(|(other, _)| format!("imported \"{other}\""))
+                .chain(
+                    pins[..index]
+                        .iter()
+                        .filter(|(_, other_codepoint)| other_codepoint == codepoint)
+                        .map(|(other, _)| format!("pinned \"{other}\"")),
+                )
+                .collect::<Vec<_>>();
+            others.sort();
+            if !others.is_empty() {
+                problems.push(format!(
+                    "\"{name}\" is pinned to {codepoint_name}, which {} has",
+                    others.join(" and ")
+                ));
+            }
+        }
+        if !problems.is_empty() {
+            return Err(PackError::InvalidPins(problems));
+        }
+
+        config.existing_mappings.extend(pins);
+        info!(
+            "curated {} pinned, {} renamed and {} excluded emoji",
+            self.pins.len(),
+            self.renames.len(),
+            self.excluded.len()
+        );
+        Ok(())
+    }
+}
+
+/// An emoji being curated.
+#[derive(Debug, Clone)]
+pub struct CuratedEmoji {
+    /// The emoji's name as scanned.
+    pub name: String,
+
+    /// The emoji's codepoint in the existing mappings.
+    pub imported: Option<char>,
+}
+
+/// The emoji being curated and the decisions made so far, with one of them selected to edit.
+#[derive(Debug, Clone)]
+pub struct CurationState {
+    pub emoji: Vec<CuratedEmoji>,
+    pub curation: Curation,
+    selected: usize,
+}
+
+impl CurationState {
+    /// Starts curating the scanned emoji, from earlier decisions. Decisions about emoji which no longer exist are
+    /// dropped.
+    pub fn new(sources: &[EmojiSource], config: &PackConfig, mut curation: Curation) -> Self {
+        let emoji = sources
+            .iter()
+            .map(|source| CuratedEmoji {
+                name: source.name.clone(),
+                imported: config.imported_codepoint(&source.name),
+            })
+            .collect::<Vec<_>>();
+
+        let exists = |name: &String| emoji.iter().any(|emoji| emoji.name == *name);
+        curation.pins.retain(|name, _| exists(name));
+        curation.renames.retain(|name, _| exists(name));
+        curation.excluded.retain(exists);
+
+        Self {
+            emoji,
+            curation,
+            selected: 0,
+        }
+    }
+
+    /// Index of the selected emoji.
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// The selected emoji, unless there are none.
+    pub fn selected(&self) -> Option<&CuratedEmoji> {
+        self.emoji.get(self.selected)
+    }
+
+    /// Selects the next emoji, if there is one.
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.emoji.len() {
+            self.selected += 1;
+        }
+    }
+
+    /// Selects the previous emoji, if there is one.
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// The name an emoji will be packed under.
+    pub fn name<'a>(&'a self, emoji: &'a CuratedEmoji) -> &'a str {
+        self.curation
+            .renames
+            .get(&emoji.name)
+            .unwrap_or(&emoji.name)
+    }
+
+    /// The codepoint an emoji will keep, if it's pinned or imported.
+    pub fn codepoint(&self, emoji: &CuratedEmoji) -> Option<char> {
+        self.curation
+            .pins
+            .get(&emoji.name)
+            .copied()
+            .or(emoji.imported)
+    }
+
+    /// Whether an emoji will be left out.
+    pub fn is_excluded(&self, emoji: &CuratedEmoji) -> bool {
+        self.curation.excluded.contains(&emoji.name)
+    }
+
+    /// Excludes the selected emoji, or includes it again if it was excluded.
+    pub fn toggle_excluded(&mut self) {
+        let Some(name) = self.selected().map(|emoji| emoji.name.clone()) else {
+            return;
+        };
+        if !self.curation.excluded.remove(&name) {
+            self.curation.excluded.insert(name);
+        }
+    }
+
+    /// Pins a codepoint (like `U+F0010`) to the selected emoji, unless it's outside the private use planes or another
+    /// emoji keeps it.
+    pub fn pin(&mut self, codepoint: &str) -> Result<(), String> {
+        let codepoint = crate::read_codepoint(codepoint).map_err(|err| err.to_string())?;
+        if !crate::is_private_use(codepoint) {
+            return Err(format!(
+                "U+{:04X} isn't in the private use planes (U+F0000-U+10FFFD)",
+                codepoint as u32
+            ));
+        }
+        let Some(selected) = self.selected() else {
+            return Ok(());
+        };
+        if let Some(other) = self
+            .emoji
+            .iter()
+            .find(|emoji| emoji.name != selected.name && self.codepoint(emoji) == Some(codepoint))
+        {
+            return Err(format!(
+                "U+{:04X} is kept by \"{}\"",
+                codepoint as u32,
+                self.name(other)
+            ));
+        }
+
+        let name = selected.name.clone();
+        self.curation.pins.insert(name, codepoint);
+        Ok(())
+    }
+
+    /// Unpins the selected emoji's codepoint, so it keeps its imported one, if any.
+    pub fn unpin(&mut self) {
+        if let Some(name) = self.selected().map(|emoji| emoji.name.clone()) {
+            self.curation.pins.remove(&name);
+        }
+    }
+
+    /// Renames the selected emoji, unless another emoji has the name. Renaming it back to its scanned name (or to
+    /// nothing) undoes the rename.
+    pub fn rename(&mut self, new_name: &str, config: &PackConfig) -> Result<(), String> {
+        let Some(selected) = self.selected() else {
+            return Ok(());
+        };
+        let name = selected.name.clone();
+        let new_name = config.normalize_name(new_name.trim());
+        if new_name.is_empty() || new_name == name {
+            self.curation.renames.remove(&name);
+            return Ok(());
+        }
+
+        if let Some(other) = self
+            .emoji
+            .iter()
+            .find(|emoji| emoji.name != name && name_key(self.name(emoji)) == name_key(&new_name))
+        {
+            return Err(format!("\"{}\" already has that name", other.name));
+        }
+        self.curation.renames.insert(name, new_name);
+        Ok(())
+    }
+}
+
+/// Writing pinned codepoints in hex, for a curation file which is easy to read and edit by hand.
+mod pin_codepoints {
+    use std::collections::BTreeMap;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::CodepointFormat;
+
+    pub fn serialize<S: Serializer>(
+        pins: &BTreeMap<String, char>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        pins.iter()
+            .map(|(name, codepoint)| (name, CodepointFormat::Hex.to_json(*codepoint)))
+            .collect::<BTreeMap<_, _>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<String, char>, D::Error> {
+        BTreeMap::<String, serde_json::Value>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(name, codepoint)| {
+                let codepoint = CodepointFormat::from_json(&codepoint).map_err(D::Error::custom)?;
+                Ok((name, codepoint))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn sources(names: &[&str]) -> Vec<EmojiSource> {
+        names
+            .iter()
+            .map(|name| EmojiSource {
+                name: name.to_string(),
+                path: PathBuf::from(format!("{name}.png")),
+                mask: None,
+                alpha: None,
+                metadata: Default::default(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn edits_are_checked_and_applied() {
+        let mut config = PackConfig {
+            existing_mappings: HashMap::from([("blobcat".to_string(), '\u{F0000}')]),
+            ..PackConfig::default()
+        };
+        let mut scanned = sources(&["blobcat", "neofox", "old"]);
+        // a decision about an emoji which has gone is dropped
+        let earlier = Curation {
+            excluded: BTreeSet::from(["gone".to_string()]),
+            ..Curation::default()
+        };
+        let mut state = CurationState::new(&scanned, &config, earlier);
+        assert!(state.curation.excluded.is_empty());
+        assert_eq!(state.codepoint(&state.emoji[0]), Some('\u{F0000}'));
+
+        // neofox can't take blobcat's codepoint or name
+        state.select_next();
+        assert!(state.pin("U+F0000").is_err());
+        assert!(state.pin("41").is_err());
+        assert!(state.rename("BlobCat", &config).is_err());
+        state.pin("U+F0010").unwrap();
+        state.rename("Neofox Happy", &config).unwrap();
+        let neofox = state.selected().unwrap();
+        assert_eq!(state.name(neofox), "neofox happy");
+        assert_eq!(state.codepoint(neofox), Some('\u{F0010}'));
+
+        // excluding twice includes it again, and selection stops at the last emoji
+        state.select_next();
+        state.select_next();
+        state.toggle_excluded();
+        state.toggle_excluded();
+        state.toggle_excluded();
+        assert_eq!(state.selected_index(), 2);
+        assert!(state.is_excluded(&state.emoji[2]));
+
+        // pins are saved in hex
+        let curation = state.curation;
+        let saved = serde_json::to_value(&curation).unwrap();
+        assert_eq!(saved["pins"]["neofox"], "U+F0010");
+        assert_eq!(serde_json::from_value::<Curation>(saved).unwrap(), curation);
+
+        curation.apply(&mut scanned, &mut config).unwrap();
+        let names = scanned
+            .iter()
+            .map(|source| source.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["blobcat", "neofox happy"]);
+        assert_eq!(config.imported_codepoint("neofox happy"), Some('\u{F0010}'));
+        assert_eq!(config.imported_codepoint("blobcat"), Some('\u{F0000}'));
+    }
+
+    #[test]
+    fn clashing_or_public_pins_are_refused() {
+        let config = PackConfig {
+            existing_mappings: HashMap::from([("test_1".to_string(), '\u{F0001}')]),
+            ..PackConfig::default()
+        };
+        let apply = |pins: &[(&str, char)]| {
+            let curation = Curation {
+                pins: pins
+                    .iter()
+                    .map(|(name, codepoint)| (name.to_string(), *codepoint))
+                    .collect(),
+                ..Curation::default()
+            };
+            let mut config = config.clone();
+            curation.apply(&mut sources(&["test_0", "test_1", "test_2"]), &mut config)
+        };
+
+        assert!(apply(&[("test_0", '\u{F0002}'), ("test_1", '\u{F0001}')]).is_ok());
+        for (pins, problem) in [
+            (
+                &[("test_0", '\u{F0001}')][..],
+                "\"test_0\" is pinned to U+F0001, which imported \"test_1\" has",
+            ),
+            (
+                &[("test_0", '\u{F0005}'), ("test_2", '\u{F0005}')][..],
+                "\"test_2\" is pinned to U+F0005, which pinned \"test_0\" has",
+            ),
+            (
+                &[("test_0", 'A')][..],
+                "\"test_0\" is pinned to U+0041, outside the private use planes",
+            ),
+        ] {
+            match apply(pins) {
+                Err(PackError::InvalidPins(problems)) => assert_eq!(problems, [problem]),
+                other => panic!("expected {pins:?} to be refused, got {other:?}"),
+            }
+        }
+    }
+}
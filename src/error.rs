@@ -0,0 +1,184 @@
+use std::path::PathBuf;
+
+/// An error which prevents a pack from being generated.
+#[derive(Debug, thiserror::Error)]
+pub enum PackError {
+    #[error("emoji directory {} does not exist", .0.display())]
+    EmojiDirMissing(PathBuf),
+
+    #[error("mapping file {} does not exist", .0.display())]
+    ImportMissing(PathBuf),
+
+    #[error("failed to parse imported mapping: {0}")]
+    ImportParse(#[from] serde_json::Error),
+
+    #[error("failed to parse Aseprite export: {0}")]
+    AsepriteParse(serde_json::Error),
+
+    #[error("failed to read font {}: {reason}", .path.display())]
+    FontRead { path: PathBuf, reason: String },
+
+    #[error("export archive {} does not exist", .0.display())]
+    ExportMissing(PathBuf),
+
+    #[error("failed to read export archive {}: {source}", .path.display())]
+    ExportRead {
+        path: PathBuf,
+        source: zip::result::ZipError,
+    },
+
+    #[error("export archive {} has no {manifest}", .path.display())]
+    ExportManifestMissing {
+        path: PathBuf,
+        manifest: &'static str,
+    },
+
+    #[error("failed to parse export manifest: {0}")]
+    ExportParse(serde_json::Error),
+
+    #[error("CSV listing {} does not exist", .0.display())]
+    ListingMissing(PathBuf),
+
+    #[error("failed to read CSV listing {}: {source}", .path.display())]
+    ListingRead { path: PathBuf, source: csv::Error },
+
+    #[error("failed to read image {}: {source}", .path.display())]
+    ImageRead {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+
+    #[error("bundle {} has no fedimoji index", .0.display())]
+    BundleIndexMissing(PathBuf),
+
+    #[error("invalid font provider definition: {0}")]
+    InvalidProvider(String),
+
+    #[error("nothing at JSON pointer {pointer:?} in {}", .path.display())]
+    InvalidPointer { path: PathBuf, pointer: String },
+
+    #[error("no valid emoji provided!")]
+    NoValidEmoji,
+
+    #[error("no emoji named {0:?} in the emoji directory")]
+    EmojiNotFound(String),
+
+    #[error("not enough codepoints! {needed} needed, but only {available} available")]
+    CodepointsExhausted { needed: usize, available: usize },
+
+    #[error("emoji {} share the codepoint U+{:04X}", .names.join(", "), *.codepoint as u32)]
+    CodepointConflict { codepoint: char, names: Vec<String> },
+
+    #[error("atlas would take {bytes} bytes, over the limit of {limit} bytes")]
+    AtlasTooLarge { bytes: u64, limit: u64 },
+
+    #[error(
+        "the reserved regions leave no room for {needed} glyphs in an atlas within the size limit"
+    )]
+    ReservedRegionsFull { needed: u32 },
+
+    #[error("atlas is {width}x{height}, too large for the binary index's 16-bit positions")]
+    IndexTooLarge { width: u32, height: u32 },
+
+    #[error("no glyph size makes an atlas PNG of at most {target_bytes} bytes")]
+    OverBudget { target_bytes: u64 },
+
+    #[error("some emoji names are reserved: {}", .0.join(", "))]
+    ReservedNames(Vec<String>),
+
+    #[error("emoji names don't match the expected names ({} missing, {} unexpected)", .missing.len(), .extra.len())]
+    UnexpectedNames {
+        missing: Vec<String>,
+        extra: Vec<String>,
+    },
+
+    #[error("imported emoji have no image: {}", .0.join(", "))]
+    StaleImports(Vec<String>),
+
+    #[error("some emoji files are too large: {}", .0.join(", "))]
+    OversizedFiles(Vec<String>),
+
+    #[error("some emoji have no attribution: {}", .0.join(", "))]
+    MissingAttribution(Vec<String>),
+
+    #[error("emoji would get a different codepoint than they're imported with: {}", .0.join(", "))]
+    CodepointsReassigned(Vec<String>),
+
+    #[error("can't shorten \"{name}\" to {max_length} characters without it colliding with another name")]
+    NameLengthTooShort { name: String, max_length: usize },
+
+    #[error("some curation pins can't be used: {}", .0.join("; "))]
+    InvalidPins(Vec<String>),
+
+    #[error("glyphs don't match their place in the written atlas: {}", .0.join(", "))]
+    RoundTripFailed(Vec<String>),
+
+    #[error("padding of {pad}px leaves no room in a {glyph_size}px glyph")]
+    InvalidPadding { pad: u32, glyph_size: u32 },
+
+    #[error("{what} of {margin}px makes {glyph_width}x{glyph_height} glyph cells too large")]
+    InvalidMargin {
+        what: &'static str,
+        margin: u32,
+        glyph_width: u32,
+        glyph_height: u32,
+    },
+
+    #[error("tiles of {tile_size}px can't hold a whole number of {glyph_width}x{glyph_height} glyph cells")]
+    InvalidTileSize {
+        tile_size: u32,
+        glyph_width: u32,
+        glyph_height: u32,
+    },
+
+    #[error("invalid codepoint {0:?}, expected hex like U+F0000")]
+    InvalidCodepoint(String),
+
+    #[error("invalid Minecraft version {0:?}, expected one like 1.20.4")]
+    InvalidMcVersion(String),
+
+    #[error("invalid SOURCE_DATE_EPOCH {0:?}, expected a number of seconds since 1970")]
+    InvalidSourceDateEpoch(String),
+
+    #[error("build isn't reproducible: {0}")]
+    NotReproducible(String),
+
+    #[error("invalid profile: {0}")]
+    InvalidProfile(String),
+
+    #[error("invalid exclude pattern: {0}")]
+    InvalidPattern(#[from] globset::Error),
+
+    #[error("output path {} exists, but isn't a directory", .0.display())]
+    OutputNotDir(PathBuf),
+
+    #[error("output directory {} is inside the emoji directory, so its atlas would be read as an emoji next time; choose an output directory elsewhere", .0.display())]
+    OutputInEmojiDir(PathBuf),
+
+    #[error("resource pack zip {} does not exist", .0.display())]
+    ZipMissing(PathBuf),
+
+    #[error("failed to update zip {}: {source}", .path.display())]
+    Zip {
+        path: PathBuf,
+        source: zip::result::ZipError,
+    },
+
+    #[error("output directory is locked by another run (process {pid}), as {} exists", .path.display())]
+    OutputLocked { path: PathBuf, pid: String },
+
+    #[error("failed to write {}: {source}", .path.display())]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("failed to upload to \"{url}\": {message}")]
+    Upload { url: String, message: String },
+
+    #[error("post-build hook failed: {0}")]
+    HookFailed(std::process::ExitStatus),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
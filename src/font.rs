@@ -0,0 +1,1161 @@
+//! Reading emoji from the glyphs of a TrueType font, rasterizing each at the cell height, so an emoji font can be packed
+//! without exporting its glyphs first.
+//!
+//! Only TrueType (`glyf`) outlines are read, filled with the nonzero rule. Color glyphs made of version 0 `COLR` layers
+//! are drawn in their colors from the first `CPAL` palette, and glyphs without layers are drawn in white. Fonts of CFF
+//! outlines (most `.otf` files) or of embedded bitmaps only aren't supported, and color glyphs as bitmaps (`CBDT`,
+//! `sbix`) or SVG documents are drawn from their outlines instead.
+//!
+//! Fonts are untrusted input, so the work and memory spent on each glyph are bounded: see [`MAX_OUTLINE_POINTS`],
+//! [`MAX_ASPECT_RATIO`] and [`MAX_CHARACTERS`].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use tracing::{info, warn};
+
+use crate::{PackConfig, PackError};
+
+/// Color to draw glyphs without color layers in, and a layer's "current color".
+const FOREGROUND: [u8; 4] = [255, 255, 255, 255];
+
+/// How many rows of samples to take in each row of pixels, for antialiasing.
+const SUBSAMPLES: u32 = 8;
+
+/// How deeply composite glyphs may nest, so a font which refers to itself in a loop can't recurse forever.
+const MAX_COMPONENT_DEPTH: u32 = 8;
+
+/// How many points (and components) a glyph's outline may have across all its layers, so a font whose composite glyphs
+/// each use another many times over can't take exponential time to read.
+const MAX_OUTLINE_POINTS: usize = 1 << 16;
+
+/// How many times wider than it's tall a glyph may be drawn, so a huge advance can't allocate a huge image.
+const MAX_ASPECT_RATIO: u32 = 4;
+
+/// How many characters a character map may cover, counting those of overlapping ranges again, which is one more than
+/// the last codepoint in a valid font.
+const MAX_CHARACTERS: u64 = char::MAX as u64 + 1;
+
+/// Reads the emoji from a font's glyphs, each rasterized as tall as a cell and as wide as its advance.
+///
+/// With a mapping of emoji name -> codepoint (as read by [`crate::read_mapping_file`]), just those characters' glyphs
+/// are read, under those names. Otherwise, every character with an outline is read, named after its glyph's name in
+/// the font, or after its codepoint (like `u1f600`) if it has none.
+pub fn read_font_glyphs(
+    path: &Path,
+    names: Option<&HashMap<String, char>>,
+    config: &PackConfig,
+) -> Result<Vec<(String, DynamicImage)>, PackError> {
+    let data = std::fs::read(path)?;
+    let font = Font::parse(&data).map_err(|reason| PackError::FontRead {
+        path: path.to_path_buf(),
+        reason,
+    })?;
+    for table in &font.unsupported_color {
+        warn!(
+            kind = "unsupported",
+            "font's `{table}` color glyphs aren't supported (drawing their outlines instead)"
+        );
+    }
+
+    let characters = match names {
+        Some(names) => names
+            .iter()
+            .filter_map(|(name, codepoint)| match font.characters.get(codepoint) {
+                Some(glyph) => Some((name.clone(), *glyph)),
+                None => {
+                    warn!(
+                        kind = "skipped",
+                        "font has no glyph for \"{name}\", U+{:04X} (skipping it)",
+                        *codepoint as u32
+                    );
+                    None
+                }
+            })
+            .collect::<BTreeMap<_, _>>(),
+        None => font
+            .characters
+            .iter()
+            .map(|(codepoint, glyph)| {
+                let name = font
+                    .glyph_names
+                    .get(glyph)
+                    .cloned()
+                    .unwrap_or_else(|| format!("u{:04x}", *codepoint as u32));
+                (name, *glyph)
+            })
+            .collect(),
+    };
+
+    let mut emoji = Vec::new();
+    for (name, glyph) in characters {
+        let image = font
+            .rasterize(glyph, config.glyph_height)
+            .map_err(|reason| PackError::FontRead {
+                path: path.to_path_buf(),
+                reason,
+            })?;
+        match image {
+            Some(image) => emoji.push((config.normalize_name(&name), image.into())),
+            // only warn about blank glyphs which were asked for by name
+            None if names.is_some() => warn!(
+                kind = "skipped",
+                "font's glyph for \"{name}\" has no outline (skipping it)"
+            ),
+            None => {}
+        }
+    }
+
+    config.shorten_names(emoji.iter_mut().map(|(name, _)| name))?;
+    info!("read {} emoji from \"{}\"", emoji.len(), path.display());
+    Ok(emoji)
+}
+
+/// Bounds-checked big-endian reads from a font's bytes.
+#[derive(Clone, Copy)]
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Bytes<'a> {
+    fn slice(self, offset: usize, len: usize) -> Result<Bytes<'a>, String> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.0.get(offset..end))
+            .map(Bytes)
+            .ok_or_else(|| "font data ends early".to_string())
+    }
+
+    fn from(self, offset: usize) -> Result<Bytes<'a>, String> {
+        self.slice(offset, self.0.len().saturating_sub(offset))
+    }
+
+    fn u8(self, offset: usize) -> Result<u8, String> {
+        Ok(self.slice(offset, 1)?.0[0])
+    }
+
+    fn u16(self, offset: usize) -> Result<u16, String> {
+        let bytes = self.slice(offset, 2)?.0;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i16(self, offset: usize) -> Result<i16, String> {
+        Ok(self.u16(offset)? as i16)
+    }
+
+    fn u32(self, offset: usize) -> Result<u32, String> {
+        let bytes = self.slice(offset, 4)?.0;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// A point of a glyph's outline, in font units with y up.
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    x: f32,
+    y: f32,
+    on_curve: bool,
+}
+
+/// A straight edge of an outline, from one point to another, in pixels.
+type Edge = ((f32, f32), (f32, f32));
+
+/// A colored layer of a color glyph: the glyph giving its shape, and its color.
+struct Layer {
+    glyph: u16,
+    color: [u8; 4],
+}
+
+/// The color layers of a font's glyphs, read from a version 0 `COLR` table when a glyph is drawn, as many glyphs may
+/// share the same layers.
+struct ColorLayers<'a> {
+    /// The range of layer records of each color glyph.
+    bases: HashMap<u16, std::ops::Range<usize>>,
+    records: Bytes<'a>,
+    /// The colors of the first `CPAL` palette.
+    palette: Vec<[u8; 4]>,
+}
+
+impl ColorLayers<'_> {
+    /// The layers of a glyph, or [`None`] if it isn't a color glyph.
+    fn get(&self, glyph: u16) -> Result<Option<Vec<Layer>>, String> {
+        let Some(range) = self.bases.get(&glyph) else {
+            return Ok(None);
+        };
+        range
+            .clone()
+            .map(|layer| {
+                let index = self.records.u16(4 * layer + 2)?;
+                let color = match index {
+                    0xFFFF => FOREGROUND,
+                    index => *self
+                        .palette
+                        .get(usize::from(index))
+                        .ok_or_else(|| format!("color {index} isn't in the palette"))?,
+                };
+                Ok(Layer {
+                    glyph: self.records.u16(4 * layer)?,
+                    color,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+}
+
+/// The parts of a TrueType font needed to rasterize its characters' glyphs.
+struct Font<'a> {
+    glyf: Bytes<'a>,
+    /// Where each glyph's data starts in `glyf`, with one more entry for where the last one ends.
+    loca: Vec<usize>,
+    ascender: f32,
+    descender: f32,
+    advances: Vec<u16>,
+    characters: BTreeMap<char, u16>,
+    glyph_names: HashMap<u16, String>,
+    layers: Option<ColorLayers<'a>>,
+    /// Tags of the tables of color glyphs in formats which aren't supported.
+    unsupported_color: Vec<&'static str>,
+}
+
+impl<'a> Font<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, String> {
+        let data = Bytes(data);
+        let tables = match data.u32(0)? {
+            0x0001_0000 | 0x7472_7565 => read_table_directory(data)?,
+            0x4F54_544F => {
+                return Err("CFF outlines aren't supported, only TrueType ones".to_string())
+            }
+            0x7474_6366 => return Err("font collections aren't supported".to_string()),
+            _ => return Err("not a TrueType font".to_string()),
+        };
+        let table = |tag: &str| {
+            tables
+                .get(tag)
+                .copied()
+                .ok_or_else(|| format!("no `{tag}` table"))
+        };
+
+        let head = table("head")?;
+        let long_offsets = head.i16(50)? != 0;
+        let num_glyphs = table("maxp")?.u16(4)?;
+        let hhea = table("hhea")?;
+        let ascender = f32::from(hhea.i16(4)?);
+        let descender = f32::from(hhea.i16(6)?);
+        if ascender <= descender {
+            return Err("ascender isn't above the descender".to_string());
+        }
+
+        let hmtx = table("hmtx")?;
+        let num_metrics = hhea.u16(34)?.clamp(1, num_glyphs.max(1));
+        let advances = (0..num_glyphs)
+            .map(|glyph| hmtx.u16(4 * usize::from(glyph.min(num_metrics - 1))))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let glyf = tables.get("glyf").copied().ok_or(
+            "no TrueType outlines (`glyf` table); CFF and bitmap-only fonts aren't supported",
+        )?;
+        let loca = table("loca")?;
+        let loca = (0..=usize::from(num_glyphs))
+            .map(|glyph| match long_offsets {
+                true => loca.u32(4 * glyph).map(|offset| offset as usize),
+                false => loca.u16(2 * glyph).map(|offset| 2 * usize::from(offset)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let layers = match (tables.get("COLR"), tables.get("CPAL")) {
+            (Some(colr), Some(cpal)) => Some(read_color_layers(*colr, *cpal)?),
+            _ => None,
+        };
+        let unsupported_color = ["CBDT", "sbix", "SVG "]
+            .into_iter()
+            .filter(|tag| tables.contains_key(*tag))
+            .map(str::trim_end)
+            .collect();
+
+        Ok(Self {
+            glyf,
+            loca,
+            ascender,
+            descender,
+            advances,
+            characters: read_cmap(table("cmap")?, num_glyphs)?,
+            glyph_names: match tables.get("post") {
+                Some(post) => read_glyph_names(*post)?,
+                None => HashMap::new(),
+            },
+            layers,
+            unsupported_color,
+        })
+    }
+
+    /// Rasterizes a glyph as tall as `height`, with the ascender at the top and the descender at the bottom, or
+    /// returns [`None`] if it has no outline.
+    fn rasterize(&self, glyph: u16, height: u32) -> Result<Option<RgbaImage>, String> {
+        let scale = height as f32 / (self.ascender - self.descender);
+        let advance = self.advances.get(usize::from(glyph)).copied().unwrap_or(0);
+        let width = (f32::from(advance) * scale)
+            .round()
+            .clamp(1.0, (height.saturating_mul(MAX_ASPECT_RATIO)) as f32)
+            as u32;
+        let to_pixels = |point: Point| (point.x * scale, (self.ascender - point.y) * scale);
+
+        let layers = match &self.layers {
+            Some(layers) => layers.get(glyph)?,
+            None => None,
+        };
+        let layers = layers.unwrap_or_else(|| {
+            vec![Layer {
+                glyph,
+                color: FOREGROUND,
+            }]
+        });
+
+        // the budget is shared by all the layers, as each may be as complex as a whole glyph
+        let mut budget = MAX_OUTLINE_POINTS;
+        let mut image = RgbaImage::new(width, height);
+        let mut drawn = false;
+        for layer in layers {
+            let contours = self.outline(layer.glyph, 0, &mut budget)?;
+            if contours.is_empty() {
+                continue;
+            }
+            let edges = contours
+                .iter()
+                .flat_map(|contour| flatten(contour))
+                .map(|(from, to)| (to_pixels(from), to_pixels(to)))
+                .collect::<Vec<_>>();
+            fill(&mut image, &edges, layer.color);
+            drawn = true;
+        }
+        Ok(drawn.then_some(image))
+    }
+
+    /// The contours of a glyph's outline, following its components if it's a composite glyph, taking the points and
+    /// components read from the budget.
+    fn outline(
+        &self,
+        glyph: u16,
+        depth: u32,
+        budget: &mut usize,
+    ) -> Result<Vec<Vec<Point>>, String> {
+        let glyph = usize::from(glyph);
+        let (Some(&start), Some(&end)) = (self.loca.get(glyph), self.loca.get(glyph + 1)) else {
+            return Err(format!("glyph {glyph} isn't in the font"));
+        };
+        if end <= start {
+            return Ok(Vec::new());
+        }
+        let data = self.glyf.slice(start, end - start)?;
+        let num_contours = data.i16(0)?;
+        if num_contours >= 0 {
+            let contours = read_simple_glyph(data, num_contours as usize)?;
+            spend(budget, contours.iter().map(Vec::len).sum())?;
+            Ok(contours)
+        } else if depth < MAX_COMPONENT_DEPTH {
+            self.read_composite_glyph(data, depth, budget)
+        } else {
+            Err(format!("glyph {glyph}'s components nest too deeply"))
+        }
+    }
+
+    /// Reads a composite glyph's components, transforming each one's outline into place.
+    fn read_composite_glyph(
+        &self,
+        data: Bytes,
+        depth: u32,
+        budget: &mut usize,
+    ) -> Result<Vec<Vec<Point>>, String> {
+        const WORD_ARGS: u16 = 0x0001;
+        const XY_VALUES: u16 = 0x0002;
+        const SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+        const XY_SCALE: u16 = 0x0040;
+        const TWO_BY_TWO: u16 = 0x0080;
+        let f2dot14 = |offset| data.i16(offset).map(|value| f32::from(value) / 16384.0);
+
+        let mut contours = Vec::new();
+        let mut offset = 10;
+        loop {
+            spend(budget, 1)?;
+            let flags = data.u16(offset)?;
+            let component = data.u16(offset + 2)?;
+            offset += 4;
+            let (dx, dy) = if flags & WORD_ARGS != 0 {
+                offset += 4;
+                (data.i16(offset - 4)?.into(), data.i16(offset - 2)?.into())
+            } else {
+                offset += 2;
+                (
+                    f32::from(data.u8(offset - 2)? as i8),
+                    f32::from(data.u8(offset - 1)? as i8),
+                )
+            };
+            // matching up points of the components isn't supported, so those are placed at their origin
+            let (dx, dy) = if flags & XY_VALUES != 0 {
+                (dx, dy)
+            } else {
+                (0.0, 0.0)
+            };
+
+            let [a, b, c, d] = if flags & SCALE != 0 {
+                offset += 2;
+                let scale = f2dot14(offset - 2)?;
+                [scale, 0.0, 0.0, scale]
+            } else if flags & XY_SCALE != 0 {
+                offset += 4;
+                [f2dot14(offset - 4)?, 0.0, 0.0, f2dot14(offset - 2)?]
+            } else if flags & TWO_BY_TWO != 0 {
+                offset += 8;
+                [
+                    f2dot14(offset - 8)?,
+                    f2dot14(offset - 6)?,
+                    f2dot14(offset - 4)?,
+                    f2dot14(offset - 2)?,
+                ]
+            } else {
+                [1.0, 0.0, 0.0, 1.0]
+            };
+
+            for contour in self.outline(component, depth + 1, budget)? {
+                contours.push(
+                    contour
+                        .into_iter()
+                        .map(|point| Point {
+                            x: a * point.x + c * point.y + dx,
+                            y: b * point.x + d * point.y + dy,
+                            on_curve: point.on_curve,
+                        })
+                        .collect(),
+                );
+            }
+
+            if flags & MORE_COMPONENTS == 0 {
+                return Ok(contours);
+            }
+        }
+    }
+}
+
+/// Takes some work from a glyph's budget, failing if there isn't enough left.
+fn spend(budget: &mut usize, work: usize) -> Result<(), String> {
+    *budget = budget
+        .checked_sub(work)
+        .ok_or_else(|| format!("glyph has over {MAX_OUTLINE_POINTS} points"))?;
+    Ok(())
+}
+
+/// Reads where each table is in the font, by tag.
+fn read_table_directory(data: Bytes) -> Result<HashMap<String, Bytes>, String> {
+    let num_tables = usize::from(data.u16(4)?);
+    (0..num_tables)
+        .map(|i| {
+            let record = data.slice(12 + 16 * i, 16)?;
+            let tag = String::from_utf8_lossy(record.slice(0, 4)?.0).into_owned();
+            let table = data.slice(record.u32(8)? as usize, record.u32(12)? as usize)?;
+            Ok((tag, table))
+        })
+        .collect()
+}
+
+/// Reads which glyph each character maps to, from the Unicode subtable of the `cmap` table, preferring one covering
+/// all of Unicode (format 12) to one covering just the Basic Multilingual Plane (format 4).
+fn read_cmap(cmap: Bytes, num_glyphs: u16) -> Result<BTreeMap<char, u16>, String> {
+    let num_subtables = usize::from(cmap.u16(2)?);
+    let mut best = None;
+    for i in 0..num_subtables {
+        let platform = cmap.u16(4 + 8 * i)?;
+        let encoding = cmap.u16(6 + 8 * i)?;
+        let subtable = cmap.from(cmap.u32(8 + 8 * i)? as usize)?;
+        let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+        let format = subtable.u16(0)?;
+        if unicode && (format == 4 || format == 12) && best.is_none_or(|(best, _)| format > best) {
+            best = Some((format, subtable));
+        }
+    }
+
+    // overlapping ranges could otherwise make a small table take a long time to read
+    let mut covered = 0u64;
+    let mut cover = |start: u32, end: u32| {
+        covered += u64::from(end.saturating_sub(start)) + 1;
+        match covered <= MAX_CHARACTERS {
+            true => Ok(()),
+            false => Err("character map covers too many characters".to_string()),
+        }
+    };
+
+    let mut characters = BTreeMap::new();
+    let mut map = |codepoint: u32, glyph: u32| {
+        if let (Some(codepoint), Ok(glyph)) = (char::from_u32(codepoint), u16::try_from(glyph)) {
+            if glyph != 0 && glyph < num_glyphs {
+                characters.insert(codepoint, glyph);
+            }
+        }
+    };
+    match best {
+        Some((12, subtable)) => {
+            for group in 0..subtable.u32(12)? as usize {
+                let start = subtable.u32(16 + 12 * group)?;
+                let end = subtable.u32(20 + 12 * group)?;
+                let start_glyph = subtable.u32(24 + 12 * group)?;
+                let end = end.min(char::MAX as u32);
+                if end < start {
+                    continue;
+                }
+                cover(start, end)?;
+                for codepoint in start..=end {
+                    // glyphs past the last one the font can have aren't mapped
+                    let Some(glyph) = start_glyph.checked_add(codepoint - start) else {
+                        break;
+                    };
+                    map(codepoint, glyph);
+                }
+            }
+        }
+        Some((_, subtable)) => {
+            let segments = usize::from(subtable.u16(6)? / 2);
+            let ends = 14;
+            let starts = ends + 2 * segments + 2;
+            let deltas = starts + 2 * segments;
+            let range_offsets = deltas + 2 * segments;
+            for segment in 0..segments {
+                let end = subtable.u16(ends + 2 * segment)?;
+                let start = subtable.u16(starts + 2 * segment)?;
+                let delta = subtable.u16(deltas + 2 * segment)?;
+                let range_offset = usize::from(subtable.u16(range_offsets + 2 * segment)?);
+                if end < start {
+                    continue;
+                }
+                cover(u32::from(start), u32::from(end))?;
+                // the last segment only maps U+FFFF, which isn't a character
+                for codepoint in start..=end.min(0xFFFE) {
+                    let glyph = if range_offset == 0 {
+                        codepoint.wrapping_add(delta)
+                    } else {
+                        let offset = range_offsets
+                            + 2 * segment
+                            + range_offset
+                            + 2 * usize::from(codepoint - start);
+                        match subtable.u16(offset)? {
+                            0 => 0,
+                            glyph => glyph.wrapping_add(delta),
+                        }
+                    };
+                    map(u32::from(codepoint), u32::from(glyph));
+                }
+            }
+        }
+        None => return Err("no Unicode character map".to_string()),
+    }
+    Ok(characters)
+}
+
+/// Reads the glyphs' names from a version 2 `post` table. Glyphs with one of the standard Macintosh names (like
+/// `space`) are left out, as those aren't stored in the font.
+fn read_glyph_names(post: Bytes) -> Result<HashMap<u16, String>, String> {
+    if post.u32(0)? != 0x0002_0000 {
+        return Ok(HashMap::new());
+    }
+    let num_glyphs = post.u16(32)?;
+    let indices = (0..num_glyphs)
+        .map(|glyph| post.u16(34 + 2 * usize::from(glyph)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // the names which aren't standard ones follow, as Pascal strings
+    let mut strings = Vec::new();
+    let mut offset = 34 + 2 * usize::from(num_glyphs);
+    while offset < post.0.len() {
+        let len = usize::from(post.u8(offset)?);
+        strings.push(String::from_utf8_lossy(post.slice(offset + 1, len)?.0).into_owned());
+        offset += 1 + len;
+    }
+
+    Ok((0..num_glyphs)
+        .zip(indices)
+        .filter_map(|(glyph, index)| {
+            let name = strings.get(usize::from(index).checked_sub(258)?)?;
+            Some((glyph, name.clone()))
+        })
+        .collect())
+}
+
+/// Reads where the layers of each color glyph are in a version 0 `COLR` table, and the colors of the first `CPAL`
+/// palette.
+fn read_color_layers<'a>(colr: Bytes<'a>, cpal: Bytes<'a>) -> Result<ColorLayers<'a>, String> {
+    let num_entries = usize::from(cpal.u16(2)?);
+    let colors = cpal.from(cpal.u32(8)? as usize)?;
+    let first_color = usize::from(cpal.u16(12)?);
+    let palette = (first_color..first_color + num_entries)
+        .map(|index| {
+            let record = colors.slice(4 * index, 4)?.0;
+            let [blue, green, red, alpha] = [record[0], record[1], record[2], record[3]];
+            Ok([red, green, blue, alpha])
+        })
+        .collect::<Result<_, String>>()?;
+
+    let num_bases = usize::from(colr.u16(2)?);
+    let bases = colr.from(colr.u32(4)? as usize)?;
+    let records = colr.from(colr.u32(8)? as usize)?;
+    let num_records = usize::from(colr.u16(12)?);
+    let bases = (0..num_bases)
+        .map(|i| {
+            let glyph = bases.u16(6 * i)?;
+            let first = usize::from(bases.u16(6 * i + 2)?);
+            let count = usize::from(bases.u16(6 * i + 4)?);
+            if first + count > num_records {
+                return Err(format!("glyph {glyph}'s color layers aren't in the font"));
+            }
+            Ok((glyph, first..first + count))
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(ColorLayers {
+        bases,
+        records,
+        palette,
+    })
+}
+
+/// Reads the contours of a glyph made of points.
+fn read_simple_glyph(data: Bytes, num_contours: usize) -> Result<Vec<Vec<Point>>, String> {
+    const ON_CURVE: u8 = 0x01;
+    const X_SHORT: u8 = 0x02;
+    const Y_SHORT: u8 = 0x04;
+    const REPEAT: u8 = 0x08;
+    const X_SAME_OR_POSITIVE: u8 = 0x10;
+    const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+    let ends = (0..num_contours)
+        .map(|contour| data.u16(10 + 2 * contour).map(usize::from))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(num_points) = ends.last().map(|last| last + 1) else {
+        return Ok(Vec::new());
+    };
+    let instructions = usize::from(data.u16(10 + 2 * num_contours)?);
+    let mut offset = 12 + 2 * num_contours + instructions;
+
+    let mut flags = Vec::with_capacity(num_points);
+    while flags.len() < num_points {
+        let flag = data.u8(offset)?;
+        offset += 1;
+        let repeats = if flag & REPEAT != 0 {
+            offset += 1;
+            usize::from(data.u8(offset - 1)?)
+        } else {
+            0
+        };
+        flags.extend(std::iter::repeat_n(flag, repeats + 1));
+    }
+    flags.truncate(num_points);
+
+    // each coordinate is a byte (with a sign flag), the same as the last one, or a signed word relative to it
+    let mut read_coordinates = |short: u8, same_or_positive: u8| {
+        let mut value = 0i64;
+        flags
+            .iter()
+            .map(|&flag| {
+                if flag & short != 0 {
+                    let delta = i64::from(data.u8(offset)?);
+                    offset += 1;
+                    value += if flag & same_or_positive != 0 {
+                        delta
+                    } else {
+                        -delta
+                    };
+                } else if flag & same_or_positive == 0 {
+                    value += i64::from(data.i16(offset)?);
+                    offset += 2;
+                }
+                Ok(value as f32)
+            })
+            .collect::<Result<Vec<_>, String>>()
+    };
+    let xs = read_coordinates(X_SHORT, X_SAME_OR_POSITIVE)?;
+    let ys = read_coordinates(Y_SHORT, Y_SAME_OR_POSITIVE)?;
+
+    let mut start = 0;
+    ends.into_iter()
+        .map(|end| {
+            if end < start || end >= num_points {
+                return Err("contour ends are out of order".to_string());
+            }
+            let contour = (start..=end)
+                .map(|i| Point {
+                    x: xs[i],
+                    y: ys[i],
+                    on_curve: flags[i] & ON_CURVE != 0,
+                })
+                .collect();
+            start = end + 1;
+            Ok(contour)
+        })
+        .collect()
+}
+
+/// Turns a closed contour of quadratic curves into straight edges, adding the on-curve points implied between
+/// consecutive off-curve ones.
+fn flatten(contour: &[Point]) -> Vec<(Point, Point)> {
+    const STEPS: usize = 8;
+    let midpoint = |a: Point, b: Point| Point {
+        x: (a.x + b.x) / 2.0,
+        y: (a.y + b.y) / 2.0,
+        on_curve: true,
+    };
+
+    // start from an on-curve point, making one between the first two points if there isn't one
+    let (start, rest) = match contour.iter().position(|point| point.on_curve) {
+        Some(i) => (contour[i], contour[i + 1..].iter().chain(&contour[..=i])),
+        None if contour.len() >= 2 => (
+            midpoint(contour[0], contour[1]),
+            contour[1..].iter().chain(&contour[..1]),
+        ),
+        None => return Vec::new(),
+    };
+    // close the contour back to the made-up start, as it isn't one of the points
+    let closing = (!contour.iter().any(|point| point.on_curve)).then_some(start);
+
+    let mut edges = Vec::new();
+    let mut current = start;
+    let mut control: Option<Point> = None;
+    let points = rest.copied().chain(closing);
+    for point in points {
+        match (control, point.on_curve) {
+            (None, true) => {
+                edges.push((current, point));
+                current = point;
+            }
+            (None, false) => control = Some(point),
+            (Some(ctrl), on_curve) => {
+                let end = if on_curve {
+                    point
+                } else {
+                    midpoint(ctrl, point)
+                };
+                let mut previous = current;
+                for step in 1..=STEPS {
+                    let t = step as f32 / STEPS as f32;
+                    let u = 1.0 - t;
+                    let next = Point {
+                        x: u * u * current.x + 2.0 * u * t * ctrl.x + t * t * end.x,
+                        y: u * u * current.y + 2.0 * u * t * ctrl.y + t * t * end.y,
+                        on_curve: true,
+                    };
+                    edges.push((previous, next));
+                    previous = next;
+                }
+                current = end;
+                control = (!on_curve).then_some(point);
+            }
+        }
+    }
+    edges
+}
+
+/// Fills the inside of the edges (in pixels, with y down) by the nonzero rule, blending the color over the image
+/// with the antialiased coverage of each pixel.
+fn fill(image: &mut RgbaImage, edges: &[Edge], color: [u8; 4]) {
+    let (width, height) = image.dimensions();
+    let mut coverage = vec![0.0f32; width as usize];
+    for y in 0..height {
+        coverage.fill(0.0);
+        for sample in 0..SUBSAMPLES {
+            let sample_y = y as f32 + (sample as f32 + 0.5) / SUBSAMPLES as f32;
+
+            // where the edges cross this row of samples, and which way they wind
+            let mut crossings = edges
+                .iter()
+                .filter_map(|&((x0, y0), (x1, y1))| {
+                    let (top, bottom, winding) = if y0 < y1 { (y0, y1, 1) } else { (y1, y0, -1) };
+                    if sample_y < top || sample_y >= bottom {
+                        return None;
+                    }
+                    Some((x0 + (sample_y - y0) / (y1 - y0) * (x1 - x0), winding))
+                })
+                .collect::<Vec<_>>();
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                if winding != 0 {
+                    add_span(&mut coverage, pair[0].0, pair[1].0);
+                }
+            }
+        }
+
+        for (x, covered) in coverage.iter().enumerate() {
+            let alpha = (covered / SUBSAMPLES as f32).min(1.0) * f32::from(color[3]) / 255.0;
+            if alpha > 0.0 {
+                blend(image.get_pixel_mut(x as u32, y), color, alpha);
+            }
+        }
+    }
+}
+
+/// Adds the horizontal span from `start` to `end` to the coverage of the pixels it covers, partly covering the pixels
+/// at its ends.
+fn add_span(coverage: &mut [f32], start: f32, end: f32) {
+    let start = start.clamp(0.0, coverage.len() as f32);
+    let end = end.clamp(0.0, coverage.len() as f32);
+    let mut x = start;
+    while x < end {
+        let pixel = x.floor();
+        let next = (pixel + 1.0).min(end);
+        coverage[pixel as usize] += next - x;
+        x = next;
+    }
+}
+
+/// Blends a color with some opacity over a pixel.
+fn blend(pixel: &mut Rgba<u8>, color: [u8; 4], alpha: f32) {
+    let below = f32::from(pixel[3]) / 255.0;
+    let out = alpha + below * (1.0 - alpha);
+    for channel in 0..3 {
+        let mixed = (f32::from(color[channel]) * alpha
+            + f32::from(pixel[channel]) * below * (1.0 - alpha))
+            / out;
+        pixel[channel] = mixed.round() as u8;
+    }
+    pixel[3] = (out * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A glyph's outline: contours of points (x, y, on-curve), or other glyphs each placed at an offset.
+    enum Outline<'a> {
+        Simple(&'a [&'a [(i16, i16, bool)]]),
+        Composite(&'a [(u16, i16, i16)]),
+    }
+
+    /// Encodes a glyph for the `glyf` table, with each coordinate as a word relative to the last one.
+    fn encode_glyph(outline: &Outline) -> Vec<u8> {
+        let mut data = Vec::new();
+        match outline {
+            Outline::Simple(contours) => {
+                data.extend((contours.len() as i16).to_be_bytes());
+                data.extend([0; 8]);
+                let mut end = 0;
+                for contour in *contours {
+                    end += contour.len();
+                    data.extend((end as u16 - 1).to_be_bytes());
+                }
+                data.extend(0u16.to_be_bytes());
+                let points = contours.iter().flat_map(|contour| contour.iter());
+                data.extend(points.clone().map(|&(_, _, on_curve)| u8::from(on_curve)));
+                for coordinate in [|(x, _, _): (i16, i16, bool)| x, |(_, y, _)| y] {
+                    let mut last = 0;
+                    for &point in points.clone() {
+                        data.extend((coordinate(point) - last).to_be_bytes());
+                        last = coordinate(point);
+                    }
+                }
+            }
+            Outline::Composite(components) => {
+                data.extend((-1i16).to_be_bytes());
+                data.extend([0; 8]);
+                for (i, (glyph, dx, dy)) in components.iter().enumerate() {
+                    // word offsets, with more components to come for all but the last
+                    let more = if i + 1 < components.len() { 0x0020 } else { 0 };
+                    data.extend((0x0003u16 | more).to_be_bytes());
+                    data.extend(glyph.to_be_bytes());
+                    data.extend(dx.to_be_bytes());
+                    data.extend(dy.to_be_bytes());
+                }
+            }
+        }
+        data
+    }
+
+    /// Builds a font with 1000 units from the descender (-200) to the ascender (800), so each unit is 1/50 of a pixel
+    /// at 20px tall, and every glyph is as wide as it's tall. Its glyphs are:
+    ///
+    /// 1. `ring`, filling the cell but for a hole from (5, 5) to (15, 15)
+    /// 2. `square`, filling the top-left quarter
+    /// 3. `smiley`, the ring in red under the square in blue, as color layers
+    /// 4. `circle`, made only of off-curve points at the cell's corners
+    /// 5. `corner`, the square moved to the bottom-right quarter
+    fn test_font() -> Vec<u8> {
+        let outlines = [
+            Outline::Simple(&[]),
+            Outline::Simple(&[
+                &[
+                    (0, -200, true),
+                    (0, 800, true),
+                    (1000, 800, true),
+                    (1000, -200, true),
+                ],
+                &[
+                    (250, 50, true),
+                    (750, 50, true),
+                    (750, 550, true),
+                    (250, 550, true),
+                ],
+            ]),
+            Outline::Simple(&[&[
+                (0, 300, true),
+                (0, 800, true),
+                (500, 800, true),
+                (500, 300, true),
+            ]]),
+            Outline::Simple(&[]),
+            Outline::Simple(&[&[
+                (0, -200, false),
+                (0, 800, false),
+                (1000, 800, false),
+                (1000, -200, false),
+            ]]),
+            Outline::Composite(&[(2, 500, -500)]),
+        ];
+        let characters = [('A', 1u16), ('B', 2), ('C', 5), ('O', 4), ('\u{263A}', 3)];
+        let names = ["ring", "square", "smiley", "circle", "corner"];
+        build_font(&outlines, &characters, &names)
+    }
+
+    /// Builds a font of the given glyphs (the first being the missing glyph), characters and glyph names (of the
+    /// glyphs after the first), in the metrics of [`test_font`], with glyph 3 colored as its `smiley`.
+    fn build_font(outlines: &[Outline], characters: &[(char, u16)], names: &[&str]) -> Vec<u8> {
+        let num_glyphs = outlines.len() as u16;
+
+        let mut head = vec![0; 54];
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        head[50..52].copy_from_slice(&1u16.to_be_bytes());
+
+        let mut hhea = vec![0; 36];
+        hhea[4..6].copy_from_slice(&800i16.to_be_bytes());
+        hhea[6..8].copy_from_slice(&(-200i16).to_be_bytes());
+        hhea[34..36].copy_from_slice(&num_glyphs.to_be_bytes());
+
+        let mut maxp = vec![0, 0, 0x50, 0];
+        maxp.extend(num_glyphs.to_be_bytes());
+
+        let hmtx = (0..num_glyphs)
+            .flat_map(|_| [1000u16.to_be_bytes(), [0, 0]].concat())
+            .collect::<Vec<_>>();
+
+        let (mut glyf, mut loca) = (Vec::new(), 0u32.to_be_bytes().to_vec());
+        for outline in outlines {
+            glyf.extend(encode_glyph(outline));
+            loca.extend((glyf.len() as u32).to_be_bytes());
+        }
+
+        // a format 4 subtable with a segment per character, then the segment of U+FFFF which ends it
+        let segments = characters
+            .iter()
+            .map(|&(c, glyph)| (c as u16, glyph.wrapping_sub(c as u16)))
+            .chain([(0xFFFF, 1)])
+            .collect::<Vec<_>>();
+        let mut subtable = [4u16, 0, 0, 2 * segments.len() as u16, 0, 0, 0]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect::<Vec<_>>();
+        subtable.extend(segments.iter().flat_map(|(c, _)| c.to_be_bytes()));
+        subtable.extend([0, 0]);
+        subtable.extend(segments.iter().flat_map(|(c, _)| c.to_be_bytes()));
+        subtable.extend(segments.iter().flat_map(|(_, delta)| delta.to_be_bytes()));
+        subtable.extend(segments.iter().flat_map(|_| [0, 0]));
+        let mut cmap = [0u16, 1, 3, 1]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect::<Vec<_>>();
+        cmap.extend(12u32.to_be_bytes());
+        cmap.extend(subtable);
+
+        let mut post = vec![0, 2, 0, 0];
+        post.extend([0; 28]);
+        post.extend(num_glyphs.to_be_bytes());
+        post.extend(0u16.to_be_bytes());
+        post.extend((0..names.len() as u16).flat_map(|i| (258 + i).to_be_bytes()));
+        post.extend((names.len() + 1..outlines.len()).flat_map(|_| 0u16.to_be_bytes()));
+        for name in names {
+            post.push(name.len() as u8);
+            post.extend(name.as_bytes());
+        }
+
+        // the smiley is the ring in color 0, then the square in color 1
+        let mut colr = [0u16, 1]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect::<Vec<_>>();
+        colr.extend(14u32.to_be_bytes());
+        colr.extend(20u32.to_be_bytes());
+        colr.extend(2u16.to_be_bytes());
+        colr.extend(
+            [3u16, 0, 2, 1, 0, 2, 1]
+                .iter()
+                .flat_map(|v| v.to_be_bytes()),
+        );
+        let mut cpal = [0u16, 2, 1, 2]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect::<Vec<_>>();
+        cpal.extend(14u32.to_be_bytes());
+        cpal.extend(0u16.to_be_bytes());
+        cpal.extend([0, 0, 255, 255, 255, 0, 0, 255]);
+
+        let tables: [(&[u8; 4], Vec<u8>); 10] = [
+            (b"COLR", colr),
+            (b"CPAL", cpal),
+            (b"cmap", cmap),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"loca", loca),
+            (b"maxp", maxp),
+            (b"post", post),
+        ];
+        let mut font = 0x0001_0000u32.to_be_bytes().to_vec();
+        font.extend((tables.len() as u16).to_be_bytes());
+        font.extend([0; 6]);
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, table) in &tables {
+            font.extend(*tag);
+            font.extend([0; 4]);
+            font.extend((offset as u32).to_be_bytes());
+            font.extend((table.len() as u32).to_be_bytes());
+            offset += table.len();
+        }
+        for (_, table) in tables {
+            font.extend(table);
+        }
+        font
+    }
+
+    fn read_test_font(names: Option<&HashMap<String, char>>) -> BTreeMap<String, RgbaImage> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("emoji.ttf");
+        std::fs::write(&path, test_font()).unwrap();
+        let config = PackConfig {
+            glyph_width: 20,
+            glyph_height: 20,
+            ..PackConfig::default()
+        };
+        read_font_glyphs(&path, names, &config)
+            .unwrap()
+            .into_iter()
+            .map(|(name, image)| (name, image.into_rgba8()))
+            .collect()
+    }
+
+    const CLEAR: Rgba<u8> = Rgba([0, 0, 0, 0]);
+    const WHITE: Rgba<u8> = Rgba(FOREGROUND);
+
+    #[test]
+    fn glyphs_are_named_and_filled_around_their_holes() {
+        let glyphs = read_test_font(None);
+        assert_eq!(
+            glyphs.keys().collect::<Vec<_>>(),
+            ["circle", "corner", "ring", "smiley", "square"]
+        );
+        assert!(glyphs.values().all(|glyph| glyph.dimensions() == (20, 20)));
+
+        let ring = &glyphs["ring"];
+        assert_eq!(*ring.get_pixel(2, 2), WHITE);
+        assert_eq!(*ring.get_pixel(17, 17), WHITE);
+        assert_eq!(*ring.get_pixel(10, 10), CLEAR);
+
+        assert_eq!(*glyphs["square"].get_pixel(5, 5), WHITE);
+        assert_eq!(*glyphs["square"].get_pixel(15, 15), CLEAR);
+        assert_eq!(*glyphs["corner"].get_pixel(5, 5), CLEAR);
+        assert_eq!(*glyphs["corner"].get_pixel(15, 15), WHITE);
+
+        let circle = &glyphs["circle"];
+        assert_eq!(*circle.get_pixel(10, 10), WHITE);
+        assert_eq!(*circle.get_pixel(10, 1), WHITE);
+        assert_eq!(*circle.get_pixel(0, 0), CLEAR);
+        assert_eq!(*circle.get_pixel(19, 19), CLEAR);
+    }
+
+    #[test]
+    fn color_glyphs_are_drawn_in_their_layers() {
+        let smiley = &read_test_font(None)["smiley"];
+        assert_eq!(*smiley.get_pixel(2, 17), Rgba([255, 0, 0, 255]));
+        assert_eq!(*smiley.get_pixel(2, 2), Rgba([0, 0, 255, 255]));
+        assert_eq!(*smiley.get_pixel(12, 12), CLEAR);
+    }
+
+    #[test]
+    fn a_mapping_picks_and_names_the_characters() {
+        let names = HashMap::from([("happy".to_string(), '\u{263A}'), ("zed".to_string(), 'Z')]);
+        let glyphs = read_test_font(Some(&names));
+        assert_eq!(glyphs.keys().collect::<Vec<_>>(), ["happy"]);
+        assert_eq!(*glyphs["happy"].get_pixel(2, 2), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn cff_fonts_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("emoji.otf");
+        std::fs::write(&path, b"OTTO\0\0\0\0").unwrap();
+        let result = read_font_glyphs(&path, None, &PackConfig::default());
+        assert!(matches!(
+            result,
+            Err(PackError::FontRead { reason, .. }) if reason.contains("CFF")
+        ));
+    }
+
+    /// Builds a `cmap` table with one format 12 subtable of the given groups of (first, last codepoint, first glyph).
+    fn format_12_cmap(groups: &[(u32, u32, u32)]) -> Vec<u8> {
+        let mut cmap = [0u16, 1, 3, 10]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect::<Vec<_>>();
+        cmap.extend(12u32.to_be_bytes());
+        cmap.extend([0, 12, 0, 0]);
+        cmap.extend((16 + 12 * groups.len() as u32).to_be_bytes());
+        cmap.extend(0u32.to_be_bytes());
+        cmap.extend((groups.len() as u32).to_be_bytes());
+        for (start, end, glyph) in groups {
+            cmap.extend(
+                [start, end, glyph]
+                    .into_iter()
+                    .flat_map(|v| v.to_be_bytes()),
+            );
+        }
+        cmap
+    }
+
+    #[test]
+    fn character_maps_are_read_without_overflowing() {
+        // glyphs past the last one are ignored, even those past the largest glyph id
+        let cmap = format_12_cmap(&[(0x41, 0x42, 1), (0x1F600, 0x10FFFF, u32::MAX - 1)]);
+        let characters = read_cmap(Bytes(&cmap), 3).unwrap();
+        assert_eq!(characters, BTreeMap::from([('A', 1), ('B', 2)]));
+
+        // groups overlapping over and over would take a long time to read
+        let cmap = format_12_cmap(&[(0, 0x10FFFF, 1); 2]);
+        assert!(read_cmap(Bytes(&cmap), 3)
+            .unwrap_err()
+            .contains("too many characters"));
+    }
+
+    #[test]
+    fn glyphs_too_complex_or_wide_are_bounded() {
+        // each glyph is 16 of the last, so the last would have 4 * 16^7 points
+        let square: &[&[(i16, i16, bool)]] = &[&[(0, 0, true), (0, 100, true), (100, 100, true)]];
+        let components = (1..=7u16)
+            .map(|glyph| [(glyph, 0, 0); 16])
+            .collect::<Vec<_>>();
+        let mut outlines = vec![Outline::Simple(&[]), Outline::Simple(square)];
+        outlines.extend(components.iter().map(|glyphs| Outline::Composite(glyphs)));
+        let font = build_font(&outlines, &[('A', 8)], &[]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bomb.ttf");
+        std::fs::write(&path, font).unwrap();
+        let result = read_font_glyphs(&path, None, &PackConfig::default());
+        assert!(matches!(
+            result,
+            Err(PackError::FontRead { reason, .. }) if reason.contains("points")
+        ));
+
+        // a huge advance is drawn at most a few times as wide as the glyph is tall
+        let data = test_font();
+        let mut font = Font::parse(&data).unwrap();
+        font.advances[1] = u16::MAX;
+        let ring = font.rasterize(1, 20).unwrap().unwrap();
+        assert_eq!(ring.dimensions(), (20 * MAX_ASPECT_RATIO, 20));
+    }
+}
@@ -0,0 +1,187 @@
+//! Serializing the output JSON files.
+
+use std::io;
+
+use serde::Serialize;
+use serde_json::ser::{Formatter, PrettyFormatter};
+
+/// Serializes a value as pretty-printed JSON.
+///
+/// If `escape_chars` is set, all non-ASCII characters in strings are written as `\uXXXX` escapes. JSON has no
+/// escape for characters outside the Basic Multilingual Plane, so those are written as UTF-16 surrogate pairs,
+/// which JSON parsers decode back to the same character.
+pub fn to_string_pretty(value: &impl Serialize, escape_chars: bool) -> serde_json::Result<String> {
+    if !escape_chars {
+        return serde_json::to_string_pretty(value);
+    }
+
+    let mut writer = Vec::new();
+    let mut serializer =
+        serde_json::Serializer::with_formatter(&mut writer, EscapingFormatter::default());
+    value.serialize(&mut serializer)?;
+
+    // the formatter only ever writes ASCII
+    Ok(String::from_utf8(writer).expect("escaped JSON is not valid UTF-8"))
+}
+
+/// A pretty-printing formatter which escapes all non-ASCII characters.
+#[derive(Default)]
+struct EscapingFormatter(PrettyFormatter<'static>);
+
+impl Formatter for EscapingFormatter {
+    fn write_string_fragment<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> io::Result<()> {
+        for c in fragment.chars() {
+            if c.is_ascii() {
+                writer.write_all(&[c as u8])?;
+            } else {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    write!(writer, "\\u{unit:04X}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn begin_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.begin_array(writer)
+    }
+
+    fn end_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.end_array(writer)
+    }
+
+    fn begin_array_value<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.0.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.0.begin_object_key(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.0.end_object_value(writer)
+    }
+}
+
+/// Serializes a font provider definition as JSON5, with a comment before each provider noting the range of
+/// codepoints it covers. This is for reading and hand-editing; Minecraft itself wants strict JSON.
+pub fn to_commented_font_provider(
+    font_provider: &serde_json::Value,
+    escape_chars: bool,
+) -> serde_json::Result<String> {
+    let providers = font_provider["providers"]
+        .as_array()
+        .map_or(&[][..], Vec::as_slice);
+
+    let mut out =
+        String::from("// font provider definition generated by fedimoji\n{\n  \"providers\": [\n");
+    for (i, provider) in providers.iter().enumerate() {
+        out += &format!("    // {}\n", provider_comment(provider));
+
+        // indent the provider to sit within the array
+        let provider = to_string_pretty(provider, escape_chars)?;
+        for line in provider.lines() {
+            out += &format!("    {line}\n");
+        }
+        if i + 1 < providers.len() {
+            out.insert(out.len() - 1, ',');
+        }
+    }
+    out += "  ]\n}\n";
+    Ok(out)
+}
+
+/// Describes the file and range of codepoints of a bitmap provider, skipping the NUL padding.
+fn provider_comment(provider: &serde_json::Value) -> String {
+    let file = provider["file"].as_str().unwrap_or("(no file)");
+    let chars = provider["chars"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(serde_json::Value::as_str)
+        .flat_map(str::chars)
+        .filter(|&c| c != '\0');
+    let (count, range) = chars.fold((0, None), |(count, range), c| {
+        let range = range.map_or((c, c), |(first, last): (char, char)| {
+            (first.min(c), last.max(c))
+        });
+        (count + 1, Some(range))
+    });
+    match range {
+        Some((first, last)) => format!(
+            "{file}: U+{:04X}-U+{:04X} ({count} glyphs)",
+            first as u32, last as u32
+        ),
+        None => format!("{file}: no glyphs"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn escaped_chars_round_trip() {
+        let value = json!({ "chars": ["\u{F0001}\u{0}", "é"] });
+        let escaped = to_string_pretty(&value, true).unwrap();
+        assert!(escaped.is_ascii());
+        assert!(escaped.contains(r"\uDB80\uDC01\u0000"));
+        assert!(escaped.contains(r"\u00E9"));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&escaped).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn commented_font_provider_strips_to_the_same_data() {
+        let value = json!({ "providers": [
+            { "type": "bitmap", "file": "minecraft:font/a.png", "chars": ["ab", "c"] },
+            { "type": "bitmap", "file": "minecraft:font/b.png", "chars": ["xy"] },
+        ] });
+        let commented = to_commented_font_provider(&value, false).unwrap();
+        assert!(commented.contains("// minecraft:font/a.png: U+0061-U+0063 (3 glyphs)"));
+        assert!(commented.contains("// minecraft:font/b.png: U+0078-U+0079 (2 glyphs)"));
+
+        // the comments are on lines of their own, so dropping those lines leaves strict JSON
+        let stripped = commented
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("//"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&stripped).unwrap(),
+            value
+        );
+    }
+}
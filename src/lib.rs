@@ -0,0 +1,4685 @@
+//! Generates the files needed to display custom emoji in Minecraft: a font bitmap (atlas) of all the
+//! emoji, a font provider definition for it, and a mapping of emoji names to their codepoints.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io::Cursor,
+    ops::RangeInclusive,
+    path::Path,
+    sync::Arc,
+    time::Instant,
+};
+
+use image::{
+    codecs::png::{CompressionType, PngEncoder},
+    imageops::ColorMap,
+    DynamicImage, GenericImage, GenericImageView, ImageFormat, RgbaImage,
+};
+use serde_json::json;
+use timing::Timings;
+use tracing::{debug, info, warn};
+
+/// Version of fedimoji, followed by the git commit it was built from in brackets, if that's known.
+pub const VERSION: &str = env!("FEDIMOJI_VERSION");
+
+/// Short hash of the git commit fedimoji was built from, if it was built from a git checkout.
+pub const GIT_HASH: Option<&str> = option_env!("FEDIMOJI_GIT_HASH");
+
+/// Describes the build of fedimoji, for tracing a pack back to the exact build which made it.
+pub fn build_info() -> serde_json::Value {
+    json!({
+        "generator": "fedimoji",
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_hash": GIT_HASH,
+    })
+}
+
+/// When the pack is being built, as seconds since the Unix epoch.
+///
+/// This is `SOURCE_DATE_EPOCH` if it's set, as is the convention for reproducible builds, or else the current time,
+/// unless the build is to be reproducible, when there's no time to give.
+pub fn build_time(reproducible: bool) -> Result<Option<u64>, PackError> {
+    match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => epoch
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| PackError::InvalidSourceDateEpoch(epoch)),
+        Err(_) if reproducible => Ok(None),
+        Err(_) => Ok(Some(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        )),
+    }
+}
+
+pub mod aseprite;
+pub mod compare;
+pub mod credits;
+pub mod curate;
+mod error;
+#[cfg(feature = "font")]
+pub mod font;
+pub mod json;
+#[cfg(feature = "profile-memory")]
+pub mod memory;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod output;
+pub mod platform;
+#[cfg(feature = "psd")]
+pub mod psd;
+pub mod repack;
+pub mod scan;
+pub mod timing;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod warnings;
+
+pub use error::PackError;
+
+/// Default width and height of each glyph in the atlas, in pixels.
+pub const GLYPH_SIZE: u32 = 64;
+
+/// Default limit on the size of the atlas in memory, in bytes.
+pub const DEFAULT_ATLAS_BYTE_LIMIT: u64 = 256 * 1024 * 1024;
+
+/// Default largest atlas width or height, in pixels, that Minecraft samples glyphs from accurately.
+///
+/// Glyph UVs lose precision on larger textures, so neighbouring glyphs start to bleed into each other.
+pub const DEFAULT_UV_PRECISION_LIMIT: u32 = 8192;
+
+/// Default maximum length of an emoji name, as Fediverse servers commonly limit shortcodes to this.
+pub const DEFAULT_SHORTCODE_MAX_LENGTH: usize = 30;
+
+/// Emoji names which collide with Minecraft's chat formatting tokens.
+pub const DEFAULT_RESERVED_NAMES: &[&str] = &[
+    "black",
+    "dark_blue",
+    "dark_green",
+    "dark_aqua",
+    "dark_red",
+    "dark_purple",
+    "gold",
+    "gray",
+    "dark_gray",
+    "blue",
+    "green",
+    "aqua",
+    "red",
+    "light_purple",
+    "yellow",
+    "white",
+    "obfuscated",
+    "bold",
+    "strikethrough",
+    "underline",
+    "italic",
+    "reset",
+];
+
+/// Bit depth of each channel of the atlas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum BitDepth {
+    /// 8 bits per channel, which is all Minecraft uses.
+    #[default]
+    #[value(name = "8")]
+    Eight,
+
+    /// 16 bits per channel, preserving the precision of 16-bit source images.
+    #[value(name = "16")]
+    Sixteen,
+}
+
+impl BitDepth {
+    /// Number of bytes taken by each pixel.
+    pub fn bytes_per_pixel(self) -> u64 {
+        match self {
+            BitDepth::Eight => 4,
+            BitDepth::Sixteen => 8,
+        }
+    }
+
+    /// Converts an image to RGBA with this bit depth.
+    fn convert(self, image: DynamicImage) -> DynamicImage {
+        match self {
+            BitDepth::Eight => DynamicImage::ImageRgba8(image.into_rgba8()),
+            BitDepth::Sixteen => DynamicImage::ImageRgba16(image.into_rgba16()),
+        }
+    }
+}
+
+/// A drop shadow put beneath each glyph, written like `2,1.5,#00000080` (offset, blur, color).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DropShadow {
+    /// Distance the shadow is moved down and to the right, in pixels.
+    pub offset: u32,
+
+    /// Standard deviation of the gaussian blur applied to the shadow, in pixels.
+    pub blur: f32,
+
+    /// Color of the shadow, as RGBA.
+    pub color: [u8; 4],
+}
+
+impl DropShadow {
+    /// How far the shadow can reach past the bottom and right of its glyph.
+    fn margin(&self) -> u32 {
+        self.offset.saturating_add((self.blur * 3.0).ceil() as u32)
+    }
+
+    /// Puts the shadow beneath a glyph, growing it by the margin.
+    fn apply(&self, glyph: &DynamicImage, bit_depth: BitDepth) -> DynamicImage {
+        let margin = self.margin();
+        let [r, g, b, a] = self.color;
+
+        // the shadow is the glyph's alpha in the shadow color, moved by the offset
+        let glyph_alpha = glyph.to_rgba8();
+        let mut shadow = RgbaImage::from_pixel(
+            glyph.width() + margin,
+            glyph.height() + margin,
+            image::Rgba([r, g, b, 0]),
+        );
+        for (x, y, pixel) in glyph_alpha.enumerate_pixels() {
+            let alpha = u16::from(pixel[3]) * u16::from(a) / 255;
+            shadow.put_pixel(
+                x + self.offset,
+                y + self.offset,
+                image::Rgba([r, g, b, alpha as u8]),
+            );
+        }
+        if self.blur > 0.0 {
+            shadow = image::imageops::blur(&shadow, self.blur);
+        }
+
+        let mut shadowed = bit_depth.convert(DynamicImage::ImageRgba8(shadow));
+        image::imageops::overlay(&mut shadowed, glyph, 0, 0);
+        shadowed
+    }
+}
+
+impl std::str::FromStr for DropShadow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [offset, blur, color] = s.split(',').collect::<Vec<_>>()[..] else {
+            return Err("expected `offset,blur,color`, like `2,1.5,#00000080`".to_string());
+        };
+        let offset = offset
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid shadow offset {offset:?}"))?;
+        let blur: f32 = blur
+            .trim()
+            .parse()
+            .ok()
+            .filter(|blur: &f32| *blur >= 0.0)
+            .ok_or_else(|| format!("invalid shadow blur {blur:?}"))?;
+
+        let color = parse_color(color)?;
+
+        Ok(DropShadow {
+            offset,
+            blur,
+            color,
+        })
+    }
+}
+
+/// A solid outline traced around each glyph, written like `2,#ffffff` (width, color).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outline {
+    /// Width of the outline, in pixels.
+    pub width: u8,
+
+    /// Color of the outline, as RGBA.
+    pub color: [u8; 4],
+}
+
+impl Outline {
+    /// Puts the outline beneath a glyph, growing it by the outline width on every side.
+    fn apply(&self, glyph: &DynamicImage, bit_depth: BitDepth) -> DynamicImage {
+        let width = u32::from(self.width);
+        let [r, g, b, a] = self.color;
+
+        // dilate the glyph's (opaque-ish) pixels, with room for the outline around them
+        let glyph_alpha = glyph.to_rgba8();
+        let mut mask = image::GrayImage::new(glyph.width() + width * 2, glyph.height() + width * 2);
+        for (x, y, pixel) in glyph_alpha.enumerate_pixels() {
+            if pixel[3] > 0 {
+                mask.put_pixel(x + width, y + width, image::Luma([255]));
+            }
+        }
+        let mask = imageproc::morphology::dilate(
+            &mask,
+            imageproc::distance_transform::Norm::L2,
+            self.width,
+        );
+
+        let outline = RgbaImage::from_fn(mask.width(), mask.height(), |x, y| {
+            let alpha = if mask.get_pixel(x, y)[0] > 0 { a } else { 0 };
+            image::Rgba([r, g, b, alpha])
+        });
+        let mut outlined = bit_depth.convert(DynamicImage::ImageRgba8(outline));
+        image::imageops::overlay(&mut outlined, glyph, i64::from(width), i64::from(width));
+        outlined
+    }
+}
+
+impl std::str::FromStr for Outline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [width, color] = s.split(',').collect::<Vec<_>>()[..] else {
+            return Err("expected `width,color`, like `2,#ffffff`".to_string());
+        };
+        let width = width
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid outline width {width:?}"))?;
+        let color = parse_color(color)?;
+
+        Ok(Outline { width, color })
+    }
+}
+
+/// A shift of every glyph's colors, for making a themed variant of a pack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorTransform {
+    /// Degrees to rotate the hue by.
+    pub hue_shift: f32,
+
+    /// Factor to multiply the saturation by, where 0 makes the glyphs gray.
+    pub saturation: f32,
+
+    /// Factor to multiply the brightness (HSV value) by.
+    pub brightness: f32,
+}
+
+impl ColorTransform {
+    /// Shifts the colors of a glyph in HSV, leaving its alpha alone.
+    fn apply(&self, glyph: &DynamicImage, bit_depth: BitDepth) -> DynamicImage {
+        let mut pixels = glyph.to_rgba32f();
+        for pixel in pixels.pixels_mut() {
+            let [r, g, b, _] = &mut pixel.0;
+            let (hue, saturation, value) = rgb_to_hsv(*r, *g, *b);
+            let hue = (hue + self.hue_shift).rem_euclid(360.0);
+            let saturation = (saturation * self.saturation).clamp(0.0, 1.0);
+            let value = (value * self.brightness).clamp(0.0, 1.0);
+            [*r, *g, *b] = hsv_to_rgb(hue, saturation, value);
+        }
+        bit_depth.convert(DynamicImage::ImageRgba32F(pixels))
+    }
+}
+
+/// Converts a color from RGB to HSV, with the hue in degrees and the rest from 0 to 1.
+fn rgb_to_hsv(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    let hue = if chroma == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+    (hue, saturation, max)
+}
+
+/// Converts a color from HSV back to RGB.
+fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let min = value - chroma;
+    [r + min, g + min, b + min]
+}
+
+/// A rectangle of the atlas, in pixels, written like `0,0,256,64` (x, y, width, height).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Whether this overlaps another rectangle, in 64 bits so neither can overflow.
+    fn overlaps(&self, x: u64, y: u64, width: u64, height: u64) -> bool {
+        u64::from(self.x) < x + width
+            && x < u64::from(self.x) + u64::from(self.width)
+            && u64::from(self.y) < y + height
+            && y < u64::from(self.y) + u64::from(self.height)
+    }
+}
+
+impl std::str::FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(',')
+            .map(|part| part.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid region {s:?}"))?;
+        let [x, y, width, height] = parts[..] else {
+            return Err("expected `x,y,width,height`, like `0,0,256,64`".to_string());
+        };
+        Ok(Region {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+}
+
+/// Parses a color written as `#RRGGBB` or `#RRGGBBAA`.
+fn parse_color(color: &str) -> Result<[u8; 4], String> {
+    let hex = color.trim().trim_start_matches('#');
+    let channels = (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+        })
+        .collect::<Option<Vec<_>>>();
+    match channels.as_deref() {
+        Some(&[r, g, b]) => Ok([r, g, b, 255]),
+        Some(&[r, g, b, a]) => Ok([r, g, b, a]),
+        _ => Err(format!(
+            "invalid color {color:?}, expected `#RRGGBB` or `#RRGGBBAA`"
+        )),
+    }
+}
+
+/// How to spread out the error when reducing the colors of a glyph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Dither {
+    /// Round each pixel to the nearest color, which can leave bands in gradients.
+    #[default]
+    None,
+
+    /// Floyd-Steinberg error diffusion, which smooths gradients at the cost of a larger PNG.
+    FloydSteinberg,
+}
+
+/// A reduced set of colors, with evenly spaced levels of each of red, green and blue.
+struct ColorLevels(u8);
+
+impl ColorLevels {
+    /// Index of the level nearest to a channel value.
+    fn level_of(&self, value: u8) -> usize {
+        let steps = u32::from(self.0) - 1;
+        ((u32::from(value) * steps + 127) / 255) as usize
+    }
+
+    /// Channel value of a level.
+    fn value_of(&self, level: usize) -> u8 {
+        let steps = u32::from(self.0) - 1;
+        ((level as u32 * 255 + steps / 2) / steps) as u8
+    }
+
+    /// Reduces the colors of a glyph to these levels, leaving its alpha alone.
+    fn apply(&self, glyph: &DynamicImage, dither: Dither, bit_depth: BitDepth) -> DynamicImage {
+        // the levels fit in 8 bits, so nothing is lost converting 16-bit glyphs
+        let mut reduced = glyph.to_rgba8();
+        match dither {
+            Dither::None => {
+                for pixel in reduced.pixels_mut() {
+                    self.map_color(pixel);
+                }
+            }
+            Dither::FloydSteinberg => image::imageops::dither(&mut reduced, self),
+        }
+        bit_depth.convert(DynamicImage::ImageRgba8(reduced))
+    }
+}
+
+impl ColorMap for ColorLevels {
+    type Color = image::Rgba<u8>;
+
+    fn index_of(&self, color: &image::Rgba<u8>) -> usize {
+        let levels = usize::from(self.0);
+        color.0[..3]
+            .iter()
+            .rev()
+            .fold(0, |index, &value| index * levels + self.level_of(value))
+    }
+
+    fn map_color(&self, color: &mut image::Rgba<u8>) {
+        for value in &mut color.0[..3] {
+            *value = self.value_of(self.level_of(*value));
+        }
+    }
+}
+
+/// What to set the color of fully transparent pixels to, so colors hidden under zero alpha can't fringe the glyphs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TransparencyFill {
+    /// Black.
+    Zero,
+
+    /// The color of the nearest opaque pixels, spread outwards from the glyph's edges, which keeps mipmaps clean.
+    Bleed,
+}
+
+impl TransparencyFill {
+    /// Sets the color of the fully transparent pixels of a glyph, leaving its alpha alone.
+    fn apply(self, glyph: &DynamicImage, bit_depth: BitDepth) -> DynamicImage {
+        // 8-bit glyphs go through 16 bits losslessly
+        let mut filled = glyph.to_rgba16();
+        for pixel in filled.pixels_mut() {
+            if pixel[3] == 0 {
+                pixel.0 = [0; 4];
+            }
+        }
+        if self == TransparencyFill::Bleed {
+            bleed_edges(&mut filled, u32::MAX);
+        }
+        bit_depth.convert(DynamicImage::ImageRgba16(filled))
+    }
+}
+
+/// Spreads the colors of the opaque pixels of an image into the transparent ones, a ring at a time, each transparent
+/// pixel taking the mean color of its neighbours colored so far, for at most `max_rings` rings.
+fn bleed_edges(image: &mut image::ImageBuffer<image::Rgba<u16>, Vec<u16>>, max_rings: u32) {
+    let (width, height) = image.dimensions();
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+    let neighbours = move |x: u32, y: u32| {
+        (-1i64..=1)
+            .flat_map(move |dy| (-1i64..=1).map(move |dx| (dx, dy)))
+            .filter(|&offset| offset != (0, 0))
+            .map(move |(dx, dy)| (i64::from(x) + dx, i64::from(y) + dy))
+            .filter(move |&(x, y)| {
+                x >= 0 && y >= 0 && x < i64::from(width) && y < i64::from(height)
+            })
+            .map(|(x, y)| (x as u32, y as u32))
+    };
+
+    // pixels which have a color, and transparent ones next to them which are waiting to be given one
+    let mut colored: Vec<bool> = image.pixels().map(|pixel| pixel[3] > 0).collect();
+    let mut queued = colored.clone();
+    let mut ring: Vec<(u32, u32)> = Vec::new();
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[3] > 0 {
+            for (x, y) in neighbours(x, y) {
+                if !queued[index(x, y)] {
+                    queued[index(x, y)] = true;
+                    ring.push((x, y));
+                }
+            }
+        }
+    }
+
+    let mut rings = 0;
+    while !ring.is_empty() && rings < max_rings {
+        rings += 1;
+        let colors = ring
+            .iter()
+            .map(|&(x, y)| {
+                let (sum, count) = neighbours(x, y)
+                    .filter(|&(x, y)| colored[index(x, y)])
+                    .fold(([0u32; 3], 0u32), |(mut sum, count), (x, y)| {
+                        let pixel = image.get_pixel(x, y);
+                        for (sum, value) in sum.iter_mut().zip(pixel.0) {
+                            *sum += u32::from(value);
+                        }
+                        (sum, count + 1)
+                    });
+                sum.map(|sum| (sum / count.max(1)) as u16)
+            })
+            .collect::<Vec<_>>();
+        for (&(x, y), [r, g, b]) in ring.iter().zip(colors) {
+            image.put_pixel(x, y, image::Rgba([r, g, b, 0]));
+            colored[index(x, y)] = true;
+        }
+
+        let mut next_ring = Vec::new();
+        for &(x, y) in &ring {
+            for (x, y) in neighbours(x, y) {
+                if !queued[index(x, y)] {
+                    queued[index(x, y)] = true;
+                    next_ring.push((x, y));
+                }
+            }
+        }
+        ring = next_ring;
+    }
+}
+
+/// Corner of a glyph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// How the built-in allocator lays out the codepoints it gives new emoji.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum AllocationStrategy {
+    /// Each new emoji gets the next free codepoint.
+    #[default]
+    Dense,
+
+    /// Each group of new emoji (see [`PackConfig::groups`]) starts on a multiple of 16, leaving gaps between the
+    /// groups, so the groups are easy to pick out in the codepoint map.
+    BlockAligned,
+}
+
+/// Chooses codepoints for emoji which aren't in the existing mappings or the plan, in place of the built-in
+/// allocator, which gives each the next free codepoint.
+///
+/// Any function from an emoji name to a codepoint is an allocator.
+pub trait CodepointAllocator {
+    /// Chooses a codepoint for an emoji, given a way to check whether a codepoint is free (not in the existing
+    /// mappings or plan, and not already allocated).
+    ///
+    /// If this returns [`None`], or a codepoint which isn't free, the emoji gets the next free codepoint instead.
+    fn allocate(&self, name: &str, is_free: &dyn Fn(char) -> bool) -> Option<char>;
+}
+
+impl<F: Fn(&str) -> Option<char>> CodepointAllocator for F {
+    fn allocate(&self, name: &str, _is_free: &dyn Fn(char) -> bool) -> Option<char> {
+        self(name)
+    }
+}
+
+impl std::fmt::Debug for dyn CodepointAllocator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CodepointAllocator")
+    }
+}
+
+/// Transforms each glyph once it's resized and all the built-in effects are applied, for effects of your own.
+///
+/// Processors are given glyphs at 8 bits per channel, whatever [`PackConfig::atlas_bit_depth`] is, and must keep
+/// them the same size. Any function from an emoji name and its glyph to a new glyph is a processor.
+pub trait GlyphProcessor {
+    /// Transforms the glyph of an emoji.
+    fn process(&self, name: &str, glyph: RgbaImage) -> RgbaImage;
+}
+
+impl<F: Fn(&str, RgbaImage) -> RgbaImage> GlyphProcessor for F {
+    fn process(&self, name: &str, glyph: RgbaImage) -> RgbaImage {
+        self(name, glyph)
+    }
+}
+
+impl std::fmt::Debug for dyn GlyphProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("GlyphProcessor")
+    }
+}
+
+impl GlyphProcessor for ColorTransform {
+    fn process(&self, _name: &str, glyph: RgbaImage) -> RgbaImage {
+        self.apply(&DynamicImage::ImageRgba8(glyph), BitDepth::Eight)
+            .into_rgba8()
+    }
+}
+
+impl GlyphProcessor for TransparencyFill {
+    fn process(&self, _name: &str, glyph: RgbaImage) -> RgbaImage {
+        self.apply(&DynamicImage::ImageRgba8(glyph), BitDepth::Eight)
+            .into_rgba8()
+    }
+}
+
+/// Options controlling how a pack is generated.
+#[derive(Debug, Clone)]
+pub struct PackConfig {
+    /// Existing mapping of emoji name -> codepoint, which is used in preference to allocating new codepoints.
+    pub existing_mappings: HashMap<String, char>,
+
+    /// Planned mapping of emoji name -> codepoint, which may include emoji that don't exist yet.
+    ///
+    /// All planned codepoints are kept free for their emoji, which get them once they appear. Existing mappings take
+    /// precedence over the plan.
+    pub planned_mappings: HashMap<String, char>,
+
+    /// Old names of renamed emoji -> their new names, kept in the mapping with the codepoint of the emoji they were
+    /// renamed to, so both shortcodes work.
+    pub kept_aliases: HashMap<String, String>,
+
+    /// Prefix added to every emoji name.
+    pub name_prefix: String,
+
+    /// Whether to keep the case of emoji names, rather than lowercasing them.
+    ///
+    /// Names are still compared case-insensitively, to find duplicates and look them up in the existing mappings and
+    /// plan.
+    pub preserve_case: bool,
+
+    /// Suffix added to every emoji name.
+    pub name_suffix: String,
+
+    /// Suffix of the file names of tint masks, which are combined with the color layer of the same name (with
+    /// [`PackConfig::color_suffix`] in place of this) into one emoji. Both must be set for this.
+    pub mask_suffix: Option<String>,
+
+    /// Suffix of the file names of color layers, for [`PackConfig::mask_suffix`].
+    pub color_suffix: Option<String>,
+
+    /// Suffix of the file names of separate alpha files, whose luminance replaces the alpha of the image with the same
+    /// name without the suffix. Images without one keep their own alpha.
+    pub alpha_suffix: Option<String>,
+
+    /// Whether to fail on inputs which would make otherwise identical runs give different output, rather than
+    /// warning about them.
+    pub reproducible: bool,
+
+    /// Whether to follow symlinks when scanning the emoji directory.
+    pub follow_symlinks: bool,
+
+    /// Whether to silently ignore color profiles embedded in emoji images, rather than warning about them.
+    pub strip_color_profile: bool,
+
+    /// Name of the layer to read from Photoshop documents, or `None` for their flattened composite.
+    #[cfg(feature = "psd")]
+    pub psd_layer: Option<String>,
+
+    /// Order in which to pack emoji found in the emoji directory, or `None` for the order the directory lists them in.
+    pub sort: Option<scan::SortOrder>,
+
+    /// Names of emoji to pack first, in this order, with the rest following sorted by name. This decides where the
+    /// emoji go in the atlas, and which codepoints they get with [`PackConfig::positional_codepoints`].
+    pub order: Vec<String>,
+
+    /// Groups of emoji to keep in contiguous cells of the atlas, as normalized, lowercased emoji name -> group name.
+    /// The groups go before the other emoji (which keep their order), in order of group name, with their emoji sorted by name.
+    pub groups: HashMap<String, String>,
+
+    /// Emoji to pack from the emoji directory, with their names, categories and order, or `None` to pack every image
+    /// named after its file.
+    pub listing: Option<Vec<scan::ListedEmoji>>,
+
+    /// Whether to also pack the images missing from [`PackConfig::listing`], after the listed ones.
+    pub include_unlisted: bool,
+
+    /// Glob patterns of files in the emoji directory to skip, in addition to those in its ignore file.
+    pub exclude: Vec<String>,
+
+    /// Image formats to read from the emoji directory, most preferred first for when files in several formats
+    /// have the same name.
+    pub format_priority: Vec<ImageFormat>,
+
+    /// Emoji names which are warned about, as they could be confused with Minecraft chat formatting.
+    pub reserved_names: Vec<String>,
+
+    /// Whether to fail, rather than just warn, when an emoji name is reserved.
+    pub strict_names: bool,
+
+    /// Whether to warn about emoji names which Fediverse servers would reject as shortcodes.
+    pub validate_names: bool,
+
+    /// Whether to fix emoji names which Fediverse servers would reject as shortcodes, by truncating them and
+    /// replacing disallowed characters. This implies `validate_names`.
+    pub fix_names: bool,
+
+    /// Maximum length of an emoji name, when validating them.
+    pub shortcode_max_length: usize,
+
+    /// Width of each glyph cell in the atlas, in pixels.
+    pub glyph_width: u32,
+
+    /// Height of each glyph cell in the atlas, in pixels.
+    pub glyph_height: u32,
+
+    /// Transparent padding kept around each glyph within its cell, in pixels.
+    pub pad: u32,
+
+    /// Transparent space between neighbouring glyph cells in the atlas, in pixels, against texture bleeding.
+    ///
+    /// Minecraft divides the atlas into an even grid, so this is added to the right and bottom of every cell, which
+    /// grows the cells.
+    pub gutter: u32,
+
+    /// How far to spread the colors at the edges of each glyph into the transparent rest of its cell (including the
+    /// gutter), in pixels, leaving the alpha alone, so mipmaps don't fringe the glyphs with the transparent color.
+    pub mipmap_bleed: u32,
+
+    /// Whether to leave images smaller than a glyph at their native size (centered in the cell), only shrinking
+    /// larger images.
+    pub no_upscale: bool,
+
+    /// Emoji to place at their native size (centered in the cell) without resizing, by normalized, lowercased name,
+    /// which are skipped if they don't fit.
+    pub no_resize: HashSet<String>,
+
+    /// Whether to only resize images by whole factors (or one over whole factors), using nearest-neighbour sampling,
+    /// skipping images which would need any other scale.
+    pub integer_scale_only: bool,
+
+    /// Whether to resize images in linear light rather than sRGB, so high-contrast edges don't darken as they're
+    /// shrunk.
+    pub linear_resize: bool,
+
+    /// Whether to make every glyph white, keeping only its alpha, so it's tinted by the text color.
+    pub tintable: bool,
+
+    /// Largest size of atlas to allocate, in bytes.
+    pub atlas_byte_limit: u64,
+
+    /// Largest atlas width or height, in pixels, to generate without warning that glyphs may be sampled inaccurately.
+    pub uv_precision_limit: u32,
+
+    /// Directory of the `fedimoji` namespace's textures to put the atlas in, like `font/v3`, which the providers
+    /// refer to it in. [`None`] puts it in `font`.
+    pub atlas_subpath: Option<String>,
+
+    /// How hard to compress the atlas PNG, trading encoding time for size.
+    pub png_compression: CompressionType,
+
+    /// Side of the square tiles to cut the atlas into, in pixels, so clients can load it a piece at a time. It has to
+    /// hold a whole number of glyph cells each way.
+    pub tile_size: Option<u32>,
+
+    /// Bit depth of each channel of the atlas.
+    pub atlas_bit_depth: BitDepth,
+
+    /// Whether to only pack emoji without an existing mapping, for an add-on pack.
+    ///
+    /// The pack's name -> codepoint mapping still includes all the existing mappings.
+    pub only_new: bool,
+
+    /// Whether to round the number of rows in the atlas up to a power of two, leaving the extra rows blank.
+    pub pad_to_pow2: bool,
+
+    /// Whether to crop trailing rows of only padding off the atlas once the glyphs are placed.
+    pub trim_atlas: bool,
+
+    /// Image the atlas starts out as, such as hand-drawn glyphs, placed at its top left. The atlas is grown to fit it.
+    ///
+    /// Glyphs are placed over it, except in the [reserved regions](Self::reserved_regions).
+    pub atlas_template: Option<DynamicImage>,
+
+    /// Regions of the atlas to leave alone, such as parts of the template. No glyphs are placed in cells which overlap
+    /// any of them.
+    pub reserved_regions: Vec<Region>,
+
+    /// Warn about glyphs whose coverage (mean alpha across the cell, from 0 to 1) is below this once resized.
+    pub min_coverage: Option<f32>,
+
+    /// Whether to skip glyphs which are fully or mostly transparent once processed, rather than only warning about
+    /// them.
+    pub skip_blank: bool,
+
+    /// Only allocate new codepoints after this one, e.g. the highest codepoint used by a base pack.
+    pub codepoint_start_after: Option<char>,
+
+    /// How the built-in allocator lays out the codepoints of new emoji.
+    pub allocation_strategy: AllocationStrategy,
+
+    /// Number of glyphs in each row of the atlas.
+    ///
+    /// Minecraft versions since 1.20 are happy with one, but older versions want rows of 16 (see
+    /// [`atlas_columns_for_mc_version`]).
+    pub atlas_columns: u32,
+
+    /// Whether to choose the number of glyphs in each row so the atlas is as square as it can be, wasting as little
+    /// space as possible, in place of [`PackConfig::atlas_columns`].
+    pub auto_atlas: bool,
+
+    /// Whether to log why each emoji got its codepoint at info level, rather than debug.
+    pub explain: bool,
+
+    /// Truncate emoji names to this many characters, suffixing any which then collide (see
+    /// [`PackConfig::shorten_names`]).
+    pub max_name_length: Option<usize>,
+
+    /// Whether to trim transparent columns off the left of each glyph, so its advance (which Minecraft measures from
+    /// the rightmost opaque column) follows its content rather than the cell.
+    pub variable_advance: bool,
+
+    /// Text (such as the pack's author and license) to render into extra glyphs named
+    /// [`credits::CREDITS_PREFIX`] followed by their index, at the last codepoints of the private use plane.
+    pub credits_text: Option<String>,
+
+    /// Aspect ratio (width over height) beyond which an image, such as a banner, is sliced into square glyphs named
+    /// after it with `_0`, `_1` and so on, to be typed one after another.
+    pub slice_wide: Option<f32>,
+
+    /// Image (such as a badge) blended onto a corner of every glyph, once resized.
+    pub overlay: Option<DynamicImage>,
+
+    /// Corner of each glyph to put the overlay in.
+    pub overlay_position: Corner,
+
+    /// Drop shadow to put beneath each glyph, which grows the glyph cells to make room for it.
+    pub drop_shadow: Option<DropShadow>,
+
+    /// Shift of the colors of each glyph, applied once it's resized, before any overlay, outline or drop shadow.
+    pub color_transform: Option<ColorTransform>,
+
+    /// Outline to trace around each glyph, which grows the glyph cells to make room for it.
+    pub outline: Option<Outline>,
+
+    /// Only carry forward imported emoji whose codepoint is in this range.
+    ///
+    /// Imported emoji outside it are dropped rather than given new codepoints, and their codepoints stay reserved so
+    /// they aren't given to other emoji. Emoji which weren't imported are allocated codepoints as usual.
+    pub codepoint_filter: Option<RangeInclusive<char>>,
+
+    /// Whether to only allocate new codepoints which pass [`is_safe_codepoint`].
+    pub safe_codepoints: bool,
+
+    /// Codepoints never to allocate to new emoji, such as ones some clients render as boxes.
+    pub blocked_codepoints: HashSet<char>,
+
+    /// Give glyphs whose perceptual hashes differ by at most this many bits (of 128) and with similar mean colors a
+    /// single cell of the atlas, rather than packing each separately.
+    pub similarity_threshold: Option<u32>,
+
+    /// Whether to work out the provider's ascent from where the glyphs' content is, putting its average vertical
+    /// center level with the middle of the text, rather than putting the bottom of the glyphs on the baseline.
+    pub auto_ascent: bool,
+
+    /// How far the top of the glyphs sits above the text baseline, out of the provider's height of 8, unless
+    /// [`PackConfig::auto_ascent`] is set.
+    pub ascent: i32,
+
+    /// Ascents for particular emoji, by name, which are given providers of their own.
+    pub ascent_overrides: HashMap<String, i32>,
+
+    /// Chooses codepoints for emoji which aren't in the existing mappings or the plan, rather than giving each the
+    /// next free codepoint.
+    pub allocator: Option<Arc<dyn CodepointAllocator>>,
+
+    /// Transforms applied to each glyph in turn, after the built-in effects.
+    pub glyph_processors: Vec<Arc<dyn GlyphProcessor>>,
+
+    /// Whether to give the Nth emoji, sorted by name (or by [`PackConfig::order`]), the Nth codepoint from the start,
+    /// ignoring the existing mappings and plan, so the mapping follows only from the names.
+    pub positional_codepoints: bool,
+
+    /// Whether to fail if any emoji ends up with a different codepoint than the existing mappings give it, as
+    /// messages already using the old codepoint would show something else.
+    pub fail_on_reassignment: bool,
+
+    /// Reduce each of red, green and blue to this many evenly spaced levels (at least 2).
+    pub color_levels: Option<u8>,
+
+    /// How to spread out the error when reducing colors with [`color_levels`](Self::color_levels).
+    pub dither: Dither,
+
+    /// What to set the color of each glyph's fully transparent pixels to, once it's otherwise finished, or `None` to
+    /// leave whatever color the image had under them.
+    pub normalize_transparency: Option<TransparencyFill>,
+}
+
+impl Default for PackConfig {
+    fn default() -> Self {
+        Self {
+            existing_mappings: HashMap::new(),
+            planned_mappings: HashMap::new(),
+            kept_aliases: HashMap::new(),
+            name_prefix: String::new(),
+            preserve_case: false,
+            name_suffix: String::new(),
+            follow_symlinks: true,
+            strip_color_profile: false,
+            #[cfg(feature = "psd")]
+            psd_layer: None,
+            mask_suffix: None,
+            color_suffix: None,
+            alpha_suffix: None,
+            reproducible: false,
+            sort: None,
+            order: Vec::new(),
+            groups: HashMap::new(),
+            listing: None,
+            include_unlisted: false,
+            exclude: Vec::new(),
+            format_priority: vec![ImageFormat::Png],
+            reserved_names: DEFAULT_RESERVED_NAMES
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            strict_names: false,
+            validate_names: false,
+            fix_names: false,
+            shortcode_max_length: DEFAULT_SHORTCODE_MAX_LENGTH,
+            glyph_width: GLYPH_SIZE,
+            glyph_height: GLYPH_SIZE,
+            pad: 0,
+            gutter: 0,
+            mipmap_bleed: 0,
+            no_upscale: false,
+            no_resize: HashSet::new(),
+            integer_scale_only: false,
+            linear_resize: false,
+            tintable: false,
+            atlas_byte_limit: DEFAULT_ATLAS_BYTE_LIMIT,
+            uv_precision_limit: DEFAULT_UV_PRECISION_LIMIT,
+            atlas_subpath: None,
+            png_compression: CompressionType::Fast,
+            tile_size: None,
+            atlas_bit_depth: BitDepth::Eight,
+            only_new: false,
+            pad_to_pow2: false,
+            trim_atlas: false,
+            atlas_template: None,
+            reserved_regions: Vec::new(),
+            min_coverage: None,
+            skip_blank: false,
+            codepoint_start_after: None,
+            allocation_strategy: AllocationStrategy::Dense,
+            atlas_columns: 1,
+            auto_atlas: false,
+            explain: false,
+            max_name_length: None,
+            variable_advance: false,
+            credits_text: None,
+            slice_wide: None,
+            overlay: None,
+            overlay_position: Corner::BottomRight,
+            drop_shadow: None,
+            color_transform: None,
+            outline: None,
+            codepoint_filter: None,
+            safe_codepoints: false,
+            blocked_codepoints: HashSet::new(),
+            allocator: None,
+            glyph_processors: Vec::new(),
+            positional_codepoints: false,
+            fail_on_reassignment: false,
+            auto_ascent: false,
+            ascent: 8,
+            ascent_overrides: HashMap::new(),
+            similarity_threshold: None,
+            color_levels: None,
+            dither: Dither::None,
+            normalize_transparency: None,
+        }
+    }
+}
+
+impl PackConfig {
+    /// Normalizes an emoji name, applying the configured prefix and suffix, composing its characters (Unicode NFC), and
+    /// lowercasing it unless the case is to be preserved.
+    ///
+    /// Composing means an `é` spelled as `e` plus a combining accent is the same name as one spelled as a single `é`.
+    pub fn normalize_name(&self, name: &str) -> String {
+        use unicode_normalization::UnicodeNormalization;
+
+        let name = format!("{}{}{}", self.name_prefix, name, self.name_suffix)
+            .nfc()
+            .collect::<String>();
+        if self.preserve_case {
+            name
+        } else {
+            name.to_lowercase()
+        }
+    }
+
+    /// Checks whether an emoji name would be accepted as a Fediverse shortcode, warning if not, and fixing it if
+    /// configured to.
+    ///
+    /// Shortcodes may only contain ASCII letters, digits, and underscores.
+    pub fn validate_name(&self, name: String) -> String {
+        if !(self.validate_names || self.fix_names) {
+            return name;
+        }
+
+        let length = name.chars().count();
+        if length > self.shortcode_max_length {
+            warn!(
+                kind = "invalid_name",
+                "emoji name \"{name}\" is {length} characters long, over the limit of {}",
+                self.shortcode_max_length
+            );
+        }
+        let disallowed = name
+            .chars()
+            .filter(|c| !is_shortcode_char(*c))
+            .collect::<String>();
+        if !disallowed.is_empty() {
+            warn!(
+                kind = "invalid_name",
+                "emoji name \"{name}\" contains disallowed characters \"{disallowed}\""
+            );
+        }
+
+        if !self.fix_names || (length <= self.shortcode_max_length && disallowed.is_empty()) {
+            return name;
+        }
+        let fixed = name
+            .chars()
+            .map(|c| if is_shortcode_char(c) { c } else { '_' })
+            .take(self.shortcode_max_length)
+            .collect::<String>();
+        warn!("renaming \"{name}\" to \"{fixed}\"");
+        fixed
+    }
+
+    /// Truncates names longer than the configured maximum length, given all the (distinct) names at once.
+    ///
+    /// If a truncated name would collide with another name, it's shortened further to make room for a numeric
+    /// suffix (`_1`, `_2`, ...), taking the first one which is free, so the results only depend on the order of
+    /// the names. Fails if the maximum length leaves no room for a suffix after at least one character of the name.
+    pub fn shorten_names<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a mut String>,
+    ) -> Result<(), PackError> {
+        let Some(max_length) = self.max_name_length else {
+            return Ok(());
+        };
+        let mut names = names.into_iter().collect::<Vec<_>>();
+
+        // names which are short enough are kept as they are
+        let mut taken: HashSet<String> = names
+            .iter()
+            .filter(|name| name.chars().count() <= max_length)
+            .map(|name| name.to_string())
+            .collect();
+
+        for name in names
+            .iter_mut()
+            .filter(|name| name.chars().count() > max_length)
+        {
+            let mut shortened: String = name.chars().take(max_length).collect();
+            let mut suffix = 0;
+            while shortened.is_empty() || taken.contains(&shortened) {
+                suffix += 1;
+                let suffix = format!("_{suffix}");
+                if suffix.len() >= max_length {
+                    return Err(PackError::NameLengthTooShort {
+                        name: name.to_string(),
+                        max_length,
+                    });
+                }
+                shortened = name
+                    .chars()
+                    .take(max_length - suffix.len())
+                    .chain(suffix.chars())
+                    .collect();
+            }
+            info!("shortening \"{name}\" to \"{shortened}\"");
+            taken.insert(shortened.clone());
+            **name = shortened;
+        }
+        Ok(())
+    }
+
+    /// Whether an emoji is imported with a codepoint outside the configured filter, so shouldn't be packed.
+    pub fn is_filtered_out(&self, name: &str) -> bool {
+        match (&self.codepoint_filter, self.imported_codepoint(name)) {
+            (Some(filter), Some(codepoint)) => !filter.contains(&codepoint),
+            _ => false,
+        }
+    }
+
+    /// Whether an emoji name could be confused with Minecraft chat formatting.
+    pub fn is_reserved_name(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        name.contains('§') || self.reserved_names.contains(&name)
+    }
+
+    /// The codepoint of an emoji in the existing mappings, matching its name case-insensitively.
+    pub fn imported_codepoint(&self, name: &str) -> Option<char> {
+        self.existing_mappings.get(&name_key(name)).copied()
+    }
+
+    /// The codepoint of an emoji in the allocation plan, matching its name case-insensitively.
+    pub fn planned_codepoint(&self, name: &str) -> Option<char> {
+        self.planned_mappings.get(&name_key(name)).copied()
+    }
+}
+
+/// An emoji name as it's matched against others: composed (Unicode NFC) and lowercased, so differently spelled or
+/// cased versions of a name are the same.
+pub fn name_key(name: &str) -> String {
+    use unicode_normalization::UnicodeNormalization;
+
+    name.nfc().collect::<String>().to_lowercase()
+}
+
+/// Reads an existing mapping of emoji name -> codepoint, as written to `fedimoji.json`.
+///
+/// The mapping can be nested inside a larger JSON file, by following the path with a JSON pointer to it
+/// (`config.json#/emoji/codepoints`). Names are composed and lowercased, and empty names are ignored.
+pub fn import_mappings(path: &Path) -> Result<HashMap<String, char>, PackError> {
+    let existing_mappings = read_mapping_file(path)?;
+    info!("imported {} existing mappings", existing_mappings.len());
+    Ok(existing_mappings)
+}
+
+/// Conflicts found when merging several existing mappings.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct MergeConflicts {
+    /// Names which appeared in more than one mapping.
+    pub names: Vec<NameConflict>,
+
+    /// Codepoints which were claimed by more than one name.
+    pub codepoints: Vec<CodepointConflict>,
+}
+
+/// A name which appeared in more than one mapping.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NameConflict {
+    pub name: String,
+
+    /// The codepoint the name has in each mapping it appeared in, in order.
+    #[serde(serialize_with = "serialize_codepoints")]
+    pub codepoints: Vec<char>,
+
+    /// The codepoint which was kept, from the first mapping.
+    #[serde(serialize_with = "serialize_codepoint")]
+    pub winner: char,
+}
+
+/// A codepoint which was claimed by more than one name.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CodepointConflict {
+    #[serde(serialize_with = "serialize_codepoint")]
+    pub codepoint: char,
+
+    /// The names claiming it, sorted.
+    pub names: Vec<String>,
+}
+
+fn serialize_codepoint<S: serde::Serializer>(
+    codepoint: &char,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&format_args!("U+{:04X}", *codepoint as u32))
+}
+
+fn serialize_codepoints<S: serde::Serializer>(
+    codepoints: &[char],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(
+        codepoints
+            .iter()
+            .map(|codepoint| format!("U+{:04X}", *codepoint as u32)),
+    )
+}
+
+/// Merges several existing mappings of emoji name -> codepoint, earlier ones taking precedence.
+///
+/// Also returns the conflicts found, of names in several mappings and codepoints claimed by several names.
+pub fn merge_mappings(
+    mappings: Vec<HashMap<String, char>>,
+) -> (HashMap<String, char>, MergeConflicts) {
+    // every codepoint each name was given, and every name each codepoint was given to
+    let mut name_codepoints: BTreeMap<String, Vec<char>> = BTreeMap::new();
+    let mut codepoint_names: BTreeMap<char, BTreeSet<String>> = BTreeMap::new();
+    for mapping in &mappings {
+        for (name, codepoint) in mapping {
+            name_codepoints
+                .entry(name.clone())
+                .or_default()
+                .push(*codepoint);
+            codepoint_names
+                .entry(*codepoint)
+                .or_default()
+                .insert(name.clone());
+        }
+    }
+
+    let mut merged: HashMap<String, char> = HashMap::new();
+    for mapping in mappings {
+        for (name, codepoint) in mapping {
+            merged.entry(name).or_insert(codepoint);
+        }
+    }
+
+    let conflicts = MergeConflicts {
+        names: name_codepoints
+            .into_iter()
+            .filter(|(_, codepoints)| codepoints.len() > 1)
+            .map(|(name, codepoints)| NameConflict {
+                winner: merged[&name],
+                name,
+                codepoints,
+            })
+            .collect(),
+        codepoints: codepoint_names
+            .into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .map(|(codepoint, names)| CodepointConflict {
+                codepoint,
+                names: names.into_iter().collect(),
+            })
+            .collect(),
+    };
+    for conflict in &conflicts.names {
+        if conflict
+            .codepoints
+            .iter()
+            .any(|codepoint| *codepoint != conflict.winner)
+        {
+            warn!(
+                "\"{}\" has different codepoints in the imported mappings, keeping U+{:04X}",
+                conflict.name, conflict.winner as u32
+            );
+        }
+    }
+    for conflict in &conflicts.codepoints {
+        warn!(
+            "U+{:04X} is claimed by several imported emoji: {}",
+            conflict.codepoint as u32,
+            conflict.names.join(", ")
+        );
+    }
+
+    (merged, conflicts)
+}
+
+/// Reads an allocation plan, a mapping of emoji name -> codepoint in the same format as `fedimoji.json`.
+///
+/// Names are composed and lowercased, and empty names are ignored.
+pub fn read_plan(path: &Path) -> Result<HashMap<String, char>, PackError> {
+    let planned_mappings = read_mapping_file(path)?;
+    info!("read {} planned mappings", planned_mappings.len());
+    Ok(planned_mappings)
+}
+
+/// Key of the entry in `fedimoji.json` (and the font provider definition) describing the build which wrote it.
+pub const META_KEY: &str = "_meta";
+
+/// How codepoints are written in `fedimoji.json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CodepointFormat {
+    /// The character itself, like `"\u{F0001}"`.
+    #[default]
+    Char,
+
+    /// The codepoint as a decimal number, like `983041`.
+    Decimal,
+
+    /// The codepoint in hex, like `"U+F0001"`.
+    Hex,
+}
+
+impl CodepointFormat {
+    /// Writes a codepoint in this format.
+    pub fn to_json(self, codepoint: char) -> serde_json::Value {
+        match self {
+            CodepointFormat::Char => serde_json::Value::from(codepoint.to_string()),
+            CodepointFormat::Decimal => serde_json::Value::from(codepoint as u32),
+            CodepointFormat::Hex => serde_json::Value::from(format!("U+{:04X}", codepoint as u32)),
+        }
+    }
+
+    /// Reads a codepoint written in any of the formats.
+    pub(crate) fn from_json(value: &serde_json::Value) -> Result<char, PackError> {
+        let invalid = || PackError::InvalidCodepoint(value.to_string());
+        match value {
+            serde_json::Value::Number(number) => number
+                .as_u64()
+                .and_then(|number| u32::try_from(number).ok())
+                .and_then(char::from_u32)
+                .ok_or_else(invalid),
+            serde_json::Value::String(text) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(codepoint), None) => Ok(codepoint),
+                    _ if text.starts_with("U+") || text.starts_with("u+") => parse_codepoint(text),
+                    _ => Err(invalid()),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Reads a mapping of emoji name -> codepoint, lowercasing names and ignoring empty ones.
+///
+/// The path may end with `#` and a JSON pointer to the mapping within the file. Codepoints may be written in any
+/// [`CodepointFormat`].
+pub fn read_mapping_file(path: &Path) -> Result<HashMap<String, char>, PackError> {
+    // split off a pointer, unless the `#` is really part of the file name
+    let (path, pointer) = match path.to_str().and_then(|path| path.rsplit_once('#')) {
+        Some((file, pointer)) if !path.is_file() => (Path::new(file), Some(pointer)),
+        _ => (path, None),
+    };
+
+    if !path.is_file() {
+        return Err(PackError::ImportMissing(path.to_path_buf()));
+    }
+    let contents = std::fs::read_to_string(path)?;
+    let mut document: serde_json::Value = serde_json::from_str(&contents)?;
+    let mut value = match pointer {
+        Some(pointer) => document
+            .pointer_mut(pointer)
+            .ok_or_else(|| PackError::InvalidPointer {
+                path: path.to_path_buf(),
+                pointer: pointer.to_string(),
+            })?
+            .take(),
+        None => document,
+    };
+    // mappings written by fedimoji describe the build which wrote them, which isn't an emoji
+    if let Some(object) = value.as_object_mut() {
+        object.remove(META_KEY);
+    }
+    let mapping: HashMap<String, serde_json::Value> = serde_json::from_value(value)?;
+
+    let mut mappings: HashMap<String, char> = HashMap::new();
+    for (name, codepoint) in mapping {
+        if !name.is_empty() {
+            mappings.insert(name_key(&name), CodepointFormat::from_json(&codepoint)?);
+        }
+    }
+    Ok(mappings)
+}
+
+/// Reads a map of renamed emoji, old name -> new name, as a JSON object. Names are composed and lowercased, and empty
+/// names are ignored.
+pub fn read_renames(path: &Path) -> Result<HashMap<String, String>, PackError> {
+    if !path.is_file() {
+        return Err(PackError::ImportMissing(path.to_path_buf()));
+    }
+    let renames: HashMap<String, String> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(renames
+        .into_iter()
+        .filter(|(old, new)| !old.is_empty() && !new.is_empty())
+        .map(|(old, new)| (name_key(&old), name_key(&new)))
+        .collect())
+}
+
+/// Moves the codepoints of renamed emoji in an existing mapping from their old names to their new ones.
+///
+/// If the new name already has a codepoint of its own, that's kept.
+pub fn rename_mappings(mappings: &mut HashMap<String, char>, renames: &HashMap<String, String>) {
+    for (old, new) in renames {
+        let Some(codepoint) = mappings.remove(old) else {
+            continue;
+        };
+        match mappings.get(new) {
+            Some(existing) if *existing != codepoint => warn!(
+                "\"{old}\" was renamed to \"{new}\", which already has U+{:04X}, so keeps that rather than U+{:04X}",
+                *existing as u32, codepoint as u32
+            ),
+            Some(_) => {}
+            None => {
+                debug!(
+                    "carrying U+{:04X} from \"{old}\" over to \"{new}\"",
+                    codepoint as u32
+                );
+                mappings.insert(new.clone(), codepoint);
+            }
+        }
+    }
+}
+
+/// Reads a blocklist of codepoints never to allocate, such as ones some clients are known to render as boxes. Each
+/// line is a codepoint (like `U+F0010`) or a range of them (like `U+F0100-U+F010F`), and blank lines and lines starting
+/// with `#` are ignored.
+pub fn read_codepoint_blocklist(path: &Path) -> Result<HashSet<char>, PackError> {
+    let mut blocked = HashSet::new();
+    for line in std::fs::read_to_string(path)?.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.contains('-') {
+            blocked.extend(parse_codepoint_range(line)?);
+        } else {
+            blocked.insert(parse_codepoint(line)?);
+        }
+    }
+    info!("read {} blocklisted codepoints", blocked.len());
+    Ok(blocked)
+}
+
+/// Reads a codepoint, written in hex like `U+F0010`, `0xF0010` or `F0010`, either given directly or as the
+/// contents of a file.
+pub fn read_codepoint(arg: &str) -> Result<char, PackError> {
+    let path = Path::new(arg);
+    let text = if path.is_file() {
+        std::fs::read_to_string(path)?
+    } else {
+        arg.to_string()
+    };
+
+    parse_codepoint(&text)
+}
+
+/// Whether a codepoint is in the private use planes (U+F0000-U+FFFFD and U+100000-U+10FFFD), where emoji go.
+pub fn is_private_use(c: char) -> bool {
+    matches!(c as u32, 0xF0000..=0xFFFFD | 0x100000..=0x10FFFD)
+}
+
+/// Parses a range of codepoints, written like `U+F0000-U+F00FF`.
+pub fn parse_codepoint_range(arg: &str) -> Result<RangeInclusive<char>, PackError> {
+    let (start, end) = arg
+        .split_once('-')
+        .ok_or_else(|| PackError::InvalidCodepoint(arg.to_string()))?;
+    Ok(parse_codepoint(start)?..=parse_codepoint(end)?)
+}
+
+/// Parses a codepoint, written in hex like `U+F0010`, `0xF0010` or `F0010`.
+fn parse_codepoint(text: &str) -> Result<char, PackError> {
+    let text = text.trim();
+    let hex = ["U+", "u+", "0x", "0X"]
+        .iter()
+        .find_map(|prefix| text.strip_prefix(prefix))
+        .unwrap_or(text);
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| PackError::InvalidCodepoint(text.to_string()))
+}
+
+/// Number of glyphs in each row of the atlas which a Minecraft version (like `1.19.4`) expects.
+pub fn atlas_columns_for_mc_version(version: &str) -> Result<u32, PackError> {
+    let mut parts = version.trim().split('.').map(str::parse::<u32>);
+    let (Some(Ok(major)), Some(Ok(minor))) = (parts.next(), parts.next()) else {
+        return Err(PackError::InvalidMcVersion(version.to_string()));
+    };
+    if parts.any(|part| part.is_err()) {
+        return Err(PackError::InvalidMcVersion(version.to_string()));
+    }
+
+    // before 1.20, bitmap providers were laid out 16 glyphs wide
+    Ok(if (major, minor) < (1, 20) { 16 } else { 1 })
+}
+
+/// Counts of the emoji given to [`generate_pack_from_iter`], by what became of them.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct PackStats {
+    /// Every emoji given.
+    pub total: usize,
+
+    /// Emoji packed with a newly allocated codepoint.
+    pub new: usize,
+
+    /// Emoji packed with their codepoint from the existing mappings.
+    pub imported: usize,
+
+    /// Emoji packed with their codepoint from the allocation plan.
+    pub planned: usize,
+
+    /// Emoji skipped for any reason other than being duplicates, e.g. already being in the existing pack.
+    pub skipped: usize,
+
+    /// Emoji skipped because an earlier one had the same name.
+    pub duplicates: usize,
+}
+
+/// A generated pack.
+#[derive(Debug, Clone)]
+pub struct Pack {
+    /// The font bitmap, with [`Pack::columns`] glyphs per row.
+    ///
+    /// This is RGBA, with the configured bit depth.
+    pub atlas: DynamicImage,
+
+    /// The codepoint of each glyph, in atlas order, with NUL in cells left for the
+    /// [reserved regions](PackConfig::reserved_regions).
+    pub chars: Vec<char>,
+
+    /// Mapping of emoji name -> codepoint.
+    pub names: BTreeMap<String, char>,
+
+    /// Number of blank glyphs after the real ones at the end of the atlas, which aren't mapped to codepoints.
+    pub padding_glyphs: u32,
+
+    /// Codepoints of emoji which share another's glyph, as alias -> codepoint of the glyph in the atlas.
+    pub aliases: BTreeMap<char, char>,
+
+    /// How far the top of the glyphs sits above the text baseline, out of the provider's height of 8.
+    pub ascent: i32,
+
+    /// Ascents of glyphs which don't use [`Pack::ascent`], by codepoint.
+    pub glyph_ascents: BTreeMap<char, i32>,
+
+    /// Names the emoji were given, by the names they're packed under, for those renamed while packing: sliced with
+    /// [`PackConfig::slice_wide`], or fixed with [`PackConfig::fix_names`].
+    pub source_names: BTreeMap<String, String>,
+
+    /// How many emoji were packed, and how many were skipped.
+    pub stats: PackStats,
+
+    /// Number of glyphs in each row of the atlas.
+    pub columns: u32,
+
+    /// Resource location of the atlas, which the providers refer to, like `fedimoji:font/emoji.png`.
+    pub atlas_file: String,
+
+    /// How hard [`Pack::encode_atlas`] compresses the atlas, from [`PackConfig::png_compression`].
+    pub png_compression: CompressionType,
+
+    /// Side of the tiles the atlas is cut into, from [`PackConfig::tile_size`], if it's cut into tiles.
+    pub tile_size: Option<u32>,
+
+    /// Top-left corner of the cell each glyph was placed in, by codepoint, as recorded while placing them (for
+    /// checking the layout the providers describe).
+    pub cells: BTreeMap<char, (u32, u32)>,
+
+    /// Time spent generating the pack.
+    ///
+    /// Only the stages done by [`generate_pack_from_iter`] are filled in. Its resize stage includes any time spent
+    /// producing the images, which may be decoded lazily.
+    pub timings: Timings,
+}
+
+/// A tile of the atlas, which clients can load on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtlasTile {
+    /// Row of the tile, from the top.
+    pub row: u32,
+
+    /// Column of the tile, from the left.
+    pub column: u32,
+
+    /// Left edge of the tile in the atlas.
+    pub x: u32,
+
+    /// Top edge of the tile in the atlas.
+    pub y: u32,
+
+    pub width: u32,
+    pub height: u32,
+
+    /// Resource location of the tile's image, which its providers refer to, like `fedimoji:font/emoji_tile_0_1.png`.
+    pub file: String,
+}
+
+impl AtlasTile {
+    /// File name of the tile's image, without the namespace and directory of [`AtlasTile::file`].
+    pub fn file_name(&self) -> &str {
+        self.file.rsplit(['/', ':']).next().unwrap_or(&self.file)
+    }
+
+    /// Whether a point in the atlas is within the tile.
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+impl Pack {
+    /// Number of glyphs in the pack.
+    pub fn num_glyphs(&self) -> u32 {
+        self.chars.iter().filter(|c| **c != '\0').count() as u32
+    }
+
+    /// Number of cells in the atlas, including blank ones.
+    pub fn num_cells(&self) -> u32 {
+        self.chars.len() as u32 + self.padding_glyphs
+    }
+
+    /// Estimated size of the atlas once loaded as a texture by the game, in bytes.
+    ///
+    /// Textures are uploaded uncompressed at 4 bytes per pixel, whatever the atlas's bit depth. If the game also
+    /// generates mipmaps for it, they add about another third.
+    pub fn texture_memory(&self) -> u64 {
+        u64::from(self.atlas.width()) * u64::from(self.atlas.height()) * 4
+    }
+
+    /// The atlas, encoded as a PNG at [`Pack::png_compression`].
+    pub fn encode_atlas(&self) -> image::ImageResult<Vec<u8>> {
+        self.encode_png(&self.atlas)
+    }
+
+    /// A tile of the atlas, encoded as a PNG at [`Pack::png_compression`].
+    pub fn encode_tile(&self, tile: &AtlasTile) -> image::ImageResult<Vec<u8>> {
+        self.encode_png(&self.atlas.crop_imm(tile.x, tile.y, tile.width, tile.height))
+    }
+
+    fn encode_png(&self, image: &DynamicImage) -> image::ImageResult<Vec<u8>> {
+        let mut png = Vec::new();
+        let encoder = PngEncoder::new_with_quality(
+            Cursor::new(&mut png),
+            self.png_compression,
+            Default::default(),
+        );
+        image.write_with_encoder(encoder)?;
+        Ok(png)
+    }
+
+    /// Size of each glyph's cell in the atlas, of at least a pixel.
+    fn cell_size(&self) -> (u32, u32) {
+        let columns = self.columns.max(1);
+        let rows = self.num_cells().div_ceil(columns).max(1);
+        (
+            (self.atlas.width() / columns).max(1),
+            (self.atlas.height() / rows).max(1),
+        )
+    }
+
+    /// The tiles the atlas is cut into which the providers refer to, row by row, or the whole atlas as a single tile
+    /// if it isn't cut up. Tiles holding none of the glyphs (only padding or blank cells) are left out, as nothing would
+    /// load them. Tiles at the right and bottom edges are cut short by the edge of the atlas.
+    pub fn tiles(&self) -> Vec<AtlasTile> {
+        let files = self
+            .providers()
+            .iter()
+            .filter_map(|provider| provider["file"].as_str().map(str::to_string))
+            .collect::<HashSet<_>>();
+        let mut tiles = self.all_tiles();
+        tiles.retain(|tile| files.contains(&tile.file));
+        tiles
+    }
+
+    /// Every tile the atlas is cut into, row by row, whether or not it holds any glyphs.
+    fn all_tiles(&self) -> Vec<AtlasTile> {
+        let (width, height) = self.atlas.dimensions();
+        let Some(tile_size) = self.tile_size.filter(|tile_size| *tile_size > 0) else {
+            return vec![AtlasTile {
+                row: 0,
+                column: 0,
+                x: 0,
+                y: 0,
+                width,
+                height,
+                file: self.atlas_file.clone(),
+            }];
+        };
+
+        let stem = self
+            .atlas_file
+            .strip_suffix(".png")
+            .unwrap_or(&self.atlas_file);
+        let mut tiles = Vec::new();
+        for row in 0..height.div_ceil(tile_size) {
+            for column in 0..width.div_ceil(tile_size) {
+                let (x, y) = (column * tile_size, row * tile_size);
+                tiles.push(AtlasTile {
+                    row,
+                    column,
+                    x,
+                    y,
+                    width: tile_size.min(width - x),
+                    height: tile_size.min(height - y),
+                    file: format!("{stem}_tile_{row}_{column}.png"),
+                });
+            }
+        }
+        tiles
+    }
+
+    /// Mapping of codepoint -> emoji name, the inverse of [`Pack::names`].
+    ///
+    /// Fails if several emoji share a codepoint, which can happen if the imported mapping has two names with the
+    /// same codepoint.
+    pub fn inverse_names(&self) -> Result<BTreeMap<char, String>, PackError> {
+        let mut inverse: BTreeMap<char, String> = BTreeMap::new();
+        for (name, codepoint) in &self.names {
+            if let Some(other) = inverse.insert(*codepoint, name.clone()) {
+                let mut names = vec![other, name.clone()];
+                names.sort();
+                return Err(PackError::CodepointConflict {
+                    codepoint: *codepoint,
+                    names,
+                });
+            }
+        }
+        Ok(inverse)
+    }
+
+    /// The font provider definition, telling Minecraft how to display the glyphs.
+    pub fn font_provider(&self) -> serde_json::Value {
+        json!({
+          "providers": self.providers()
+        })
+    }
+
+    /// The individual providers within the font provider definition.
+    pub fn providers(&self) -> Vec<serde_json::Value> {
+        // each cell can only have one codepoint per provider, so aliases go in layers of extra providers over the
+        // same atlas, with NUL (which Minecraft skips) in the cells they don't use
+        let mut layers = vec![self.chars.clone()];
+        for (alias, glyph) in &self.aliases {
+            let Some(index) = self.chars.iter().position(|c| c == glyph) else {
+                continue;
+            };
+            match layers[1..].iter_mut().find(|layer| layer[index] == '\0') {
+                Some(layer) => layer[index] = *alias,
+                None => {
+                    let mut layer = vec!['\0'; self.chars.len()];
+                    layer[index] = *alias;
+                    layers.push(layer);
+                }
+            }
+        }
+
+        // a provider has one ascent, so glyphs with ascents of their own are split out of each layer into providers
+        // of their own in the same way
+        let ascent_of = |c: &char| self.glyph_ascents.get(c).copied().unwrap_or(self.ascent);
+        let tiles = self.all_tiles();
+        let tiled = self.tile_size.is_some();
+        let (cell_width, cell_height) = self.cell_size();
+        let mut providers = Vec::new();
+        for (index, layer) in layers.into_iter().enumerate() {
+            let mut ascents = layer
+                .iter()
+                .filter(|c| **c != '\0')
+                .map(ascent_of)
+                .collect::<BTreeSet<_>>();
+            // the first layer always gets a provider at the pack's ascent, even if every glyph has its own
+            if index == 0 {
+                ascents.insert(self.ascent);
+            }
+            let ascents = std::iter::once(self.ascent)
+                .filter(|ascent| ascents.contains(ascent))
+                .chain(
+                    ascents
+                        .iter()
+                        .copied()
+                        .filter(|ascent| *ascent != self.ascent),
+                );
+
+            for ascent in ascents {
+                // a provider can't cross a Unicode plane either, so each plane of glyphs gets providers of its own
+                let planes = layer
+                    .iter()
+                    .filter(|c| **c != '\0' && ascent_of(c) == ascent)
+                    .map(|c| plane(*c))
+                    .collect::<BTreeSet<_>>();
+                let planes = planes
+                    .iter()
+                    .copied()
+                    .map(Some)
+                    .chain(planes.is_empty().then_some(None));
+
+                for plane_filter in planes {
+                    let in_provider = |c: &char| {
+                        ascent_of(c) == ascent
+                            && plane_filter.is_none_or(|filter| plane(*c) == filter)
+                    };
+
+                    // NUL fills the padding too, and any of the last row the padding doesn't
+                    let columns = self.columns.max(1) as usize;
+                    let mut chars = layer
+                        .iter()
+                        .map(|c| if in_provider(c) { *c } else { '\0' })
+                        .chain(std::iter::repeat_n('\0', self.padding_glyphs as usize))
+                        .collect::<Vec<_>>();
+                    chars.resize(chars.len().next_multiple_of(columns), '\0');
+
+                    let rows = chars.chunks(columns).collect::<Vec<_>>();
+
+                    // each tile gets providers of its own, for the cells within it, leaving out tiles with none of
+                    // this provider's glyphs
+                    for tile in &tiles {
+                        let (tile_rows, tile_columns) = if tiled {
+                            let cells = |start: u32, length: u32, cell_size: u32, count: usize| {
+                                (start / cell_size) as usize
+                                    ..(((start + length) / cell_size) as usize).min(count)
+                            };
+                            (
+                                &rows[cells(tile.y, tile.height, cell_height, rows.len())],
+                                cells(tile.x, tile.width, cell_width, columns),
+                            )
+                        } else {
+                            (&rows[..], 0..columns)
+                        };
+                        if tiled
+                            && tile_rows
+                                .iter()
+                                .all(|row| row[tile_columns.clone()].iter().all(|c| *c == '\0'))
+                        {
+                            continue;
+                        }
+
+                        // one string of glyphs per row of the tile, which Minecraft needs to all be the same length
+                        let chars = tile_rows
+                            .iter()
+                            .map(|row| String::from_iter(&row[tile_columns.clone()]))
+                            .collect::<Vec<_>>();
+                        assert!(
+                            chars
+                                .iter()
+                                .all(|row| row.chars().count() == tile_columns.len()),
+                            "every row of a provider should have {} codepoints",
+                            tile_columns.len()
+                        );
+                        providers.push(json!({
+                          "type": "bitmap",
+                          "file": tile.file,
+                          "height": 8,
+                          "ascent": ascent,
+                          "chars": chars
+                        }));
+                    }
+                }
+            }
+        }
+        providers
+    }
+}
+
+/// The Unicode plane of a codepoint, from 0 (the Basic Multilingual Plane) to 16.
+fn plane(c: char) -> u32 {
+    c as u32 >> 16
+}
+
+/// Whether a codepoint is unlikely to upset bidirectional text or combining characters in chat clients.
+///
+/// That's when it:
+/// - has a bidi class which doesn't change the direction of, or attach to, the text around it (so not
+///   right-to-left, an explicit embedding, override or isolate, a non-spacing mark, or a separator),
+/// - isn't a combining mark, and has a canonical combining class of zero,
+/// - is unchanged by NFC and NFKC normalization, so clients normalizing messages keep it as it is.
+pub fn is_safe_codepoint(c: char) -> bool {
+    use unicode_bidi::BidiClass::*;
+    use unicode_normalization::UnicodeNormalization;
+
+    let bidi_safe = !matches!(
+        unicode_bidi::bidi_class(c),
+        R | AL | AN | NSM | BN | B | S | LRE | LRO | RLE | RLO | PDF | LRI | RLI | FSI | PDI
+    );
+    let combining_safe = !unicode_normalization::char::is_combining_mark(c)
+        && unicode_normalization::char::canonical_combining_class(c) == 0;
+    let text = c.to_string();
+    let normalization_safe = text.nfc().eq(text.chars()) && text.nfkc().eq(text.chars());
+
+    bidi_safe && combining_safe && normalization_safe
+}
+
+/// Whether a character is allowed in Fediverse shortcodes.
+fn is_shortcode_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Slices an image wider than the `threshold` aspect ratio into as many squarish pieces as fit, named after it with
+/// `_0`, `_1` and so on from left to right, so they follow one another in the atlas.
+fn slice_wide(
+    name: String,
+    image: DynamicImage,
+    threshold: Option<f32>,
+) -> Vec<(String, DynamicImage)> {
+    let (width, height) = image.dimensions();
+    let aspect_ratio = width as f32 / height.max(1) as f32;
+    let Some(threshold) = threshold.filter(|threshold| aspect_ratio > *threshold) else {
+        return vec![(name, image)];
+    };
+
+    let count = (aspect_ratio.round() as u32).clamp(1, width);
+    info!("slicing \"{name}\" ({width}x{height}) into {count} glyphs, as it's wider than {threshold}:1");
+    (0..count)
+        .map(|index| {
+            // spread any leftover columns over the slices, rather than dropping them
+            let x = index * width / count;
+            let slice_width = (index + 1) * width / count - x;
+            (
+                format!("{name}_{index}"),
+                image.crop_imm(x, 0, slice_width, height),
+            )
+        })
+        .collect()
+}
+
+/// Generates a pack from already-named, in-memory emoji images.
+///
+/// Each image is resized to fit a glyph, assigned a codepoint, and placed in the atlas. Names are used as given,
+/// and later images with an already-seen name are skipped.
+pub fn generate_pack_from_iter(
+    images: impl Iterator<Item = (String, DynamicImage)>,
+    config: &PackConfig,
+) -> Result<Pack, PackError> {
+    let (mut glyph_width, mut glyph_height) = (config.glyph_width, config.glyph_height);
+    if config.pad.saturating_mul(2) >= glyph_width.min(glyph_height) {
+        return Err(PackError::InvalidPadding {
+            pad: config.pad,
+            glyph_size: glyph_width.min(glyph_height),
+        });
+    }
+
+    // size of the glyphs within their padding
+    let inner_width = glyph_width - config.pad * 2;
+    let inner_height = glyph_height - config.pad * 2;
+
+    // the cells grown by a margin, or an error if that makes them too large to address
+    let grow = |width: u32, height: u32, margin: u32, what: &'static str| {
+        width
+            .checked_add(margin)
+            .zip(height.checked_add(margin))
+            .ok_or(PackError::InvalidMargin {
+                what,
+                margin,
+                glyph_width: width,
+                glyph_height: height,
+            })
+    };
+
+    // grow the cells to fit the outline and drop shadow, if there are any
+    let (mut cell_inner_width, mut cell_inner_height) = (inner_width, inner_height);
+    if let Some(outline) = &config.outline {
+        let margin = u32::from(outline.width) * 2;
+        if margin > 0 {
+            (glyph_width, glyph_height) = grow(glyph_width, glyph_height, margin, "outline")?;
+            (cell_inner_width, cell_inner_height) =
+                grow(cell_inner_width, cell_inner_height, margin, "outline")?;
+            warn!("growing glyph cells to {glyph_width}x{glyph_height} to fit the outline");
+        }
+    }
+    if let Some(drop_shadow) = &config.drop_shadow {
+        let margin = drop_shadow.margin();
+        if margin > 0 {
+            (glyph_width, glyph_height) = grow(glyph_width, glyph_height, margin, "drop shadow")?;
+            (cell_inner_width, cell_inner_height) =
+                grow(cell_inner_width, cell_inner_height, margin, "drop shadow")?;
+            warn!("growing glyph cells to {glyph_width}x{glyph_height} to fit the drop shadow");
+        }
+    }
+
+    // leave a gutter at the right and bottom of each cell, so there's space between neighbours
+    if config.gutter > 0 {
+        (glyph_width, glyph_height) = grow(glyph_width, glyph_height, config.gutter, "gutter")?;
+        info!(
+            "spacing glyphs {}px apart, in {glyph_width}x{glyph_height} cells",
+            config.gutter
+        );
+    }
+
+    // a single cell over the size limit can't be in any atlas, so there's no use drawing the glyphs (or their shadows)
+    let cell_bytes = (u64::from(glyph_width) * u64::from(glyph_height))
+        .saturating_mul(config.atlas_bit_depth.bytes_per_pixel());
+    if cell_bytes > config.atlas_byte_limit {
+        return Err(PackError::AtlasTooLarge {
+            bytes: cell_bytes,
+            limit: config.atlas_byte_limit,
+        });
+    }
+
+    // a glyph can't straddle tiles, as each tile's providers divide it into whole cells
+    if let Some(tile_size) = config.tile_size {
+        if tile_size == 0 || tile_size % glyph_width != 0 || tile_size % glyph_height != 0 {
+            return Err(PackError::InvalidTileSize {
+                tile_size,
+                glyph_width,
+                glyph_height,
+            });
+        }
+    }
+
+    if config.tintable {
+        warn!("making glyphs tintable, discarding their color");
+    }
+    let color_levels = config.color_levels.map(|levels| ColorLevels(levels.max(2)));
+    if color_levels.is_none() && config.dither != Dither::None {
+        warn!("dithering has no effect without reducing the colors");
+    }
+    if config.atlas_bit_depth == BitDepth::Sixteen {
+        warn!("making a 16-bit atlas, though Minecraft only uses 8 bits per channel");
+    }
+
+    // the overlay, at the same bit depth as the glyphs
+    let overlay = config
+        .overlay
+        .clone()
+        .map(|overlay| config.atlas_bit_depth.convert(overlay));
+    if let Some(overlay) = &overlay {
+        if overlay.width() > inner_width || overlay.height() > inner_height {
+            warn!(
+                "overlay is {}x{}, larger than the {inner_width}x{inner_height} glyphs it's put on",
+                overlay.width(),
+                overlay.height()
+            );
+        }
+    }
+
+    // the credits glyphs, which are packed after the emoji at codepoints of their own
+    let credits = config
+        .credits_text
+        .as_deref()
+        .map(|text| credits::render_credits(text, inner_width.min(inner_height)))
+        .unwrap_or_default();
+    let credits_mappings = credits
+        .iter()
+        .map(|(name, _)| name.clone())
+        .zip(credits::credits_codepoints(credits.len()))
+        .collect::<HashMap<_, _>>();
+    if !credits.is_empty() {
+        info!(
+            "rendered the credits into {} glyphs, from U+{:04X}",
+            credits.len(),
+            credits_mappings[&format!("{}0", credits::CREDITS_PREFIX)] as u32
+        );
+    }
+
+    // codepoints used in the existing mapping, the plan or the credits, or blocklisted
+    let reserved_codepoints = config
+        .existing_mappings
+        .values()
+        .chain(config.planned_mappings.values())
+        .chain(credits_mappings.values())
+        .chain(&config.blocked_codepoints)
+        .copied()
+        .collect::<HashSet<char>>();
+
+    // figure out which codepoints we can allocate to emoji not in the existing mapping
+    let mut available_codepoints = (0xF0000..=0xFFFFD)
+        .filter_map(char::from_u32)
+        .filter(|c| !reserved_codepoints.contains(c))
+        .filter(|c| config.codepoint_start_after.is_none_or(|after| *c > after))
+        .filter(|c| !config.safe_codepoints || is_safe_codepoint(*c));
+
+    // names of the emoji we've already seen, for skipping duplicates
+    let mut seen_names: HashSet<String> = HashSet::new();
+
+    // names which could be confused with chat formatting
+    let mut reserved_names: Vec<String> = Vec::new();
+
+    let mut stats = PackStats::default();
+
+    let mut timings = Timings::default();
+    let start = Instant::now();
+
+    // names the emoji were given, by the names they're packed under, where slicing or fixing them changed them
+    let mut source_names: BTreeMap<String, String> = BTreeMap::new();
+
+    let mut images = images
+        .flat_map(|(name, image)| {
+            slice_wide(name.clone(), image, config.slice_wide)
+                .into_iter()
+                .map(|(slice, image)| {
+                    let slice = config.validate_name(slice);
+                    if slice != name {
+                        source_names.insert(slice.clone(), name.clone());
+                    }
+                    (slice, image)
+                })
+                .collect::<Vec<_>>()
+        })
+        .chain(credits)
+        .inspect(|_| stats.total += 1)
+        .filter(|(name, _)| {
+            // skip emoji whose name we've already seen
+            if seen_names.insert(name_key(name)) {
+                true
+            } else {
+                warn!(kind = "duplicate", "duplicate emoji name \"{name}\" (skipping it)");
+                stats.duplicates += 1;
+                false
+            }
+        })
+        .filter(|(name, _)| {
+            // skip emoji which are already in the existing pack, if we only want new ones
+            if config.only_new && config.imported_codepoint(name).is_some() {
+                debug!("skipping existing emoji \"{name}\"");
+                false
+            } else if config.is_filtered_out(name) {
+                info!("skipping \"{name}\", as its imported codepoint is outside the filter");
+                false
+            } else {
+                true
+            }
+        })
+        .inspect(|(name, _)| {
+            if config.is_reserved_name(name) {
+                warn!(
+                    kind = "reserved_name",
+                    "emoji name \"{name}\" could be confused with Minecraft chat formatting"
+                );
+                reserved_names.push(name.clone());
+            }
+        })
+        .filter_map(|(name, image)| {
+            let fits = image.width() <= inner_width && image.height() <= inner_height;
+
+            // leave pixel art which mustn't be resampled at its native size, as long as it fits
+            if config.no_resize.contains(&name_key(&name)) {
+                if !fits {
+                    warn!(
+                        kind = "skipped",
+                        "emoji \"{name}\" mustn't be resized, but at {}x{} it's larger than the {inner_width}x{inner_height} glyphs (skipping it)",
+                        image.width(),
+                        image.height()
+                    );
+                    return None;
+                }
+                debug!("not resizing \"{}\", as asked", name);
+                return Some((name, config.atlas_bit_depth.convert(image)));
+            }
+
+            // leave it at its native size if it already fits and we don't want to upscale
+            if config.no_upscale && fits {
+                debug!("not resizing \"{}\"", name);
+                return Some((name, config.atlas_bit_depth.convert(image)));
+            }
+
+            // only scale pixel art by whole factors, where nearest-neighbour sampling keeps it crisp
+            let filter = if config.integer_scale_only {
+                if !is_integer_scale(image.width(), image.height(), inner_width, inner_height) {
+                    warn!(
+                        kind = "skipped",
+                        "emoji \"{name}\" is {}x{}, which doesn't scale by a whole factor to the {inner_width}x{inner_height} glyphs (skipping it)",
+                        image.width(),
+                        image.height()
+                    );
+                    return None;
+                }
+                image::imageops::FilterType::Nearest
+            } else {
+                image::imageops::FilterType::Triangle
+            };
+
+            // resize it
+            let image = if config.linear_resize {
+                resize_linear(&image, inner_width, inner_height, filter)
+            } else {
+                image.resize(inner_width, inner_height, filter)
+            };
+            debug!("resized \"{}\"", name);
+            Some((name, config.atlas_bit_depth.convert(image)))
+        })
+        .map(|(name, image)| match &config.color_transform {
+            Some(transform) => (name, transform.apply(&image, config.atlas_bit_depth)),
+            None => (name, image),
+        })
+        .map(|(name, mut image)| {
+            // blend the overlay onto the glyph, if there is one
+            if let Some(overlay) = &overlay {
+                let x = match config.overlay_position {
+                    Corner::TopLeft | Corner::BottomLeft => 0,
+                    Corner::TopRight | Corner::BottomRight => {
+                        i64::from(image.width()) - i64::from(overlay.width())
+                    }
+                };
+                let y = match config.overlay_position {
+                    Corner::TopLeft | Corner::TopRight => 0,
+                    Corner::BottomLeft | Corner::BottomRight => {
+                        i64::from(image.height()) - i64::from(overlay.height())
+                    }
+                };
+                image::imageops::overlay(&mut image, overlay, x, y);
+            }
+            (name, image)
+        })
+        .inspect(|(name, image)| {
+            // warn about glyphs which are too faint to be seen
+            if let Some(min_coverage) = config.min_coverage {
+                let coverage = coverage(image, inner_width * inner_height);
+                if coverage < min_coverage {
+                    warn!(
+                        kind = "faint",
+                        "emoji \"{name}\" is nearly blank once resized (coverage {coverage:.3}, below {min_coverage})"
+                    );
+                }
+            }
+        })
+        .map(|(name, image)| match &color_levels {
+            Some(color_levels) => (
+                name,
+                color_levels.apply(&image, config.dither, config.atlas_bit_depth),
+            ),
+            None => (name, image),
+        })
+        .map(|(name, mut image)| {
+            if config.tintable {
+                make_tintable(&mut image);
+            }
+            (name, image)
+        })
+        .map(|(name, image)| match &config.outline {
+            Some(outline) => (name, outline.apply(&image, config.atlas_bit_depth)),
+            None => (name, image),
+        })
+        .map(|(name, image)| match &config.drop_shadow {
+            Some(drop_shadow) => (name, drop_shadow.apply(&image, config.atlas_bit_depth)),
+            None => (name, image),
+        })
+        .map(|(name, image)| match config.normalize_transparency {
+            Some(fill) => (name, fill.apply(&image, config.atlas_bit_depth)),
+            None => (name, image),
+        })
+        .map(|(name, image)| {
+            if config.glyph_processors.is_empty() {
+                return (name, image);
+            }
+            let mut glyph = image.into_rgba8();
+            for processor in &config.glyph_processors {
+                let processed = processor.process(&name, glyph.clone());
+                if processed.dimensions() == glyph.dimensions() {
+                    glyph = processed;
+                } else {
+                    warn!(
+                        "a glyph processor resized \"{name}\" from {}x{} to {}x{} (ignoring its change)",
+                        glyph.width(),
+                        glyph.height(),
+                        processed.width(),
+                        processed.height()
+                    );
+                }
+            }
+            (name, config.atlas_bit_depth.convert(DynamicImage::ImageRgba8(glyph)))
+        })
+        .filter(|(name, image)| {
+            // flag glyphs which would be invisible, which usually means a broken export
+            let Some(blank) = blank_kind(image) else {
+                return true;
+            };
+            if config.skip_blank {
+                warn!(kind = "blank", "emoji \"{name}\" is {blank} (skipping it)");
+            } else {
+                warn!(kind = "blank", "emoji \"{name}\" is {blank}");
+            }
+            !config.skip_blank
+        })
+        .collect::<Vec<_>>();
+
+    timings.resize = start.elapsed();
+    let start = Instant::now();
+
+    if config.strict_names && !reserved_names.is_empty() {
+        return Err(PackError::ReservedNames(reserved_names));
+    }
+
+    if images.is_empty() {
+        return Err(PackError::NoValidEmoji);
+    }
+
+    // where the search for the next new codepoint starts, for explaining which were skipped
+    let mut next_new = config
+        .codepoint_start_after
+        .map_or(0xF0000, |after| after as u32 + 1);
+
+    // with positional codepoints, the Nth emoji by name gets the Nth codepoint, whatever the mappings say
+    let mut positional_codepoints = (next_new..=0xFFFFD)
+        .filter_map(char::from_u32)
+        .filter(|c| !config.blocked_codepoints.contains(c));
+    if config.positional_codepoints {
+        warn!("allocating codepoints by position, so adding or removing an emoji shifts the codepoints of the emoji after it");
+        images.sort_by(|(a, _), (b, _)| a.cmp(b));
+    }
+
+    // put the emoji in the given order, if there is one, with the rest after them by name
+    if !config.order.is_empty() {
+        let positions = config
+            .order
+            .iter()
+            .enumerate()
+            .map(|(position, name)| (name_key(name), position))
+            .collect::<HashMap<_, _>>();
+        let names = images
+            .iter()
+            .map(|(name, _)| name_key(name))
+            .collect::<HashSet<_>>();
+        for name in &config.order {
+            if !names.contains(&name_key(name)) {
+                warn!(
+                    kind = "unknown_name",
+                    "\"{name}\" is in the order, but isn't an emoji being packed"
+                );
+            }
+        }
+        images.sort_by_cached_key(|(name, _)| match positions.get(&name_key(name)) {
+            Some(position) => (*position, String::new()),
+            None => (usize::MAX, name.clone()),
+        });
+    }
+
+    // keep the emoji in each group together, with the groups first, in order of group name
+    if !config.groups.is_empty() {
+        images.sort_by_cached_key(|(name, _)| {
+            let key = name_key(name);
+            match config.groups.get(&key) {
+                Some(group) => (false, group.clone(), key),
+                None => (true, String::new(), String::new()),
+            }
+        });
+    }
+
+    // make sure there are enough codepoints for the emoji without an existing mapping
+    let (needed, available) = if config.positional_codepoints {
+        let needed = images
+            .iter()
+            .filter(|(name, _)| !credits_mappings.contains_key(name))
+            .count();
+        (needed, positional_codepoints.clone().count())
+    } else {
+        let needed = images
+            .iter()
+            .filter(|(name, _)| {
+                config.imported_codepoint(name).is_none()
+                    && config.planned_codepoint(name).is_none()
+                    && !credits_mappings.contains_key(name)
+            })
+            .count();
+        (needed, available_codepoints.clone().count())
+    };
+    if needed > available {
+        return Err(PackError::CodepointsExhausted { needed, available });
+    }
+
+    // codepoints given to new emoji so far
+    let mut allocated: HashSet<char> = HashSet::new();
+
+    // with block-aligned allocation, the group of the last emoji given the next free codepoint, and how many free
+    // codepoints were left unused to align the groups
+    let mut block_aligned = config.allocation_strategy == AllocationStrategy::BlockAligned;
+    if block_aligned && config.groups.is_empty() {
+        block_aligned = false;
+        warn!("block-aligned allocation has no effect without groups, so the emoji are allocated densely");
+    }
+    let mut last_group: Option<Option<&String>> = None;
+    let mut alignment_gaps = 0;
+
+    let images = images
+        .into_iter()
+        .map(|(name, image)| {
+            if let Some(&codepoint) = credits_mappings.get(&name) {
+                explain(
+                    config,
+                    format!(
+                        "using U+{:04X} for \"{name}\", as it's one of the credits glyphs",
+                        codepoint as u32
+                    ),
+                );
+                (name, codepoint, image)
+            } else if config.positional_codepoints {
+                // we checked there are enough codepoints above
+                let codepoint = positional_codepoints.next().unwrap();
+                explain(
+                    config,
+                    format!(
+                        "using U+{:04X} for \"{name}\", by its position in the sorted names",
+                        codepoint as u32
+                    ),
+                );
+                (name, codepoint, image)
+            } else if let Some(codepoint) = config.imported_codepoint(&name) {
+                // if we have an existing mapping for this emoji, use that
+                explain(
+                    config,
+                    format!(
+                        "using existing mapping for \"{name}\", U+{:04X}, from the imported mapping",
+                        codepoint as u32
+                    ),
+                );
+                if let Some(planned) = config
+                    .planned_codepoint(&name)
+                    .filter(|planned| *planned != codepoint)
+                {
+                    warn!(
+                        "\"{name}\" is planned to have U+{:04X}, but keeps U+{:04X} from the imported mapping",
+                        planned as u32,
+                        codepoint as u32
+                    );
+                }
+                (name, codepoint, image)
+            } else if let Some(codepoint) = config.planned_codepoint(&name) {
+                explain(
+                    config,
+                    format!(
+                        "using planned mapping for \"{name}\", U+{:04X}, from the allocation plan",
+                        codepoint as u32
+                    ),
+                );
+                (name, codepoint, image)
+            } else if let Some(codepoint) = config.allocator.as_ref().and_then(|allocator| {
+                let is_free = |c: char| !reserved_codepoints.contains(&c) && !allocated.contains(&c);
+                allocator
+                    .allocate(&name, &is_free)
+                    .filter(|codepoint| is_free(*codepoint))
+            }) {
+                explain(
+                    config,
+                    format!(
+                        "using new mapping for \"{name}\", U+{:04X}, from the custom allocator",
+                        codepoint as u32
+                    ),
+                );
+                allocated.insert(codepoint);
+                (name, codepoint, image)
+            } else {
+                if config.allocator.is_some() {
+                    warn!("the custom allocator gave no free codepoint for \"{name}\", so it gets the next free one");
+                }
+
+                // start each group on a block of 16, if there's still room to, leaving enough codepoints after the
+                // block's start for every emoji yet to get one
+                let group = config.groups.get(&name_key(&name));
+                let starts_group = last_group != Some(group);
+                last_group = Some(group);
+                let aligned = (block_aligned && starts_group)
+                    .then(|| {
+                        let mut aligned_codepoints = available_codepoints.clone();
+                        let codepoint = aligned_codepoints
+                            .find(|c| !allocated.contains(c) && (*c as u32).is_multiple_of(16))?;
+                        let still_needed = needed - allocated.len();
+                        let room = 1 + aligned_codepoints
+                            .clone()
+                            .filter(|c| !allocated.contains(c))
+                            .take(still_needed)
+                            .count();
+                        if room < still_needed {
+                            debug!("not enough codepoints left to align \"{name}\"'s group, so it's allocated densely");
+                            return None;
+                        }
+                        alignment_gaps += available_codepoints
+                            .clone()
+                            .take_while(|c| *c < codepoint)
+                            .filter(|c| !allocated.contains(c))
+                            .count();
+                        available_codepoints = aligned_codepoints;
+                        Some(codepoint)
+                    })
+                    .flatten();
+
+                // we checked there are enough codepoints above, and the custom allocator takes at most one per emoji
+                let codepoint = aligned.unwrap_or_else(|| {
+                    available_codepoints
+                        .find(|c| !allocated.contains(c))
+                        .unwrap()
+                });
+                allocated.insert(codepoint);
+                let skipped = reserved_codepoints
+                    .iter()
+                    .filter(|&&c| (next_new..codepoint as u32).contains(&(c as u32)))
+                    .count();
+                next_new = codepoint as u32 + 1;
+                let reason = if skipped > 0 {
+                    format!(
+                        "the next free codepoint, skipping {skipped} used by the imported mapping or plan, or blocklisted"
+                    )
+                } else {
+                    "the next free codepoint".to_string()
+                };
+                explain(
+                    config,
+                    format!(
+                        "using new mapping for \"{name}\", U+{:04X}, as it isn't in the imported mapping or plan: {reason}",
+                        codepoint as u32
+                    ),
+                );
+                (name, codepoint, image)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // report the blocklisted codepoints passed over, up to the last new codepoint
+    if let Some(last_new) = images
+        .iter()
+        .filter(|(name, _, _)| {
+            config.imported_codepoint(name).is_none() && config.planned_codepoint(name).is_none()
+        })
+        .map(|(_, codepoint, _)| *codepoint)
+        .filter(|codepoint| {
+            !credits_mappings
+                .values()
+                .any(|credits| credits == codepoint)
+        })
+        .max()
+    {
+        let skipped = config
+            .blocked_codepoints
+            .iter()
+            .filter(|blocked| **blocked < last_new)
+            .filter(|blocked| {
+                config
+                    .codepoint_start_after
+                    .is_none_or(|after| **blocked > after)
+            })
+            .count();
+        if skipped > 0 {
+            info!("skipped {skipped} blocklisted codepoints while allocating");
+        }
+    }
+
+    if alignment_gaps > 0 {
+        info!("left {alignment_gaps} codepoints unused to align the groups to blocks of 16");
+    }
+
+    // check no emoji moved from its imported codepoint
+    let reassigned = images
+        .iter()
+        .filter_map(|(name, codepoint, _)| {
+            let imported = config
+                .imported_codepoint(name)
+                .filter(|imported| imported != codepoint)?;
+            Some(format!(
+                "{name} (U+{:04X} -> U+{:04X})",
+                imported as u32, *codepoint as u32
+            ))
+        })
+        .collect::<Vec<_>>();
+    if !reassigned.is_empty() {
+        if config.fail_on_reassignment {
+            return Err(PackError::CodepointsReassigned(reassigned));
+        }
+        warn!(
+            "{} emoji get a different codepoint than the imported mapping gives them: {}",
+            reassigned.len(),
+            reassigned.join(", ")
+        );
+    }
+
+    // count where the packed emoji's codepoints came from
+    for (name, _, _) in &images {
+        if config.positional_codepoints {
+            stats.new += 1;
+        } else if config.imported_codepoint(name).is_some() {
+            stats.imported += 1;
+        } else if config.planned_codepoint(name).is_some() {
+            stats.planned += 1;
+        } else {
+            stats.new += 1;
+        }
+    }
+    stats.skipped = stats.total - stats.duplicates - images.len();
+
+    // share one cell between near-identical glyphs, if desired, with the later ones aliasing the first
+    let mut aliases: BTreeMap<char, char> = BTreeMap::new();
+    let mut alias_names: BTreeMap<String, char> = BTreeMap::new();
+    let images = match config.similarity_threshold {
+        None => images,
+        Some(threshold) => {
+            let mut kept: Vec<(String, char, DynamicImage, GlyphHash)> = Vec::new();
+            for (name, codepoint, image) in images {
+                let hash = GlyphHash::new(&image);
+                let similar = kept
+                    .iter()
+                    .find(|(.., kept_hash)| hash.is_similar(kept_hash, threshold));
+                match similar {
+                    Some((kept_name, kept_codepoint, ..)) => {
+                        info!("\"{name}\" looks like \"{kept_name}\", so shares its glyph");
+                        aliases.insert(codepoint, *kept_codepoint);
+                        alias_names.insert(name, codepoint);
+                    }
+                    None => kept.push((name, codepoint, image, hash)),
+                }
+            }
+            kept.into_iter()
+                .map(|(name, codepoint, image, _)| (name, codepoint, image))
+                .collect()
+        }
+    };
+
+    let num_glyphs: u32 = images.len() as u32;
+    let mut columns = config.atlas_columns.max(1);
+    if columns > 1 && glyph_height % 8 != 0 {
+        warn!("glyph height {glyph_height}px isn't a multiple of 8, so glyphs won't scale evenly in older versions");
+    }
+    if config.auto_atlas {
+        columns = squarest_columns(num_glyphs, glyph_width, glyph_height, config.pad_to_pow2);
+        let rows = num_glyphs.div_ceil(columns);
+        info!(
+            "laying out the atlas as {columns}x{rows} glyphs ({}x{}px)",
+            columns * glyph_width,
+            rows * glyph_height
+        );
+    }
+
+    // the atlas has to be large enough to hold the template, if there is one
+    let template = config
+        .atlas_template
+        .clone()
+        .map(|template| config.atlas_bit_depth.convert(template));
+    let template_rows = match &template {
+        Some(template) => {
+            let template_columns = template.width().div_ceil(glyph_width);
+            if template_columns > columns {
+                warn!("widening the atlas to {template_columns} glyphs, to fit the template");
+                columns = template_columns;
+            }
+            template.height().div_ceil(glyph_height)
+        }
+        None => 0,
+    };
+
+    // the cells the glyphs go in, skipping any which overlap a reserved region
+    let is_reserved = |cell: u32| {
+        let (x, y) = (
+            u64::from(cell % columns) * u64::from(glyph_width),
+            u64::from(cell / columns) * u64::from(glyph_height),
+        );
+        config
+            .reserved_regions
+            .iter()
+            .any(|region| region.overlaps(x, y, u64::from(glyph_width), u64::from(glyph_height)))
+    };
+    // only as many cells as fit within the atlas size limit are worth searching, as the atlas couldn't hold any more (the
+    // glyphs' own cells are always searched, so that an atlas too large even without reserved regions is reported as such)
+    let max_cells = (config.atlas_byte_limit / cell_bytes.max(1)).min(u64::from(u32::MAX)) as u32;
+    let max_cells = max_cells.max(num_glyphs);
+    let glyph_cells = (0..max_cells)
+        .filter(|cell| !is_reserved(*cell))
+        .take(num_glyphs as usize)
+        .collect::<Vec<_>>();
+    if glyph_cells.len() < num_glyphs as usize {
+        return Err(PackError::ReservedRegionsFull { needed: num_glyphs });
+    }
+    let num_cells = glyph_cells.last().map_or(0, |cell| cell + 1);
+    if num_cells > num_glyphs {
+        debug!(
+            "leaving {} cells free for the reserved regions",
+            num_cells - num_glyphs
+        );
+    }
+
+    // add blank rows to reach a power of two, if desired
+    let mut num_rows = num_cells.div_ceil(columns).max(template_rows);
+    if config.pad_to_pow2 {
+        num_rows = num_rows.next_power_of_two();
+    }
+    let mut padding_glyphs = num_rows * columns - num_cells;
+    if padding_glyphs > 0 {
+        info!("padding the atlas with {padding_glyphs} blank glyphs, to fill {num_rows} rows");
+    }
+
+    // make sure the atlas isn't unreasonably large before allocating it
+    let atlas_bytes = (u64::from(glyph_width) * u64::from(columns))
+        .saturating_mul(u64::from(glyph_height) * u64::from(num_rows))
+        .saturating_mul(config.atlas_bit_depth.bytes_per_pixel());
+    if atlas_bytes > config.atlas_byte_limit {
+        return Err(PackError::AtlasTooLarge {
+            bytes: atlas_bytes,
+            limit: config.atlas_byte_limit,
+        });
+    }
+
+    // allocate the atlas
+    let mut atlas = config
+        .atlas_bit_depth
+        .convert(DynamicImage::ImageRgba8(RgbaImage::new(
+            glyph_width * columns,
+            glyph_height * num_rows,
+        )));
+    debug!(
+        "allocated {}x{} pixel atlas",
+        glyph_width * columns,
+        glyph_height * num_rows
+    );
+    if let Some(template) = &template {
+        image::imageops::replace(&mut atlas, template, 0, 0);
+    }
+
+    // mapping of name -> codepoint
+    let mut names: BTreeMap<String, char> = BTreeMap::new();
+
+    // set of glyph characters, with NUL in the reserved cells
+    let mut chars: Vec<char> = vec!['\0'; num_cells as usize];
+
+    // vertical centers of the glyphs' content within their cells, for working out the ascent
+    let mut content_centers: Vec<f32> = Vec::new();
+
+    // where each glyph's cell is
+    let mut cells: BTreeMap<char, (u32, u32)> = BTreeMap::new();
+
+    // place the images in the atlas
+    for ((name, codepoint, mut image), index) in images.into_iter().zip(glyph_cells) {
+        cells.insert(
+            codepoint,
+            (
+                (index % columns) * glyph_width,
+                (index / columns) * glyph_height,
+            ),
+        );
+        let mut x = (index % columns) * glyph_width + config.pad;
+        let mut y = (index / columns) * glyph_height + config.pad;
+        if config.no_upscale || config.no_resize.contains(&name_key(&name)) {
+            // center glyphs which may not fill their cell
+            x += (cell_inner_width - image.width()) / 2;
+            y += (cell_inner_height - image.height()) / 2;
+        }
+        if config.variable_advance {
+            // start the glyph at the left of its cell, so only its content takes up space (blank glyphs are left alone)
+            if let Some((left, right)) = content_columns(&image) {
+                image = image.crop_imm(left, 0, right - left + 1, image.height());
+                x = (index % columns) * glyph_width + config.pad;
+                debug!(
+                    "`{name}` has {}px of content, for an advance of about {}",
+                    image.width(),
+                    (config.pad + image.width()) * 8 / glyph_height + 1
+                );
+            }
+        }
+        match (&mut atlas, &image) {
+            // copy at full precision, as copying the images themselves would go through 8 bits
+            (DynamicImage::ImageRgba16(atlas), DynamicImage::ImageRgba16(image)) => {
+                atlas.copy_from(image, x, y).unwrap()
+            }
+            (atlas, image) => atlas.copy_from(image, x, y).unwrap(),
+        }
+        debug!("copied `{}` to ({}, {})", name, x, y);
+
+        // spread the glyph's edge colors through its cell, which can't reach the neighbouring cells
+        if config.mipmap_bleed > 0 {
+            let cell_x = (index % columns) * glyph_width;
+            let cell_y = (index / columns) * glyph_height;
+            let mut cell = atlas
+                .crop_imm(cell_x, cell_y, glyph_width, glyph_height)
+                .to_rgba16();
+            bleed_edges(&mut cell, config.mipmap_bleed);
+            match (
+                &mut atlas,
+                config
+                    .atlas_bit_depth
+                    .convert(DynamicImage::ImageRgba16(cell)),
+            ) {
+                (DynamicImage::ImageRgba16(atlas), DynamicImage::ImageRgba16(cell)) => {
+                    atlas.copy_from(&cell, cell_x, cell_y).unwrap()
+                }
+                (atlas, cell) => atlas.copy_from(&cell, cell_x, cell_y).unwrap(),
+            }
+        }
+        if let Some((top, bottom)) = content_rows(&image) {
+            let cell_y = (index / columns) * glyph_height;
+            content_centers.push((y - cell_y) as f32 + (top + bottom + 1) as f32 / 2.0);
+        }
+
+        names.insert(name, codepoint);
+        chars[index as usize] = codepoint;
+    }
+    names.extend(alias_names);
+
+    // crop off trailing rows of nothing but padding, if desired, leaving any row with a real glyph or the template
+    if config.trim_atlas {
+        let used_rows = num_cells.div_ceil(columns).max(template_rows);
+        if used_rows < num_rows {
+            if config.pad_to_pow2 {
+                warn!("trimming the atlas undoes padding it to a power of two rows");
+            }
+            info!("trimming {} blank rows off the atlas", num_rows - used_rows);
+            atlas = atlas.crop_imm(0, 0, atlas.width(), used_rows * glyph_height);
+            padding_glyphs = used_rows * columns - num_cells;
+        }
+    }
+
+    // the mapping should still be complete when only packing new emoji
+    if config.only_new {
+        for (name, codepoint) in &config.existing_mappings {
+            if !config.is_filtered_out(name) {
+                names.entry(name.clone()).or_insert(*codepoint);
+            }
+        }
+    }
+
+    // keep the old names of renamed emoji, if desired
+    for (old, new) in &config.kept_aliases {
+        if let Some(codepoint) = names.get(new).copied() {
+            debug!("keeping \"{old}\" as an alias of \"{new}\"");
+            names.entry(old.clone()).or_insert(codepoint);
+        }
+    }
+
+    let ascent = if config.auto_ascent {
+        auto_ascent(&content_centers, glyph_height)
+    } else {
+        config.ascent
+    };
+
+    // emoji with an ascent of their own, which can't be more than the provider's height
+    let mut glyph_ascents = BTreeMap::new();
+    for (name, override_ascent) in &config.ascent_overrides {
+        let Some(codepoint) = names.get(name).copied() else {
+            continue;
+        };
+        if *override_ascent > 8 {
+            warn!(
+                kind = "invalid_ascent",
+                "ascent of {override_ascent} for \"{name}\" is over the glyph height of 8 (using 8)"
+            );
+        }
+        let override_ascent = (*override_ascent).min(8);
+        if override_ascent != ascent {
+            glyph_ascents.insert(codepoint, override_ascent);
+        }
+    }
+
+    // Minecraft's glyph UVs can't address single pixels of a huge texture precisely
+    let atlas_size = atlas.width().max(atlas.height());
+    if atlas_size > config.uv_precision_limit {
+        warn!(
+            kind = "atlas_size",
+            "atlas is {}x{}, over {}px, so Minecraft may sample glyphs imprecisely and bleed neighbouring ones into \
+             them; consider splitting the emoji across several packs or using a smaller glyph size",
+            atlas.width(),
+            atlas.height(),
+            config.uv_precision_limit
+        );
+    }
+
+    // providers can't cross a plane, so say why there are more of them
+    let planes = chars
+        .iter()
+        .chain(aliases.keys())
+        .filter(|c| **c != '\0')
+        .map(|c| plane(*c))
+        .collect::<BTreeSet<_>>();
+    if planes.len() > 1 {
+        info!(
+            "codepoints span {} Unicode planes, so each is given providers of its own",
+            planes.len()
+        );
+    }
+
+    source_names.retain(|name, _| names.contains_key(name));
+
+    timings.allocate = start.elapsed();
+
+    Ok(Pack {
+        atlas,
+        chars,
+        names,
+        padding_glyphs,
+        aliases,
+        ascent,
+        glyph_ascents,
+        source_names,
+        stats,
+        columns,
+        atlas_file: format!(
+            "fedimoji:{}/emoji.png",
+            config.atlas_subpath.as_deref().unwrap_or("font")
+        ),
+        png_compression: config.png_compression,
+        tile_size: config.tile_size,
+        cells,
+        timings,
+    })
+}
+
+/// A perceptual hash of a glyph, for finding near-identical ones.
+struct GlyphHash {
+    /// Difference hash of the glyph's brightness and alpha: whether each pixel of a 9x8 thumbnail is darker (or more
+    /// transparent) than the one to its right.
+    bits: u128,
+
+    /// Mean color, as the difference hash says nothing about color (all flat glyphs have the same hash).
+    mean: [u8; 4],
+}
+
+impl GlyphHash {
+    fn new(image: &DynamicImage) -> Self {
+        let thumbnail = image
+            .resize_exact(9, 8, image::imageops::FilterType::Triangle)
+            .to_rgba8();
+        let luma = |pixel: &image::Rgba<u8>| {
+            let [r, g, b, a] = pixel.0.map(u32::from);
+            (r * 299 + g * 587 + b * 114) * a
+        };
+
+        let mut bits = 0u128;
+        for y in 0..8 {
+            for x in 0..8 {
+                let (left, right) = (thumbnail.get_pixel(x, y), thumbnail.get_pixel(x + 1, y));
+                bits = bits << 2
+                    | u128::from(luma(left) < luma(right)) << 1
+                    | u128::from(left[3] < right[3]);
+            }
+        }
+
+        let mean = image.resize_exact(1, 1, image::imageops::FilterType::Triangle);
+        Self {
+            bits,
+            mean: mean.to_rgba8().get_pixel(0, 0).0,
+        }
+    }
+
+    /// Whether two glyphs look alike: their hashes differ by at most `threshold` bits, and their mean colors are
+    /// close.
+    fn is_similar(&self, other: &GlyphHash, threshold: u32) -> bool {
+        (self.bits ^ other.bits).count_ones() <= threshold
+            && self
+                .mean
+                .iter()
+                .zip(other.mean)
+                .all(|(a, b)| a.abs_diff(b) <= 8)
+    }
+}
+
+/// Logs a codepoint allocation decision, at info level if it was asked to be explained.
+fn explain(config: &PackConfig, message: String) {
+    if config.explain {
+        info!("{message}");
+    } else {
+        debug!("{message}");
+    }
+}
+
+/// The number of glyphs in each row which makes the most square atlas: the one with the shortest longest side, and
+/// of those, the least area.
+fn squarest_columns(
+    num_glyphs: u32,
+    glyph_width: u32,
+    glyph_height: u32,
+    pad_to_pow2: bool,
+) -> u32 {
+    (1..=num_glyphs.max(1))
+        .min_by_key(|&columns| {
+            let mut rows = num_glyphs.div_ceil(columns);
+            if pad_to_pow2 {
+                rows = rows.next_power_of_two();
+            }
+            let width = u64::from(columns) * u64::from(glyph_width);
+            let height = u64::from(rows) * u64::from(glyph_height);
+            (width.max(height), width * height)
+        })
+        .unwrap_or(1)
+}
+
+/// The leftmost and rightmost columns of an image with any opaque pixels, if it has any.
+pub(crate) fn content_columns(image: &DynamicImage) -> Option<(u32, u32)> {
+    let opaque = |x: &u32| (0..image.height()).any(|y| image.get_pixel(*x, y)[3] > 0);
+    let left = (0..image.width()).find(opaque)?;
+    let right = (0..image.width()).rev().find(opaque)?;
+    Some((left, right))
+}
+
+/// The topmost and bottommost rows of an image with any opaque pixels, if it has any.
+pub(crate) fn content_rows(image: &DynamicImage) -> Option<(u32, u32)> {
+    let opaque = |y: &u32| (0..image.width()).any(|x| image.get_pixel(x, *y)[3] > 0);
+    let top = (0..image.height()).find(opaque)?;
+    let bottom = (0..image.height()).rev().find(opaque)?;
+    Some((top, bottom))
+}
+
+/// Works out an ascent which puts the average vertical center of the glyphs' content level with the middle of
+/// Minecraft's text, which rises 7 of the 8 units above the baseline.
+///
+/// The ascent can't be more than the provider's height, so content low in its cell can't be raised past sitting on
+/// the baseline.
+fn auto_ascent(content_centers: &[f32], glyph_height: u32) -> i32 {
+    if content_centers.is_empty() {
+        return 8;
+    }
+    let center = content_centers.iter().sum::<f32>() / content_centers.len() as f32;
+    let center = center * 8.0 / glyph_height as f32;
+    let ascent = ((center + 3.5).round() as i32).clamp(0, 8);
+    debug!("content is centered {center:.2}/8 down the glyphs, for an ascent of {ascent}");
+    ascent
+}
+
+/// Whether fitting an image into an area (keeping its aspect ratio) scales it by a whole factor, or one over a whole
+/// factor.
+fn is_integer_scale(width: u32, height: u32, area_width: u32, area_height: u32) -> bool {
+    // the scale is the smaller of area_width / width and area_height / height
+    let (to, from) =
+        if u64::from(area_width) * u64::from(height) <= u64::from(area_height) * u64::from(width) {
+            (area_width, width)
+        } else {
+            (area_height, height)
+        };
+    from != 0 && to != 0 && (to % from == 0 || from % to == 0)
+}
+
+/// Resizes an image to fit within an area (keeping its aspect ratio), blending its colors in linear light.
+///
+/// Resizing the sRGB values directly averages them in a space where the midpoint is too dark, which darkens the
+/// edges of shrunk high-contrast details.
+fn resize_linear(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    filter: image::imageops::FilterType,
+) -> DynamicImage {
+    let mut linear = image.to_rgba32f();
+    for pixel in linear.pixels_mut() {
+        for channel in &mut pixel.0[..3] {
+            *channel = if *channel <= 0.04045 {
+                *channel / 12.92
+            } else {
+                ((*channel + 0.055) / 1.055).powf(2.4)
+            };
+        }
+    }
+
+    let mut resized = DynamicImage::ImageRgba32F(linear)
+        .resize(width, height, filter)
+        .into_rgba32f();
+    for pixel in resized.pixels_mut() {
+        for channel in &mut pixel.0 {
+            *channel = channel.clamp(0.0, 1.0);
+        }
+        for channel in &mut pixel.0[..3] {
+            *channel = if *channel <= 0.0031308 {
+                *channel * 12.92
+            } else {
+                1.055 * channel.powf(1.0 / 2.4) - 0.055
+            };
+        }
+    }
+    DynamicImage::ImageRgba32F(resized)
+}
+
+/// How a glyph is blank, if it is: fully transparent, or mostly so, with at most one pixel in 256 showing anything.
+///
+/// A glyph of a single opaque color isn't blank, as plain squares and bars are sometimes meant.
+fn blank_kind(image: &DynamicImage) -> Option<&'static str> {
+    let visible = image.pixels().filter(|(_, _, pixel)| pixel[3] != 0).count() as u64;
+    let area = u64::from(image.width()) * u64::from(image.height());
+    if visible == 0 {
+        Some("fully transparent")
+    } else if visible * 256 <= area {
+        Some("mostly transparent")
+    } else {
+        None
+    }
+}
+
+/// Mean alpha of an image across an area (which it should fit within), from 0 to 1.
+fn coverage(image: &DynamicImage, area: u32) -> f32 {
+    let alpha: f32 = image.to_rgba32f().pixels().map(|pixel| pixel[3]).sum();
+    alpha / area as f32
+}
+
+/// Makes a glyph white, keeping only its alpha, so Minecraft tints it with the text color.
+fn make_tintable(image: &mut DynamicImage) {
+    if let Some(image) = image.as_mut_rgba16() {
+        for pixel in image.pixels_mut() {
+            pixel.0[..3].fill(u16::MAX);
+        }
+    } else if let Some(image) = image.as_mut_rgba8() {
+        for pixel in image.pixels_mut() {
+            pixel.0[..3].fill(u8::MAX);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small config, so tests pack quickly.
+    fn config() -> PackConfig {
+        PackConfig {
+            glyph_width: 16,
+            glyph_height: 16,
+            ..PackConfig::default()
+        }
+    }
+
+    /// An opaque gradient emoji, different for each seed, so it's neither blank nor a duplicate of another.
+    fn emoji(seed: u8) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, y| {
+            image::Rgba([seed, x as u8 * 16, y as u8 * 16, 255])
+        }))
+    }
+
+    /// Writes emoji images named after the given names to a new directory.
+    fn emoji_dir(names: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (i, name) in names.iter().enumerate() {
+            emoji(i as u8 * 8)
+                .save(dir.path().join(format!("{name}.png")))
+                .unwrap();
+        }
+        dir
+    }
+
+    fn generate(names: &[&str], config: &PackConfig) -> Result<Pack, PackError> {
+        let images = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.to_string(), emoji(i as u8 * 8)));
+        generate_pack_from_iter(images, config)
+    }
+
+    #[test]
+    fn name_prefix_and_suffix_apply_to_scanned_names() {
+        let dir = emoji_dir(&["blobcat", "Neofox"]);
+        let config = PackConfig {
+            name_prefix: "srv_".to_string(),
+            name_suffix: "_x".to_string(),
+            ..config()
+        };
+        let sources = scan::scan_emoji_dir(dir.path(), &config).unwrap();
+        let pack = generate_pack_from_iter(scan::read_emoji(&sources, &config), &config).unwrap();
+
+        let names = pack.names.keys().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(names, ["srv_blobcat_x", "srv_neofox_x"]);
+    }
+
+    #[test]
+    fn codepoints_are_allocated_from_the_private_use_plane() {
+        let pack = generate(&["a", "b", "c"], &config()).unwrap();
+        assert_eq!(
+            pack.names.values().copied().collect::<Vec<_>>(),
+            ['\u{F0000}', '\u{F0001}', '\u{F0002}']
+        );
+
+        // only the last two codepoints of the plane are left
+        let config = PackConfig {
+            codepoint_start_after: Some('\u{FFFFB}'),
+            ..config()
+        };
+        let pack = generate(&["a", "b"], &config).unwrap();
+        assert_eq!(
+            pack.names.values().copied().collect::<Vec<_>>(),
+            ['\u{FFFFC}', '\u{FFFFD}']
+        );
+        assert!(matches!(
+            generate(&["a", "b", "c"], &config),
+            Err(PackError::CodepointsExhausted {
+                needed: 3,
+                available: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn reserved_names_only_fail_when_strict() {
+        let pack = generate(&["bold", "blobcat"], &config()).unwrap();
+        assert!(pack.names.contains_key("bold"));
+
+        let config = PackConfig {
+            strict_names: true,
+            ..config()
+        };
+        assert!(matches!(
+            generate(&["bold", "blobcat", "§a"], &config),
+            Err(PackError::ReservedNames(names)) if names == ["bold", "§a"]
+        ));
+    }
+
+    #[test]
+    fn padding_leaves_a_transparent_border_in_each_cell() {
+        let config = PackConfig { pad: 3, ..config() };
+        let pack = generate(&["a"], &config).unwrap();
+        let alpha = |x, y| pack.atlas.get_pixel(x, y)[3];
+        for i in 0..16 {
+            for edge in [0, 2, 13, 15] {
+                assert_eq!(alpha(i, edge), 0);
+                assert_eq!(alpha(edge, i), 0);
+            }
+        }
+        assert_eq!(alpha(3, 3), 255);
+        assert_eq!(alpha(12, 12), 255);
+
+        let config = PackConfig { pad: 8, ..config };
+        assert!(matches!(
+            generate(&["a"], &config),
+            Err(PackError::InvalidPadding {
+                pad: 8,
+                glyph_size: 16
+            })
+        ));
+    }
+
+    #[test]
+    fn mappings_round_trip_and_keep_their_codepoints() {
+        let pack = generate(&["blobcat", "neofox"], &config()).unwrap();
+
+        // a mapping as fedimoji writes it, nested in a larger config file
+        let mut mapping = serde_json::to_value(&pack.names).unwrap();
+        mapping[META_KEY] = build_info();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        std::fs::write(
+            &path,
+            json!({ "servers": { "emoji": mapping } }).to_string(),
+        )
+        .unwrap();
+
+        let imported = read_mapping_file(&dir.path().join("config.json#/servers/emoji")).unwrap();
+        assert_eq!(imported, pack.names.clone().into_iter().collect());
+        assert!(matches!(
+            read_mapping_file(&dir.path().join("config.json#/nothing")),
+            Err(PackError::InvalidPointer { .. })
+        ));
+
+        // a new emoji sorting first doesn't move the imported ones
+        let config = PackConfig {
+            existing_mappings: imported,
+            ..config()
+        };
+        let repacked = generate(&["aaa", "blobcat", "neofox"], &config).unwrap();
+        assert_eq!(repacked.names["blobcat"], pack.names["blobcat"]);
+        assert_eq!(repacked.names["neofox"], pack.names["neofox"]);
+        assert_eq!(repacked.names["aaa"], '\u{F0002}');
+    }
+
+    #[test]
+    fn glyph_cells_can_be_rectangular() {
+        let config = PackConfig {
+            glyph_width: 24,
+            glyph_height: 12,
+            atlas_columns: 2,
+            ..config()
+        };
+        let pack = generate(&["a", "b", "c"], &config).unwrap();
+        assert_eq!(pack.atlas.dimensions(), (48, 24));
+        assert_eq!(pack.cells[&pack.names["c"]], (0, 12));
+
+        // the square emoji are scaled to fit the height, leaving the right of the cell empty
+        assert_eq!(pack.atlas.get_pixel(11, 6)[3], 255);
+        assert_eq!(pack.atlas.get_pixel(12, 6)[3], 0);
+    }
+
+    #[test]
+    fn tintable_glyphs_are_white_with_their_alpha() {
+        let config = PackConfig {
+            tintable: true,
+            pad: 2,
+            ..config()
+        };
+        let pack = generate(&["a"], &config).unwrap();
+        let atlas = pack.atlas.to_rgba8();
+        assert_eq!(atlas.get_pixel(8, 8).0, [255, 255, 255, 255]);
+        assert_eq!(atlas.get_pixel(0, 0)[3], 0);
+        assert!(atlas
+            .pixels()
+            .all(|pixel| pixel[3] == 0 || pixel.0[..3] == [255; 3]));
+    }
+
+    #[test]
+    fn atlas_byte_limit_is_checked_before_allocating() {
+        // two 16x16 RGBA glyphs take 2048 bytes
+        let config = PackConfig {
+            atlas_byte_limit: 2048,
+            ..config()
+        };
+        assert!(generate(&["a", "b"], &config).is_ok());
+
+        let config = PackConfig {
+            atlas_byte_limit: 2047,
+            ..config
+        };
+        assert!(matches!(
+            generate(&["a", "b"], &config),
+            Err(PackError::AtlasTooLarge {
+                bytes: 2048,
+                limit: 2047
+            })
+        ));
+    }
+
+    #[test]
+    fn inverse_names_map_codepoints_back_to_names() {
+        let mut pack = generate(&["a", "b"], &config()).unwrap();
+        let inverse = pack.inverse_names().unwrap();
+        assert_eq!(inverse[&'\u{F0000}'], "a");
+        assert_eq!(inverse[&'\u{F0001}'], "b");
+
+        pack.names.insert("c".to_string(), '\u{F0000}');
+        assert!(matches!(
+            pack.inverse_names(),
+            Err(PackError::CodepointConflict { codepoint: '\u{F0000}', names }) if names == ["a", "c"]
+        ));
+    }
+
+    #[test]
+    fn only_new_packs_just_the_new_emoji_but_maps_them_all() {
+        let config = PackConfig {
+            existing_mappings: HashMap::from([("a".to_string(), '\u{F0000}')]),
+            only_new: true,
+            ..config()
+        };
+        let pack = generate(&["a", "b"], &config).unwrap();
+        assert_eq!(pack.chars, ['\u{F0001}']);
+        assert_eq!(pack.names["a"], '\u{F0000}');
+        assert_eq!(pack.names["b"], '\u{F0001}');
+    }
+
+    #[test]
+    fn no_upscale_only_shrinks_images() {
+        let config = PackConfig {
+            no_upscale: true,
+            ..config()
+        };
+        let small =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, image::Rgba([255, 0, 0, 255])));
+        let large =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(32, 32, image::Rgba([0, 0, 255, 255])));
+        let images = [("small".to_string(), small), ("large".to_string(), large)];
+        let pack = generate_pack_from_iter(images.into_iter(), &config).unwrap();
+
+        // the small one is centred at its native size, and the large one fills its cell
+        let alpha = |x, y| pack.atlas.get_pixel(x, y)[3];
+        assert_eq!(
+            (alpha(3, 3), alpha(4, 4), alpha(11, 11), alpha(12, 12)),
+            (0, 255, 255, 0)
+        );
+        assert_eq!((alpha(0, 16), alpha(15, 31)), (255, 255));
+    }
+
+    #[test]
+    fn sixteen_bit_atlases_keep_the_precision() {
+        // a shade between two 8-bit levels
+        let image = DynamicImage::ImageRgba16(image::ImageBuffer::from_fn(16, 16, |x, _| {
+            image::Rgba([0x1234 + x as u16, 0, 0, u16::MAX])
+        }));
+        let images = || std::iter::once(("a".to_string(), image.clone()));
+
+        let config = PackConfig {
+            atlas_bit_depth: BitDepth::Sixteen,
+            ..config()
+        };
+        let pack = generate_pack_from_iter(images(), &config).unwrap();
+        assert_eq!(pack.atlas.as_rgba16().unwrap().get_pixel(0, 0)[0], 0x1234);
+
+        let pack = generate_pack_from_iter(images(), &self::config()).unwrap();
+        assert_eq!(pack.atlas.as_rgba8().unwrap().get_pixel(0, 0)[0], 0x12);
+    }
+
+    #[test]
+    fn fixing_names_makes_them_valid_shortcodes() {
+        let config = PackConfig {
+            validate_names: true,
+            shortcode_max_length: 8,
+            ..config()
+        };
+        assert_eq!(config.validate_name("neo-fox!".to_string()), "neo-fox!");
+
+        let config = PackConfig {
+            fix_names: true,
+            ..config
+        };
+        assert_eq!(config.validate_name("blob_cat".to_string()), "blob_cat");
+        assert_eq!(config.validate_name("neo-fox!".to_string()), "neo_fox_");
+        assert_eq!(
+            config.validate_name("blobcat_happy".to_string()),
+            "blobcat_"
+        );
+    }
+
+    #[test]
+    fn padding_to_a_power_of_two_adds_blank_rows() {
+        let config = PackConfig {
+            pad_to_pow2: true,
+            ..config()
+        };
+        let pack = generate(&["a", "b", "c"], &config).unwrap();
+        assert_eq!(pack.atlas.dimensions(), (16, 64));
+        assert_eq!(pack.padding_glyphs, 1);
+        assert_eq!(
+            pack.providers()[0]["chars"],
+            json!(["\u{F0000}", "\u{F0001}", "\u{F0002}", "\0"])
+        );
+    }
+
+    #[test]
+    fn coverage_is_the_mean_alpha_across_the_cell() {
+        let dot = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, y| {
+            image::Rgba([0, 0, 0, if (x, y) == (0, 0) { 255 } else { 0 }])
+        }));
+        assert_eq!(coverage(&dot, 16), 1.0 / 16.0);
+
+        // an image smaller than the cell counts the rest of the cell as transparent
+        let half =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 2, image::Rgba([0, 0, 0, 255])));
+        assert_eq!(coverage(&half, 16), 0.5);
+    }
+
+    #[test]
+    fn old_minecraft_versions_get_rows_of_sixteen() {
+        assert_eq!(atlas_columns_for_mc_version("1.19.4").unwrap(), 16);
+        assert_eq!(atlas_columns_for_mc_version("1.20").unwrap(), 1);
+        assert_eq!(atlas_columns_for_mc_version("1.21.1").unwrap(), 1);
+        assert!(atlas_columns_for_mc_version("1.x").is_err());
+
+        let config = PackConfig {
+            atlas_columns: atlas_columns_for_mc_version("1.19").unwrap(),
+            ..config()
+        };
+        let pack = generate(&["a", "b", "c"], &config).unwrap();
+        assert_eq!(pack.atlas.dimensions(), (256, 16));
+        let chars = &pack.providers()[0]["chars"];
+        assert_eq!(chars.as_array().unwrap().len(), 1);
+        assert_eq!(chars[0].as_str().unwrap().chars().count(), 16);
+    }
+
+    #[test]
+    fn shortened_names_get_suffixes_when_they_collide() {
+        let config = PackConfig {
+            max_name_length: Some(6),
+            ..config()
+        };
+        let mut names = [
+            "blobcat_happy",
+            "blobcat_sad",
+            "blobca",
+            "neofox",
+            "blobcat_angry",
+        ]
+        .map(String::from);
+        config.shorten_names(names.iter_mut()).unwrap();
+        assert_eq!(
+            names,
+            ["blob_1", "blob_2", "blobca", "neofox", "blob_3"].map(String::from)
+        );
+    }
+
+    #[test]
+    fn variable_advance_moves_content_to_the_left_of_the_cell() {
+        // content only in the right half
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, y| {
+            image::Rgba([x as u8 * 16, y as u8 * 16, 0, if x >= 8 { 255 } else { 0 }])
+        }));
+        let images = || std::iter::once(("a".to_string(), image.clone()));
+
+        let pack = generate_pack_from_iter(images(), &config()).unwrap();
+        assert_eq!(pack.atlas.get_pixel(0, 0)[3], 0);
+        assert_eq!(pack.atlas.get_pixel(15, 0)[3], 255);
+
+        let config = PackConfig {
+            variable_advance: true,
+            ..config()
+        };
+        let pack = generate_pack_from_iter(images(), &config).unwrap();
+        assert_eq!(pack.atlas.get_pixel(0, 0)[3], 255);
+        assert_eq!(pack.atlas.get_pixel(7, 0)[3], 255);
+        assert_eq!(pack.atlas.get_pixel(8, 0)[3], 0);
+    }
+
+    #[test]
+    fn planned_codepoints_are_kept_free_for_their_emoji() {
+        let config = PackConfig {
+            planned_mappings: HashMap::from([
+                ("b".to_string(), '\u{F0000}'),
+                ("later".to_string(), '\u{F0001}'),
+                ("c".to_string(), '\u{F0005}'),
+            ]),
+            existing_mappings: HashMap::from([("c".to_string(), '\u{F0010}')]),
+            ..config()
+        };
+        let pack = generate(&["a", "b", "c"], &config).unwrap();
+        assert_eq!(pack.names["a"], '\u{F0002}');
+        assert_eq!(pack.names["b"], '\u{F0000}');
+        assert_eq!(pack.names["c"], '\u{F0010}');
+    }
+
+    #[test]
+    fn overlay_is_blended_onto_the_corner_of_each_glyph() {
+        let badge = DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            4,
+            4,
+            image::Rgba([255, 255, 255, 255]),
+        ));
+        let config = PackConfig {
+            overlay: Some(badge),
+            ..config()
+        };
+        let pack = generate(&["a", "b"], &config).unwrap();
+        let atlas = pack.atlas.to_rgba8();
+        for cell_y in [0, 16] {
+            assert_eq!(atlas.get_pixel(12, cell_y + 12).0, [255; 4]);
+            assert_eq!(atlas.get_pixel(15, cell_y + 15).0, [255; 4]);
+            assert_ne!(atlas.get_pixel(11, cell_y + 11).0, [255; 4]);
+        }
+
+        let config = PackConfig {
+            overlay_position: Corner::TopLeft,
+            ..config
+        };
+        let pack = generate(&["a"], &config).unwrap();
+        assert_eq!(pack.atlas.to_rgba8().get_pixel(0, 0).0, [255; 4]);
+    }
+
+    #[test]
+    fn drop_shadow_grows_the_cells_and_falls_below_the_glyph() {
+        let drop_shadow: DropShadow = "2,0,#00000080".parse().unwrap();
+        assert_eq!(
+            drop_shadow,
+            DropShadow {
+                offset: 2,
+                blur: 0.0,
+                color: [0, 0, 0, 128]
+            }
+        );
+        assert!("2,-1,#000000".parse::<DropShadow>().is_err());
+
+        let config = PackConfig {
+            drop_shadow: Some(drop_shadow),
+            ..config()
+        };
+        let pack = generate(&["a"], &config).unwrap();
+        let atlas = pack.atlas.to_rgba8();
+        assert_eq!(atlas.dimensions(), (18, 18));
+        assert_eq!(atlas.get_pixel(17, 17).0, [0, 0, 0, 128]);
+        assert_eq!(atlas.get_pixel(17, 0)[3], 0);
+        assert_eq!(atlas.get_pixel(15, 15)[3], 255);
+    }
+
+    #[test]
+    fn codepoint_filter_drops_imported_emoji_outside_it() {
+        let config = PackConfig {
+            existing_mappings: HashMap::from([
+                ("inside".to_string(), '\u{F0001}'),
+                ("outside".to_string(), '\u{F0000}'),
+            ]),
+            codepoint_filter: Some('\u{F0001}'..='\u{F00FF}'),
+            ..config()
+        };
+        let pack = generate(&["inside", "outside", "new"], &config).unwrap();
+        assert_eq!(pack.names.len(), 2);
+        assert_eq!(pack.names["inside"], '\u{F0001}');
+        // the dropped emoji's codepoint isn't given to another
+        assert_eq!(pack.names["new"], '\u{F0002}');
+    }
+
+    #[test]
+    fn safe_codepoints_leave_out_bidi_combining_and_normalized_characters() {
+        assert!(is_safe_codepoint('\u{F0000}'));
+        assert!(is_safe_codepoint('a'));
+        // right-to-left, an override, a combining accent, and the ohm sign, which NFC turns into omega
+        for unsafe_codepoint in ['\u{05D0}', '\u{202E}', '\u{0301}', '\u{2126}'] {
+            assert!(!is_safe_codepoint(unsafe_codepoint), "{unsafe_codepoint:?}");
+        }
+
+        let config = PackConfig {
+            safe_codepoints: true,
+            ..config()
+        };
+        let pack = generate(&["a", "b"], &config).unwrap();
+        assert!(pack.names.values().all(|c| is_safe_codepoint(*c)));
+    }
+
+    #[test]
+    fn outline_is_traced_around_the_glyph() {
+        let outline: Outline = "2,#ffffff".parse().unwrap();
+        assert_eq!(
+            outline,
+            Outline {
+                width: 2,
+                color: [255, 255, 255, 255]
+            }
+        );
+
+        // a dot in the middle of the image
+        let dot = DynamicImage::ImageRgba8(RgbaImage::from_fn(16, 16, |x, y| {
+            let inside = (6..10).contains(&x) && (6..10).contains(&y);
+            image::Rgba([255, 0, 0, if inside { 255 } else { 0 }])
+        }));
+        let config = PackConfig {
+            outline: Some(outline),
+            ..config()
+        };
+        let pack =
+            generate_pack_from_iter(std::iter::once(("dot".to_string(), dot)), &config).unwrap();
+        let atlas = pack.atlas.to_rgba8();
+        assert_eq!(atlas.dimensions(), (20, 20));
+        // the dot is moved in by the outline's width, and surrounded by it
+        assert_eq!(atlas.get_pixel(9, 9).0, [255, 0, 0, 255]);
+        assert_eq!(atlas.get_pixel(6, 9).0, [255, 255, 255, 255]);
+        assert_eq!(atlas.get_pixel(13, 9).0, [255, 255, 255, 255]);
+        assert_eq!(atlas.get_pixel(4, 9)[3], 0);
+    }
+
+    #[test]
+    fn mapping_files_can_be_selected_from_by_pointer() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.json");
+        std::fs::write(
+            &config,
+            r#"{"packs": [{"emoji/v2": {"Blobcat": 983040, "neofox": "U+F0001"}}]}"#,
+        )
+        .unwrap();
+        let mappings =
+            read_mapping_file(&dir.path().join("config.json#/packs/0/emoji~1v2")).unwrap();
+        assert_eq!(
+            mappings,
+            HashMap::from([
+                ("blobcat".to_string(), '\u{F0000}'),
+                ("neofox".to_string(), '\u{F0001}')
+            ])
+        );
+
+        // a `#` in the name of a file which exists isn't a pointer
+        let hashed = dir.path().join("map#1.json");
+        std::fs::write(&hashed, r#"{"a": "U+F0002"}"#).unwrap();
+        assert_eq!(read_mapping_file(&hashed).unwrap()["a"], '\u{F0002}');
+    }
+
+    #[test]
+    fn color_levels_round_channels_and_dithering_mixes_them() {
+        let grey = DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            8,
+            8,
+            image::Rgba([100, 100, 100, 200]),
+        ));
+        let levels = ColorLevels(2);
+
+        let banded = levels
+            .apply(&grey, Dither::None, BitDepth::Eight)
+            .to_rgba8();
+        assert!(banded.pixels().all(|pixel| pixel.0 == [0, 0, 0, 200]));
+
+        let dithered = levels
+            .apply(&grey, Dither::FloydSteinberg, BitDepth::Eight)
+            .to_rgba8();
+        assert!(dithered
+            .pixels()
+            .all(|pixel| matches!(pixel.0[0], 0 | 255) && pixel.0[3] == 200));
+        let light = dithered.pixels().filter(|pixel| pixel.0[0] == 255).count();
+        // about 100/255 of the pixels are light, so the average stays close to the original grey
+        assert!((20..30).contains(&light), "{light} light pixels");
+    }
+
+    #[test]
+    fn no_resize_emoji_are_placed_untouched_whatever_their_case() {
+        let pixel_art = RgbaImage::from_fn(8, 8, |x, y| {
+            image::Rgba([x as u8 * 32, y as u8 * 32, 0, 255])
+        });
+        let config = PackConfig {
+            preserve_case: true,
+            no_resize: HashSet::from([name_key("Pixel")]),
+            ..config()
+        };
+        let images = [
+            (
+                "Pixel".to_string(),
+                DynamicImage::ImageRgba8(pixel_art.clone()),
+            ),
+            (
+                "Smooth".to_string(),
+                DynamicImage::ImageRgba8(pixel_art.clone()),
+            ),
+        ];
+        let pack = generate_pack_from_iter(images.into_iter(), &config).unwrap();
+        let atlas = pack.atlas.to_rgba8();
+
+        // centered in its cell at its native size
+        let (x, y) = pack.cells[&pack.names["Pixel"]];
+        let placed =
+            image::imageops::crop_imm(&atlas, x + config.pad + 4, y + config.pad + 4, 8, 8)
+                .to_image();
+        assert_eq!(placed, pixel_art);
+
+        // the other is scaled up to fill its cell
+        let (x, y) = pack.cells[&pack.names["Smooth"]];
+        assert_ne!(atlas.get_pixel(x + config.pad, y + config.pad).0[3], 0);
+    }
+
+    #[test]
+    fn positional_codepoints_follow_the_sorted_names() {
+        let config = PackConfig {
+            positional_codepoints: true,
+            codepoint_start_after: Some('\u{F00FF}'),
+            existing_mappings: HashMap::from([("a".to_string(), '\u{F0042}')]),
+            ..config()
+        };
+        let pack = generate(&["c", "a", "b"], &config).unwrap();
+        assert_eq!(
+            pack.names.into_iter().collect::<Vec<_>>(),
+            [
+                ("a".to_string(), '\u{F0100}'),
+                ("b".to_string(), '\u{F0101}'),
+                ("c".to_string(), '\u{F0102}'),
+            ]
+        );
+    }
+
+    #[test]
+    fn integer_scale_only_skips_images_scaled_by_a_fraction() {
+        let config = PackConfig {
+            glyph_width: 64,
+            glyph_height: 64,
+            integer_scale_only: true,
+            ..PackConfig::default()
+        };
+        let image = |size| {
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+                size,
+                size,
+                image::Rgba([200, 0, 0, 255]),
+            ))
+        };
+        let images = [
+            ("half".to_string(), image(32)),
+            ("three_quarters".to_string(), image(48)),
+            ("double".to_string(), image(128)),
+        ];
+        let pack = generate_pack_from_iter(images.into_iter(), &config).unwrap();
+        assert_eq!(
+            pack.names.keys().map(String::as_str).collect::<Vec<_>>(),
+            ["double", "half"]
+        );
+        assert!(!is_integer_scale(48, 48, 64, 64));
+        assert!(is_integer_scale(16, 8, 64, 64));
+    }
+
+    #[test]
+    fn texture_memory_is_four_bytes_per_pixel_at_any_bit_depth() {
+        let pack = generate(&["a", "b", "c", "d"], &config()).unwrap();
+        let (width, height) = pack.atlas.dimensions();
+        assert_eq!((width, height), (16, 64));
+        assert_eq!(pack.texture_memory(), 4096);
+
+        let config = PackConfig {
+            atlas_bit_depth: BitDepth::Sixteen,
+            ..config()
+        };
+        let deep = generate(&["a", "b", "c", "d"], &config).unwrap();
+        assert_eq!(deep.atlas.dimensions(), (width, height));
+        assert_eq!(deep.texture_memory(), pack.texture_memory());
+    }
+
+    #[test]
+    fn near_identical_glyphs_share_a_cell_under_a_loose_threshold() {
+        // the same emoji with a few pixels of compression artifacts
+        let original = emoji(0);
+        let mut artifacts = original.to_rgba8();
+        for (x, y) in [(5, 5), (6, 5), (5, 6), (6, 6)] {
+            artifacts.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+        }
+        let artifacts = DynamicImage::ImageRgba8(artifacts);
+        let distance =
+            (GlyphHash::new(&original).bits ^ GlyphHash::new(&artifacts).bits).count_ones();
+        assert!(
+            (1..=8).contains(&distance),
+            "hashes differ by {distance} bits"
+        );
+
+        let generate = |threshold| {
+            let config = PackConfig {
+                similarity_threshold: Some(threshold),
+                ..config()
+            };
+            let images = [
+                ("a".to_string(), original.clone()),
+                ("b".to_string(), artifacts.clone()),
+            ];
+            generate_pack_from_iter(images.into_iter(), &config).unwrap()
+        };
+
+        let loose = generate(8);
+        assert_eq!(loose.names.len(), 2);
+        assert_eq!(loose.cells.len(), 1);
+        assert_eq!(
+            loose.aliases,
+            BTreeMap::from([(loose.names["b"], loose.names["a"])])
+        );
+
+        let strict = generate(0);
+        assert_eq!(strict.cells.len(), 2);
+        assert!(strict.aliases.is_empty());
+    }
+
+    #[test]
+    fn auto_ascent_follows_where_the_content_is() {
+        // emoji whose content is in the top or bottom six rows, with the rest transparent
+        let emoji = |seed: u8, rows: std::ops::Range<u32>| {
+            let mut image = emoji(seed).to_rgba8();
+            for (_, y, pixel) in image.enumerate_pixels_mut() {
+                if !rows.contains(&y) {
+                    *pixel = image::Rgba([0, 0, 0, 0]);
+                }
+            }
+            DynamicImage::ImageRgba8(image)
+        };
+        let auto_ascent = PackConfig {
+            auto_ascent: true,
+            ..config()
+        };
+        let ascent = |rows: std::ops::Range<u32>| {
+            let images = (0..3).map(|i| (format!("e{i}"), emoji(i * 8, rows.clone())));
+            generate_pack_from_iter(images, &auto_ascent)
+                .unwrap()
+                .ascent
+        };
+
+        let top_heavy = ascent(0..6);
+        let bottom_heavy = ascent(10..16);
+        assert_eq!(top_heavy, 5);
+        assert_eq!(bottom_heavy, 8);
+        assert_eq!(
+            generate(
+                &["a"],
+                &PackConfig {
+                    ascent: 3,
+                    ..config()
+                }
+            )
+            .unwrap()
+            .ascent,
+            3
+        );
+    }
+
+    #[test]
+    fn trim_atlas_crops_padded_rows_but_no_glyphs() {
+        let names = ["a", "b", "c", "d", "e"];
+        let padded = PackConfig {
+            pad_to_pow2: true,
+            atlas_columns: 2,
+            ..config()
+        };
+        let pack = generate(&names, &padded).unwrap();
+        assert_eq!(pack.atlas.dimensions(), (32, 64));
+
+        let trimmed = PackConfig {
+            trim_atlas: true,
+            ..padded
+        };
+        let pack = generate(&names, &trimmed).unwrap();
+        // three rows hold the five glyphs, with one padding glyph after them
+        assert_eq!(pack.atlas.dimensions(), (32, 48));
+        assert_eq!(pack.padding_glyphs, 1);
+        assert_eq!(pack.providers()[0]["chars"].as_array().unwrap().len(), 3);
+        for (x, y) in pack.cells.values() {
+            assert!(x + 16 <= 32 && y + 16 <= 48);
+        }
+    }
+
+    #[test]
+    fn gutter_leaves_transparent_space_between_glyphs() {
+        let config = PackConfig {
+            gutter: 2,
+            atlas_columns: 2,
+            ..config()
+        };
+        let pack = generate(&["a", "b"], &config).unwrap();
+        let atlas = pack.atlas.to_rgba8();
+        assert_eq!(atlas.dimensions(), (36, 18));
+        assert_eq!(
+            pack.cells.values().copied().collect::<Vec<_>>(),
+            [(0, 0), (18, 0)]
+        );
+
+        for (x, y, pixel) in atlas.enumerate_pixels() {
+            let in_gutter = x % 18 >= 16 || y >= 16;
+            assert_eq!(pixel[3] == 0, in_gutter, "pixel ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn custom_allocator_chooses_new_codepoints() {
+        // codepoints from the names' lengths, which collide for names of the same length
+        let by_length = |name: &str| char::from_u32(0xF1000 + name.len() as u32);
+        let config = PackConfig {
+            allocator: Some(Arc::new(by_length)),
+            existing_mappings: HashMap::from([("imported".to_string(), '\u{F0042}')]),
+            ..config()
+        };
+        let pack = generate(&["a", "bb", "ccc", "dd", "imported"], &config).unwrap();
+        assert_eq!(
+            pack.names.into_iter().collect::<Vec<_>>(),
+            [
+                ("a".to_string(), '\u{F1001}'),
+                ("bb".to_string(), '\u{F1002}'),
+                ("ccc".to_string(), '\u{F1003}'),
+                // its codepoint was taken, so it gets the next free one
+                ("dd".to_string(), '\u{F0000}'),
+                ("imported".to_string(), '\u{F0042}'),
+            ]
+        );
+    }
+
+    #[test]
+    fn transparency_fill_replaces_hidden_colors() {
+        // opaque red on the left, with green hidden under zero alpha on the right
+        let glyph = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, _| {
+            if x < 2 {
+                image::Rgba([255, 0, 0, 255])
+            } else {
+                image::Rgba([0, 255, 0, 0])
+            }
+        }));
+        let fill = |fill: TransparencyFill| fill.apply(&glyph, BitDepth::Eight).to_rgba8();
+
+        let zeroed = fill(TransparencyFill::Zero);
+        for (x, _, pixel) in zeroed.enumerate_pixels() {
+            let expected = if x < 2 {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 0, 0]
+            };
+            assert_eq!(pixel.0, expected);
+        }
+
+        let bled = fill(TransparencyFill::Bleed);
+        for (x, _, pixel) in bled.enumerate_pixels() {
+            let expected = if x < 2 {
+                [255, 0, 0, 255]
+            } else {
+                [255, 0, 0, 0]
+            };
+            assert_eq!(pixel.0, expected);
+        }
+    }
+
+    #[test]
+    fn glyphs_avoid_the_reserved_regions_of_the_template() {
+        let blue = image::Rgba([0, 0, 255, 255]);
+        let config = PackConfig {
+            atlas_template: Some(DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+                32, 16, blue,
+            ))),
+            reserved_regions: vec![Region {
+                x: 0,
+                y: 0,
+                width: 32,
+                height: 16,
+            }],
+            ..config()
+        };
+        let pack = generate(&["a", "b", "c"], &config).unwrap();
+
+        assert_eq!(pack.columns, 2);
+        assert_eq!(pack.atlas.dimensions(), (32, 48));
+        let mut cells = pack.cells.values().copied().collect::<Vec<_>>();
+        cells.sort();
+        assert_eq!(cells, [(0, 16), (0, 32), (16, 16)]);
+        let atlas = pack.atlas.to_rgba8();
+        for (x, y, pixel) in atlas.enumerate_pixels() {
+            if y < 16 {
+                assert_eq!(*pixel, blue, "pixel ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn preserved_case_is_kept_in_the_map_but_matched_without_it() {
+        let config = PackConfig {
+            preserve_case: true,
+            existing_mappings: HashMap::from([("catjam".to_string(), '\u{F0042}')]),
+            ..config()
+        };
+        let dir = emoji_dir(&["CatJam", "NeoFox"]);
+        let sources = scan::scan_emoji_dir(dir.path(), &config).unwrap();
+        let pack = generate_pack_from_iter(scan::read_emoji(&sources, &config), &config).unwrap();
+        assert_eq!(
+            pack.names.into_iter().collect::<Vec<_>>(),
+            [
+                ("CatJam".to_string(), '\u{F0042}'),
+                ("NeoFox".to_string(), '\u{F0000}'),
+            ]
+        );
+
+        // names differing only in case still collide
+        let dir = emoji_dir(&["CatJam", "catjam"]);
+        let sources = scan::scan_emoji_dir(dir.path(), &config).unwrap();
+        assert_eq!(sources.len(), 1);
+        assert!(sources[0].name.eq_ignore_ascii_case("catjam"));
+    }
+
+    #[test]
+    fn listed_emoji_lead_in_the_given_order() {
+        let config = PackConfig {
+            // spelled with a combining accent and in another case, which still matches the packed names
+            order: vec![
+                "Cafe\u{301}".to_string(),
+                "b".to_string(),
+                "missing".to_string(),
+            ],
+            positional_codepoints: true,
+            ..config()
+        };
+        let pack = generate(&["a", "b", "c", "caf\u{e9}"], &config).unwrap();
+        let mut by_codepoint = pack
+            .names
+            .iter()
+            .map(|(name, c)| (*c, name.as_str()))
+            .collect::<Vec<_>>();
+        by_codepoint.sort();
+        assert_eq!(
+            by_codepoint,
+            [
+                ('\u{F0000}', "caf\u{e9}"),
+                ('\u{F0001}', "b"),
+                ('\u{F0002}', "a"),
+                ('\u{F0003}', "c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn provider_rows_all_have_the_same_length() {
+        // five glyphs in rows of three, split across ascents and planes, which leaves the last row short
+        let names = ["a", "b", "c", "d", "e"];
+        let config = PackConfig {
+            atlas_columns: 3,
+            ascent_overrides: HashMap::from([("b".to_string(), 3)]),
+            existing_mappings: HashMap::from([("e".to_string(), '\u{100000}')]),
+            ..config()
+        };
+        let tiled = PackConfig {
+            tile_size: Some(32),
+            ..config.clone()
+        };
+        for config in [config, tiled] {
+            let pack = generate(&names, &config).unwrap();
+            let providers = pack.providers();
+            assert!(providers.len() > 2);
+            for provider in providers {
+                let rows = provider["chars"].as_array().unwrap();
+                let lengths = rows
+                    .iter()
+                    .map(|row| row.as_str().unwrap().chars().count())
+                    .collect::<HashSet<_>>();
+                assert_eq!(lengths.len(), 1, "ragged rows in {provider}");
+            }
+        }
+    }
+
+    #[test]
+    fn color_transform_shifts_colors_but_not_alpha() {
+        let glyph =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, image::Rgba([220, 40, 40, 128])));
+        let transform = |hue_shift, saturation, brightness| {
+            let transform = ColorTransform {
+                hue_shift,
+                saturation,
+                brightness,
+            };
+            transform
+                .apply(&glyph, BitDepth::Eight)
+                .to_rgba8()
+                .get_pixel(0, 0)
+                .0
+        };
+
+        let [r, g, b, a] = transform(0.0, 0.0, 1.0);
+        assert!(
+            r.abs_diff(g) <= 1 && g.abs_diff(b) <= 1,
+            "{r}, {g}, {b} isn't gray"
+        );
+        assert_eq!(a, 128);
+
+        // red rotated a third of the way round is green
+        assert_eq!(transform(120.0, 1.0, 1.0), [40, 220, 40, 128]);
+        assert_eq!(transform(0.0, 1.0, 0.5), [110, 20, 20, 128]);
+    }
+
+    /// Counts the warnings logged while running something, by kind.
+    pub(crate) fn count_warnings(run: impl FnOnce()) -> BTreeMap<String, usize> {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let counter = warnings::WarningCounter::default();
+        tracing::subscriber::with_default(
+            tracing_subscriber::registry().with(counter.clone()),
+            run,
+        );
+        counter.counts()
+    }
+
+    #[test]
+    fn atlases_past_the_uv_precision_limit_are_warned_about() {
+        let names = ["a", "b", "c"];
+        for (limit, warned) in [(32, true), (48, false)] {
+            let config = PackConfig {
+                uv_precision_limit: limit,
+                ..config()
+            };
+            let warnings = count_warnings(|| {
+                assert_eq!(generate(&names, &config).unwrap().atlas.height(), 48);
+            });
+            assert_eq!(warnings.contains_key("atlas_size"), warned, "limit {limit}");
+        }
+    }
+
+    #[test]
+    fn groups_match_names_by_name_key() {
+        let config = PackConfig {
+            // the metadata's names, composed and lowercased, for emoji packed under decomposed, capitalized ones
+            groups: HashMap::from([
+                ("café".to_string(), "drinks".to_string()),
+                ("zeta".to_string(), "drinks".to_string()),
+            ]),
+            ..config()
+        };
+        let pack = generate(&["alpha", "Zeta", "Cafe\u{301}"], &config).unwrap();
+        let order = pack
+            .names
+            .iter()
+            .map(|(name, codepoint)| (*codepoint, name.as_str()))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            order.into_values().collect::<Vec<_>>(),
+            ["Cafe\u{301}", "Zeta", "alpha"]
+        );
+    }
+
+    #[test]
+    fn mipmap_bleed_spreads_edge_colors_without_alpha() {
+        // red on the left half, transparent on the right
+        let image = RgbaImage::from_fn(16, 16, |x, _| {
+            if x < 8 {
+                image::Rgba([255, 0, 0, 255])
+            } else {
+                image::Rgba([0, 0, 0, 0])
+            }
+        });
+        let atlas = |mipmap_bleed| {
+            let config = PackConfig {
+                mipmap_bleed,
+                ..config()
+            };
+            let images = [("half".to_string(), DynamicImage::ImageRgba8(image.clone()))];
+            generate_pack_from_iter(images.into_iter(), &config)
+                .unwrap()
+                .atlas
+                .to_rgba8()
+        };
+
+        let plain = atlas(0);
+        let bled = atlas(2);
+        assert_eq!(plain.get_pixel(9, 4).0, [0, 0, 0, 0]);
+        for (x, y, pixel) in bled.enumerate_pixels() {
+            assert_eq!(pixel[3], plain.get_pixel(x, y)[3], "alpha at ({x}, {y})");
+            let expected = match x {
+                0..10 => [255, 0, 0],
+                _ => [0, 0, 0],
+            };
+            assert_eq!(pixel.0[..3], expected, "color at ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn linear_resizing_keeps_fine_detail_as_bright_as_it_looks() {
+        // a checkerboard of black and white pixels, which looks mid-gray: about 188 in sRGB, not 128
+        let checkerboard = image::RgbaImage::from_fn(32, 32, |x, y| {
+            let value = if (x + y) % 2 == 0 { 255 } else { 0 };
+            image::Rgba([value, value, value, 255])
+        });
+        let center_gray = |linear_resize| {
+            let config = PackConfig {
+                linear_resize,
+                ..config()
+            };
+            let images = [(
+                "checks".to_string(),
+                DynamicImage::ImageRgba8(checkerboard.clone()),
+            )];
+            let atlas = generate_pack_from_iter(images.into_iter(), &config)
+                .unwrap()
+                .atlas
+                .to_rgba8();
+            atlas.get_pixel(8, 8)[0]
+        };
+
+        let naive = center_gray(false);
+        let linear = center_gray(true);
+        assert!(naive.abs_diff(128) <= 4, "{naive}");
+        assert!(linear.abs_diff(188) <= 4, "{linear}");
+    }
+
+    #[test]
+    fn auto_atlas_lays_out_a_near_square_grid() {
+        let config = PackConfig {
+            auto_atlas: true,
+            ..config()
+        };
+        let names = (0..8).map(|i| format!("e{i}")).collect::<Vec<_>>();
+        let pack = generate(
+            &names.iter().map(String::as_str).collect::<Vec<_>>(),
+            &config,
+        )
+        .unwrap();
+        // 8 glyphs fit in 3x3 at 48x48, rather than a row or column of them
+        assert_eq!(pack.columns, 3);
+        assert_eq!((pack.atlas.width(), pack.atlas.height()), (48, 48));
+
+        assert_eq!(squarest_columns(16, 16, 16, false), 4);
+        // wide glyphs make for fewer columns
+        assert_eq!(squarest_columns(16, 32, 8, false), 2);
+        assert_eq!(squarest_columns(0, 16, 16, false), 1);
+    }
+
+    #[test]
+    fn providers_are_split_at_plane_boundaries() {
+        // imported at the end of plane 15 and the start of plane 16, with the new emoji at the start of plane 15
+        let config = PackConfig {
+            existing_mappings: HashMap::from([
+                ("a".to_string(), '\u{FFFFD}'),
+                ("b".to_string(), '\u{100000}'),
+            ]),
+            atlas_columns: 4,
+            ..config()
+        };
+        let pack = generate(&["a", "b", "c", "d"], &config).unwrap();
+        let planes = pack
+            .names
+            .values()
+            .map(|c| plane(*c))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(planes, BTreeSet::from([15, 16]));
+
+        let providers = pack.providers();
+        assert!(providers.len() >= 2);
+        for provider in providers {
+            let planes = provider["chars"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .flat_map(|row| row.as_str().unwrap().chars())
+                .filter(|c| *c != '\0')
+                .map(plane)
+                .collect::<BTreeSet<_>>();
+            assert_eq!(planes.len(), 1, "{provider} crosses a plane");
+        }
+    }
+
+    #[test]
+    fn glyph_processors_run_in_order() {
+        let invert = |_: &str, mut glyph: RgbaImage| {
+            image::imageops::invert(&mut glyph);
+            glyph
+        };
+        let mark = |_: &str, mut glyph: RgbaImage| {
+            glyph.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+            glyph
+        };
+        let config = PackConfig {
+            glyph_processors: vec![Arc::new(invert), Arc::new(mark)],
+            ..config()
+        };
+        let pack = generate(&["a"], &config).unwrap();
+        let (x, y) = pack.cells[&pack.names["a"]];
+        let atlas = pack.atlas.to_rgba8();
+
+        // inverted, then marked, so the mark isn't inverted
+        assert_eq!(*atlas.get_pixel(x, y), image::Rgba([255, 0, 0, 255]));
+        let original = emoji(0).to_rgba8();
+        let [r, g, b, a] = original.get_pixel(5, 7).0;
+        assert_eq!(
+            *atlas.get_pixel(x + 5, y + 7),
+            image::Rgba([255 - r, 255 - g, 255 - b, a])
+        );
+    }
+
+    #[test]
+    fn transparent_glyphs_are_blank_but_solid_ones_are_not() {
+        let transparent = RgbaImage::new(16, 16);
+        let mut speck = RgbaImage::new(16, 16);
+        speck.put_pixel(8, 8, image::Rgba([255, 255, 255, 255]));
+        let solid = RgbaImage::from_pixel(16, 16, image::Rgba([255, 0, 0, 255]));
+        let images = [
+            ("transparent", transparent),
+            ("speck", speck),
+            ("solid", solid),
+            ("gradient", emoji(0).into_rgba8()),
+        ]
+        .map(|(name, image)| (name.to_string(), DynamicImage::ImageRgba8(image)));
+
+        for skip_blank in [false, true] {
+            let config = PackConfig {
+                skip_blank,
+                ..config()
+            };
+            let mut pack = None;
+            let warnings = count_warnings(|| {
+                pack = Some(generate_pack_from_iter(images.clone().into_iter(), &config).unwrap());
+            });
+            assert_eq!(warnings.get("blank"), Some(&2));
+            let names = pack.unwrap().names.into_keys().collect::<Vec<_>>();
+            if skip_blank {
+                assert_eq!(names, ["gradient", "solid"]);
+            } else {
+                assert_eq!(names, ["gradient", "solid", "speck", "transparent"]);
+            }
+        }
+    }
+
+    #[test]
+    fn block_aligned_groups_start_on_multiples_of_16() {
+        let groups = HashMap::from(
+            [
+                ("a1", "a"),
+                ("a2", "a"),
+                ("a3", "a"),
+                ("b1", "b"),
+                ("b2", "b"),
+            ]
+            .map(|(name, group)| (name.to_string(), group.to_string())),
+        );
+        let names = ["a1", "a2", "a3", "b1", "b2"];
+        let codepoints = |allocation_strategy| {
+            let config = PackConfig {
+                groups: groups.clone(),
+                allocation_strategy,
+                ..config()
+            };
+            let pack = generate(&names, &config).unwrap();
+            names.map(|name| pack.names[name] as u32)
+        };
+
+        assert_eq!(
+            codepoints(AllocationStrategy::Dense),
+            [0xF0000, 0xF0001, 0xF0002, 0xF0003, 0xF0004]
+        );
+        assert_eq!(
+            codepoints(AllocationStrategy::BlockAligned),
+            [0xF0000, 0xF0001, 0xF0002, 0xF0010, 0xF0011]
+        );
+    }
+
+    #[test]
+    fn credits_glyphs_are_packed_at_the_end_of_the_plane() {
+        let config = PackConfig {
+            // 7 characters, two to a glyph
+            credits_text: Some("by seer".to_string()),
+            ..config()
+        };
+        let pack = generate(&["blobcat"], &config).unwrap();
+
+        let names = pack
+            .names
+            .iter()
+            .map(|(name, codepoint)| (name.as_str(), *codepoint as u32))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                ("_credits_0", 0xFFFFA),
+                ("_credits_1", 0xFFFFB),
+                ("_credits_2", 0xFFFFC),
+                ("_credits_3", 0xFFFFD),
+                ("blobcat", 0xF0000),
+            ]
+        );
+
+        // "b" is drawn in white at the top left of the first credits glyph, scaled up to fill the cell
+        let (x, y) = pack.cells[&'\u{FFFFA}'];
+        let atlas = pack.atlas.to_rgba8();
+        assert_eq!(
+            *atlas.get_pixel(x, y + 2),
+            image::Rgba([255, 255, 255, 255])
+        );
+        assert_eq!(atlas.get_pixel(x, y)[3], 0);
+    }
+
+    #[test]
+    fn reassigned_codepoints_fail_when_asked() {
+        // positional codepoints take precedence over the imported ones, forcing "b" off its codepoint
+        let config = PackConfig {
+            existing_mappings: HashMap::from([
+                ("a".to_string(), '\u{F0000}'),
+                ("b".to_string(), '\u{F0005}'),
+            ]),
+            positional_codepoints: true,
+            ..config()
+        };
+        let pack = generate(&["a", "b"], &config).unwrap();
+        assert_eq!(pack.names["b"], '\u{F0001}');
+
+        let config = PackConfig {
+            fail_on_reassignment: true,
+            ..config
+        };
+        match generate(&["a", "b"], &config) {
+            Err(PackError::CodepointsReassigned(names)) => {
+                assert_eq!(names, ["b (U+F0005 -> U+F0001)"]);
+            }
+            other => panic!("expected the reassignment to fail, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn best_png_compression_is_no_bigger_than_fast() {
+        let names = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        let encoded_size = |png_compression| {
+            let config = PackConfig {
+                png_compression,
+                ..config()
+            };
+            let pack = generate(&names, &config).unwrap();
+            let png = pack.encode_atlas().unwrap();
+            assert_eq!(image::load_from_memory(&png).unwrap(), pack.atlas);
+            png.len()
+        };
+        assert!(encoded_size(CompressionType::Best) <= encoded_size(CompressionType::Fast));
+    }
+
+    #[test]
+    fn blocklisted_codepoints_are_never_allocated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocklist.txt");
+        std::fs::write(
+            &path,
+            "# boxes in some clients\nU+F0001\n\nU+F0003-U+F0004\n",
+        )
+        .unwrap();
+        let blocked_codepoints = read_codepoint_blocklist(&path).unwrap();
+        assert_eq!(
+            blocked_codepoints,
+            HashSet::from(['\u{F0001}', '\u{F0003}', '\u{F0004}'])
+        );
+
+        let config = PackConfig {
+            blocked_codepoints,
+            ..config()
+        };
+        let pack = generate(&["a", "b", "c", "d"], &config).unwrap();
+        assert_eq!(
+            pack.names.values().copied().collect::<Vec<_>>(),
+            ['\u{F0000}', '\u{F0002}', '\u{F0005}', '\u{F0006}']
+        );
+    }
+
+    #[test]
+    fn wide_banners_are_sliced_into_consecutive_glyphs() {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let banner = RgbaImage::from_fn(192, 64, |x, _| image::Rgba(colors[x as usize / 64]));
+        let images = || {
+            [
+                (
+                    "banner".to_string(),
+                    DynamicImage::ImageRgba8(banner.clone()),
+                ),
+                ("blobcat".to_string(), emoji(0)),
+            ]
+            .into_iter()
+        };
+
+        let config = PackConfig {
+            slice_wide: Some(2.0),
+            ..config()
+        };
+        let pack = generate_pack_from_iter(images(), &config).unwrap();
+        assert_eq!(
+            pack.names
+                .iter()
+                .map(|(name, c)| (name.as_str(), *c))
+                .collect::<Vec<_>>(),
+            [
+                ("banner_0", '\u{F0000}'),
+                ("banner_1", '\u{F0001}'),
+                ("banner_2", '\u{F0002}'),
+                ("blobcat", '\u{F0003}'),
+            ]
+        );
+        let atlas = pack.atlas.to_rgba8();
+        for (index, color) in colors.iter().enumerate() {
+            let (x, y) = pack.cells[&pack.names[&format!("banner_{index}")]];
+            assert_eq!(atlas.get_pixel(x + 8, y + 8).0, *color, "banner_{index}");
+        }
+
+        // a banner not wider than the threshold stays whole
+        let config = PackConfig {
+            slice_wide: Some(3.0),
+            ..config
+        };
+        let pack = generate_pack_from_iter(images(), &config).unwrap();
+        assert_eq!(pack.names.keys().collect::<Vec<_>>(), ["banner", "blobcat"]);
+    }
+
+    #[test]
+    fn only_tiles_with_glyphs_are_kept() {
+        let config = PackConfig {
+            atlas_columns: 2,
+            tile_size: Some(16),
+            ..config()
+        };
+        // a 2x2 grid of 16px cells, with the bottom right one blank
+        let pack = generate(&["a", "b", "c"], &config).unwrap();
+        assert_eq!(pack.atlas.dimensions(), (32, 32));
+
+        let tiles = pack.tiles();
+        let files = tiles.iter().map(AtlasTile::file_name).collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                "emoji_tile_0_0.png",
+                "emoji_tile_0_1.png",
+                "emoji_tile_1_0.png"
+            ]
+        );
+        let referenced = pack
+            .providers()
+            .iter()
+            .map(|provider| provider["file"].as_str().unwrap().to_string())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            referenced,
+            tiles
+                .iter()
+                .map(|tile| tile.file.clone())
+                .collect::<BTreeSet<_>>()
+        );
+    }
+
+    #[test]
+    fn providers_are_plain_bitmaps_with_even_rows() {
+        // aliases, ascent overrides, two planes and tiles each split the glyphs over more providers
+        let config = PackConfig {
+            existing_mappings: HashMap::from([("e".to_string(), '\u{100000}')]),
+            similarity_threshold: Some(0),
+            ascent_overrides: HashMap::from([("b".to_string(), 5)]),
+            atlas_columns: 3,
+            tile_size: Some(32),
+            ..config()
+        };
+        // "d" is a copy of "a", and the rest look nothing alike
+        let images = [
+            ("a", emoji(0)),
+            ("b", emoji(240).fliph()),
+            ("c", emoji(120).flipv()),
+            ("d", emoji(0)),
+            ("e", emoji(180).rotate90()),
+        ]
+        .map(|(name, image)| (name.to_string(), image));
+        let pack = generate_pack_from_iter(images.into_iter(), &config).unwrap();
+        assert_eq!(pack.aliases.len(), 1);
+
+        let providers = pack.providers();
+        assert!(providers.len() > 3, "{} providers", providers.len());
+        let mut provided = BTreeSet::new();
+        for provider in providers {
+            let keys = provider.as_object().unwrap().keys().collect::<Vec<_>>();
+            assert_eq!(keys, ["ascent", "chars", "file", "height", "type"]);
+            assert_eq!(provider["type"], "bitmap");
+
+            let rows = provider["chars"].as_array().unwrap();
+            let widths = rows
+                .iter()
+                .map(|row| row.as_str().unwrap().chars().count())
+                .collect::<BTreeSet<_>>();
+            assert_eq!(widths.len(), 1, "{provider} has rows of different lengths");
+            assert!(!widths.contains(&0));
+            for c in rows.iter().flat_map(|row| row.as_str().unwrap().chars()) {
+                assert!(
+                    c == '\0' || provided.insert(c),
+                    "U+{:04X} is provided twice",
+                    c as u32
+                );
+            }
+        }
+        assert_eq!(
+            provided,
+            pack.names.values().copied().collect::<BTreeSet<_>>()
+        );
+    }
+
+    #[test]
+    fn huge_reserved_regions_are_handled_without_overflow() {
+        // reaching past the edge of every coordinate only reserves the top row
+        let reaching = PackConfig {
+            reserved_regions: vec!["1,0,4294967295,1".parse().unwrap()],
+            ..config()
+        };
+        let pack = generate(&["a", "b"], &reaching).unwrap();
+        let mut cells = pack.cells.values().copied().collect::<Vec<_>>();
+        cells.sort();
+        assert_eq!(cells, [(0, 16), (0, 32)]);
+
+        // covering every cell leaves no room at all
+        let covering = PackConfig {
+            reserved_regions: vec!["0,0,64,4294967295".parse().unwrap()],
+            ..config()
+        };
+        assert!(matches!(
+            generate(&["a", "b"], &covering),
+            Err(PackError::ReservedRegionsFull { needed: 2 })
+        ));
+    }
+
+    #[test]
+    fn shortened_names_never_exceed_the_limit() {
+        let config = PackConfig {
+            max_name_length: Some(3),
+            ..config()
+        };
+        let mut names = (0..9).map(|i| format!("blob{i}")).collect::<Vec<_>>();
+        config.shorten_names(names.iter_mut()).unwrap();
+        assert_eq!(names[..3], ["blo", "b_1", "b_2"]);
+        assert!(names.iter().all(|name| name.chars().count() <= 3));
+
+        // a tenth collision needs a suffix which leaves no room for the name
+        let mut names = (0..11).map(|i| format!("blob{i}")).collect::<Vec<_>>();
+        assert!(matches!(
+            config.shorten_names(names.iter_mut()),
+            Err(PackError::NameLengthTooShort { max_length: 3, .. })
+        ));
+
+        // and names can't be shortened to nothing
+        let config = PackConfig {
+            max_name_length: Some(0),
+            ..config
+        };
+        let mut names = ["blobcat".to_string()];
+        assert!(config.shorten_names(names.iter_mut()).is_err());
+    }
+
+    #[test]
+    fn margins_too_large_for_the_cells_are_refused() {
+        let gutter = PackConfig {
+            gutter: u32::MAX,
+            ..config()
+        };
+        assert!(matches!(
+            generate(&["a"], &gutter),
+            Err(PackError::InvalidMargin { what: "gutter", .. })
+        ));
+
+        let shadow = PackConfig {
+            drop_shadow: Some("4294967295,1,#000000".parse().unwrap()),
+            ..config()
+        };
+        assert!(matches!(
+            generate(&["a"], &shadow),
+            Err(PackError::InvalidMargin {
+                what: "drop shadow",
+                ..
+            })
+        ));
+
+        // cells which fit in a u32 but not in the atlas fail before any glyph is drawn
+        let huge = PackConfig {
+            gutter: 4_000_000_000,
+            ..config()
+        };
+        assert!(matches!(
+            generate(&["a"], &huge),
+            Err(PackError::AtlasTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn block_alignment_gives_way_on_a_nearly_full_plane() {
+        // 15 codepoints are left, U+FFFEF to U+FFFFD, so aligning to U+FFFF0 leaves room for only 14
+        let first_codepoint = |count: usize| {
+            let names = (0..count).map(|i| format!("e{i:02}")).collect::<Vec<_>>();
+            let config = PackConfig {
+                groups: names
+                    .iter()
+                    .map(|name| (name.clone(), "group".to_string()))
+                    .collect(),
+                allocation_strategy: AllocationStrategy::BlockAligned,
+                codepoint_start_after: Some('\u{FFFEE}'),
+                ..config()
+            };
+            let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+            let pack = generate(&names, &config).unwrap();
+            assert_eq!(pack.names.len(), count);
+            pack.names["e00"] as u32
+        };
+        assert_eq!(first_codepoint(14), 0xFFFF0);
+        assert_eq!(first_codepoint(15), 0xFFFEF);
+    }
+}
@@ -1,229 +1,2400 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io::Cursor,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
 
 use clap::Parser;
-use image::GenericImage;
+use fedimoji::{
+    compare::{compare_atlases, diff_atlases},
+    json,
+    output::{self, PackOutput},
+    platform::Platform,
+    scan,
+    timing::{self, Timings},
+    warnings::WarningCounter,
+    AllocationStrategy, BitDepth, CodepointFormat, ColorTransform, Corner, Dither, DropShadow,
+    MergeConflicts, Outline, Pack, PackConfig, PackError, Region, TransparencyFill,
+};
+use image::ImageFormat;
 use serde_json::json;
 use tracing::{debug, error, info, warn};
-use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::{layer::SubscriberExt, FmtSubscriber};
 
-const GLYPH_SIZE: u32 = 64;
+/// Counts the memory allocated, for --profile-memory.
+#[cfg(feature = "profile-memory")]
+#[global_allocator]
+static ALLOCATOR: fedimoji::memory::PeakAlloc = fedimoji::memory::PeakAlloc::new();
 
-fn main() {
+fn main() -> ExitCode {
     let args = Args::parse();
 
-    // setup tracing
+    // setup tracing, logging to stderr so stdout is left for command output
     let tracing_subscriber = FmtSubscriber::builder()
+        .with_writer(std::io::stderr)
         .with_max_level({
             if args.verbose {
                 tracing::Level::DEBUG
             } else {
                 tracing::Level::INFO
             }
-        })
-        .finish();
+        });
+
+    // count the warnings as they're logged, to summarize them at the end
+    let warnings = WarningCounter::default();
+    match args.log_format {
+        LogFormat::Text => tracing::subscriber::set_global_default(
+            tracing_subscriber.finish().with(warnings.clone()),
+        ),
+        LogFormat::Json => tracing::subscriber::set_global_default(
+            tracing_subscriber.json().finish().with(warnings.clone()),
+        ),
+    }
+    .expect("setting default subscriber failed");
+
+    // don't leave half-written files or a held lock behind if the run is interrupted
+    if let Err(err) = ctrlc::set_handler(|| {
+        output::remove_pending_files();
+        error!("interrupted");
+        std::process::exit(130);
+    }) {
+        warn!("failed to set up the interrupt handler: {err}");
+    }
+
+    // run a subcommand instead of generating a pack, if one was given
+    let result = match &args.command {
+        Some(Command::Compare {
+            expected,
+            actual,
+            tolerance,
+        }) => {
+            return if compare_atlases(expected, actual, *tolerance) {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            };
+        }
+        Some(Command::DiffAtlas {
+            old,
+            new,
+            out,
+            tolerance,
+        }) => {
+            return if diff_atlases(old, new, out, *tolerance) {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            };
+        }
+        Some(Command::List) => list(&args),
+        Some(Command::Count) => count(&args),
+        Some(Command::Codepoints { map }) => list_codepoints(map),
+        Some(Command::GenTestPack { count, out, noise }) => {
+            gen_test_pack(&args, *count, out, *noise)
+        }
+        Some(Command::Repack { atlas, provider }) => repack(&args, atlas, provider),
+        None => generate(&args),
+    };
+    report_warnings(&args, &warnings);
+
+    #[cfg(feature = "profile-memory")]
+    if args.profile_memory {
+        info!(
+            peak_bytes = ALLOCATOR.peak(),
+            "peak memory use: {}",
+            format_bytes(ALLOCATOR.peak() as u64)
+        );
+    }
 
-    tracing::subscriber::set_global_default(tracing_subscriber)
-        .expect("setting default subscriber failed");
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{err}");
+            ExitCode::from(exit_code(&err))
+        }
+    }
+}
 
-    // ensure we can read the emoji directory
-    let emoji_dir = args.emoji_dir;
-    if !emoji_dir.is_dir() {
-        error!("emoji directory {} does not exist", emoji_dir.display());
-        return;
+/// Summarizes the warnings logged during the run, grouped by kind, so they aren't lost among the other logs.
+fn report_warnings(args: &Args, warnings: &WarningCounter) {
+    if args.warnings_as_json {
+        // stdout may be taken by command output or an archive
+        eprintln!("{}", warnings.as_json());
+    } else if let Some(summary) = warnings.summary() {
+        info!("warnings: {summary}");
     }
+}
+
+/// The process exit code for an error.
+fn exit_code(err: &PackError) -> u8 {
+    match err {
+        PackError::EmojiDirMissing(_)
+        | PackError::ImportMissing(_)
+        | PackError::ExportMissing(_)
+        | PackError::ListingMissing(_) => 2,
+        PackError::ImportParse(_)
+        | PackError::InvalidPointer { .. }
+        | PackError::AsepriteParse(_)
+        | PackError::FontRead { .. }
+        | PackError::ExportRead { .. }
+        | PackError::ExportManifestMissing { .. }
+        | PackError::ExportParse(_)
+        | PackError::ListingRead { .. }
+        | PackError::ImageRead { .. }
+        | PackError::BundleIndexMissing(_) => 3,
+        PackError::NoValidEmoji | PackError::EmojiNotFound(_) => 4,
+        PackError::CodepointsExhausted { .. }
+        | PackError::AtlasTooLarge { .. }
+        | PackError::OverBudget { .. }
+        | PackError::IndexTooLarge { .. }
+        | PackError::ReservedRegionsFull { .. }
+        | PackError::CodepointConflict { .. } => 5,
+        PackError::ReservedNames(_)
+        | PackError::UnexpectedNames { .. }
+        | PackError::StaleImports(_)
+        | PackError::MissingAttribution(_)
+        | PackError::RoundTripFailed(_)
+        | PackError::InvalidPins(_)
+        | PackError::NameLengthTooShort { .. }
+        | PackError::CodepointsReassigned(_)
+        | PackError::OversizedFiles(_)
+        | PackError::InvalidPadding { .. }
+        | PackError::InvalidMargin { .. }
+        | PackError::InvalidTileSize { .. }
+        | PackError::InvalidCodepoint(_)
+        | PackError::InvalidProvider(_)
+        | PackError::InvalidMcVersion(_)
+        | PackError::InvalidSourceDateEpoch(_)
+        | PackError::InvalidPattern(_)
+        | PackError::InvalidProfile(_)
+        | PackError::NotReproducible(_) => 6,
+        PackError::OutputNotDir(_)
+        | PackError::OutputLocked { .. }
+        | PackError::OutputInEmojiDir(_)
+        | PackError::ZipMissing(_)
+        | PackError::Zip { .. }
+        | PackError::Write { .. }
+        | PackError::Upload { .. }
+        | PackError::Io(_) => 7,
+        PackError::HookFailed(_) => 8,
+    }
+}
 
-    // load an existing mapping file to import, if desired
-    let mut existing_mappings: HashMap<String, char> = HashMap::new();
-    if let Some(mapping_path) = args.import {
-        if !mapping_path.is_file() {
-            error!(
-                "imported mapping file {} does not exist",
-                mapping_path.display()
+/// Prints the emoji which would be packed, without generating anything.
+fn list(args: &Args) -> Result<(), PackError> {
+    let config = args.pack_config()?;
+    let sources = args.scan_emoji(&config)?;
+    args.check_expected_names(sources.iter().map(|source| source.name.as_str()))?;
+    scan::check_stale_imports(
+        sources.iter().map(|source| source.name.as_str()),
+        &config.existing_mappings,
+        args.strict_import,
+    )?;
+
+    for source in sources {
+        if let Err(err) = scan::read_dimensions(&source.path) {
+            warn!(
+                kind = "decode_failure",
+                "failed to read \"{}\" (skipping it): {}",
+                source.path.display(),
+                err
             );
-            return;
+            continue;
         }
-        let contents = std::fs::read_to_string(mapping_path).unwrap();
-        let mapping: HashMap<String, char> = serde_json::from_str(&contents).unwrap();
-        for (name, codepoint) in mapping {
-            if !name.is_empty() {
-                existing_mappings.insert(name.to_lowercase(), codepoint);
-            }
+        if config.is_filtered_out(&source.name) {
+            continue;
         }
-        info!("imported {} existing mappings", existing_mappings.len());
+
+        let codepoint = match config
+            .imported_codepoint(&source.name)
+            .or_else(|| config.planned_codepoint(&source.name))
+        {
+            Some(codepoint) => format!("U+{:04X}", codepoint as u32),
+            None => "new".to_string(),
+        };
+        println!("{}\t{}\t{}", source.name, source.path.display(), codepoint);
     }
+    Ok(())
+}
 
-    // codepoints used in the existing mapping
-    let reserved_codepoints = existing_mappings.values().collect::<Vec<_>>();
+/// Repacks the glyphs of an existing atlas, and writes the new pack to the output directory.
+fn repack(args: &Args, atlas_path: &Path, provider_path: &Path) -> Result<(), PackError> {
+    let mut config = args.pack_config()?;
+    if args.import.is_empty() {
+        warn!("no mapping was imported with `--import`, so no glyphs can be named");
+    }
 
-    // figure out which codepoints we can allocate to emoji not in the existing mapping
-    let mut available_codepoints = (0xF0000..=0xFFFFD)
-        .filter_map(char::from_u32)
-        .filter(|c| !reserved_codepoints.contains(&c));
+    let atlas = scan::decode_image(atlas_path, &config).map_err(|source| PackError::ImageRead {
+        path: atlas_path.to_path_buf(),
+        source,
+    })?;
+    let font_provider: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(provider_path)?)
+            .map_err(|err| PackError::InvalidProvider(err.to_string()))?;
 
-    // get an iterator over all the PNG files in the emoji directory
-    let images = emoji_dir
-        .read_dir()
-        .expect("reading emoji directory failed")
-        .filter_map(|entry| entry.ok())
-        .filter_map(|entry| {
-            let path = entry.path();
-            if path.is_file() && path.extension() == Some("png".as_ref()) {
-                Some(path)
+    let start = Instant::now();
+    let sliced = fedimoji::repack::slice_atlas(&atlas, &font_provider, &config.existing_mappings)?;
+    let timings = Timings {
+        decode: start.elapsed(),
+        ..Timings::default()
+    };
+    // glyphs keep the ascents of their providers
+    config.ascent_overrides.extend(sliced.ascents);
+    let pack = fedimoji::generate_pack_from_iter(sliced.glyphs.into_iter(), &config)?;
+    write_pack(args, &pack, &[], timings)
+}
+
+/// Prints how many emoji would be packed from the emoji directory, for scripts.
+///
+/// The images aren't read, so ones which would fail to decode are still counted.
+fn count(args: &Args) -> Result<(), PackError> {
+    let config = args.pack_config()?;
+    let sources = args.scan_emoji(&config)?;
+    let count = sources
+        .iter()
+        .filter(|source| !config.is_filtered_out(&source.name))
+        .count();
+    println!("{count}");
+    Ok(())
+}
+
+/// Prints the codepoints used by a mapping, one per line in order, then the ranges of unused codepoints between the
+/// first and last.
+fn list_codepoints(map: &Path) -> Result<(), PackError> {
+    let codepoints = fedimoji::read_mapping_file(map)?
+        .into_values()
+        .map(u32::from)
+        .collect::<BTreeSet<_>>();
+    for codepoint in &codepoints {
+        println!("U+{codepoint:04X}");
+    }
+
+    let gaps = codepoints
+        .iter()
+        .zip(codepoints.iter().skip(1))
+        .filter(|(a, b)| *b - *a > 1)
+        .map(|(a, b)| (a + 1, b - 1))
+        .collect::<Vec<_>>();
+    if !gaps.is_empty() {
+        println!("\ngaps:");
+        for (first, last) in gaps {
+            if first == last {
+                println!("U+{first:04X}");
             } else {
-                None
+                println!("U+{first:04X}-U+{last:04X} ({})", last - first + 1);
             }
-        })
-        .filter_map(|path| {
-            path.file_name()
-                .map(|s| s.to_string_lossy().into_owned())
-                .map(|s| (path, s))
-        })
-        .map(|(path, name)| (path, name.to_lowercase()))
-        .filter_map(|(path, name)| {
-            // read the image
-            match image::open(&path) {
-                Err(err) => {
-                    warn!(
-                        "failed to read \"{}\" (skipping it): {}",
-                        path.display(),
-                        err
-                    );
-                    None
-                }
-                Ok(image) => Some((name, image)),
+        }
+    }
+    Ok(())
+}
+
+/// Writes synthetic emoji images at the glyph size into a directory, for benchmarking and testing without real art.
+///
+/// The images are the same every time: each is a distinct color, shaded darker towards the bottom, or with `noise`,
+/// pseudorandom pixels.
+fn gen_test_pack(args: &Args, count: u32, out: &Path, noise: bool) -> Result<(), PackError> {
+    let width = args.glyph_width.unwrap_or(args.glyph_size);
+    let height = args.glyph_height.unwrap_or(args.glyph_size);
+    std::fs::create_dir_all(out)?;
+
+    for index in 0..count {
+        // spread the colors out, so neighbouring emoji look different
+        let color = (index + 1).wrapping_mul(0x9E37_79B9).to_le_bytes();
+        let mut state = u64::from(index) + 1;
+        let image = image::RgbaImage::from_fn(width, height, |_, y| {
+            if !noise {
+                // shaded from the color at the top to half of it at the bottom, so it isn't a plain square
+                let shade =
+                    |channel: u8| (u32::from(channel) * (2 * height - y) / (2 * height)) as u8;
+                return image::Rgba([shade(color[0]), shade(color[1]), shade(color[2]), 255]);
             }
-        })
-        .map(|(name, image)| {
-            // resize it
-            let image = image.resize(
-                GLYPH_SIZE,
-                GLYPH_SIZE,
-                image::imageops::FilterType::Triangle,
-            );
-            debug!("resized \"{}\"", name);
-            (name, image)
-        })
-        .map(|(name, image)| {
-            // strip ".png" from the name
-            let new_name = name.trim_end_matches(".png").to_string();
-            (new_name, image)
-        })
-        .filter_map(|(name, image)| {
-            // if we have an existing mapping for this emoji, use that
-            if let Some(codepoint) = existing_mappings.get(&name) {
-                debug!(
-                    "using existing mapping for \"{name}\", U+{:04X}",
-                    *codepoint as u32
-                );
-                Some((name, *codepoint, image))
-            } else if let Some(codepoint) = available_codepoints.next() {
-                debug!(
-                    "using new mapping for \"{name}\", U+{:04X}",
-                    codepoint as u32
-                );
-                Some((name, codepoint, image))
-            } else {
-                // we ran out of codepoints
-                error!("no remaining codepoints! skipping \"{name}\"");
-                None
+            // xorshift, seeded by the index
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let [r, g, b, a, ..] = state.to_le_bytes();
+            image::Rgba([r, g, b, a])
+        });
+
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        write_output(
+            &out.join(format!("test_{index}.png")),
+            &png,
+            "synthetic emoji",
+        )?;
+    }
+    info!(
+        "wrote {count} synthetic {width}x{height} emoji to `{}`",
+        out.display()
+    );
+    Ok(())
+}
+
+/// Generates a pack and writes it to the output directory.
+fn generate(args: &Args) -> Result<(), PackError> {
+    let mut config = args.pack_config()?;
+
+    // only process one emoji for inspection, if desired
+    if let (Some(name), Some(preview_out)) = (&args.preview_single, &args.preview_out) {
+        return preview_single(&config, args.scan_emoji(&config)?, name, preview_out);
+    }
+
+    // download the emoji from URLs, if they were given
+    #[cfg(feature = "net")]
+    if let Some(url_list) = &args.urls {
+        let urls = fedimoji::net::read_url_list(url_list, &config)?;
+        args.check_expected_names(urls.iter().map(|url| url.name.as_str()))?;
+        let start = Instant::now();
+        let images = fedimoji::net::download_emoji(&urls, args.concurrency);
+        let timings = Timings {
+            decode: start.elapsed(),
+            ..Timings::default()
+        };
+        let pack = fedimoji::generate_pack_from_iter(images.into_iter(), &config)?;
+        return write_pack(args, &pack, &[], timings);
+    }
+
+    // slice the emoji out of an Aseprite export, if one was given
+    if let Some(aseprite_json) = &args.aseprite_json {
+        let start = Instant::now();
+        let images = fedimoji::aseprite::read_aseprite_export(aseprite_json, &config)?;
+        args.check_expected_names(images.iter().map(|(name, _)| name.as_str()))?;
+        let timings = Timings {
+            decode: start.elapsed(),
+            ..Timings::default()
+        };
+        let pack = fedimoji::generate_pack_from_iter(images.into_iter(), &config)?;
+        return write_pack(args, &pack, &[], timings);
+    }
+
+    // rasterize the emoji from a font's glyphs, if one was given
+    #[cfg(feature = "font")]
+    if let Some(font) = &args.from_font {
+        let start = Instant::now();
+        let names = args
+            .font_names
+            .as_deref()
+            .map(fedimoji::read_mapping_file)
+            .transpose()?;
+        let images = fedimoji::font::read_font_glyphs(font, names.as_ref(), &config)?;
+        args.check_expected_names(images.iter().map(|(name, _)| name.as_str()))?;
+        let timings = Timings {
+            decode: start.elapsed(),
+            ..Timings::default()
+        };
+        let pack = fedimoji::generate_pack_from_iter(images.into_iter(), &config)?;
+        return write_pack(args, &pack, &[], timings);
+    }
+
+    // read the emoji from a chat platform's export, if one was given
+    if let (Some(platform), Some(export)) = (args.import_platform, &args.platform_export) {
+        let start = Instant::now();
+        let export = fedimoji::platform::read_platform_export(export, platform, &config)?;
+        args.check_expected_names(export.emoji.iter().map(|(name, _)| name.as_str()))?;
+        config.kept_aliases.extend(export.aliases);
+        let timings = Timings {
+            decode: start.elapsed(),
+            ..Timings::default()
+        };
+        let pack = fedimoji::generate_pack_from_iter(export.emoji.into_iter(), &config)?;
+        return write_pack(args, &pack, &[], timings);
+    }
+
+    // make sure nothing is written where it would be read back as an emoji
+    let output_dirs = [
+        (args.update_zip.is_none() && args.output_dir.as_os_str() != "-")
+            .then_some(args.output_dir.as_path()),
+        args.emit_html.as_deref(),
+        args.emit_web.as_deref(),
+        args.emit_fedi_manifest
+            .as_deref()
+            .map(|path| match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            }),
+    ];
+    for output_dir in output_dirs.into_iter().flatten() {
+        for emoji_dir in &args.emoji_dir {
+            output::check_outside_emoji_dir(output_dir, emoji_dir)?;
+        }
+    }
+
+    // find and read all the emoji, then generate the pack from them
+    let start = Instant::now();
+    let mut sources = args.scan_emoji(&config)?;
+    let scan_time = start.elapsed();
+    if let Some(curation_path) = &args.curation {
+        #[cfg(feature = "tui")]
+        if args.curate && !curate(args, curation_path, &sources, &config)? {
+            return Ok(());
+        }
+        fedimoji::curate::read_curation(curation_path)?.apply(&mut sources, &mut config)?;
+    }
+    args.check_expected_names(sources.iter().map(|source| source.name.as_str()))?;
+    scan::check_stale_imports(
+        sources.iter().map(|source| source.name.as_str()),
+        &config.existing_mappings,
+        args.strict_import,
+    )?;
+    if let Some(max_file_size) = args.max_file_size {
+        scan::check_file_sizes(&sources, max_file_size, args.strict_file_size)?;
+    }
+    if args.emit_attribution.is_some() || args.require_attribution {
+        scan::check_attribution(&sources, args.require_attribution)?;
+    }
+    apply_emoji_metadata(&mut config, &sources);
+
+    // lay out the atlas without reading any images, if only the plan is wanted
+    if let Some(plan_path) = &args.plan_only {
+        return plan_layout(args, &sources, &config, plan_path);
+    }
+
+    // generate a pack for each profile from the same decoded emoji, if there are profiles
+    if let Some(profiles_path) = &args.profiles {
+        let start = Instant::now();
+        let images = scan::read_emoji(&sources, &config).collect::<Vec<_>>();
+        let timings = Timings {
+            scan: scan_time,
+            decode: start.elapsed(),
+            ..Timings::default()
+        };
+        return generate_profiles(args, profiles_path, &sources, &images, timings);
+    }
+
+    // try glyph sizes until the atlas fits the budget, if there is one, decoding the emoji only once
+    if let Some(target_bytes) = args.target_bytes {
+        let start = Instant::now();
+        let images = scan::read_emoji(&sources, &config).collect::<Vec<_>>();
+        let timings = Timings {
+            scan: scan_time,
+            decode: start.elapsed(),
+            ..Timings::default()
+        };
+        let pack = generate_within_budget(&images, &config, target_bytes)?;
+        return write_pack(args, &pack, &sources, timings);
+    }
+
+    let (images, decode_time) = timing::timed(scan::read_emoji(&sources, &config));
+    let pack = fedimoji::generate_pack_from_iter(images, &config)?;
+    let timings = Timings {
+        scan: scan_time,
+        decode: decode_time.get(),
+        ..Timings::default()
+    };
+    write_pack(args, &pack, &sources, timings)
+}
+
+/// Lets the user curate the emoji interactively, starting from the decisions in the curation file if it exists, and
+/// saves their decisions to it. Returns whether they were saved, rather than the curation being quit.
+#[cfg(feature = "tui")]
+fn curate(
+    args: &Args,
+    curation_path: &Path,
+    sources: &[scan::EmojiSource],
+    config: &PackConfig,
+) -> Result<bool, PackError> {
+    let curation = if curation_path.is_file() {
+        fedimoji::curate::read_curation(curation_path)?
+    } else {
+        fedimoji::curate::Curation::default()
+    };
+    let state = fedimoji::curate::CurationState::new(sources, config, curation);
+    let Some(curation) = fedimoji::tui::run(state, config)? else {
+        info!("quit curating, so no pack was generated");
+        return Ok(false);
+    };
+
+    write_output(
+        curation_path,
+        json::to_string_pretty(&curation, args.escape_chars)
+            .unwrap()
+            .as_bytes(),
+        "curation decisions",
+    )?;
+    Ok(true)
+}
+
+/// Works out the layout of the atlas (its size, and each glyph's codepoint and cell) without decoding or encoding any
+/// images, and writes it as JSON.
+///
+/// Each emoji stands in as a tiny placeholder, so anything which depends on what the images look like isn't planned:
+/// emoji which fail to decode are still laid out, and near-identical glyphs aren't shared.
+fn plan_layout(
+    args: &Args,
+    sources: &[scan::EmojiSource],
+    config: &PackConfig,
+    plan_path: &Path,
+) -> Result<(), PackError> {
+    // opaque, so the placeholders aren't taken for blank glyphs
+    let placeholder = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(2, 1, |x, _| {
+        image::Rgba([255, 255 * x as u8, 0, 255])
+    }));
+    let config = PackConfig {
+        similarity_threshold: None,
+        ..config.clone()
+    };
+    let pack = fedimoji::generate_pack_from_iter(
+        sources
+            .iter()
+            .map(|source| (source.name.clone(), placeholder.clone())),
+        &config,
+    )?;
+
+    let mut plan = output::glyph_rects(&pack);
+    plan["columns"] = json!(pack.columns);
+    write_output(
+        plan_path,
+        json::to_string_pretty(&plan, args.escape_chars)
+            .unwrap()
+            .as_bytes(),
+        "layout plan",
+    )?;
+    info!(
+        "planned a {}x{} atlas with {} glyphs",
+        pack.atlas.width(),
+        pack.atlas.height(),
+        pack.num_glyphs()
+    );
+    Ok(())
+}
+
+/// Generates the pack with the largest glyph size, up to the configured one, whose atlas PNG takes at most
+/// `target_bytes`, keeping the glyphs' aspect ratio.
+///
+/// The glyph size is found by a binary search, assuming smaller glyphs make a smaller atlas. The trial packs are
+/// generated without logging anything, so only the chosen pack's warnings are shown.
+fn generate_within_budget(
+    images: &[(String, image::DynamicImage)],
+    config: &PackConfig,
+    target_bytes: u64,
+) -> Result<Pack, PackError> {
+    let with_width = |width: u32| PackConfig {
+        glyph_width: width,
+        glyph_height: (u64::from(config.glyph_height) * u64::from(width)
+            / u64::from(config.glyph_width))
+        .max(1) as u32,
+        ..config.clone()
+    };
+
+    // the size of the atlas PNG with glyphs of a width, or `None` if they're too small or too many to make one
+    let png_size = |width: u32| {
+        let config = with_width(width);
+        tracing::subscriber::with_default(tracing::subscriber::NoSubscriber::default(), || {
+            match fedimoji::generate_pack_from_iter(images.iter().cloned(), &config) {
+                Ok(pack) => Ok(Some(pack.encode_atlas().unwrap().len() as u64)),
+                Err(PackError::InvalidPadding { .. } | PackError::AtlasTooLarge { .. }) => Ok(None),
+                Err(err) => Err(err),
             }
         })
-        .collect::<Vec<_>>();
+    };
 
-    if images.is_empty() {
-        error!("no valid emoji provided!");
-        return;
+    let mut best = None;
+    let (mut low, mut high) = (1, config.glyph_width);
+    while low <= high {
+        let width = low + (high - low) / 2;
+        match png_size(width)? {
+            Some(bytes) if bytes <= target_bytes => {
+                best = Some((width, bytes));
+                low = width + 1;
+            }
+            _ => high = width - 1,
+        }
     }
 
-    let num_glyphs: u32 = images.len() as u32;
+    let Some((width, bytes)) = best else {
+        return Err(PackError::OverBudget { target_bytes });
+    };
+    let config = with_width(width);
+    info!(
+        "using {}x{} glyphs, the largest whose atlas ({}) fits in {}",
+        config.glyph_width,
+        config.glyph_height,
+        format_bytes(bytes),
+        format_bytes(target_bytes)
+    );
+    fedimoji::generate_pack_from_iter(images.iter().cloned(), &config)
+}
 
-    // allocate the atlas
-    let mut atlas = image::RgbaImage::new(GLYPH_SIZE, GLYPH_SIZE * num_glyphs);
-    debug!(
-        "allocated {}x{} pixel atlas",
-        GLYPH_SIZE,
-        GLYPH_SIZE * num_glyphs
+/// Applies the options from the emoji's sidecar metadata files which the pack generation needs to know about.
+fn apply_emoji_metadata(config: &mut PackConfig, sources: &[scan::EmojiSource]) {
+    config.no_resize.extend(
+        sources
+            .iter()
+            .filter(|source| source.metadata.no_resize)
+            .map(|source| fedimoji::name_key(&source.name)),
     );
+    config
+        .ascent_overrides
+        .extend(sources.iter().filter_map(|source| {
+            source
+                .metadata
+                .ascent
+                .map(|ascent| (source.name.clone(), ascent))
+        }));
+
+    // emoji with a position of their own go after those in the --order, by position
+    let mut ordered = sources
+        .iter()
+        .filter_map(|source| Some((source.metadata.order?, &source.name)))
+        .collect::<Vec<_>>();
+    ordered.sort();
+    config
+        .order
+        .extend(ordered.into_iter().map(|(_, name)| name.clone()));
 
-    // mapping of name -> codepoint
-    let mut names: HashMap<String, char> = HashMap::new();
+    config.groups.extend(sources.iter().filter_map(|source| {
+        let group = source.metadata.group.clone()?;
+        Some((fedimoji::name_key(&source.name), group))
+    }));
+}
 
-    // set of glyph characters
-    let mut chars: Vec<char> = Vec::new();
+/// Generates a pack for each profile into its own directory within the output directory, from the same decoded
+/// emoji.
+///
+/// Profiles are generated in order of name, and later profiles keep the codepoints earlier ones gave the emoji, so
+/// every variant maps each emoji to the same codepoint.
+fn generate_profiles(
+    args: &Args,
+    profiles_path: &Path,
+    sources: &[scan::EmojiSource],
+    images: &[(String, image::DynamicImage)],
+    timings: Timings,
+) -> Result<(), PackError> {
+    let profiles: BTreeMap<String, Vec<String>> =
+        serde_json::from_str(&std::fs::read_to_string(profiles_path)?)
+            .map_err(|err| PackError::InvalidProfile(err.to_string()))?;
+    if profiles.is_empty() {
+        return Err(PackError::InvalidProfile(format!(
+            "no profiles in `{}`",
+            profiles_path.display()
+        )));
+    }
 
-    // place the images in the atlas
-    for ((name, codepoint, image), index) in images.into_iter().zip(0u32..) {
-        let y = index * GLYPH_SIZE;
-        atlas.copy_from(&image, 0, y).unwrap();
-        debug!("copied `{}` to ({}, {})", name, 0, y);
+    // codepoints given to the emoji by the profiles so far
+    let mut codepoints: HashMap<String, char> = HashMap::new();
+    for (name, flags) in &profiles {
+        let profile_args = args.for_profile(name, flags)?;
+        let mut config = profile_args.pack_config()?;
+        apply_emoji_metadata(&mut config, sources);
+        for (emoji, codepoint) in &codepoints {
+            config
+                .existing_mappings
+                .entry(emoji.clone())
+                .or_insert(*codepoint);
+        }
 
-        names.insert(name, codepoint);
-        chars.push(codepoint);
+        info!("generating profile \"{name}\"");
+        let pack = match profile_args.target_bytes {
+            Some(target_bytes) => generate_within_budget(images, &config, target_bytes)?,
+            None => fedimoji::generate_pack_from_iter(images.iter().cloned(), &config)?,
+        };
+        for (emoji, codepoint) in &pack.names {
+            codepoints
+                .entry(fedimoji::name_key(emoji))
+                .or_insert(*codepoint);
+        }
+        write_pack(&profile_args, &pack, sources, timings)?;
     }
+    Ok(())
+}
 
-    // get the output directory, creating it if it doesn't exist
-    let output_dir = args.output_dir;
-    if !output_dir.is_dir() {
-        std::fs::create_dir_all(&output_dir).unwrap();
+/// Processes a single one of the emoji as it would be for the pack, and writes just its glyph.
+fn preview_single(
+    config: &PackConfig,
+    sources: Vec<scan::EmojiSource>,
+    name: &str,
+    preview_out: &Path,
+) -> Result<(), PackError> {
+    let name = config.normalize_name(name);
+    let sources = sources
+        .into_iter()
+        .filter(|source| fedimoji::name_key(&source.name) == fedimoji::name_key(&name))
+        .collect::<Vec<_>>();
+    if sources.is_empty() {
+        return Err(PackError::EmojiNotFound(name));
     }
 
-    // write the atlas
-    atlas.save(&output_dir.join("emoji.png")).unwrap();
-    debug!(
-        "wrote atlas to `{}`",
-        output_dir.join("emoji.png").display()
+    let pack = fedimoji::generate_pack_from_iter(scan::read_emoji(&sources, config), config)?;
+    let Some((_, glyph)) = output::glyph_images(&pack).into_iter().next() else {
+        return Err(PackError::NoValidEmoji);
+    };
+    let mut png = Vec::new();
+    glyph
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    write_output(preview_out, &png, "glyph preview")?;
+    info!(
+        "wrote the {}x{} glyph for \"{name}\" to `{}`",
+        glyph.width(),
+        glyph.height(),
+        preview_out.display()
     );
+    Ok(())
+}
 
-    // write the font provider definition
-    let font_provider = json!({
-      "providers": [
-        {
-          "type": "bitmap",
-          "file": "fedimoji:font/emoji.png",
-          "height": 8,
-          "ascent": 8,
-          "chars": chars
+/// Writes a generated pack to the output directory.
+///
+/// The timings of reading the emoji are completed with those of generating and writing the pack, and reported if
+/// desired.
+fn write_pack(
+    args: &Args,
+    pack: &Pack,
+    sources: &[scan::EmojiSource],
+    timings: Timings,
+) -> Result<(), PackError> {
+    // the pack is generated, so decoding and building the atlas are done
+    #[cfg(feature = "profile-memory")]
+    if args.profile_memory {
+        info!(
+            peak_bytes = ALLOCATOR.peak(),
+            "peak memory use while decoding the emoji and building the atlas: {}",
+            format_bytes(ALLOCATOR.peak() as u64)
+        );
+    }
+
+    // make sure the atlas and providers describe the glyphs as they were placed, if desired
+    if args.round_trip_test {
+        fedimoji::repack::round_trip_check(pack)?;
+        info!("round trip test passed");
+    }
+
+    // stop short of writing anything if we're only checking the emoji, once the checks the writes would do have run
+    if args.validate_only {
+        if args.emit_inverse_map.is_some() {
+            pack.inverse_names()?;
         }
-      ]
+        info!(
+            "validation passed: {} glyphs would be packed (nothing was written)",
+            pack.num_glyphs()
+        );
+        return Ok(());
+    }
+
+    // compare with the old mapping before anything is written, in case it's the one about to be replaced
+    let changelog = match &args.changelog {
+        Some(old_path) => Some(output::changelog(
+            &fedimoji::read_mapping_file(old_path)?,
+            &pack.names,
+        )),
+        None => None,
+    };
+
+    // a bad SOURCE_DATE_EPOCH stops the build before anything is written
+    let built_at = fedimoji::build_time(args.reproducible)?;
+
+    // uploads are read back from the output directory, so there has to be one
+    #[cfg(feature = "net")]
+    if let (Some(base_url), true) = (&args.upload, args.output_dir.as_os_str() == "-") {
+        return Err(PackError::Upload {
+            url: base_url.clone(),
+            message: "only a pack written to an output directory can be uploaded".to_string(),
+        });
+    }
+
+    let start = Instant::now();
+    let mut encode_time = Duration::ZERO;
+    let mut png_size = 0;
+
+    // cut the atlas into tiles, if desired, encoding them up front
+    let tiles = pack.tile_size.map(|_| {
+        let start = Instant::now();
+        let tiles = pack
+            .tiles()
+            .into_iter()
+            .map(|tile| {
+                let png = pack.encode_tile(&tile).unwrap();
+                (tile, png)
+            })
+            .collect::<Vec<_>>();
+        encode_time += start.elapsed();
+        png_size = tiles.iter().map(|(_, png)| png.len()).sum();
+        tiles
     });
-    std::fs::write(
-        output_dir.join("emoji.json"),
-        serde_json::to_string_pretty(&font_provider).unwrap(),
-    )
-    .unwrap();
-    debug!(
-        "wrote font provider definition to `{}`",
-        output_dir.join("emoji.json").display()
-    );
+
+    let mut encode_atlas = || {
+        let start = Instant::now();
+        let png = pack.encode_atlas().unwrap();
+        encode_time += start.elapsed();
+        png_size = png.len();
+        png
+    };
+
+    // get the output directory, creating it if it doesn't exist, or stream an archive to stdout for `-`, unless
+    // we're updating a resource pack zip instead
+    let output_dir = args.update_zip.as_ref().unwrap_or(&args.output_dir);
+    let (mut output, lock) = if let Some(zip_path) = &args.update_zip {
+        (PackOutput::zip(zip_path)?, None)
+    } else if output_dir.as_os_str() == "-" {
+        (PackOutput::tar(std::io::stdout()), None)
+    } else {
+        // keep other runs from writing to the directory at the same time
+        let output = PackOutput::dir(output_dir)?;
+        let lock = output::OutputLock::acquire(output_dir, args.wait_for_lock)?;
+        (output, Some(lock))
+    };
+
+    // paths of every file written, for the summary
+    let mut files: Vec<PathBuf> = [
+        &args.emit_conflicts,
+        &args.emit_provider_fragment,
+        &args.export_shortcodes,
+        &args.emit_inverse_map,
+        &args.emit_csv,
+        &args.emit_allocation,
+        &args.emit_fedi_manifest,
+        &args.emit_attribution,
+        &args.emit_glyph_sizes,
+        &args.emit_data_uris,
+        &args.emit_binary_index,
+        &args.emit_preview,
+        &args.emit_bundle,
+    ]
+    .into_iter()
+    .flatten()
+    .cloned()
+    .collect();
+
+    // write the atlas, in its subdirectory of the textures if it has one
+    let minecraft = args.output_format == OutputFormat::Minecraft;
+    let atlas_name = match &args.atlas_subpath {
+        Some(subpath) if minecraft => format!("{subpath}/emoji.png"),
+        _ => "emoji.png".to_string(),
+    };
+    let texture_names = match &tiles {
+        None => vec![atlas_name.clone()],
+        Some(tiles) => {
+            let atlas_dir = atlas_name.strip_suffix("emoji.png").unwrap_or_default();
+            tiles
+                .iter()
+                .map(|(tile, _)| format!("{atlas_dir}{}", tile.file_name()))
+                .collect()
+        }
+    };
+    match &tiles {
+        None => output.write(&atlas_name, &encode_atlas())?,
+        Some(tiles) => {
+            // or its tiles, next to where it would be, with an index of the glyphs in them
+            for ((_, png), name) in tiles.iter().zip(&texture_names) {
+                output.write(name, png)?;
+            }
+            output.write(
+                "tiles.json",
+                json::to_string_pretty(&output::tile_index(pack), args.escape_chars)
+                    .unwrap()
+                    .as_bytes(),
+            )?;
+            info!(
+                "cut the atlas into {} tiles of {}px",
+                tiles.len(),
+                pack.tile_size.unwrap_or_default()
+            );
+        }
+    }
+
+    // describe the glyphs without anything Minecraft-specific, in place of the rest of the pack, if desired
+    if !minecraft && tiles.is_none() {
+        output.write(
+            "glyphs.json",
+            json::to_string_pretty(&output::glyph_rects(pack), args.escape_chars)
+                .unwrap()
+                .as_bytes(),
+        )?;
+    }
+
+    // write the texture metadata of the atlas (or each tile), asking for crisp (unfiltered) rendering
+    if minecraft && args.pixel_perfect {
+        let texture_metadata = json!({
+          "texture": {
+            "blur": false,
+            "clamp": false
+          }
+        });
+        for texture_name in &texture_names {
+            output.write(
+                &format!("{texture_name}.mcmeta"),
+                serde_json::to_string_pretty(&texture_metadata)
+                    .unwrap()
+                    .as_bytes(),
+            )?;
+        }
+    }
+
+    // describe the build which made the pack, and when, so it can be traced back to it
+    if minecraft {
+        let mut build_info = fedimoji::build_info();
+        build_info["built_at"] = json!(built_at);
+        output.write(
+            "build-info.json",
+            serde_json::to_string_pretty(&build_info)
+                .unwrap()
+                .as_bytes(),
+        )?;
+    }
+
+    // write the font provider definition, noting the build which made it (which Minecraft ignores)
+    let mut font_provider = pack.font_provider();
+    font_provider[fedimoji::META_KEY] = fedimoji::build_info();
+    if minecraft {
+        output.write(
+            "emoji.json",
+            json::to_string_pretty(&font_provider, args.escape_chars)
+                .unwrap()
+                .as_bytes(),
+        )?;
+    }
+
+    // write a commented copy of the font provider definition, for reading and hand-editing, if desired
+    if minecraft && args.emit_json5 {
+        output.write(
+            "emoji.json5",
+            json::to_commented_font_provider(&font_provider, args.escape_chars)
+                .unwrap()
+                .as_bytes(),
+        )?;
+    }
 
     // write the name->codepoint mapping
-    std::fs::write(
-        output_dir.join("fedimoji.json"),
-        serde_json::to_string_pretty(&names).unwrap(),
-    )
-    .unwrap();
-    debug!(
-        "wrote name->codepoint mapping to `{}`",
-        output_dir.join("fedimoji.json").display()
+    if minecraft {
+        output.write(
+            "fedimoji.json",
+            json::to_string_pretty(
+                &mapping_with_meta(&pack.names, args.map_codepoint_format),
+                args.escape_chars,
+            )
+            .unwrap()
+            .as_bytes(),
+        )?;
+    }
+
+    // write the conflicts found while merging the imported mappings, if desired
+    if let Some(conflicts_path) = &args.emit_conflicts {
+        let (_, conflicts) = args.imported_mappings()?;
+        write_output(
+            conflicts_path,
+            serde_json::to_string_pretty(&conflicts).unwrap().as_bytes(),
+            "merge conflicts",
+        )?;
+    }
+
+    // write just the providers, for merging into another font definition, if desired
+    if let Some(fragment_path) = &args.emit_provider_fragment {
+        write_output(
+            fragment_path,
+            json::to_string_pretty(&pack.providers(), args.escape_chars)
+                .unwrap()
+                .as_bytes(),
+            "font provider fragment",
+        )?;
+    }
+
+    let metadata = sources
+        .iter()
+        .map(|source| (source.name.clone(), &source.metadata))
+        .collect::<HashMap<_, _>>();
+
+    // write the shortcodes for chat clients, with the keywords and categories from the emoji metadata, if desired
+    if let Some(shortcodes_path) = &args.export_shortcodes {
+        write_output(
+            shortcodes_path,
+            json::to_string_pretty(
+                &output::shortcodes(&pack.names, &metadata, &args.variant_suffix_pattern),
+                args.escape_chars,
+            )
+            .unwrap()
+            .as_bytes(),
+            "shortcodes",
+        )?;
+    }
+
+    // write the credits for the emoji from their metadata, if desired
+    if let Some(attribution_path) = &args.emit_attribution {
+        write_output(
+            attribution_path,
+            json::to_string_pretty(
+                &output::attribution(&pack.names, &metadata),
+                args.escape_chars,
+            )
+            .unwrap()
+            .as_bytes(),
+            "attribution",
+        )?;
+    }
+
+    // write the codepoint->name mapping, if desired
+    if let Some(inverse_map_path) = &args.emit_inverse_map {
+        write_output(
+            inverse_map_path,
+            json::to_string_pretty(&pack.inverse_names()?, args.escape_chars)
+                .unwrap()
+                .as_bytes(),
+            "codepoint->name mapping",
+        )?;
+    }
+
+    // write the name->codepoint mapping as CSV, if desired
+    if let Some(csv_path) = &args.emit_csv {
+        write_output(
+            csv_path,
+            &output::names_csv(&pack.names).unwrap(),
+            "name->codepoint CSV",
+        )?;
+    }
+
+    // write a custom emoji manifest for Fediverse software, with an image of each glyph beside it, if desired
+    if let Some(manifest_path) = &args.emit_fedi_manifest {
+        let glyphs_dir = manifest_path.with_file_name("glyphs");
+        std::fs::create_dir_all(&glyphs_dir).map_err(|source| PackError::Write {
+            path: glyphs_dir.clone(),
+            source,
+        })?;
+        for (name, glyph) in output::glyph_images(pack) {
+            let mut png = Vec::new();
+            glyph
+                .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                .unwrap();
+            let glyph_path = glyphs_dir.join(format!("{name}.png"));
+            write_output(&glyph_path, &png, "glyph image")?;
+            files.push(glyph_path);
+        }
+        write_output(
+            manifest_path,
+            json::to_string_pretty(
+                &output::fedi_manifest(pack, &metadata, &args.fedi_url_prefix),
+                args.escape_chars,
+            )
+            .unwrap()
+            .as_bytes(),
+            "Fediverse emoji manifest",
+        )?;
+    }
+
+    // write a report of the codepoints used, if desired
+    if let Some(allocation_path) = &args.emit_allocation {
+        write_output(
+            allocation_path,
+            serde_json::to_string_pretty(&output::allocation_report(pack))
+                .unwrap()
+                .as_bytes(),
+            "codepoint allocation report",
+        )?;
+    }
+
+    // write the size of each glyph's content, for laying out emoji outside Minecraft, if desired
+    if let Some(glyph_sizes_path) = &args.emit_glyph_sizes {
+        write_output(
+            glyph_sizes_path,
+            serde_json::to_string_pretty(&output::glyph_sizes(pack))
+                .unwrap()
+                .as_bytes(),
+            "glyph sizes",
+        )?;
+    }
+
+    // write each glyph as a data URI, for web clients to show without any image requests, if desired
+    if let Some(data_uris_path) = &args.emit_data_uris {
+        let data_uris =
+            json::to_string_pretty(&output::data_uris(pack), args.escape_chars).unwrap();
+        if data_uris.len() > DATA_URIS_WARN_BYTES {
+            warn!(
+                "the data URIs take {}, which web clients have to download in one go",
+                format_bytes(data_uris.len() as u64)
+            );
+        }
+        write_output(data_uris_path, data_uris.as_bytes(), "data URIs")?;
+    }
+
+    // bundle the atlas and its index into one file, if desired
+    if let Some(bundle_path) = &args.emit_bundle {
+        write_output(bundle_path, &output::bundle(pack).unwrap(), "bundle")?;
+    }
+
+    // lay the emoji out for printing, if desired, with any pages after the first numbered
+    if let Some(preview_path) = &args.emit_preview {
+        let pages = output::print_preview(pack, args.preview_page_size, args.preview_dpi);
+        let (width, height) = args.preview_page_size.pixels(args.preview_dpi);
+        info!(
+            "laid out the emoji on {} {width}x{height} pages at {} DPI",
+            pages.len(),
+            args.preview_dpi
+        );
+        for (index, page) in pages.iter().enumerate() {
+            let page_path = if index == 0 {
+                preview_path.clone()
+            } else {
+                let stem = preview_path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy();
+                let page_path = preview_path.with_file_name(format!("{stem}-{}.png", index + 1));
+                files.push(page_path.clone());
+                page_path
+            };
+            let png =
+                output::png_with_dpi(page, args.preview_dpi).map_err(|err| PackError::Write {
+                    path: page_path.clone(),
+                    source: std::io::Error::other(err),
+                })?;
+            write_output(&page_path, &png, "print preview")?;
+        }
+    }
+
+    // write the glyph positions in binary, for client mods to load quickly, if desired
+    if let Some(binary_index_path) = &args.emit_binary_index {
+        write_output(
+            binary_index_path,
+            &output::binary_index(pack)?,
+            "binary index",
+        )?;
+    }
+
+    // write an HTML preview gallery, if desired
+    if let Some(html_dir) = &args.emit_html {
+        let mut gallery = PackOutput::dir(html_dir)?;
+        gallery.write("index.html", output::html_gallery(pack).as_bytes())?;
+        gallery.write("emoji.png", &encode_atlas())?;
+        files.extend_from_slice(gallery.written());
+    }
+
+    // write a bundle for static web hosting, if desired, with only relative paths between its files
+    if let Some(web_dir) = &args.emit_web {
+        let mut bundle = PackOutput::dir(web_dir)?;
+        bundle.write("index.html", output::html_gallery(pack).as_bytes())?;
+        bundle.write("emoji.png", &encode_atlas())?;
+        bundle.write("fedimoji.js", output::SPRITE_SCRIPT.as_bytes())?;
+        bundle.write(
+            "manifest.json",
+            json::to_string_pretty(&output::web_manifest(pack), args.escape_chars)
+                .unwrap()
+                .as_bytes(),
+        )?;
+        files.extend_from_slice(bundle.written());
+    }
+
+    // copy the original source images, if desired
+    if args.copy_originals {
+        // only the emoji which made it into the pack, under whatever name they were packed
+        let packed = pack
+            .names
+            .keys()
+            .map(|name| pack.source_names.get(name).unwrap_or(name))
+            .collect::<HashSet<_>>();
+        let sources = sources
+            .iter()
+            .filter(|source| packed.contains(&source.name));
+        for scan::EmojiSource { name, path, .. } in sources {
+            let extension = path.extension().unwrap_or_default().to_string_lossy();
+            output.write(
+                &format!("originals/{name}.{extension}"),
+                &std::fs::read(path)?,
+            )?;
+        }
+    }
+
+    // write the release notes, if desired
+    if let Some(changelog) = &changelog {
+        output.write("CHANGELOG.md", changelog.as_bytes())?;
+    }
+
+    // remember the codepoints given, along with those given before to emoji which have since gone, for the next run
+    if let Some(persisted_path) = args.persisted_allocation_path() {
+        let mut persisted = BTreeMap::new();
+        if persisted_path.is_file() {
+            let taken = pack.names.values().collect::<HashSet<_>>();
+            persisted.extend(
+                fedimoji::read_mapping_file(&persisted_path)?
+                    .into_iter()
+                    .filter(|(_, codepoint)| !taken.contains(codepoint)),
+            );
+        }
+        persisted.extend(
+            pack.names
+                .iter()
+                .map(|(name, codepoint)| (name.clone(), *codepoint)),
+        );
+        write_output(
+            &persisted_path,
+            json::to_string_pretty(
+                &mapping_with_meta(&persisted, CodepointFormat::Char),
+                args.escape_chars,
+            )
+            .unwrap()
+            .as_bytes(),
+            "persisted allocation",
+        )?;
+        files.push(persisted_path);
+    }
+
+    files.extend_from_slice(output.written());
+    #[cfg(feature = "net")]
+    let pack_files = output.written().to_vec();
+    output.finish()?;
+    drop(lock);
+
+    // publish the pack once it's safely written, if desired
+    #[cfg(feature = "net")]
+    if let Some(base_url) = &args.upload {
+        fedimoji::net::upload(base_url, output_dir, &pack_files)?;
+    }
+
+    info!("done! generated pack with {} glyphs", pack.num_glyphs());
+
+    // estimate how heavy the atlas is for clients
+    let texture_memory = pack.texture_memory();
+    info!(
+        texture_bytes = texture_memory,
+        texture_bytes_with_mipmaps = texture_memory + texture_memory / 3,
+        png_bytes = png_size,
+        "the atlas takes about {} of video memory ({} with mipmaps), and {} as a PNG",
+        format_bytes(texture_memory),
+        format_bytes(texture_memory + texture_memory / 3),
+        format_bytes(png_size as u64)
     );
 
-    info!("done! generated pack with {} glyphs", num_glyphs);
+    // the pack's resize stage also covers decoding the images as they're read
+    let timings = Timings {
+        resize: pack.timings.resize.saturating_sub(timings.decode),
+        allocate: pack.timings.allocate,
+        encode: encode_time,
+        write: start.elapsed().saturating_sub(encode_time),
+        ..timings
+    };
+    if args.timing {
+        timings.report();
+    }
+
+    // write a summary of the run for tooling, if desired
+    if let Some(summary_path) = &args.emit_summary {
+        let summary = run_summary(pack, &files, (!args.reproducible).then_some(&timings));
+        write_output(
+            summary_path,
+            serde_json::to_string_pretty(&summary).unwrap().as_bytes(),
+            "run summary",
+        )?;
+    }
+
+    // run the post-build hook, if there is one
+    if let Some(command) = &args.post_build {
+        run_post_build(command, output_dir)?;
+    }
+
+    Ok(())
+}
+
+/// The name -> codepoint mapping, with the codepoints in a format, and an entry naming the build which made it.
+///
+/// The entry's value is a string like the codepoints, as chat mods reading the mapping expect every value to be one.
+fn mapping_with_meta(names: &BTreeMap<String, char>, format: CodepointFormat) -> serde_json::Value {
+    let mut mapping = serde_json::Map::new();
+    mapping.insert(
+        fedimoji::META_KEY.to_string(),
+        json!(format!("fedimoji {}", fedimoji::VERSION)),
+    );
+    for (name, codepoint) in names {
+        mapping.insert(name.clone(), format.to_json(*codepoint));
+    }
+    serde_json::Value::Object(mapping)
+}
+
+/// Summarizes the outcome of a run: what became of the emoji, the atlas's layout, the codepoints used, the files
+/// written and the timings, if they're given.
+fn run_summary(pack: &Pack, files: &[PathBuf], timings: Option<&Timings>) -> serde_json::Value {
+    let codepoint = |c: &char| format!("U+{:04X}", *c as u32);
+    let rows = pack.num_cells().div_ceil(pack.columns.max(1));
+    json!({
+        "counts": pack.stats,
+        "glyphs": pack.num_glyphs(),
+        "atlas": {
+            "width": pack.atlas.width(),
+            "height": pack.atlas.height(),
+            "columns": pack.columns,
+            "rows": rows,
+        },
+        "codepoints": {
+            "first": pack.names.values().min().map(codepoint),
+            "last": pack.names.values().max().map(codepoint),
+        },
+        "files": files,
+        "timings_ms": timings.map(Timings::as_millis),
+    })
+}
+
+/// Runs a post-build hook command through the shell, passing it the output directory.
+///
+/// The directory is given as the first argument (`$1`) and as `FEDIMOJI_OUTPUT_DIR`.
+fn run_post_build(command: &str, output_dir: &Path) -> Result<(), PackError> {
+    info!("running post-build hook `{command}`");
+
+    #[cfg(windows)]
+    let mut hook = {
+        let mut hook = std::process::Command::new("cmd");
+        hook.arg("/C").arg(command).arg(output_dir);
+        hook
+    };
+    #[cfg(not(windows))]
+    let mut hook = {
+        let mut hook = std::process::Command::new("sh");
+        hook.arg("-c").arg(command).arg("sh").arg(output_dir);
+        hook
+    };
+
+    let status = hook.env("FEDIMOJI_OUTPUT_DIR", output_dir).status()?;
+    if !status.success() {
+        return Err(PackError::HookFailed(status));
+    }
+    Ok(())
+}
+
+/// Size of the data URI JSON over which it's worth warning about, as clients load it all at once.
+const DATA_URIS_WARN_BYTES: usize = 1024 * 1024;
+
+/// Formats a number of bytes for people to read, like `1.5 MiB`.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} bytes");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
 }
 
-#[derive(clap::Parser)]
+/// Writes an output file, unless it's unchanged.
+fn write_output(path: &Path, contents: &[u8], description: &str) -> Result<(), PackError> {
+    let written = output::write_if_changed(path, contents).map_err(|source| PackError::Write {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    if written {
+        debug!("wrote {description} to `{}`", path.display());
+    }
+    Ok(())
+}
+
+impl Args {
+    /// The arguments for a profile: these arguments with the profile's flags added (overriding any given already),
+    /// writing into the profile's directory within the output directory.
+    fn for_profile(&self, name: &str, flags: &[String]) -> Result<Self, PackError> {
+        let mut components = Path::new(name).components();
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(std::path::Component::Normal(_)), None)
+        ) {
+            return Err(PackError::InvalidProfile(format!(
+                "profile name {name:?} isn't a plain directory name"
+            )));
+        }
+
+        let output_dir = self.output_dir.join(name);
+        let flags = std::iter::once("fedimoji".into())
+            .chain(flags.iter().map(Into::into))
+            .chain(["--output-dir".into(), output_dir.into_os_string()])
+            .collect::<Vec<std::ffi::OsString>>();
+        // clap's update insists on a subcommand being given when there isn't one already, so one stands in while the
+        // profile's flags are applied
+        let mut args = self.clone();
+        let command = args.command.replace(Command::Count);
+        args.try_update_from(flags).map_err(|err| {
+            // just the message, without clap's usage and tips
+            let err = err.to_string();
+            let message = err.lines().next().unwrap_or_default();
+            let message = message.strip_prefix("error: ").unwrap_or(message);
+            PackError::InvalidProfile(format!("profile \"{name}\": {message}"))
+        })?;
+        args.command = command;
+        Ok(args)
+    }
+
+    /// Finds the emoji images, in the emoji directory or the list of files on stdin.
+    fn scan_emoji(&self, config: &PackConfig) -> Result<Vec<scan::EmojiSource>, PackError> {
+        if self.stdin_list {
+            scan::scan_file_list(std::io::stdin().lock(), config)
+        } else {
+            scan::scan_emoji_dirs(&self.emoji_dir, config)
+        }
+    }
+
+    /// Checks the emoji names against the expected names file, if one was given.
+    fn check_expected_names<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), PackError> {
+        match &self.expect_names {
+            Some(path) => {
+                scan::check_expected_names(names, &scan::read_name_list(path)?, self.allow_extra)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Where the codepoints given to emoji are kept between runs, unless that's not desired, or the output isn't a
+    /// directory.
+    fn persisted_allocation_path(&self) -> Option<PathBuf> {
+        if self.no_persist || self.update_zip.is_some() || self.output_dir.as_os_str() == "-" {
+            return None;
+        }
+        Some(self.output_dir.join(output::PERSISTED_ALLOCATION_FILE))
+    }
+
+    /// The imported mappings merged into one, with the conflicts found while merging them.
+    fn imported_mappings(&self) -> Result<(HashMap<String, char>, MergeConflicts), PackError> {
+        let mappings = self
+            .import
+            .iter()
+            .map(|mapping_path| fedimoji::import_mappings(mapping_path))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(fedimoji::merge_mappings(mappings))
+    }
+
+    /// The pack generation options given by the arguments, reading the imported mapping file if one was given.
+    fn pack_config(&self) -> Result<PackConfig, PackError> {
+        // load an existing mapping file to import, if desired
+        let (mut existing_mappings, _) = self.imported_mappings()?;
+
+        // carry the codepoints of renamed emoji over to their new names, if desired
+        let renames = match &self.rename {
+            Some(rename_path) => fedimoji::read_renames(rename_path)?,
+            None => HashMap::new(),
+        };
+        fedimoji::rename_mappings(&mut existing_mappings, &renames);
+
+        // load an allocation plan, if desired
+        let mut planned_mappings = match &self.plan {
+            Some(plan_path) => fedimoji::read_plan(plan_path)?,
+            None => HashMap::new(),
+        };
+
+        // keep the codepoints earlier runs gave, as if they were planned, as long as nothing else claims them
+        if let Some(persisted_path) = self
+            .persisted_allocation_path()
+            .filter(|path| path.is_file())
+        {
+            let claimed = existing_mappings
+                .values()
+                .chain(planned_mappings.values())
+                .copied()
+                .collect::<HashSet<_>>();
+            for (name, codepoint) in fedimoji::read_mapping_file(&persisted_path)? {
+                if !claimed.contains(&codepoint) && !existing_mappings.contains_key(&name) {
+                    planned_mappings.entry(name).or_insert(codepoint);
+                }
+            }
+        }
+
+        // find where to continue allocating from, if desired
+        let codepoint_start_after = self
+            .codepoint_start_after
+            .as_deref()
+            .map(fedimoji::read_codepoint)
+            .transpose()?;
+
+        // load the overlay, if there is one
+        let overlay = match &self.overlay {
+            Some(overlay_path) => Some(
+                scan::decode_image(overlay_path, &PackConfig::default()).map_err(|source| {
+                    PackError::ImageRead {
+                        path: overlay_path.clone(),
+                        source,
+                    }
+                })?,
+            ),
+            None => None,
+        };
+
+        // load the atlas template, if there is one
+        let atlas_template = match &self.atlas_template {
+            Some(template_path) => Some(
+                scan::decode_image(template_path, &PackConfig::default()).map_err(|source| {
+                    PackError::ImageRead {
+                        path: template_path.clone(),
+                        source,
+                    }
+                })?,
+            ),
+            None => None,
+        };
+
+        // only carry forward imported emoji in a range of codepoints, if desired
+        let codepoint_filter = self
+            .codepoint_filter
+            .as_deref()
+            .map(fedimoji::parse_codepoint_range)
+            .transpose()?;
+
+        // keep new emoji off codepoints known to render badly in some clients
+        let blocked_codepoints = match &self.client_blocklist {
+            Some(path) => fedimoji::read_codepoint_blocklist(path)?,
+            None => HashSet::new(),
+        };
+
+        // lay out the atlas for the target Minecraft version
+        let atlas_columns = match &self.mc_version {
+            Some(version) => fedimoji::atlas_columns_for_mc_version(version)?,
+            None => 1,
+        };
+
+        Ok(PackConfig {
+            existing_mappings,
+            planned_mappings,
+            kept_aliases: if self.keep_aliases {
+                renames
+            } else {
+                HashMap::new()
+            },
+            name_prefix: self.name_prefix.clone(),
+            preserve_case: self.preserve_case,
+            name_suffix: self.name_suffix.clone(),
+            mask_suffix: self.mask_suffix.clone(),
+            color_suffix: self.color_suffix.clone(),
+            alpha_suffix: self.alpha_from.clone(),
+            follow_symlinks: self.follow_symlinks,
+            strip_color_profile: self.strip_color_profile,
+            #[cfg(feature = "psd")]
+            psd_layer: self.psd_layer.clone(),
+            sort: match (self.sort, self.reproducible) {
+                // modification times change with checkouts, and the directory order with the filesystem
+                (Some(scan::SortOrder::Mtime), true) => {
+                    return Err(PackError::NotReproducible(
+                        "sorting by modification time".to_string(),
+                    ))
+                }
+                (None, true) => Some(scan::SortOrder::Name),
+                (sort, _) => sort,
+            },
+            reproducible: self.reproducible,
+            order: match &self.order {
+                Some(path) => scan::read_name_list(path)?,
+                None => Vec::new(),
+            },
+            groups: Default::default(),
+            listing: self.csv.as_deref().map(scan::read_listing).transpose()?,
+            include_unlisted: self.include_unlisted,
+            exclude: self.exclude.clone(),
+            format_priority: self.format_priority.clone(),
+            reserved_names: self.reserved_names.clone(),
+            strict_names: self.strict_names,
+            validate_names: self.validate_names,
+            fix_names: self.fix_names,
+            shortcode_max_length: self.shortcode_max_length,
+            glyph_width: self.glyph_width.unwrap_or(self.glyph_size),
+            glyph_height: self.glyph_height.unwrap_or(self.glyph_size),
+            pad: self.pad,
+            gutter: self.gutter,
+            mipmap_bleed: self.mipmap_bleed,
+            no_upscale: self.no_upscale,
+            no_resize: Default::default(),
+            integer_scale_only: self.integer_scale_only,
+            linear_resize: self.linear_resize,
+            tintable: self.tintable,
+            atlas_byte_limit: self.limit_atlas_bytes,
+            uv_precision_limit: self.uv_precision_limit,
+            atlas_subpath: self.atlas_subpath.clone(),
+            png_compression: self.png_compression,
+            tile_size: self.tile_size,
+            atlas_bit_depth: self.atlas_bit_depth,
+            only_new: self.only_new,
+            pad_to_pow2: self.pad_to_pow2,
+            trim_atlas: self.trim_atlas,
+            atlas_template,
+            reserved_regions: self.reserve_region.clone(),
+            min_coverage: self.min_coverage,
+            skip_blank: self.skip_blank,
+            codepoint_start_after,
+            allocation_strategy: self.allocation_strategy,
+            atlas_columns,
+            auto_atlas: self.auto_atlas,
+            explain: self.explain,
+            max_name_length: self.max_name_length,
+            variable_advance: self.variable_advance,
+            credits_text: self.credits_text.clone(),
+            slice_wide: self.slice_wide,
+            overlay,
+            overlay_position: self.overlay_position,
+            drop_shadow: self.drop_shadow,
+            color_transform: (self.hue_shift.is_some()
+                || self.saturation.is_some()
+                || self.brightness.is_some())
+            .then(|| ColorTransform {
+                hue_shift: self.hue_shift.unwrap_or(0.0),
+                saturation: self.saturation.unwrap_or(1.0),
+                brightness: self.brightness.unwrap_or(1.0),
+            }),
+            outline: self.outline,
+            codepoint_filter,
+            safe_codepoints: self.safe_codepoints,
+            blocked_codepoints,
+            allocator: None,
+            glyph_processors: Vec::new(),
+            positional_codepoints: self.positional_codepoints,
+            fail_on_reassignment: self.fail_on_codepoint_reassignment,
+            auto_ascent: self.auto_ascent,
+            ascent: self.ascent,
+            ascent_overrides: Default::default(),
+            similarity_threshold: self.two_pass_dedupe.then_some(self.similarity_threshold),
+            color_levels: self.color_levels,
+            dither: self.dither,
+            normalize_transparency: self.normalize_transparency,
+        })
+    }
+}
+
+#[derive(Clone, clap::Parser)]
+#[clap(version = fedimoji::VERSION, args_override_self = true)]
 struct Args {
-    /// Directory containing emoji images
-    #[clap(long, default_value = "./emoji")]
-    emoji_dir: PathBuf,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Directory containing emoji images; give it several times to pack several directories together, with emoji in
+    /// later ones replacing those of the same name in earlier ones
+    #[clap(long, default_value = "./emoji", global = true)]
+    emoji_dir: Vec<PathBuf>,
 
-    /// Output directory
-    #[clap(long, default_value = "./out")]
+    /// Read the paths of the emoji images from stdin, one per line, in place of scanning --emoji-dir
+    #[clap(long, global = true)]
+    stdin_list: bool,
+
+    /// Output directory, or `-` to write the pack to stdout as a tar archive
+    #[clap(long, default_value = "./out", global = true)]
     output_dir: PathBuf,
 
-    /// Existing fedimoji.json file, from which existing emoji codepoints will be imported
-    #[clap(long, short)]
-    import: Option<PathBuf>,
+    /// Existing resource pack zip to update in place instead of writing to the output directory, replacing or adding
+    /// just the emoji files at their places in the pack
+    #[clap(long, value_name = "ZIP")]
+    update_zip: Option<PathBuf>,
+
+    /// Existing fedimoji.json file, from which existing emoji codepoints will be imported; give this several times
+    /// to merge several packs, with earlier files taking precedence. Append `#/json/pointer` to read a mapping
+    /// nested inside a larger file
+    #[clap(long, short, global = true)]
+    import: Vec<PathBuf>,
+
+    /// Fail, rather than just warn, when the `--import`ed mapping has emoji with no image in the emoji directory
+    #[clap(long, global = true, requires = "import")]
+    strict_import: bool,
+
+    /// Warn about emoji image files larger than this many bytes
+    #[clap(long, value_name = "BYTES")]
+    max_file_size: Option<u64>,
+
+    /// Fail if any emoji image file is larger than --max-file-size
+    #[clap(long, requires = "max_file_size")]
+    strict_file_size: bool,
+
+    /// Write a report of names and codepoints which conflict between the `--import`ed files to this path
+    #[clap(long, global = true)]
+    emit_conflicts: Option<PathBuf>,
+
+    /// JSON file mapping old emoji names to new ones, so renamed emoji keep their `--import`ed codepoints
+    #[clap(long, global = true, requires = "import", value_name = "FILE")]
+    rename: Option<PathBuf>,
+
+    /// JSON file of curation decisions (pinned codepoints, renames and excluded emoji) to apply to the scanned emoji,
+    /// as written by --curate
+    #[clap(long, value_name = "FILE")]
+    curation: Option<PathBuf>,
+
+    /// Review the emoji in an interactive terminal interface first, to pin, rename or exclude them, saving the
+    /// decisions to the --curation file and then generating the pack with them
+    #[cfg(feature = "tui")]
+    #[clap(long, requires = "curation")]
+    curate: bool,
+
+    /// Keep the old names of emoji renamed with --rename in `fedimoji.json`, with the same codepoint as the new
+    /// names, so both shortcodes work
+    #[clap(long, requires = "rename")]
+    keep_aliases: bool,
+
+    /// Only carry forward imported emoji whose codepoint is in this range (like `U+F0000-U+F00FF`), dropping the
+    /// rest; new emoji are allocated as usual
+    #[clap(long, global = true, requires = "import", value_name = "START-END")]
+    codepoint_filter: Option<String>,
+
+    /// File listing the expected emoji names, one per line, failing before generating anything if any are
+    /// missing or there are others
+    #[clap(long, global = true)]
+    expect_names: Option<PathBuf>,
+
+    /// Allow emoji which aren't in the expected names
+    #[clap(long, global = true, requires = "expect_names")]
+    allow_extra: bool,
+
+    /// Allocation plan of emoji name -> codepoint (like fedimoji.json), whose codepoints are kept for their emoji
+    /// even if they don't exist yet
+    #[clap(long, global = true)]
+    plan: Option<PathBuf>,
+
+    /// Check that slicing the encoded atlas by the font providers gives back every glyph as it was placed, failing if
+    /// not, before writing anything
+    #[clap(long)]
+    round_trip_test: bool,
+
+    /// Don't keep the codepoints given to emoji in `allocation.json` in the output directory, nor read them from there,
+    /// so they can shift between runs unless they're imported
+    #[clap(long, global = true)]
+    no_persist: bool,
+
+    /// Treat emoji images with an embedded color profile as plain sRGB without warning
+    #[clap(long)]
+    strip_color_profile: bool,
+
+    /// Read this named layer from Photoshop documents in the emoji directory, rather than their flattened composite
+    #[cfg(feature = "psd")]
+    #[clap(long)]
+    psd_layer: Option<String>,
+
+    /// Make the output depend only on the inputs, so identical runs give byte-identical output: sort the emoji by
+    /// name (unless --sort says otherwise), leave timings out of the summary, and fail on inputs which would otherwise
+    /// be skipped with a warning, like file names which aren't valid UTF-8
+    #[clap(long, global = true)]
+    reproducible: bool,
+
+    /// Order in which to pack the emoji, with ties broken by name [default: the order the directory lists them in]
+    #[clap(long, value_enum, global = true)]
+    sort: Option<scan::SortOrder>,
+
+    /// File listing emoji names, one per line, to pack first in that order (deciding their place in the atlas, and
+    /// their codepoints with --positional-codepoints); the rest follow sorted by name
+    #[clap(long)]
+    order: Option<PathBuf>,
+
+    /// CSV listing the emoji to pack, with a `file` column naming each image in the emoji directory, and optional
+    /// `name`, `category` and `order` columns (packed after any --order); images it doesn't list are skipped
+    #[clap(long, global = true)]
+    csv: Option<PathBuf>,
+
+    /// Also pack the images the --csv listing doesn't list, named after their files, after the listed ones
+    #[clap(long, global = true, requires = "csv")]
+    include_unlisted: bool,
+
+    /// Glob pattern of files in the emoji directory to skip (can be given multiple times)
+    #[clap(long, global = true)]
+    exclude: Vec<String>,
+
+    /// Image formats to read from the emoji directory, by extension; when files in several formats have the same
+    /// name, the one listed first is used
+    #[clap(
+        long,
+        global = true,
+        value_name = "EXT",
+        value_delimiter = ',',
+        default_value = "png",
+        value_parser = scan::parse_format
+    )]
+    format_priority: Vec<ImageFormat>,
+
+    /// Prefix added to every emoji name (e.g. `srv_`)
+    #[clap(long, default_value = "", global = true)]
+    name_prefix: String,
+
+    /// Suffix added to every emoji name
+    #[clap(long, default_value = "", global = true)]
+    name_suffix: String,
+
+    /// Suffix of tint mask files (e.g. `_mask`), whose alpha is applied to the color layer file with the same name
+    /// but --color-suffix (e.g. `foo_mask.png` and `foo_color.png` become `foo`)
+    #[clap(long, global = true, requires = "color_suffix")]
+    mask_suffix: Option<String>,
+
+    /// Suffix of color layer files (e.g. `_color`), combined with their --mask-suffix tint masks
+    #[clap(long, global = true, requires = "mask_suffix")]
+    color_suffix: Option<String>,
+
+    /// Suffix of grayscale alpha files (e.g. `_alpha`), whose luminance is used as the alpha of the image with the same
+    /// name without it (e.g. `foo_alpha.png` for `foo.png`); images without one keep their own alpha
+    #[clap(long, value_name = "SUFFIX", global = true)]
+    alpha_from: Option<String>,
+
+    /// Keep the case of emoji names (like `CatJam`) in the output, rather than lowercasing them; names are still
+    /// compared case-insensitively, for finding duplicates and matching the `--import`ed mapping
+    #[clap(long, global = true)]
+    preserve_case: bool,
+
+    /// Copy the original (non-resized) source images into `originals/` in the output directory
+    #[clap(long)]
+    copy_originals: bool,
+
+    /// Whether to follow symlinks in the emoji directory
+    #[clap(long, default_value_t = true, action = clap::ArgAction::Set, global = true)]
+    follow_symlinks: bool,
+
+    /// Emit `emoji.png.mcmeta` texture metadata disabling blur, for crisp pixel art
+    #[clap(long)]
+    pixel_perfect: bool,
+
+    /// Emoji names to warn about, as they could be confused with Minecraft chat formatting
+    #[clap(long, value_delimiter = ',', default_values = fedimoji::DEFAULT_RESERVED_NAMES)]
+    reserved_names: Vec<String>,
+
+    /// Fail, rather than just warn, when an emoji name is reserved
+    #[clap(long)]
+    strict_names: bool,
+
+    /// Warn about emoji names which Fediverse servers would reject as shortcodes
+    #[clap(long)]
+    validate_names: bool,
+
+    /// Fix emoji names which Fediverse servers would reject as shortcodes, by truncating them and replacing
+    /// disallowed characters with underscores
+    #[clap(long)]
+    fix_names: bool,
+
+    /// Maximum length of an emoji name, when validating them
+    #[clap(long, default_value_t = fedimoji::DEFAULT_SHORTCODE_MAX_LENGTH)]
+    shortcode_max_length: usize,
+
+    /// Width and height of each glyph cell in the atlas, in pixels
+    #[clap(long, default_value_t = fedimoji::GLYPH_SIZE, global = true)]
+    glyph_size: u32,
+
+    /// Width of each glyph cell in the atlas, in pixels [default: the glyph size]
+    #[clap(long, global = true)]
+    glyph_width: Option<u32>,
+
+    /// Height of each glyph cell in the atlas, in pixels [default: the glyph size]
+    #[clap(long, global = true)]
+    glyph_height: Option<u32>,
+
+    /// Transparent padding to keep around each glyph within its cell, in pixels
+    #[clap(long, default_value_t = 0)]
+    pad: u32,
+
+    /// Transparent space to leave between neighbouring glyphs in the atlas, in pixels, so they don't bleed into each
+    /// other in-game; unlike --pad, it's only on the right and bottom of each glyph
+    #[clap(long, default_value_t = 0)]
+    gutter: u32,
+
+    /// Spread the colors at the edges of each glyph this many pixels into the transparent rest of its cell (and the
+    /// gutter), keeping them transparent, so mipmaps of the atlas don't give the glyphs dark halos
+    #[clap(long, value_name = "PX", default_value_t = 0)]
+    mipmap_bleed: u32,
+
+    /// Write non-ASCII characters in the output JSON as `\uXXXX` escapes, rather than literally
+    #[clap(long)]
+    escape_chars: bool,
+
+    /// How to write the codepoints in `fedimoji.json`: as the character (which chat mods expect), a decimal number,
+    /// or hex like `U+F0001`
+    #[clap(long, value_enum, default_value_t)]
+    map_codepoint_format: CodepointFormat,
+
+    /// Also write `emoji.json5`, a copy of the font provider definition with comments noting the codepoints each
+    /// provider covers
+    #[clap(long)]
+    emit_json5: bool,
+
+    /// Leave images smaller than a glyph at their native size, centered in the cell, rather than scaling them up
+    #[clap(long)]
+    no_upscale: bool,
+
+    /// Only scale emoji by whole factors (like 2x or 1/2x), with nearest-neighbour sampling to keep pixel art crisp,
+    /// skipping any which would need another scale
+    #[clap(long)]
+    integer_scale_only: bool,
+
+    /// Resize emoji in linear light rather than in sRGB, which keeps shrunk high-contrast details from darkening
+    #[clap(long)]
+    linear_resize: bool,
+
+    /// Make every glyph white, keeping only its alpha, so it's tinted by the chat text color
+    #[clap(long)]
+    tintable: bool,
+
+    /// Largest size of atlas to allocate in memory, in bytes
+    #[clap(long, default_value_t = fedimoji::DEFAULT_ATLAS_BYTE_LIMIT)]
+    limit_atlas_bytes: u64,
+
+    /// Use the largest glyph size, up to --glyph-size (or --glyph-width), whose atlas PNG takes at most this many bytes
+    #[clap(long, value_name = "BYTES")]
+    target_bytes: Option<u64>,
+
+    /// Warn if the atlas is wider or taller than this, in pixels, as Minecraft samples glyphs imprecisely beyond it
+    #[clap(long, value_name = "PX", default_value_t = fedimoji::DEFAULT_UV_PRECISION_LIMIT)]
+    uv_precision_limit: u32,
+
+    /// Put the atlas in this directory of the namespace's textures (like `font/v3`, for cache-busting) in place of
+    /// `font`, writing it there in the output directory and referring to it there in the providers
+    #[clap(long, value_name = "DIR", value_parser = output::parse_atlas_subpath)]
+    atlas_subpath: Option<String>,
 
-    #[clap(short = 'v', long)]
+    /// How hard to compress the atlas PNG: `fast`, `default`, `best`, or a level from 0 (uncompressed) to 9; smaller
+    /// files take longer to encode
+    #[clap(long, value_name = "LEVEL", default_value = "fast", value_parser = output::parse_png_compression)]
+    png_compression: image::codecs::png::CompressionType,
+
+    /// Cut the atlas into square tiles this many pixels across (like 256), written as `emoji_tile_<row>_<column>.png`
+    /// with an index of each glyph's tile in `tiles.json`, so clients can load the emoji a piece at a time
+    #[clap(long, value_name = "PX")]
+    tile_size: Option<u32>,
+
+    /// Also write just the array of font providers to this path, for merging into an existing font definition
+    #[clap(long)]
+    emit_provider_fragment: Option<PathBuf>,
+
+    /// If another run is writing to the output directory, wait for it to finish rather than failing
+    #[clap(long)]
+    wait_for_lock: bool,
+
+    /// Also write a JSON summary of the run to this path, for tooling: counts of the emoji, the atlas size, the
+    /// codepoints used, the files written and the timings
+    #[clap(long)]
+    emit_summary: Option<PathBuf>,
+
+    /// Also write a custom emoji manifest for Fediverse software to this path, as an array of `{shortcode, url,
+    /// unicode}`, with an image of each glyph in a `glyphs` directory beside it for the URLs to point at
+    #[clap(long)]
+    emit_fedi_manifest: Option<PathBuf>,
+
+    /// Where the `glyphs` directory written with --emit-fedi-manifest will be served from, to put before each
+    /// image's path in the manifest (like `https://example.com/emoji/`)
+    #[clap(long, default_value = "", requires = "emit_fedi_manifest")]
+    fedi_url_prefix: String,
+
+    /// Also write a JSON list of the emoji for chat clients to this path, with each one's shortcode, character and
+    /// search keywords and category (from the emoji metadata)
+    #[clap(long)]
+    export_shortcodes: Option<PathBuf>,
+
+    /// Suffix marking an emoji as a variant of the one named without it, like a skin tone, grouped with it in the
+    /// --export-shortcodes list; `#` stands for one or more digits
+    #[clap(long, value_name = "PATTERN", default_value = output::DEFAULT_VARIANT_SUFFIX_PATTERN)]
+    variant_suffix_pattern: String,
+
+    /// Also write the author, license and source URL of each emoji (from the emoji metadata) to this path, as JSON by
+    /// name, warning about emoji without an author or license
+    #[clap(long)]
+    emit_attribution: Option<PathBuf>,
+
+    /// Fail if any emoji has no author or license in its metadata
+    #[clap(long)]
+    require_attribution: bool,
+
+    /// Also write the name -> codepoint mapping as CSV to this path, for reviewing in a spreadsheet
+    #[clap(long)]
+    emit_csv: Option<PathBuf>,
+
+    /// JSON file of named profiles, each a list of extra flags (like `{"chat": ["--glyph-size", "32", "--tintable"]}`),
+    /// to generate a variant of the pack for each into `<output-dir>/<profile>/`, decoding the emoji only once and
+    /// giving each emoji the same codepoint in every variant
+    #[clap(long, value_name = "FILE")]
+    profiles: Option<PathBuf>,
+
+    /// Instead of generating the pack, write the planned layout of the atlas (its size, columns, and each emoji's
+    /// codepoint and cell) to this JSON file, without decoding any images
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["profiles", "target_bytes"])]
+    plan_only: Option<PathBuf>,
+
+    /// Instead of generating the pack, process just this emoji from the emoji directory as it would be for the pack,
+    /// and write its glyph to --preview-out, for seeing why it looks the way it does
+    #[clap(long, value_name = "NAME", requires = "preview_out")]
+    preview_single: Option<String>,
+
+    /// Where to write the glyph processed with --preview-single, as a PNG
+    #[clap(long, value_name = "PNG", requires = "preview_single")]
+    preview_out: Option<PathBuf>,
+
+    /// Also write a report of the codepoints the pack uses to this path, for coordinating with other packs: the range
+    /// they span, each one used, and the last one (`free_after`), to pass to another pack's --codepoint-start-after
+    #[clap(long)]
+    emit_allocation: Option<PathBuf>,
+
+    /// Also write the size of each emoji's opaque content within its glyph cell, and its offset in the cell, to this
+    /// path, for laying out emoji by their content outside Minecraft
+    #[clap(long)]
+    emit_glyph_sizes: Option<PathBuf>,
+
+    /// Also write each emoji's glyph as a `data:image/png;base64,...` URI to this JSON file, by name, for web clients
+    /// which shouldn't have to request any images
+    #[clap(long)]
+    emit_data_uris: Option<PathBuf>,
+
+    /// Also write the atlas with an index of every glyph's name, codepoint and position embedded in it (as JSON in an
+    /// `iTXt` chunk with the keyword `fedimoji-index`) to this path, for loading both in one read
+    #[clap(long, value_name = "PNG")]
+    emit_bundle: Option<PathBuf>,
+
+    /// Also lay every emoji out with its name on printable pages (such as a sticker sheet), written as PNGs to this path,
+    /// with any pages after the first numbered (`preview-2.png`)
+    #[clap(long, value_name = "PNG")]
+    emit_preview: Option<PathBuf>,
+
+    /// Resolution of the printable preview, in dots per inch (72 to 2400), recorded in its PNGs
+    #[clap(
+        long,
+        default_value_t = 300,
+        requires = "emit_preview",
+        value_parser = clap::value_parser!(u32).range(72..=2400)
+    )]
+    preview_dpi: u32,
+
+    /// Paper size of the printable preview's pages
+    #[clap(long, value_enum, default_value_t = output::PageSize::A4, requires = "emit_preview")]
+    preview_page_size: output::PageSize,
+
+    /// Also write a compact little-endian binary index of each emoji's codepoint and glyph position to this path, for
+    /// client mods to load without parsing JSON (see the README for the format)
+    #[clap(long)]
+    emit_binary_index: Option<PathBuf>,
+
+    /// Also write the inverse of `fedimoji.json`, a mapping of codepoint -> emoji name, to this path
+    #[clap(long)]
+    emit_inverse_map: Option<PathBuf>,
+
+    /// Old `fedimoji.json` to compare the new mapping with, writing the emoji added, removed and given new codepoints
+    /// to `CHANGELOG.md` in the output, for release notes
+    #[clap(long, value_name = "OLD_MAP")]
+    changelog: Option<PathBuf>,
+
+    /// Also write an HTML gallery previewing every emoji (`index.html` and the atlas) into this directory
+    #[clap(long)]
+    emit_html: Option<PathBuf>,
+
+    /// Also write a self-contained bundle for static web hosting into this directory: the HTML gallery, the atlas,
+    /// a manifest of glyph positions (`manifest.json`) and a script (`fedimoji.js`) for showing emoji from them
+    #[clap(long)]
+    emit_web: Option<PathBuf>,
+
+    /// Aseprite JSON export to read the emoji from instead of the emoji directory, naming them after its slices
+    /// (or frames, if it has none)
+    #[clap(long)]
+    aseprite_json: Option<PathBuf>,
+
+    /// TrueType font to read the emoji from instead of the emoji directory, rasterizing each character's glyph and naming
+    /// it after the glyph's name in the font (or its codepoint, like `u1f600`)
+    #[cfg(feature = "font")]
+    #[clap(long, value_name = "PATH")]
+    from_font: Option<PathBuf>,
+
+    /// JSON file of emoji name -> codepoint, to read just those characters from --from-font, under those names
+    #[cfg(feature = "font")]
+    #[clap(long, value_name = "PATH", requires = "from_font")]
+    font_names: Option<PathBuf>,
+
+    /// Read the emoji from this platform's custom emoji export (given with --platform-export) instead of the emoji
+    /// directory, keeping the names they had there
+    #[clap(long, value_enum, requires = "platform_export")]
+    import_platform: Option<Platform>,
+
+    /// Zip exported from a chat platform, with a manifest naming each emoji (`emojis.json` for Discord, `emoji.json`
+    /// for Slack)
+    #[clap(long, requires = "import_platform")]
+    platform_export: Option<PathBuf>,
+
+    /// File listing emoji to download instead of reading the emoji directory, as lines of `name,url`
+    #[cfg(feature = "net")]
+    #[clap(long)]
+    urls: Option<PathBuf>,
+
+    /// Maximum number of emoji to download at once
+    #[cfg(feature = "net")]
+    #[clap(long, default_value_t = 4)]
+    concurrency: usize,
+
+    /// Report the most memory in use at once, once the atlas is built and at the end of the run, to help pick a
+    /// --glyph-size which fits the machine
+    #[cfg(feature = "profile-memory")]
+    #[clap(long, global = true)]
+    profile_memory: bool,
+
+    /// Upload the pack's files to this base URL (such as an S3-compatible bucket's) with an HTTP PUT of each, after
+    /// they're written to the output directory
+    #[cfg(feature = "net")]
+    #[clap(long, conflicts_with = "update_zip")]
+    upload: Option<String>,
+
+    /// Bits per channel of the atlas (Minecraft only uses 8, but 16 preserves the precision of 16-bit sources)
+    #[clap(long, value_enum, default_value_t = BitDepth::Eight)]
+    atlas_bit_depth: BitDepth,
+
+    /// Left-align each glyph's content in its cell, so narrow emoji take up less space in chat
+    #[clap(long)]
+    variable_advance: bool,
+
+    /// Render this text (such as the pack's author and license) into extra glyphs named `_credits_0`, `_credits_1` and
+    /// so on, at the last codepoints of the private use plane, so the credits can be shown in chat
+    #[clap(long)]
+    credits_text: Option<String>,
+
+    /// Slice emoji wider than this aspect ratio (such as 1.5) into square glyphs named `name_0`, `name_1` and so on,
+    /// which are typed one after another to show the whole banner
+    #[clap(long, value_name = "RATIO")]
+    slice_wide: Option<f32>,
+
+    /// Image (such as a badge) to blend onto a corner of every emoji
+    #[clap(long)]
+    overlay: Option<PathBuf>,
+
+    /// Corner of each emoji to put the overlay in
+    #[clap(long, value_enum, default_value_t = Corner::BottomRight)]
+    overlay_position: Corner,
+
+    /// Drop shadow to put beneath every emoji, as `offset,blur,color` (like `2,1.5,#00000080`), growing the glyphs
+    /// to make room for it
+    #[clap(long, value_name = "OFFSET,BLUR,COLOR")]
+    drop_shadow: Option<DropShadow>,
+
+    /// Solid outline to trace around every emoji, as `width,color` (like `2,#ffffff`) for a sticker look, growing
+    /// the glyphs to make room for it
+    #[clap(long, value_name = "WIDTH,COLOR")]
+    outline: Option<Outline>,
+
+    /// Rotate the hue of every emoji by this many degrees, for a themed variant of the pack
+    #[clap(long, value_name = "DEGREES", allow_negative_numbers = true)]
+    hue_shift: Option<f32>,
+
+    /// Multiply the saturation of every emoji by this factor (0 makes them gray)
+    #[clap(long, value_name = "FACTOR")]
+    saturation: Option<f32>,
+
+    /// Multiply the brightness of every emoji by this factor
+    #[clap(long, value_name = "FACTOR")]
+    brightness: Option<f32>,
+
+    /// Reduce each of red, green and blue to this many levels, for a flatter, retro look
+    #[clap(long, value_name = "LEVELS", value_parser = clap::value_parser!(u8).range(2..))]
+    color_levels: Option<u8>,
+
+    /// How to spread out the error when reducing colors with --color-levels; dithering smooths gradients, but
+    /// makes the atlas PNG larger
+    #[clap(long, value_enum, default_value_t = Dither::None)]
+    dither: Dither,
+
+    /// Set the color of fully transparent pixels, so colors hidden under them can't fringe the emoji in-game: `zero`
+    /// makes them black, and `bleed` spreads the colors of the emoji's edges into them, for clean mipmaps
+    #[clap(long, value_enum)]
+    normalize_transparency: Option<TransparencyFill>,
+
+    /// Round the number of rows in the atlas up to a power of two, leaving the extra rows blank
+    #[clap(long)]
+    pad_to_pow2: bool,
+
+    /// Crop rows of nothing but blank padding off the bottom of the atlas, keeping every row with a real glyph
+    #[clap(long)]
+    trim_atlas: bool,
+
+    /// Image to start the atlas from, such as hand-drawn glyphs, placed at its top left; emoji are placed over it
+    /// except in the --reserve-region areas
+    #[clap(long, value_name = "PNG")]
+    atlas_template: Option<PathBuf>,
+
+    /// Area of the atlas (in pixels) to leave alone, as `x,y,width,height` (like `0,0,256,64`), so no emoji are placed
+    /// in cells overlapping it (can be given multiple times)
+    #[clap(long, value_name = "X,Y,W,H")]
+    reserve_region: Vec<Region>,
+
+    /// Warn about emoji whose coverage (mean alpha across the glyph, from 0 to 1) is below this once resized
+    #[clap(long)]
+    min_coverage: Option<f32>,
+
+    /// Skip emoji which are fully or mostly transparent once processed, rather than only warning about them
+    #[clap(long)]
+    skip_blank: bool,
+
+    /// Only allocate new codepoints after this one (in hex like `U+F0010`), or after the one in this file
+    #[clap(long, value_name = "FILE_OR_HEX")]
+    codepoint_start_after: Option<String>,
+
+    /// How to lay out the codepoints of new emoji: `block-aligned` starts each group (from the `group` in sidecar
+    /// metadata) on a multiple of 16, leaving gaps so the groups are easy to pick out in the codepoint map
+    #[clap(long, value_enum, default_value_t = AllocationStrategy::Dense)]
+    allocation_strategy: AllocationStrategy,
+
+    /// Only allocate new codepoints which can't affect bidirectional text or combine with other characters
+    #[clap(long)]
+    safe_codepoints: bool,
+
+    /// File listing codepoints never to give new emoji, such as ones some clients render as boxes, one per line (like
+    /// `U+F0010`) or as ranges (`U+F0100-U+F010F`)
+    #[clap(long, value_name = "FILE")]
+    client_blocklist: Option<PathBuf>,
+
+    /// Give the Nth emoji, sorted by name, the Nth codepoint (after --codepoint-start-after, if given), ignoring any
+    /// imported mapping; adding or removing an emoji shifts the codepoints of the ones after it
+    #[clap(long)]
+    positional_codepoints: bool,
+
+    /// Fail if any emoji would get a different codepoint than the imported mapping gives it, which would change what
+    /// messages already using it show
+    #[clap(long)]
+    fail_on_codepoint_reassignment: bool,
+
+    /// Work out the provider's ascent from where the emoji's content is, so it sits level with the text
+    #[clap(long, conflicts_with = "ascent")]
+    auto_ascent: bool,
+
+    /// How far the top of the emoji sits above the text baseline, out of 8; emoji can override it with `"ascent"`
+    /// in their JSON sidecar file
+    #[clap(long, default_value_t = 8, allow_negative_numbers = true)]
+    ascent: i32,
+
+    /// Let emoji which look nearly identical (e.g. differing only by compression artifacts) share one glyph in the
+    /// atlas, each keeping its own codepoint
+    #[clap(long)]
+    two_pass_dedupe: bool,
+
+    /// How many bits (of 128) the perceptual hashes of emoji may differ by for --two-pass-dedupe to share their
+    /// glyph; higher is looser
+    #[clap(
+        long,
+        value_name = "BITS",
+        default_value_t = 4,
+        requires = "two_pass_dedupe"
+    )]
+    similarity_threshold: u32,
+
+    /// Minecraft version to lay out the atlas for, as versions before 1.20 want rows of 16 glyphs
+    #[clap(long, value_name = "VERSION")]
+    mc_version: Option<String>,
+
+    /// Choose the number of glyphs in each row of the atlas to make it as square as possible, with as little blank
+    /// space as possible, rather than a single column
+    #[clap(long, conflicts_with = "mc_version")]
+    auto_atlas: bool,
+
+    /// Explain why each emoji got its codepoint, without the rest of the `--verbose` output
+    #[clap(long)]
+    explain: bool,
+
+    /// Truncate emoji names to this many characters (at least 3), suffixing them with `_1`, `_2`, ... where that
+    /// makes them collide
+    #[clap(
+        long,
+        global = true,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(3..)
+    )]
+    max_name_length: Option<usize>,
+
+    /// Only pack emoji which aren't in the imported mapping, though `fedimoji.json` still includes all of them
+    #[clap(long, requires = "import")]
+    only_new: bool,
+
+    /// Shell command to run after the pack is written, given the output directory as `$1` and
+    /// `FEDIMOJI_OUTPUT_DIR`; the run fails if it does
+    #[clap(long, value_name = "COMMAND")]
+    post_build: Option<String>,
+
+    /// Print how long each stage of generating the pack took
+    #[clap(long)]
+    timing: bool,
+
+    /// Run every check on the emoji (names, codepoints, duplicates, sizes, coverage and the imported mapping) as if
+    /// generating the pack, but write nothing; the exit code is non-zero if any check fails
+    #[clap(long)]
+    validate_only: bool,
+
+    /// Print the summary of warnings at the end of the run as a JSON object on stderr (`{"total": N, "warnings":
+    /// {kind: count}}`), for scripts
+    #[clap(long, global = true)]
+    warnings_as_json: bool,
+
+    /// What to write to the output directory: the Minecraft resource pack files, or just the atlas and a plain
+    /// description of where each glyph is in it (`glyphs.json`), for other uses
+    #[clap(long, value_enum, default_value_t = OutputFormat::Minecraft)]
+    output_format: OutputFormat,
+
+    /// Format of the log output
+    #[clap(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    log_format: LogFormat,
+
+    #[clap(short = 'v', long, global = true)]
     verbose: bool,
 }
+
+/// What to write to the output directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    /// The atlas, font provider definition and name -> codepoint mapping for a Minecraft resource pack
+    Minecraft,
+
+    /// The atlas, and `glyphs.json` giving each glyph's name, codepoint and rectangle in the atlas
+    Generic,
+}
+
+/// Format of the log output.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum LogFormat {
+    /// Human-readable lines
+    Text,
+
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Clone, clap::Subcommand)]
+enum Command {
+    /// Compare two atlas images, failing if they differ by more than the tolerance
+    Compare {
+        /// The known-good atlas
+        expected: PathBuf,
+
+        /// The atlas to check
+        actual: PathBuf,
+
+        /// Maximum allowed difference of any channel of any pixel
+        #[clap(long, default_value_t = 0)]
+        tolerance: u8,
+    },
+
+    /// Write an image highlighting the pixels which changed between two atlases of the same size
+    DiffAtlas {
+        /// The previous atlas
+        old: PathBuf,
+
+        /// The regenerated atlas
+        new: PathBuf,
+
+        /// Where to write the diff image, as a PNG
+        #[clap(long, value_name = "PNG")]
+        out: PathBuf,
+
+        /// Maximum difference of any channel of a pixel which doesn't count as a change
+        #[clap(long, default_value_t = 0)]
+        tolerance: u8,
+    },
+
+    /// List the emoji which would be packed from the emoji directory, without generating anything
+    List,
+
+    /// Print just the number of emoji which would be packed from the emoji directory, without reading the images
+    Count,
+
+    /// Print the codepoints an existing mapping (like `fedimoji.json`) uses, in order, and the gaps between them, for
+    /// planning codepoint ranges which don't overlap another pack's
+    Codepoints {
+        /// The mapping to read
+        map: PathBuf,
+    },
+
+    /// Write synthetic emoji images at the glyph size into a directory, for benchmarking and testing packing without
+    /// real art
+    GenTestPack {
+        /// Number of emoji to write
+        #[clap(long)]
+        count: u32,
+
+        /// Directory to write them into
+        #[clap(long)]
+        out: PathBuf,
+
+        /// Fill the emoji with pseudorandom noise rather than a shaded color, which is harder to compress
+        #[clap(long)]
+        noise: bool,
+    },
+
+    /// Pack the glyphs of an existing atlas again, e.g. at a new `--glyph-size`, keeping the codepoints of the
+    /// `--import`ed mapping
+    Repack {
+        /// The existing atlas (`emoji.png`)
+        atlas: PathBuf,
+
+        /// The existing font provider definition (`emoji.json`), describing the atlas layout
+        provider: PathBuf,
+    },
+}
@@ -0,0 +1,346 @@
+//! Downloading emoji images from URLs.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use image::DynamicImage;
+use tracing::{debug, info, warn};
+
+use crate::{PackConfig, PackError};
+
+/// An emoji image to be downloaded.
+#[derive(Debug, Clone)]
+pub struct EmojiUrl {
+    /// The normalized emoji name.
+    pub name: String,
+
+    /// URL of the image.
+    pub url: String,
+}
+
+/// Reads a list of emoji URLs.
+///
+/// The list is either a JSON object mapping emoji name -> URL (if the file has a `.json` extension), or lines of
+/// `name,url`. Blank lines and lines starting with `#` are ignored.
+pub fn read_url_list(path: &Path, config: &PackConfig) -> Result<Vec<EmojiUrl>, PackError> {
+    let contents = std::fs::read_to_string(path)?;
+
+    let pairs: Vec<(String, String)> = if path.extension() == Some("json".as_ref()) {
+        serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&contents)?
+            .into_iter()
+            .filter_map(|(name, url)| match url {
+                serde_json::Value::String(url) => Some((name, url)),
+                _ => {
+                    warn!(
+                        kind = "skipped",
+                        "URL for \"{name}\" is not a string (skipping it)"
+                    );
+                    None
+                }
+            })
+            .collect()
+    } else {
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| match line.split_once(',') {
+                Some((name, url)) => Some((name.trim().to_string(), url.trim().to_string())),
+                None => {
+                    warn!(
+                        kind = "skipped",
+                        "malformed URL list line \"{line}\" (skipping it)"
+                    );
+                    None
+                }
+            })
+            .collect()
+    };
+
+    let mut urls = pairs
+        .into_iter()
+        .map(|(name, url)| EmojiUrl {
+            name: config.normalize_name(&name),
+            url,
+        })
+        .collect::<Vec<_>>();
+
+    config.shorten_names(urls.iter_mut().map(|url| &mut url.name))?;
+    Ok(urls)
+}
+
+/// Downloads the emoji images, with at most `concurrency` downloads at once, skipping any which fail.
+///
+/// The images are returned in the same order as the URLs.
+pub fn download_emoji(urls: &[EmojiUrl], concurrency: usize) -> Vec<(String, DynamicImage)> {
+    let next = AtomicUsize::new(0);
+    let downloaded: Mutex<Vec<(usize, String, DynamicImage)>> = Mutex::new(Vec::new());
+
+    std::thread::scope(|scope| {
+        for _ in 0..concurrency.clamp(1, urls.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(emoji) = urls.get(index) else {
+                    break;
+                };
+
+                match download(&emoji.url) {
+                    Err(err) => {
+                        warn!(
+                            kind = "decode_failure",
+                            "failed to download \"{}\" (skipping it): {}", emoji.url, err
+                        );
+                    }
+                    Ok(image) => {
+                        debug!("downloaded \"{}\"", emoji.url);
+                        downloaded
+                            .lock()
+                            .unwrap()
+                            .push((index, emoji.name.clone(), image));
+                    }
+                }
+            });
+        }
+    });
+
+    let mut downloaded = downloaded.into_inner().unwrap();
+    downloaded.sort_by_key(|(index, _, _)| *index);
+    downloaded
+        .into_iter()
+        .map(|(_, name, image)| (name, image))
+        .collect()
+}
+
+/// Downloads and decodes a single image.
+fn download(url: &str) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let bytes = ureq::get(url).call()?.into_body().read_to_vec()?;
+    Ok(crate::scan::expand_to_rgba(image::load_from_memory(
+        &bytes,
+    )?))
+}
+
+/// Uploads files from the output directory with an HTTP PUT of each to its path under `base_url`, such as an
+/// S3-compatible bucket's URL.
+///
+/// If `FEDIMOJI_UPLOAD_TOKEN` is set, it's sent as a bearer token. The files are only read, so a failed upload leaves
+/// them as they were.
+pub fn upload(base_url: &str, output_dir: &Path, files: &[PathBuf]) -> Result<(), PackError> {
+    let token = std::env::var("FEDIMOJI_UPLOAD_TOKEN").ok();
+    for file in files {
+        let key = file
+            .strip_prefix(output_dir)
+            .unwrap_or(file)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let url = format!("{}/{key}", base_url.trim_end_matches('/'));
+        let upload_error = |message: String| PackError::Upload {
+            url: url.clone(),
+            message,
+        };
+
+        let contents = std::fs::read(file).map_err(|err| upload_error(err.to_string()))?;
+        let mut request = ureq::put(&url).header("Content-Type", content_type(file));
+        if let Some(token) = &token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        request
+            .send(&contents[..])
+            .map_err(|err| upload_error(err.to_string()))?;
+        debug!("uploaded `{}` to \"{url}\"", file.display());
+    }
+    info!("uploaded {} files to \"{base_url}\"", files.len());
+    Ok(())
+}
+
+/// The MIME type to upload a file as, going by its extension.
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("png") => "image/png",
+        Some("json" | "mcmeta") => "application/json",
+        Some("json5") => "application/json5",
+        Some("md") => "text/markdown",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    #[test]
+    fn url_lists_are_read_as_json_or_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PackConfig::default();
+
+        let lines = dir.path().join("urls.txt");
+        std::fs::write(
+            &lines,
+            "# emoji\nBlobcat, https://example.com/blobcat.png\n\nmalformed\n",
+        )
+        .unwrap();
+        let urls = read_url_list(&lines, &config).unwrap();
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].name, "blobcat");
+        assert_eq!(urls[0].url, "https://example.com/blobcat.png");
+
+        let json = dir.path().join("urls.json");
+        std::fs::write(
+            &json,
+            r#"{"neofox": "https://example.com/neofox.png", "broken": 5}"#,
+        )
+        .unwrap();
+        let urls = read_url_list(&json, &config).unwrap();
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].name, "neofox");
+    }
+
+    #[test]
+    fn downloads_keep_their_order_and_skip_failures() {
+        let mut png = Vec::new();
+        RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        // serve the image at any path but `/missing.png`
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                BufReader::new(&stream)
+                    .read_line(&mut request_line)
+                    .unwrap();
+                let response = if request_line.contains("/missing.png") {
+                    b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_vec()
+                } else {
+                    let mut response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        png.len()
+                    )
+                    .into_bytes();
+                    response.extend_from_slice(&png);
+                    response
+                };
+                stream.write_all(&response).unwrap();
+            }
+        });
+
+        let urls = ["a", "missing", "b", "c"]
+            .map(|name| EmojiUrl {
+                name: name.to_string(),
+                url: format!("{base}/{name}.png"),
+            })
+            .to_vec();
+        let downloaded = download_emoji(&urls, 3);
+        let names = downloaded
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(downloaded[0].1.width(), 4);
+    }
+
+    /// Serves PUT requests, answering each with `status` and sending its path, content type and body down a channel.
+    fn put_server(status: u16) -> (String, std::sync::mpsc::Receiver<(String, String, Vec<u8>)>) {
+        use std::io::Read;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let (mut content_type, mut length) = (String::new(), 0);
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let Some((name, value)) = header.trim_end().split_once(": ") else {
+                        break;
+                    };
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-type" => content_type = value.to_string(),
+                        "content-length" => length = value.parse().unwrap(),
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let path = request_line.split(' ').nth(1).unwrap().to_string();
+                sender.send((path, content_type, body)).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Whatever\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+            }
+        });
+        (base, receiver)
+    }
+
+    #[test]
+    fn uploads_put_each_file_at_its_path() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("font")).unwrap();
+        let files = [
+            (dir.path().join("emoji.json"), b"{}".as_slice()),
+            (
+                dir.path().join("font").join("emoji.png"),
+                b"\x89PNG".as_slice(),
+            ),
+        ];
+        for (path, contents) in &files {
+            std::fs::write(path, contents).unwrap();
+        }
+        let paths = files
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+
+        let (base, requests) = put_server(200);
+        upload(&format!("{base}/bucket/"), dir.path(), &paths).unwrap();
+        assert_eq!(
+            requests.try_iter().collect::<Vec<_>>(),
+            [
+                (
+                    "/bucket/emoji.json".to_string(),
+                    "application/json".to_string(),
+                    b"{}".to_vec()
+                ),
+                (
+                    "/bucket/font/emoji.png".to_string(),
+                    "image/png".to_string(),
+                    b"\x89PNG".to_vec()
+                ),
+            ]
+        );
+
+        // a failed upload is an error, and leaves the files as they were
+        let (base, _requests) = put_server(500);
+        assert!(matches!(
+            upload(&base, dir.path(), &paths),
+            Err(PackError::Upload { .. })
+        ));
+        for (path, contents) in &files {
+            assert_eq!(std::fs::read(path).unwrap(), *contents);
+        }
+    }
+}
@@ -0,0 +1,1797 @@
+//! Writing the generated files.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use image::{codecs::png::CompressionType, imageops::FilterType, DynamicImage, Rgba, RgbaImage};
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use crate::{name_key, scan::EmojiMetadata, Pack, PackError};
+
+/// Destination for the files making up a pack.
+pub struct PackOutput {
+    sink: Sink,
+
+    /// Paths of the files written so far, including any which were already up to date.
+    written: Vec<PathBuf>,
+}
+
+enum Sink {
+    /// Files are written into a directory.
+    Dir(PathBuf),
+
+    /// Files are streamed as entries of a tar archive.
+    Tar(tar::Builder<Box<dyn Write>>),
+
+    /// Files are kept until the end, then put in an existing resource pack zip at their places in the pack.
+    Zip {
+        path: PathBuf,
+        entries: BTreeMap<String, Vec<u8>>,
+    },
+}
+
+impl PackOutput {
+    /// Writes the files into a directory, creating it if it doesn't exist.
+    pub fn dir(dir: &Path) -> Result<Self, PackError> {
+        if dir.exists() && !dir.is_dir() {
+            return Err(PackError::OutputNotDir(dir.to_path_buf()));
+        }
+        std::fs::create_dir_all(dir).map_err(|source| PackError::Write {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+        Ok(Self {
+            sink: Sink::Dir(dir.to_path_buf()),
+            written: Vec::new(),
+        })
+    }
+
+    /// Streams the files as a tar archive to a writer.
+    pub fn tar(writer: impl Write + 'static) -> Self {
+        Self {
+            sink: Sink::Tar(tar::Builder::new(Box::new(writer))),
+            written: Vec::new(),
+        }
+    }
+
+    /// Updates the files inside an existing resource pack zip, leaving its other entries alone.
+    ///
+    /// Nothing is written until [`PackOutput::finish`], which puts each file at its place in the pack (see
+    /// [`pack_entry_path`]).
+    pub fn zip(path: &Path) -> Result<Self, PackError> {
+        if !path.is_file() {
+            return Err(PackError::ZipMissing(path.to_path_buf()));
+        }
+        Ok(Self {
+            sink: Sink::Zip {
+                path: path.to_path_buf(),
+                entries: BTreeMap::new(),
+            },
+            written: Vec::new(),
+        })
+    }
+
+    /// Paths of the files written so far: within the directory, or within the archive.
+    pub fn written(&self) -> &[PathBuf] {
+        &self.written
+    }
+
+    /// Writes a file, at a path relative to the root of the output.
+    pub fn write(&mut self, path: &str, contents: &[u8]) -> Result<(), PackError> {
+        match &mut self.sink {
+            Sink::Dir(dir) => {
+                let path = dir.join(path);
+                let written = path
+                    .parent()
+                    .map_or(Ok(()), std::fs::create_dir_all)
+                    .and_then(|()| write_if_changed(&path, contents))
+                    .map_err(|source| PackError::Write {
+                        path: path.clone(),
+                        source,
+                    })?;
+                if written {
+                    debug!("wrote `{}`", path.display());
+                }
+                self.written.push(path);
+            }
+            Sink::Tar(builder) => {
+                // a fixed mtime keeps the archive reproducible
+                let mut header = tar::Header::new_gnu();
+                header.set_size(contents.len() as u64);
+                header.set_mode(0o644);
+                header.set_mtime(0);
+                builder
+                    .append_data(&mut header, path, contents)
+                    .map_err(|source| PackError::Write {
+                        path: PathBuf::from(path),
+                        source,
+                    })?;
+                debug!("wrote `{path}` to archive");
+                self.written.push(PathBuf::from(path));
+            }
+            Sink::Zip { entries, .. } => {
+                let entry = pack_entry_path(path);
+                entries.insert(entry.clone(), contents.to_vec());
+                self.written.push(PathBuf::from(entry));
+            }
+        }
+        Ok(())
+    }
+
+    /// Finishes writing, which for an archive writes its footer.
+    pub fn finish(self) -> Result<(), PackError> {
+        match self.sink {
+            Sink::Dir(_) => Ok(()),
+            Sink::Tar(builder) => builder
+                .into_inner()
+                .and_then(|mut writer| writer.flush())
+                .map_err(|source| PackError::Write {
+                    path: PathBuf::from("-"),
+                    source,
+                }),
+            Sink::Zip { path, entries } => update_zip(&path, entries),
+        }
+    }
+}
+
+/// Where a file of the pack goes in a resource pack, as laid out in the template: the atlas and font provider where
+/// the font definition includes them, and anything else in the `fedimoji` namespace.
+///
+/// An atlas in a subdirectory (from `--atlas-subpath`) goes in that directory of the namespace's textures.
+pub fn pack_entry_path(path: &str) -> String {
+    match path {
+        "emoji.png" | "emoji.png.mcmeta" => format!("assets/fedimoji/textures/font/{path}"),
+        "emoji.json" => format!("assets/fedimoji/font/include/{path}"),
+        _ if path.ends_with("/emoji.png") || path.ends_with("/emoji.png.mcmeta") => {
+            format!("assets/fedimoji/textures/{path}")
+        }
+        _ => format!("assets/fedimoji/{path}"),
+    }
+}
+
+/// Parses a directory of the namespace's textures to put the atlas in, as given to `--atlas-subpath`, like
+/// `font/v3`.
+///
+/// Minecraft only allows lowercase letters, digits, `_`, `-` and `.` in resource paths, and the directories can't be
+/// empty, `.` or `..`.
+pub fn parse_atlas_subpath(subpath: &str) -> Result<String, String> {
+    let subpath = subpath.trim().trim_matches('/');
+    if let Some(c) = subpath
+        .chars()
+        .find(|c| !matches!(c, 'a'..='z' | '0'..='9' | '_' | '-' | '.' | '/'))
+    {
+        return Err(format!("{c:?} isn't allowed in a resource path"));
+    }
+    if subpath
+        .split('/')
+        .any(|directory| matches!(directory, "" | "." | ".."))
+    {
+        return Err("directories can't be empty, `.` or `..`".to_string());
+    }
+    Ok(subpath.to_string())
+}
+
+/// Parses a PNG compression setting: `fast` (the default), `default`, `best`, or a level from 0 (uncompressed) to 9.
+pub fn parse_png_compression(level: &str) -> Result<CompressionType, String> {
+    match level.trim() {
+        "fast" => Ok(CompressionType::Fast),
+        "default" => Ok(CompressionType::Default),
+        "best" => Ok(CompressionType::Best),
+        "0" => Ok(CompressionType::Uncompressed),
+        level => match level.parse::<u8>() {
+            Ok(level @ 1..=9) => Ok(CompressionType::Level(level)),
+            _ => Err("expected `fast`, `default`, `best` or a level from 0 to 9".to_string()),
+        },
+    }
+}
+
+/// Rewrites a zip with the given entries, replacing any it already has with the same names, and copying the rest
+/// across untouched.
+fn update_zip(path: &Path, entries: BTreeMap<String, Vec<u8>>) -> Result<(), PackError> {
+    let zip_error = |source| PackError::Zip {
+        path: path.to_path_buf(),
+        source,
+    };
+    let write_error = |source| PackError::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?).map_err(zip_error)?;
+
+    // build the new zip next to the old one and swap it in at the end, so a failure leaves the old one intact
+    let temp_path = path.with_extension("zip.tmp");
+    add_pending(&temp_path);
+    let mut writer = zip::ZipWriter::new(std::fs::File::create(&temp_path).map_err(write_error)?);
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index).map_err(zip_error)?;
+        if entries.contains_key(entry.name()) {
+            debug!("replacing `{}` in `{}`", entry.name(), path.display());
+            continue;
+        }
+        writer.raw_copy_file(entry).map_err(zip_error)?;
+    }
+
+    // a fixed modification time keeps the entries reproducible
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(zip::DateTime::default());
+    for (name, contents) in entries {
+        writer
+            .start_file(name.as_str(), options)
+            .map_err(zip_error)?;
+        writer.write_all(&contents).map_err(write_error)?;
+        debug!("wrote `{name}` to `{}`", path.display());
+    }
+    writer
+        .finish()
+        .map_err(zip_error)?
+        .sync_all()
+        .map_err(write_error)?;
+
+    let result = std::fs::rename(&temp_path, path).map_err(write_error);
+    remove_pending(&temp_path);
+    result
+}
+
+/// Checks that an output directory isn't the emoji directory or inside it, where the atlas it's given would be read
+/// as an emoji on the next run.
+pub fn check_outside_emoji_dir(dir: &Path, emoji_dir: &Path) -> Result<(), PackError> {
+    let (Some(dir), Ok(emoji_dir)) = (resolve(dir), emoji_dir.canonicalize()) else {
+        return Ok(());
+    };
+    if dir.starts_with(&emoji_dir) {
+        return Err(PackError::OutputInEmojiDir(dir));
+    }
+    Ok(())
+}
+
+/// Resolves a path which may not exist yet, by resolving the part of it which does.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let path = std::path::absolute(path).ok()?;
+    path.ancestors().find_map(|ancestor| {
+        let resolved = ancestor.canonicalize().ok()?;
+        let rest = path.strip_prefix(ancestor).ok()?;
+        Some(if rest.as_os_str().is_empty() {
+            resolved
+        } else {
+            resolved.join(rest)
+        })
+    })
+}
+
+/// Name of the file in the output directory recording every codepoint given to an emoji, so later runs keep them.
+pub const PERSISTED_ALLOCATION_FILE: &str = "allocation.json";
+
+/// Name of the lock file held in the output directory while a run writes to it.
+pub const LOCK_FILE_NAME: &str = ".fedimoji.lock";
+
+/// How old a lock has to be to be treated as left behind by a crashed run.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// An advisory lock on an output directory, so concurrent runs don't write over each other's files.
+///
+/// The lock file holds the process ID and the time it was taken, and is removed when this is dropped.
+pub struct OutputLock {
+    path: PathBuf,
+}
+
+impl OutputLock {
+    /// Takes the lock on a directory, waiting for another run to finish with it if `wait` is set, or failing if
+    /// not. Locks left behind by crashed runs are taken over.
+    pub fn acquire(dir: &Path, wait: bool) -> Result<Self, PackError> {
+        let path = dir.join(LOCK_FILE_NAME);
+        let mut waiting = false;
+        loop {
+            // creating the file only if it doesn't exist is atomic, so only one run can win
+            match std::fs::File::create_new(&path) {
+                Ok(mut file) => {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default();
+                    write!(file, "{}\n{}\n", std::process::id(), now.as_secs()).map_err(
+                        |source| PackError::Write {
+                            path: path.clone(),
+                            source,
+                        },
+                    )?;
+                    debug!("locked `{}`", path.display());
+                    add_pending(&path);
+                    return Ok(Self { path });
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(source) => return Err(PackError::Write { path, source }),
+            }
+
+            // the lock may have been released since, in which case this reads nothing
+            let contents = std::fs::read_to_string(&path).unwrap_or_default();
+            let modified = std::fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            let mut lines = contents.lines();
+            let pid = lines.next().unwrap_or_default().trim().to_string();
+            let taken_at = lines
+                .next()
+                .and_then(|line| line.trim().parse::<u64>().ok());
+            if is_stale_lock(&pid, taken_at, modified) {
+                if take_over_stale_lock(&path, &contents) {
+                    warn!("took over a stale lock on the output directory, left by process {pid}");
+                }
+                continue;
+            }
+
+            if !wait {
+                return Err(PackError::OutputLocked { path, pid });
+            }
+            if !waiting {
+                info!("waiting for process {pid} to finish with the output directory");
+                waiting = true;
+            }
+            std::thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        remove_pending(&self.path);
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            warn!("failed to remove lock `{}`: {}", self.path.display(), err);
+        }
+    }
+}
+
+/// Removes a lock judged stale from what it held, returning whether it did.
+///
+/// Another run may have taken the lock over and made its own since it was read, so the lock is first moved aside
+/// (which only one run can do), and put back if it isn't the one which was judged stale.
+fn take_over_stale_lock(path: &Path, stale_contents: &str) -> bool {
+    let aside = path.with_extension(format!("stale-{}", std::process::id()));
+    if std::fs::rename(path, &aside).is_err() {
+        // gone already, so there's nothing to take over
+        return false;
+    }
+    let is_stale = std::fs::read_to_string(&aside).is_ok_and(|contents| contents == stale_contents);
+    if !is_stale {
+        // linking only succeeds if there's no lock in its place, so a newer one is never overwritten
+        if let Err(err) = std::fs::hard_link(&aside, path) {
+            warn!(
+                "failed to put back the lock `{}` of another run: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+    let _ = std::fs::remove_file(&aside);
+    is_stale
+}
+
+/// Whether a lock was left behind by a run which is no longer going: one whose process has exited (where we can
+/// tell), or which is very old (by the time in it, or when it was last modified if it has none).
+fn is_stale_lock(pid: &str, taken_at: Option<u64>, modified: Option<SystemTime>) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    // a lock which is still being written has no time yet, but one which never got one is left behind
+    let Some(taken_at) = taken_at else {
+        let modified = modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or(now);
+        return now.saturating_sub(modified) > STALE_LOCK_AGE;
+    };
+    if now.as_secs().saturating_sub(taken_at) > STALE_LOCK_AGE.as_secs() {
+        return true;
+    }
+
+    // only Linux makes it easy to check for a process
+    if cfg!(target_os = "linux") && !pid.is_empty() {
+        return !Path::new("/proc").join(pid).exists();
+    }
+    false
+}
+
+/// Writes a file, unless it already has exactly these contents, returning whether it was written.
+///
+/// Skipping identical writes avoids needlessly touching files, which would otherwise trigger anything watching them.
+pub fn write_if_changed(path: &Path, contents: &[u8]) -> io::Result<bool> {
+    match std::fs::read(path) {
+        Ok(existing) if existing == contents => {
+            debug!("`{}` is unchanged", path.display());
+            return Ok(false);
+        }
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    write_atomically(path, contents)?;
+    Ok(true)
+}
+
+/// Files which shouldn't outlive the run: temporary files being written, and locks being held.
+static PENDING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Removes the temporary files and locks of an interrupted run, so it leaves nothing half-written behind.
+pub fn remove_pending_files() {
+    for path in PENDING.lock().unwrap().drain(..) {
+        if std::fs::remove_file(&path).is_ok() {
+            debug!("removed `{}`", path.display());
+        }
+    }
+}
+
+/// Tracks a file which should be removed if the run is interrupted.
+fn add_pending(path: &Path) {
+    PENDING.lock().unwrap().push(path.to_path_buf());
+}
+
+/// Stops tracking a file, once it's been renamed into place or removed.
+fn remove_pending(path: &Path) {
+    PENDING.lock().unwrap().retain(|pending| pending != path);
+}
+
+/// Path of the temporary file a file is written to before being renamed into place, next to it so the rename stays
+/// within one filesystem.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Writes a file by writing a temporary file and renaming it over the old one, so the file is never seen (or left,
+/// if the run is interrupted) half-written.
+///
+/// The temporary file is synced to disk before the rename, so a crash can't leave the renamed file empty either.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp_path = temp_path(path);
+    add_pending(&temp_path);
+    let result = std::fs::File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    remove_pending(&temp_path);
+    result
+}
+
+/// Writes a name -> codepoint mapping as CSV, with a row per emoji sorted by name.
+pub fn names_csv(names: &BTreeMap<String, char>) -> csv::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["name", "codepoint_hex", "codepoint_decimal", "char"])?;
+    for (name, codepoint) in names {
+        writer.write_record([
+            name.as_str(),
+            &format!("U+{:04X}", *codepoint as u32),
+            &(*codepoint as u32).to_string(),
+            &codepoint.to_string(),
+        ])?;
+    }
+    Ok(writer.into_inner().expect("writing to memory can't fail"))
+}
+
+/// Writes a Markdown changelog of how the emoji changed since an old name -> codepoint mapping, for release notes:
+/// the emoji added, the emoji removed, and the emoji whose codepoint changed. Names are compared case-insensitively.
+pub fn changelog(old: &HashMap<String, char>, new: &BTreeMap<String, char>) -> String {
+    let codepoint = |c: &char| format!("U+{:04X}", *c as u32);
+    let new_keys = new
+        .keys()
+        .map(|name| name_key(name))
+        .collect::<BTreeSet<_>>();
+
+    let mut added = Vec::new();
+    let mut reassigned = Vec::new();
+    for (name, new_codepoint) in new {
+        match old.get(&name_key(name)) {
+            None => added.push(format!("`{name}` ({})", codepoint(new_codepoint))),
+            Some(old_codepoint) if old_codepoint != new_codepoint => reassigned.push(format!(
+                "`{name}`: {} → {}",
+                codepoint(old_codepoint),
+                codepoint(new_codepoint)
+            )),
+            Some(_) => {}
+        }
+    }
+    let removed = old
+        .iter()
+        .filter(|(name, _)| !new_keys.contains(*name))
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .map(|(name, old_codepoint)| format!("`{name}` ({})", codepoint(old_codepoint)))
+        .collect::<Vec<_>>();
+
+    let mut changelog = String::from("# Emoji changes\n");
+    if added.is_empty() && removed.is_empty() && reassigned.is_empty() {
+        changelog.push_str("\nNo changes.\n");
+    }
+    for (heading, entries) in [
+        ("Added", added),
+        ("Removed", removed),
+        ("Reassigned codepoints", reassigned),
+    ] {
+        if entries.is_empty() {
+            continue;
+        }
+        changelog.push_str(&format!("\n## {heading} ({})\n\n", entries.len()));
+        for entry in entries {
+            changelog.push_str(&format!("- {entry}\n"));
+        }
+    }
+    changelog
+}
+
+/// Describes which codepoints the pack uses, for coordinating with other packs sharing the private use area: the
+/// range they span, every one used, and the last one, which `--codepoint-start-after` can take to allocate after it.
+pub fn allocation_report(pack: &Pack) -> serde_json::Value {
+    let codepoint = |c: &char| format!("U+{:04X}", *c as u32);
+    let used = pack
+        .names
+        .values()
+        .chain(pack.aliases.keys())
+        .chain(pack.chars.iter().filter(|c| **c != '\0'))
+        .copied()
+        .collect::<BTreeSet<char>>();
+    let (first, last) = (used.first(), used.last());
+    json!({
+        "range": [first.map(codepoint), last.map(codepoint)],
+        "used": used.iter().map(codepoint).collect::<Vec<_>>(),
+        "free_after": last.map(codepoint),
+    })
+}
+
+/// Writes an HTML gallery previewing every glyph in the pack, with its name and codepoint.
+///
+/// The glyphs are shown by positioning the atlas as a CSS sprite, so it expects the atlas alongside it as
+/// `emoji.png`.
+pub fn html_gallery(pack: &Pack) -> String {
+    let (glyph_width, glyph_height) = glyph_size(pack);
+
+    let mut html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>fedimoji preview</title>
+<style>
+body {{ font-family: sans-serif; margin: 1rem; }}
+.gallery {{ display: grid; grid-template-columns: repeat(auto-fill, minmax({min_width}px, 1fr)); gap: 1rem; }}
+.emoji {{ display: flex; flex-direction: column; align-items: center; text-align: center; word-break: break-all; }}
+.glyph {{ width: {glyph_width}px; height: {glyph_height}px; background-image: url("emoji.png"); image-rendering: pixelated; }}
+.codepoint {{ color: gray; font-size: 0.8em; }}
+</style>
+</head>
+<body>
+<div class="gallery">
+"#,
+        min_width = glyph_width.max(96),
+    );
+
+    for GlyphCell {
+        name,
+        codepoint,
+        x,
+        y,
+    } in glyph_cells(pack)
+    {
+        html.push_str(&format!(
+            "<div class=\"emoji\"><div class=\"glyph\" style=\"background-position: -{x}px -{y}px\"></div>\
+             <span class=\"name\">:{}:</span><span class=\"codepoint\">U+{:04X}</span></div>\n",
+            escape_html(name),
+            codepoint as u32
+        ));
+    }
+
+    html.push_str("</div>\n</body>\n</html>\n");
+    html
+}
+
+/// Default pattern of the suffix which marks an emoji as a variant of another, like `wave_tone2` of `wave`.
+pub const DEFAULT_VARIANT_SUFFIX_PATTERN: &str = "_tone#";
+
+/// The name of the emoji which one named `name` is a variant of, if its name is that with a suffix matching `pattern`,
+/// in which `#` stands for one or more digits.
+pub fn variant_base<'a>(name: &'a str, pattern: &str) -> Option<&'a str> {
+    let base = match pattern.split_once('#') {
+        Some((before, after)) => {
+            let name = name.strip_suffix(after)?;
+            let digits = name.trim_end_matches(|c: char| c.is_ascii_digit());
+            if digits.len() == name.len() {
+                return None;
+            }
+            digits.strip_suffix(before)?
+        }
+        None => name.strip_suffix(pattern)?,
+    };
+    (!base.is_empty()).then_some(base)
+}
+
+/// Writes a list of the pack's emoji for chat clients, with each one's shortcode, character, search keywords and
+/// category.
+///
+/// Emoji without metadata get an empty list of keywords, and no category. Emoji whose name is another's with a suffix
+/// matching `variant_pattern` (see [`variant_base`]) are variants of it, like skin tones: the base emoji lists them in
+/// its `variants`, and each names the base as its `variant_of`, so pickers can show them together.
+pub fn shortcodes(
+    names: &BTreeMap<String, char>,
+    metadata: &HashMap<String, &EmojiMetadata>,
+    variant_pattern: &str,
+) -> serde_json::Value {
+    // the variants of each emoji which has any, in order of name
+    let mut variants: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for name in names.keys() {
+        if let Some(base) =
+            variant_base(name, variant_pattern).filter(|base| names.contains_key(*base))
+        {
+            variants.entry(base).or_default().push(name);
+        }
+    }
+
+    names
+        .iter()
+        .map(|(name, codepoint)| {
+            let metadata = metadata.get(name);
+            let mut entry = json!({
+                "shortcode": name,
+                "char": codepoint.to_string(),
+                "codepoint": format!("U+{:04X}", *codepoint as u32),
+                "keywords": metadata.map_or(&[][..], |metadata| metadata.keywords.as_slice()),
+            });
+            if let Some(category) = metadata.and_then(|metadata| metadata.category.as_ref()) {
+                entry["category"] = json!(category);
+            }
+            if let Some(sound) = metadata.and_then(|metadata| metadata.sound.as_ref()) {
+                entry["sound"] = json!(sound);
+            }
+            if let Some(variants) = variants.get(name.as_str()) {
+                entry["variants"] = json!(variants);
+            }
+            if let Some(base) =
+                variant_base(name, variant_pattern).filter(|base| names.contains_key(*base))
+            {
+                entry["variant_of"] = json!(base);
+            }
+            entry
+        })
+        .collect()
+}
+
+/// Writes the author, license and source of each of the pack's emoji, by name, for crediting them.
+///
+/// Fields missing from an emoji's metadata are `null`, and emoji without metadata are left out.
+pub fn attribution(
+    names: &BTreeMap<String, char>,
+    metadata: &HashMap<String, &EmojiMetadata>,
+) -> serde_json::Value {
+    names
+        .keys()
+        .filter_map(|name| {
+            let metadata = metadata.get(name)?;
+            Some((
+                name.clone(),
+                json!({
+                    "author": metadata.author,
+                    "license": metadata.license,
+                    "source_url": metadata.source_url,
+                }),
+            ))
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Writes a manifest of where each glyph is in the atlas, for the sprite helper in a web bundle.
+pub fn web_manifest(pack: &Pack) -> serde_json::Value {
+    let (glyph_width, glyph_height) = glyph_size(pack);
+    let emoji = glyph_cells(pack)
+        .into_iter()
+        .map(|cell| {
+            let entry = json!({
+                "codepoint": format!("U+{:04X}", cell.codepoint as u32),
+                "x": cell.x,
+                "y": cell.y,
+            });
+            (cell.name.to_string(), entry)
+        })
+        .collect::<serde_json::Map<_, _>>();
+    json!({
+        "atlas": "emoji.png",
+        "glyph_width": glyph_width,
+        "glyph_height": glyph_height,
+        "emoji": emoji,
+    })
+}
+
+/// Describes where each glyph is in the atlas, with nothing specific to Minecraft, for other tools to use the atlas.
+pub fn glyph_rects(pack: &Pack) -> serde_json::Value {
+    let (glyph_width, glyph_height) = glyph_size(pack);
+    let glyphs = glyph_cells(pack)
+        .into_iter()
+        .map(|cell| {
+            json!({
+                "name": cell.name,
+                "codepoint": format!("U+{:04X}", cell.codepoint as u32),
+                "x": cell.x,
+                "y": cell.y,
+                "width": glyph_width,
+                "height": glyph_height,
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "atlas": "emoji.png",
+        "width": pack.atlas.width(),
+        "height": pack.atlas.height(),
+        "glyphs": glyphs,
+    })
+}
+
+/// Indexes the tiles the atlas is cut into, and which tile each glyph is in, at what offset within it, for clients
+/// which load the tiles as they're needed.
+pub fn tile_index(pack: &Pack) -> serde_json::Value {
+    let (glyph_width, glyph_height) = glyph_size(pack);
+    let tiles = pack.tiles();
+    let glyphs = glyph_cells(pack)
+        .into_iter()
+        .filter_map(|cell| {
+            let tile = tiles.iter().find(|tile| tile.contains(cell.x, cell.y))?;
+            Some(json!({
+                "name": cell.name,
+                "codepoint": format!("U+{:04X}", cell.codepoint as u32),
+                "tile": tile.file_name(),
+                "x": cell.x - tile.x,
+                "y": cell.y - tile.y,
+                "width": glyph_width,
+                "height": glyph_height,
+            }))
+        })
+        .collect::<Vec<_>>();
+    let tiles = tiles
+        .iter()
+        .map(|tile| {
+            json!({
+                "file": tile.file_name(),
+                "row": tile.row,
+                "column": tile.column,
+                "x": tile.x,
+                "y": tile.y,
+                "width": tile.width,
+                "height": tile.height,
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "tile_size": pack.tile_size,
+        "tiles": tiles,
+        "glyphs": glyphs,
+    })
+}
+
+/// Writes a custom emoji manifest for Fediverse software, with each emoji's shortcode, the URL of its own image
+/// (`{url_prefix}glyphs/{name}.png`, as cut out by [`glyph_images`]), its character, and its sound from the emoji
+/// metadata, if it has one.
+pub fn fedi_manifest(
+    pack: &Pack,
+    metadata: &HashMap<String, &EmojiMetadata>,
+    url_prefix: &str,
+) -> serde_json::Value {
+    pack.names
+        .iter()
+        .filter(|(_, codepoint)| cell_position(pack, **codepoint).is_some())
+        .map(|(name, codepoint)| {
+            let mut entry = json!({
+                "shortcode": name,
+                "url": format!("{url_prefix}glyphs/{name}.png"),
+                "unicode": codepoint.to_string(),
+            });
+            if let Some(sound) = metadata
+                .get(name)
+                .and_then(|metadata| metadata.sound.as_ref())
+            {
+                entry["sound"] = json!(sound);
+            }
+            entry
+        })
+        .collect()
+}
+
+/// A paper size to lay out the printable preview for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PageSize {
+    /// ISO A4, 210x297mm.
+    A4,
+
+    /// US Letter, 8.5x11in.
+    Letter,
+}
+
+impl PageSize {
+    /// Width and height of the page, in inches.
+    fn inches(self) -> (f64, f64) {
+        match self {
+            PageSize::A4 => (210.0 / 25.4, 297.0 / 25.4),
+            PageSize::Letter => (8.5, 11.0),
+        }
+    }
+
+    /// Width and height of the page at a resolution, in pixels.
+    pub fn pixels(self, dpi: u32) -> (u32, u32) {
+        let (width, height) = self.inches();
+        (
+            (width * f64::from(dpi)).round() as u32,
+            (height * f64::from(dpi)).round() as u32,
+        )
+    }
+}
+
+/// Lays out every emoji on pages of the given size for printing (such as a sticker sheet), each an inch square with
+/// its name underneath, on white within half-inch margins. Emoji which don't fit on one page continue on the next.
+pub fn print_preview(pack: &Pack, page: PageSize, dpi: u32) -> Vec<RgbaImage> {
+    let (page_width, page_height) = page.pixels(dpi);
+    let margin = dpi / 2;
+    let cell = dpi.max(1);
+    let columns = (page_width.saturating_sub(margin * 2) / cell).max(1);
+    let rows = (page_height.saturating_sub(margin * 2) / cell).max(1);
+
+    // the glyph takes three quarters of the cell, with the name (in whole pixels of the font) below it
+    let glyph_size = (cell * 3 / 4).max(1);
+    let text_scale = (dpi / 100).max(1);
+    let max_chars = (cell / (crate::credits::ADVANCE * text_scale)) as usize;
+
+    let glyphs = glyph_images(pack);
+    glyphs
+        .chunks((columns * rows) as usize)
+        .map(|glyphs| {
+            let mut sheet = RgbaImage::from_pixel(page_width, page_height, Rgba([255; 4]));
+            for (index, (name, glyph)) in glyphs.iter().enumerate() {
+                let left =
+                    page_width.saturating_sub(columns * cell) / 2 + (index as u32 % columns) * cell;
+                let top = margin + (index as u32 / columns) * cell;
+
+                let glyph = glyph.resize(glyph_size, glyph_size, FilterType::Lanczos3);
+                let glyph_left = left + (cell - glyph.width()) / 2;
+                let glyph_top = top + cell / 16 + (glyph_size - glyph.height()) / 2;
+                image::imageops::overlay(
+                    &mut sheet,
+                    &glyph.to_rgba8(),
+                    i64::from(glyph_left),
+                    i64::from(glyph_top),
+                );
+
+                // shorten names too long for the cell
+                let label = if name.chars().count() > max_chars {
+                    let kept = name.chars().take(max_chars.saturating_sub(2));
+                    kept.chain("..".chars()).collect::<String>()
+                } else {
+                    name.to_string()
+                };
+                let label = crate::credits::render_text(&label, Rgba([0, 0, 0, 255]));
+                let label = image::imageops::resize(
+                    &label,
+                    label.width() * text_scale,
+                    label.height() * text_scale,
+                    FilterType::Nearest,
+                );
+                let label_left = left + cell.saturating_sub(label.width()) / 2;
+                let label_top = top + cell / 16 + glyph_size + cell / 32;
+                image::imageops::overlay(
+                    &mut sheet,
+                    &label,
+                    i64::from(label_left),
+                    i64::from(label_top),
+                );
+            }
+            sheet
+        })
+        .collect()
+}
+
+/// Encodes an image as a PNG which records its resolution (in a `pHYs` chunk), so it prints at the intended size.
+pub fn png_with_dpi(image: &RgbaImage, dpi: u32) -> image::ImageResult<Vec<u8>> {
+    let mut png = Vec::new();
+    image.write_to(&mut io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+
+    let pixels_per_metre = (f64::from(dpi) / 0.0254).round() as u32;
+    let mut physical = pixels_per_metre.to_be_bytes().repeat(2);
+    physical.push(1);
+    insert_chunk(&mut png, b"pHYs", &physical);
+    Ok(png)
+}
+
+/// Keyword of the `iTXt` chunk holding the index in a bundle.
+pub const BUNDLE_INDEX_KEYWORD: &str = "fedimoji-index";
+
+/// Bundles the atlas and an index of its glyphs into one PNG, for loading both in one read: the image is the atlas,
+/// and an `iTXt` chunk with the keyword [`BUNDLE_INDEX_KEYWORD`] holds the index, [`glyph_rects`] as compact JSON
+/// (without the atlas's file name). [`read_bundle`] reads one back.
+pub fn bundle(pack: &Pack) -> image::ImageResult<Vec<u8>> {
+    let mut png = pack.encode_atlas()?;
+    let mut index = glyph_rects(pack);
+    if let Some(index) = index.as_object_mut() {
+        index.remove("atlas");
+    }
+
+    // an uncompressed international text chunk, with no language tag or translated keyword
+    let mut text = BUNDLE_INDEX_KEYWORD.as_bytes().to_vec();
+    text.extend_from_slice(&[0, 0, 0, 0, 0]);
+    text.extend_from_slice(serde_json::to_string(&index).unwrap().as_bytes());
+    insert_chunk(&mut png, b"iTXt", &text);
+    Ok(png)
+}
+
+/// Reads a bundle written by [`bundle`], giving back the atlas and the index.
+pub fn read_bundle(path: &Path) -> Result<(DynamicImage, serde_json::Value), PackError> {
+    let png = std::fs::read(path)?;
+    let atlas = image::load_from_memory(&png).map_err(|source| PackError::ImageRead {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    // walk the chunks after the signature, each a length, type, data and CRC
+    let mut rest = png.get(8..).unwrap_or_default();
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let Some(data) = rest.get(8..8 + length) else {
+            break;
+        };
+        if &rest[4..8] == b"iTXt" {
+            if let Some(text) = data
+                .strip_prefix(BUNDLE_INDEX_KEYWORD.as_bytes())
+                .and_then(|text| text.strip_prefix(&[0, 0, 0]))
+            {
+                // skip the empty language tag and translated keyword
+                let text = text.splitn(3, |byte| *byte == 0).nth(2).unwrap_or_default();
+                return Ok((atlas, serde_json::from_slice(text)?));
+            }
+        }
+        rest = &rest[(12 + length).min(rest.len())..];
+    }
+    Err(PackError::BundleIndexMissing(path.to_path_buf()))
+}
+
+/// Inserts a chunk into an encoded PNG straight after its header (the signature and `IHDR`), where chunks which have
+/// to come before the image data belong.
+fn insert_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut checked = kind.to_vec();
+    checked.extend_from_slice(data);
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(&checked);
+    chunk.extend_from_slice(&crc32(&checked).to_be_bytes());
+    png.splice(33..33, chunk);
+}
+
+/// The CRC-32 PNG chunks are checked with.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Cuts each emoji's glyph out of the atlas, by name, including aliases (which get a copy of the glyph they share).
+///
+/// Emoji without a glyph in this atlas, like ones kept from the imported mapping when only packing new emoji, are
+/// left out.
+pub fn glyph_images(pack: &Pack) -> Vec<(&str, DynamicImage)> {
+    let (glyph_width, glyph_height) = glyph_size(pack);
+    pack.names
+        .iter()
+        .filter_map(|(name, codepoint)| {
+            let (x, y) = cell_position(pack, *codepoint)?;
+            let glyph = pack.atlas.crop_imm(x, y, glyph_width, glyph_height);
+            Some((name.as_str(), glyph))
+        })
+        .collect()
+}
+
+/// Describes the opaque content within each emoji's glyph cell, by name: its size, and its offset from the cell's
+/// top-left corner. Blank glyphs have no size and no offset.
+pub fn glyph_sizes(pack: &Pack) -> serde_json::Value {
+    glyph_images(pack)
+        .into_iter()
+        .map(|(name, glyph)| {
+            let (left, right) = crate::content_columns(&glyph).map_or((0, 0), |(l, r)| (l, r + 1));
+            let (top, bottom) = crate::content_rows(&glyph).map_or((0, 0), |(t, b)| (t, b + 1));
+            (
+                name.to_string(),
+                json!({
+                    "content_width": right - left,
+                    "content_height": bottom - top,
+                    "offset_x": left,
+                    "offset_y": top,
+                }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Each emoji's glyph as a PNG in a `data:` URI, by name, so a web client can show them without requesting any images.
+pub fn data_uris(pack: &Pack) -> serde_json::Value {
+    glyph_images(pack)
+        .into_iter()
+        .map(|(name, glyph)| {
+            let mut png = Vec::new();
+            glyph
+                .write_to(&mut io::Cursor::new(&mut png), image::ImageFormat::Png)
+                .unwrap();
+            (
+                name.to_string(),
+                json!(format!("data:image/png;base64,{}", base64(&png))),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Encodes bytes as standard, padded base64.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Magic bytes at the start of a binary index.
+pub const BINARY_INDEX_MAGIC: &[u8; 4] = b"FMJI";
+
+/// Version of the binary index format, after the magic bytes.
+pub const BINARY_INDEX_VERSION: u16 = 1;
+
+/// Writes a compact binary index of where each emoji's glyph is in the atlas, by name, for loading without parsing
+/// JSON. All numbers are little-endian.
+///
+/// The header is the magic bytes `FMJI`, the format version (u16), the number of records (u32), the glyph width and
+/// height (u16 each), and the atlas width and height (u32 each). Each record is then the emoji's codepoint (u32), the
+/// x and y of its glyph's cell in the atlas (u16 each), and its name, as its length in bytes (u16) followed by the
+/// UTF-8 bytes. Records are sorted by name, and aliases get a record pointing at the glyph they share.
+///
+/// Fails if the atlas is too large for the positions to fit in 16 bits.
+pub fn binary_index(pack: &Pack) -> Result<Vec<u8>, PackError> {
+    let (glyph_width, glyph_height) = glyph_size(pack);
+    let (atlas_width, atlas_height) = (pack.atlas.width(), pack.atlas.height());
+    let too_large = |_| PackError::IndexTooLarge {
+        width: atlas_width,
+        height: atlas_height,
+    };
+
+    let records = pack
+        .names
+        .iter()
+        .filter_map(|(name, codepoint)| Some((name, *codepoint, cell_position(pack, *codepoint)?)))
+        .collect::<Vec<_>>();
+
+    let mut index = Vec::new();
+    index.extend_from_slice(BINARY_INDEX_MAGIC);
+    index.extend_from_slice(&BINARY_INDEX_VERSION.to_le_bytes());
+    index.extend_from_slice(&(records.len() as u32).to_le_bytes());
+    index.extend_from_slice(&u16::try_from(glyph_width).map_err(too_large)?.to_le_bytes());
+    index.extend_from_slice(
+        &u16::try_from(glyph_height)
+            .map_err(too_large)?
+            .to_le_bytes(),
+    );
+    index.extend_from_slice(&atlas_width.to_le_bytes());
+    index.extend_from_slice(&atlas_height.to_le_bytes());
+    for (name, codepoint, (x, y)) in records {
+        index.extend_from_slice(&(codepoint as u32).to_le_bytes());
+        index.extend_from_slice(&u16::try_from(x).map_err(too_large)?.to_le_bytes());
+        index.extend_from_slice(&u16::try_from(y).map_err(too_large)?.to_le_bytes());
+        index.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        index.extend_from_slice(name.as_bytes());
+    }
+    Ok(index)
+}
+
+/// Script for showing emoji from a web bundle's atlas, reading positions from its manifest.
+pub const SPRITE_SCRIPT: &str = r#"// Shows emoji from a fedimoji atlas as CSS sprites. Load this next to manifest.json and emoji.png, then:
+//   const emoji = await fedimoji.load();
+//   document.body.append(emoji.sprite("blobcat"));
+const fedimoji = {
+  // where this script was loaded from, which the manifest and atlas are alongside
+  base: new URL(".", document.currentScript?.src ?? location.href),
+
+  async load(base = fedimoji.base) {
+    const manifest = await (await fetch(new URL("manifest.json", base))).json();
+    const atlas = new URL(manifest.atlas, base);
+    return {
+      manifest,
+      names: Object.keys(manifest.emoji),
+      sprite(name) {
+        const glyph = manifest.emoji[name];
+        if (!glyph) return null;
+        const element = document.createElement("span");
+        element.title = `:${name}:`;
+        Object.assign(element.style, {
+          display: "inline-block",
+          width: `${manifest.glyph_width}px`,
+          height: `${manifest.glyph_height}px`,
+          backgroundImage: `url("${atlas}")`,
+          backgroundPosition: `-${glyph.x}px -${glyph.y}px`,
+          imageRendering: "pixelated",
+        });
+        return element;
+      },
+    };
+  },
+};
+"#;
+
+/// Size of each glyph cell in the atlas, in pixels.
+fn glyph_size(pack: &Pack) -> (u32, u32) {
+    let columns = pack.columns.max(1);
+    let rows = pack.num_cells().div_ceil(columns).max(1);
+    (pack.atlas.width() / columns, pack.atlas.height() / rows)
+}
+
+/// A glyph in the atlas, with the position of its cell.
+struct GlyphCell<'a> {
+    name: &'a str,
+    codepoint: char,
+    x: u32,
+    y: u32,
+}
+
+/// Every glyph in the atlas, in order, followed by the aliases sharing their cells.
+fn glyph_cells(pack: &Pack) -> Vec<GlyphCell<'_>> {
+    let columns = pack.columns.max(1);
+    let (glyph_width, glyph_height) = glyph_size(pack);
+
+    // names of the glyphs actually in the atlas
+    let inverse: BTreeMap<char, &str> = pack
+        .names
+        .iter()
+        .map(|(name, codepoint)| (*codepoint, name.as_str()))
+        .collect();
+
+    let mut cells = (0u32..)
+        .zip(&pack.chars)
+        .filter(|(_, codepoint)| **codepoint != '\0')
+        .map(|(index, codepoint)| GlyphCell {
+            name: inverse.get(codepoint).copied().unwrap_or_default(),
+            codepoint: *codepoint,
+            x: (index % columns) * glyph_width,
+            y: (index / columns) * glyph_height,
+        })
+        .collect::<Vec<_>>();
+    for (alias, glyph) in &pack.aliases {
+        let Some(cell) = cells.iter().find(|cell| cell.codepoint == *glyph) else {
+            continue;
+        };
+        cells.push(GlyphCell {
+            name: inverse.get(alias).copied().unwrap_or_default(),
+            codepoint: *alias,
+            ..*cell
+        });
+    }
+    cells
+}
+
+/// Position of the cell holding the glyph for a codepoint, following aliases to the glyph they share.
+fn cell_position(pack: &Pack, codepoint: char) -> Option<(u32, u32)> {
+    let columns = pack.columns.max(1);
+    let (glyph_width, glyph_height) = glyph_size(pack);
+    let glyph = pack.aliases.get(&codepoint).copied().unwrap_or(codepoint);
+    let index = pack.chars.iter().position(|c| *c == glyph)? as u32;
+    Some((
+        (index % columns) * glyph_width,
+        (index / columns) * glyph_height,
+    ))
+}
+
+/// Escapes text for including in HTML.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackConfig;
+
+    /// A pack of gradient emoji with the given names, in 16px glyphs.
+    fn pack(names: &[&str], config: PackConfig) -> Pack {
+        let config = PackConfig {
+            glyph_width: 16,
+            glyph_height: 16,
+            ..config
+        };
+        let images = names.iter().enumerate().map(|(i, name)| {
+            let image = RgbaImage::from_fn(16, 16, |x, y| {
+                Rgba([i as u8 * 8, x as u8 * 16, y as u8 * 16, 255])
+            });
+            (name.to_string(), DynamicImage::ImageRgba8(image))
+        });
+        crate::generate_pack_from_iter(images, &config).unwrap()
+    }
+
+    #[test]
+    fn names_csv_has_a_row_per_emoji() {
+        let names = BTreeMap::from([
+            ("neofox".to_string(), '\u{F0001}'),
+            ("blobcat".to_string(), '\u{F0000}'),
+        ]);
+        let csv = String::from_utf8(names_csv(&names).unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "name,codepoint_hex,codepoint_decimal,char\n\
+             blobcat,U+F0000,983040,\u{F0000}\n\
+             neofox,U+F0001,983041,\u{F0001}\n"
+        );
+    }
+
+    #[test]
+    fn html_gallery_shows_each_emoji_from_the_atlas() {
+        let pack = pack(&["blobcat", "<b>"], PackConfig::default());
+        let html = html_gallery(&pack);
+        assert!(html.contains(
+            "<div class=\"glyph\" style=\"background-position: -0px -0px\"></div>\
+             <span class=\"name\">:blobcat:</span><span class=\"codepoint\">U+F0000</span>"
+        ));
+        assert!(html.contains(
+            "background-position: -0px -16px\"></div><span class=\"name\">:&lt;b&gt;:</span>"
+        ));
+        assert!(!html.contains("<b>"));
+    }
+
+    /// Writes a lock file as another run would have.
+    fn write_lock(dir: &Path, pid: &str, taken_at: u64) {
+        std::fs::write(dir.join(LOCK_FILE_NAME), format!("{pid}\n{taken_at}\n")).unwrap();
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn a_live_lock_refuses_another_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = OutputLock::acquire(dir.path(), false).unwrap();
+        assert!(matches!(
+            OutputLock::acquire(dir.path(), false),
+            Err(PackError::OutputLocked { .. })
+        ));
+
+        // released once the run is done with it
+        drop(lock);
+        assert!(!dir.path().join(LOCK_FILE_NAME).exists());
+        drop(OutputLock::acquire(dir.path(), false).unwrap());
+
+        // another live process's lock
+        write_lock(dir.path(), &std::process::id().to_string(), now());
+        assert!(matches!(
+            OutputLock::acquire(dir.path(), false),
+            Err(PackError::OutputLocked { pid, .. }) if pid == std::process::id().to_string()
+        ));
+    }
+
+    #[test]
+    fn a_stale_lock_is_taken_over() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // taken over an hour ago, even by a live process
+        write_lock(
+            dir.path(),
+            &std::process::id().to_string(),
+            now() - 2 * 60 * 60,
+        );
+        drop(OutputLock::acquire(dir.path(), false).unwrap());
+
+        // by a process which has exited
+        #[cfg(target_os = "linux")]
+        {
+            write_lock(dir.path(), "4000000000", now());
+            drop(OutputLock::acquire(dir.path(), false).unwrap());
+        }
+        assert!(!dir.path().join(LOCK_FILE_NAME).exists());
+
+        // without a time, once it was last modified over an hour ago
+        let path = dir.path().join(LOCK_FILE_NAME);
+        std::fs::write(&path, format!("{}\n", std::process::id())).unwrap();
+        assert!(OutputLock::acquire(dir.path(), false).is_err());
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() - 2 * STALE_LOCK_AGE)
+            .unwrap();
+        drop(file);
+        drop(OutputLock::acquire(dir.path(), false).unwrap());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn a_lock_taken_since_it_was_judged_stale_is_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCK_FILE_NAME);
+
+        // another run took over the stale lock and made its own before this one got to it
+        let taken_at = now();
+        write_lock(dir.path(), "123", taken_at);
+        assert!(!take_over_stale_lock(&path, "123\n0\n"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("123\n{taken_at}\n")
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // while the lock judged stale is removed
+        write_lock(dir.path(), "123", 0);
+        assert!(take_over_stale_lock(&path, "123\n0\n"));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn updating_a_zip_replaces_only_the_emoji_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let zip_path = dir.path().join("pack.zip");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+        for (name, contents) in [
+            ("pack.mcmeta", "{}"),
+            ("assets/fedimoji/textures/font/emoji.png", "old atlas"),
+            ("assets/other/thing.txt", "untouched"),
+        ] {
+            writer
+                .start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let mut output = PackOutput::zip(&zip_path).unwrap();
+        output.write("emoji.png", b"new atlas").unwrap();
+        output.write("emoji.json", b"providers").unwrap();
+        output.finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&zip_path).unwrap()).unwrap();
+        let mut entries = BTreeMap::new();
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index).unwrap();
+            let mut contents = String::new();
+            io::Read::read_to_string(&mut entry, &mut contents).unwrap();
+            entries.insert(entry.name().to_string(), contents);
+        }
+        assert_eq!(
+            entries,
+            BTreeMap::from(
+                [
+                    ("pack.mcmeta", "{}"),
+                    ("assets/fedimoji/textures/font/emoji.png", "new atlas"),
+                    ("assets/fedimoji/font/include/emoji.json", "providers"),
+                    ("assets/other/thing.txt", "untouched"),
+                ]
+                .map(|(name, contents)| (name.to_string(), contents.to_string()))
+            )
+        );
+        assert!(!zip_path.with_extension("zip.tmp").exists());
+    }
+
+    #[test]
+    fn fedi_manifest_gives_each_emoji_its_char_and_glyph_url() {
+        let pack = pack(&["blobcat", "neofox"], PackConfig::default());
+        let sound = EmojiMetadata {
+            sound: Some("meow.ogg".to_string()),
+            ..EmojiMetadata::default()
+        };
+        let metadata = HashMap::from([("blobcat".to_string(), &sound)]);
+        assert_eq!(
+            fedi_manifest(&pack, &metadata, "https://example.com/emoji/"),
+            json!([
+                {
+                    "shortcode": "blobcat",
+                    "url": "https://example.com/emoji/glyphs/blobcat.png",
+                    "unicode": "\u{F0000}",
+                    "sound": "meow.ogg",
+                },
+                {
+                    "shortcode": "neofox",
+                    "url": "https://example.com/emoji/glyphs/neofox.png",
+                    "unicode": "\u{F0001}",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn unchanged_files_are_not_rewritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("emoji.json");
+        assert!(write_if_changed(&path, b"old").unwrap());
+
+        // back-date the file, so a rewrite would show in its modification time
+        let past = SystemTime::now() - Duration::from_secs(60 * 60);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+        let modified = || std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        assert!(!write_if_changed(&path, b"old").unwrap());
+        assert_eq!(modified(), past);
+
+        assert!(write_if_changed(&path, b"new").unwrap());
+        assert_ne!(modified(), past);
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+    }
+
+    #[test]
+    fn atomic_writes_replace_whole_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("emoji.png");
+        std::fs::write(&path, b"old atlas").unwrap();
+        // left by an interrupted run
+        std::fs::write(dir.path().join(".emoji.png.tmp"), b"half an at").unwrap();
+
+        write_atomically(&path, b"new atlas").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new atlas");
+        assert_eq!(
+            std::fs::read_dir(dir.path()).unwrap().count(),
+            1,
+            "the temporary file is renamed into place"
+        );
+    }
+
+    // writing through a symlink to /dev/full fails partway, like a disk filling up mid-write
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn failed_atomic_writes_leave_the_old_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("emoji.png");
+        std::fs::write(&path, b"old atlas").unwrap();
+        std::os::unix::fs::symlink("/dev/full", temp_path(&path)).unwrap();
+
+        assert!(write_atomically(&path, b"new atlas").is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"old atlas");
+        assert_eq!(
+            std::fs::read_dir(dir.path()).unwrap().count(),
+            1,
+            "the temporary file is removed"
+        );
+    }
+
+    #[test]
+    fn allocation_report_lists_the_codepoints_used() {
+        let config = PackConfig {
+            existing_mappings: HashMap::from([("c".to_string(), '\u{F0010}')]),
+            ..PackConfig::default()
+        };
+        let pack = pack(&["a", "b", "c"], config);
+        let report = allocation_report(&pack);
+        assert_eq!(
+            report["used"].as_array().unwrap().len(),
+            pack.num_glyphs() as usize
+        );
+        assert_eq!(
+            report,
+            json!({
+                "range": ["U+F0000", "U+F0010"],
+                "used": ["U+F0000", "U+F0001", "U+F0010"],
+                "free_after": "U+F0010",
+            })
+        );
+    }
+
+    #[test]
+    fn glyph_sizes_measure_the_opaque_content() {
+        // opaque from (2, 4) to (9, 13), in a transparent margin
+        let image = RgbaImage::from_fn(16, 16, |x, y| {
+            let opaque = (2..10).contains(&x) && (4..14).contains(&y);
+            Rgba([255, 0, 0, if opaque { 255 } else { 0 }])
+        });
+        let config = PackConfig {
+            glyph_width: 16,
+            glyph_height: 16,
+            ..PackConfig::default()
+        };
+        let pack = crate::generate_pack_from_iter(
+            [("blobcat".to_string(), DynamicImage::ImageRgba8(image))].into_iter(),
+            &config,
+        )
+        .unwrap();
+
+        assert_eq!(
+            glyph_sizes(&pack),
+            json!({
+                "blobcat": {"content_width": 8, "content_height": 10, "offset_x": 2, "offset_y": 4},
+            })
+        );
+    }
+
+    #[test]
+    fn binary_index_reads_back_as_the_records() {
+        // packed out of name order, so the records are sorted differently from the glyphs
+        let pack = pack(&["neofox", "blobcat"], PackConfig::default());
+        let index = binary_index(&pack).unwrap();
+
+        let mut rest = index.as_slice();
+        let mut take = |n: usize| {
+            let (bytes, after) = rest.split_at(n);
+            rest = after;
+            bytes.to_vec()
+        };
+        let u16_at = |bytes: Vec<u8>| u16::from_le_bytes(bytes.try_into().unwrap());
+        let u32_at = |bytes: Vec<u8>| u32::from_le_bytes(bytes.try_into().unwrap());
+
+        assert_eq!(take(4), BINARY_INDEX_MAGIC);
+        assert_eq!(u16_at(take(2)), BINARY_INDEX_VERSION);
+        let count = u32_at(take(4));
+        assert_eq!((u16_at(take(2)), u16_at(take(2))), (16, 16));
+        assert_eq!(
+            (u32_at(take(4)), u32_at(take(4))),
+            (pack.atlas.width(), pack.atlas.height())
+        );
+
+        let records = (0..count)
+            .map(|_| {
+                let codepoint = char::from_u32(u32_at(take(4))).unwrap();
+                let position = (u16_at(take(2)), u16_at(take(2)));
+                let length = u16_at(take(2)) as usize;
+                let name = String::from_utf8(take(length)).unwrap();
+                (name, codepoint, position)
+            })
+            .collect::<Vec<_>>();
+        assert!(rest.is_empty());
+        assert_eq!(
+            records,
+            [
+                ("blobcat".to_string(), '\u{F0001}', (0, 16)),
+                ("neofox".to_string(), '\u{F0000}', (0, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn changelog_lists_added_removed_and_reassigned_emoji() {
+        let old = HashMap::from([
+            ("blobcat".to_string(), '\u{F0000}'),
+            ("neofox".to_string(), '\u{F0001}'),
+            ("catjam".to_string(), '\u{F0002}'),
+        ]);
+        let new = BTreeMap::from([
+            ("BlobCat".to_string(), '\u{F0000}'),
+            ("neofox".to_string(), '\u{F0005}'),
+            ("wave".to_string(), '\u{F0003}'),
+        ]);
+        assert_eq!(
+            changelog(&old, &new),
+            "# Emoji changes\n\
+             \n## Added (1)\n\n- `wave` (U+F0003)\n\
+             \n## Removed (1)\n\n- `catjam` (U+F0002)\n\
+             \n## Reassigned codepoints (1)\n\n- `neofox`: U+F0001 → U+F0005\n"
+        );
+        assert_eq!(
+            changelog(&old, &BTreeMap::from_iter(old.clone())),
+            "# Emoji changes\n\nNo changes.\n"
+        );
+    }
+
+    #[test]
+    fn shortcodes_group_variants_under_their_base() {
+        let names = BTreeMap::from([
+            ("wave".to_string(), '\u{F0000}'),
+            ("wave_tone1".to_string(), '\u{F0001}'),
+            ("wave_tone2".to_string(), '\u{F0002}'),
+            // no base emoji, so not a variant
+            ("clap_tone1".to_string(), '\u{F0003}'),
+        ]);
+        let shortcodes = shortcodes(&names, &HashMap::new(), DEFAULT_VARIANT_SUFFIX_PATTERN);
+        let entry = |name: &str| {
+            shortcodes
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|entry| entry["shortcode"] == name)
+                .unwrap()
+                .clone()
+        };
+
+        assert_eq!(
+            entry("wave")["variants"],
+            json!(["wave_tone1", "wave_tone2"])
+        );
+        assert_eq!(entry("wave_tone2")["char"], "\u{F0002}");
+        assert_eq!(entry("wave_tone2")["variant_of"], "wave");
+        assert!(entry("clap_tone1").get("variant_of").is_none());
+    }
+
+    #[test]
+    fn data_uris_decode_to_the_glyphs() {
+        fn decode_base64(text: &str) -> Vec<u8> {
+            let value = |c: u8| match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                _ => panic!("not base64: {c}"),
+            };
+            let digits = text
+                .trim_end_matches('=')
+                .bytes()
+                .map(value)
+                .collect::<Vec<_>>();
+            digits
+                .chunks(4)
+                .flat_map(|chunk| {
+                    let group = chunk.iter().enumerate().fold(0u32, |group, (i, digit)| {
+                        group | u32::from(*digit) << (18 - 6 * i)
+                    });
+                    group.to_be_bytes()[1..chunk.len()].to_vec()
+                })
+                .collect()
+        }
+
+        let pack = pack(&["blobcat", "neofox"], PackConfig::default());
+        let uris = data_uris(&pack);
+        let uris = uris.as_object().unwrap();
+        assert_eq!(uris.len(), 2);
+        for (name, glyph) in glyph_images(&pack) {
+            let uri = uris[name].as_str().unwrap();
+            let png = uri.strip_prefix("data:image/png;base64,").unwrap();
+            let decoded = image::load_from_memory(&decode_base64(png)).unwrap();
+            assert_eq!(decoded.to_rgba8(), glyph.to_rgba8(), "{name}");
+        }
+    }
+
+    #[test]
+    fn atlas_subpaths_are_valid_resource_paths() {
+        assert_eq!(parse_atlas_subpath("/font/v3/").unwrap(), "font/v3");
+        assert_eq!(
+            parse_atlas_subpath("font/v1.2_x-y").unwrap(),
+            "font/v1.2_x-y"
+        );
+        for subpath in ["Font", "font v3", "font//v3", "font/../v3", "./font", ""] {
+            assert!(parse_atlas_subpath(subpath).is_err(), "{subpath:?}");
+        }
+    }
+
+    #[test]
+    fn png_compression_parses_names_and_levels() {
+        assert!(matches!(
+            parse_png_compression("fast"),
+            Ok(CompressionType::Fast)
+        ));
+        assert!(matches!(
+            parse_png_compression("best"),
+            Ok(CompressionType::Best)
+        ));
+        assert!(matches!(
+            parse_png_compression("0"),
+            Ok(CompressionType::Uncompressed)
+        ));
+        assert!(matches!(
+            parse_png_compression(" 6 "),
+            Ok(CompressionType::Level(6))
+        ));
+        for level in ["10", "-1", "fastest", ""] {
+            assert!(parse_png_compression(level).is_err(), "{level:?}");
+        }
+    }
+
+    #[test]
+    fn print_previews_fill_the_page_at_the_dpi() {
+        let pack = pack(&["blobcat", "neofox"], PackConfig::default());
+        for (page, dpi, size) in [
+            (PageSize::A4, 300, (2480, 3508)),
+            (PageSize::Letter, 300, (2550, 3300)),
+            (PageSize::Letter, 100, (850, 1100)),
+        ] {
+            let pages = print_preview(&pack, page, dpi);
+            assert_eq!(pages.len(), 1);
+            assert_eq!(pages[0].dimensions(), size, "{page:?} at {dpi} DPI");
+        }
+
+        // the resolution is recorded right after the header, in pixels per metre
+        let page = &print_preview(&pack, PageSize::A4, 300)[0];
+        let png = png_with_dpi(page, 300).unwrap();
+        assert_eq!(&png[37..41], b"pHYs");
+        assert_eq!(png[41..45], 11811u32.to_be_bytes());
+        assert_eq!(png[45..49], 11811u32.to_be_bytes());
+        assert_eq!(png[49], 1);
+        assert_eq!(image::load_from_memory(&png).unwrap().to_rgba8(), *page);
+    }
+
+    #[test]
+    fn bundles_read_back_as_the_atlas_and_index() {
+        let pack = pack(&["blobcat", "neofox"], PackConfig::default());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("emoji.bundle.png");
+        std::fs::write(&path, bundle(&pack).unwrap()).unwrap();
+
+        let (atlas, index) = read_bundle(&path).unwrap();
+        assert_eq!(atlas.to_rgba8(), pack.atlas.to_rgba8());
+        let mut expected = glyph_rects(&pack);
+        expected.as_object_mut().unwrap().remove("atlas");
+        assert_eq!(index, expected);
+
+        // a plain atlas has no index
+        std::fs::write(&path, pack.encode_atlas().unwrap()).unwrap();
+        assert!(matches!(
+            read_bundle(&path),
+            Err(PackError::BundleIndexMissing(_))
+        ));
+    }
+
+    #[test]
+    fn tile_index_locates_each_glyph_in_its_tile() {
+        let config = PackConfig {
+            atlas_columns: 2,
+            tile_size: Some(16),
+            ..PackConfig::default()
+        };
+        let pack = pack(&["a", "b", "c"], config);
+        let index = tile_index(&pack);
+
+        assert_eq!(index["tile_size"], 16);
+        let tiles = index["tiles"].as_array().unwrap();
+        assert_eq!(tiles.len(), 3);
+        assert_eq!(
+            tiles[2],
+            json!({"file": "emoji_tile_1_0.png", "row": 1, "column": 0, "x": 0, "y": 16, "width": 16, "height": 16})
+        );
+        let glyphs = index["glyphs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|glyph| {
+                let tile = glyph["tile"].as_str().unwrap();
+                (
+                    glyph["name"].as_str().unwrap(),
+                    tile,
+                    glyph["x"].clone(),
+                    glyph["y"].clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            glyphs,
+            [
+                ("a", "emoji_tile_0_0.png", json!(0), json!(0)),
+                ("b", "emoji_tile_0_1.png", json!(0), json!(0)),
+                ("c", "emoji_tile_1_0.png", json!(0), json!(0)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! Reading emoji from a chat platform's custom emoji export, for migrating a community's emoji.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Read,
+    path::Path,
+};
+
+use image::DynamicImage;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::{PackConfig, PackError};
+
+/// A chat platform whose emoji exports can be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Platform {
+    /// A zip with an `emojis.json` array of Discord emoji objects (`{"id", "name", "animated"}`), and each image
+    /// named after its ID or name.
+    Discord,
+
+    /// A zip with an `emoji.json` in the form of Slack's `emoji.list` response (`{"emoji": {name: url}}`), and each
+    /// image named after its emoji or the file name in its URL.
+    Slack,
+}
+
+impl Platform {
+    /// File name of the manifest listing the emoji in an export.
+    fn manifest(self) -> &'static str {
+        match self {
+            Platform::Discord => "emojis.json",
+            Platform::Slack => "emoji.json",
+        }
+    }
+}
+
+/// An emoji in a Discord export, as Discord's API describes it.
+#[derive(Debug, Deserialize)]
+struct DiscordEmoji {
+    id: Option<String>,
+    name: String,
+    #[serde(default)]
+    animated: bool,
+}
+
+/// The emoji in a Slack export, as Slack's `emoji.list` API returns them.
+#[derive(Debug, Deserialize)]
+struct SlackEmojiList {
+    emoji: BTreeMap<String, String>,
+}
+
+/// An emoji listed in an export's manifest.
+struct Listed {
+    name: String,
+
+    /// File names (without a directory) the image might be saved under, most likely first. Names without an
+    /// extension match any image extension.
+    candidates: Vec<String>,
+
+    /// Name of the emoji this one is an alias of, for Slack's `alias:` entries.
+    alias_of: Option<String>,
+}
+
+/// Extensions of the images in exports.
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "gif", "webp", "jpg", "jpeg"];
+
+/// The emoji read from a platform's export.
+#[derive(Debug, Clone, Default)]
+pub struct PlatformExport {
+    /// The emoji with images, by normalized name.
+    pub emoji: Vec<(String, DynamicImage)>,
+
+    /// Emoji which are other names for one of [`PlatformExport::emoji`], as alias -> name, for
+    /// [`PackConfig::kept_aliases`].
+    pub aliases: HashMap<String, String>,
+}
+
+/// Reads the emoji from a platform's export zip, naming them as the platform does.
+///
+/// The manifest can be anywhere in the zip, with the images in its directory or any below it. Animated emoji are
+/// read as their first frame. Emoji whose image is missing or unreadable are skipped with a warning.
+pub fn read_platform_export(
+    path: &Path,
+    platform: Platform,
+    config: &PackConfig,
+) -> Result<PlatformExport, PackError> {
+    if !path.exists() {
+        return Err(PackError::ExportMissing(path.to_path_buf()));
+    }
+    let zip_error = |source| PackError::ExportRead {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?).map_err(zip_error)?;
+
+    // the shallowest manifest wins, in case an export bundles another
+    let manifest_name = archive
+        .file_names()
+        .filter(|name| file_name(name) == platform.manifest())
+        .min_by_key(|name| (name.matches('/').count(), name.to_string()))
+        .map(str::to_string)
+        .ok_or_else(|| PackError::ExportManifestMissing {
+            path: path.to_path_buf(),
+            manifest: platform.manifest(),
+        })?;
+    let prefix = manifest_name
+        .strip_suffix(platform.manifest())
+        .unwrap_or_default()
+        .to_string();
+    let mut manifest = String::new();
+    archive
+        .by_name(&manifest_name)
+        .map_err(zip_error)?
+        .read_to_string(&mut manifest)?;
+    let listed = parse_manifest(&manifest, platform)?;
+    debug!("{} emoji listed in `{manifest_name}`", listed.len());
+
+    // images by file name, with and without the extension
+    let mut images: HashMap<String, String> = HashMap::new();
+    for entry in archive.file_names() {
+        let Some(relative) = entry.strip_prefix(prefix.as_str()) else {
+            continue;
+        };
+        let name = file_name(relative);
+        let Some((stem, extension)) = name.rsplit_once('.') else {
+            continue;
+        };
+        if !IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()) {
+            continue;
+        }
+        images.entry(name.to_string()).or_insert(entry.to_string());
+        images.entry(stem.to_string()).or_insert(entry.to_string());
+    }
+
+    let mut decoded: HashMap<String, DynamicImage> = HashMap::new();
+    let mut aliases = Vec::new();
+    for emoji in listed {
+        if let Some(target) = emoji.alias_of {
+            aliases.push((emoji.name, target));
+            continue;
+        }
+        let Some(entry) = emoji
+            .candidates
+            .iter()
+            .find_map(|candidate| images.get(candidate))
+        else {
+            warn!(
+                kind = "skipped",
+                "no image for \"{}\" in the export (skipping it)", emoji.name
+            );
+            continue;
+        };
+
+        let mut bytes = Vec::new();
+        archive
+            .by_name(entry)
+            .map_err(zip_error)?
+            .read_to_end(&mut bytes)?;
+        match image::load_from_memory(&bytes) {
+            Ok(image) => {
+                decoded.insert(emoji.name, crate::scan::expand_to_rgba(image));
+            }
+            Err(err) => warn!(
+                kind = "decode_failure",
+                "failed to read `{entry}` for \"{}\" (skipping it): {err}", emoji.name
+            ),
+        }
+    }
+
+    let mut emoji = decoded.into_iter().collect::<Vec<_>>();
+    emoji.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let (mut names, images): (Vec<_>, Vec<_>) = emoji.into_iter().unzip();
+
+    // aliases keep the (normalized and shortened) name of the emoji they point to
+    let mut alias_names = Vec::new();
+    let mut targets = Vec::new();
+    for (alias, target) in aliases {
+        match names.iter().position(|name| *name == target) {
+            Some(index) => {
+                alias_names.push(alias);
+                targets.push(index);
+            }
+            None => warn!(
+                kind = "skipped",
+                "\"{alias}\" is an alias of \"{target}\", which has no image (skipping it)"
+            ),
+        }
+    }
+    for name in names.iter_mut().chain(&mut alias_names) {
+        *name = config.normalize_name(name);
+    }
+    config.shorten_names(names.iter_mut().chain(&mut alias_names))?;
+
+    let aliases = alias_names
+        .into_iter()
+        .zip(targets)
+        .map(|(alias, index)| (alias, names[index].clone()))
+        .collect();
+    info!(
+        "read {} emoji from the {platform:?} export `{}`",
+        names.len(),
+        path.display()
+    );
+    Ok(PlatformExport {
+        emoji: names.into_iter().zip(images).collect(),
+        aliases,
+    })
+}
+
+/// Lists the emoji in a manifest.
+fn parse_manifest(manifest: &str, platform: Platform) -> Result<Vec<Listed>, PackError> {
+    let listed = match platform {
+        Platform::Discord => serde_json::from_str::<Vec<DiscordEmoji>>(manifest)
+            .map_err(PackError::ExportParse)?
+            .into_iter()
+            .map(|emoji| {
+                // Discord serves animated emoji as GIFs, so prefer those for them
+                let extension = if emoji.animated { "gif" } else { "png" };
+                let mut candidates = Vec::new();
+                if let Some(id) = &emoji.id {
+                    candidates.push(format!("{id}.{extension}"));
+                    candidates.push(id.clone());
+                }
+                candidates.push(format!("{}.{extension}", emoji.name));
+                candidates.push(emoji.name.clone());
+                Listed {
+                    name: emoji.name,
+                    candidates,
+                    alias_of: None,
+                }
+            })
+            .collect(),
+        Platform::Slack => serde_json::from_str::<SlackEmojiList>(manifest)
+            .map_err(PackError::ExportParse)?
+            .emoji
+            .into_iter()
+            .map(|(name, url)| match url.strip_prefix("alias:") {
+                Some(target) => Listed {
+                    name,
+                    candidates: Vec::new(),
+                    alias_of: Some(target.to_string()),
+                },
+                None => {
+                    // emoji are usually saved under their name, but may keep the file name from the URL
+                    let from_url = file_name(url.split(['?', '#']).next().unwrap_or_default());
+                    let mut candidates = vec![name.clone()];
+                    if !from_url.is_empty() {
+                        candidates.push(from_url.to_string());
+                    }
+                    Listed {
+                        name,
+                        candidates,
+                        alias_of: None,
+                    }
+                }
+            })
+            .collect(),
+    };
+    Ok(listed)
+}
+
+/// The last component of a `/`-separated path.
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use image::{codecs::gif::GifEncoder, Frame, ImageFormat, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn png(color: [u8; 4]) -> Vec<u8> {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        RgbaImage::from_pixel(4, 4, Rgba(color))
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    /// Writes an export zip with the given entries.
+    fn export(entries: &[(&str, Vec<u8>)]) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = zip::ZipWriter::new(file.reopen().unwrap());
+        for (name, contents) in entries {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap();
+        file
+    }
+
+    fn color(export: &PlatformExport, name: &str) -> [u8; 4] {
+        let (_, image) = export.emoji.iter().find(|(n, _)| n == name).unwrap();
+        image.to_rgba8().get_pixel(0, 0).0
+    }
+
+    #[test]
+    fn discord_exports_are_named_after_their_manifest() {
+        // an animated emoji, whose first frame is used
+        let mut gif = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut gif);
+            for color in [[0, 0, 255, 255], [0, 255, 0, 255]] {
+                encoder
+                    .encode_frame(Frame::new(RgbaImage::from_pixel(4, 4, Rgba(color))))
+                    .unwrap();
+            }
+        }
+        let manifest = r#"[
+            {"id": "123", "name": "BlobCat"},
+            {"id": "456", "name": "party", "animated": true},
+            {"id": "789", "name": "gone"}
+        ]"#;
+        let file = export(&[
+            ("export/emojis.json", manifest.as_bytes().to_vec()),
+            ("export/images/123.png", png([255, 0, 0, 255])),
+            ("export/images/456.gif", gif),
+        ]);
+
+        let export =
+            read_platform_export(file.path(), Platform::Discord, &PackConfig::default()).unwrap();
+        let mut names = export
+            .emoji
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["blobcat", "party"]);
+        assert_eq!(color(&export, "blobcat"), [255, 0, 0, 255]);
+        assert_eq!(color(&export, "party"), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn slack_exports_keep_aliases() {
+        let manifest = r#"{"emoji": {
+            "blob": "https://emoji.slack-edge.com/T0/blob/abc123.png",
+            "neofox": "https://emoji.slack-edge.com/T0/neofox/def456.png",
+            "blobby": "alias:blob"
+        }}"#;
+        let file = export(&[
+            ("emoji.json", manifest.as_bytes().to_vec()),
+            ("blob.png", png([255, 0, 0, 255])),
+            ("def456.png", png([0, 255, 0, 255])),
+        ]);
+
+        let export =
+            read_platform_export(file.path(), Platform::Slack, &PackConfig::default()).unwrap();
+        assert_eq!(color(&export, "blob"), [255, 0, 0, 255]);
+        assert_eq!(color(&export, "neofox"), [0, 255, 0, 255]);
+        assert_eq!(
+            export.aliases,
+            HashMap::from([("blobby".to_string(), "blob".to_string())])
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Reading emoji from layered Photoshop documents, so artists can keep their source files in the emoji directory.
+
+use std::path::Path;
+
+use image::{
+    error::{DecodingError, ImageFormatHint},
+    DynamicImage, ImageError, RgbaImage,
+};
+
+use crate::PackConfig;
+
+/// Extension of Photoshop documents.
+const EXTENSION: &str = "psd";
+
+/// Whether a file is a Photoshop document, going by its extension.
+pub fn is_psd(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case(EXTENSION))
+}
+
+/// Decodes a Photoshop document as its flattened composite, or as just the layer named by [`PackConfig::psd_layer`]
+/// if there is one. The image is the size of the document's canvas, to be scaled to the cell like any other.
+pub fn decode_psd(path: &Path, config: &PackConfig) -> image::ImageResult<DynamicImage> {
+    let bytes = std::fs::read(path)?;
+    let psd = ::psd::Psd::from_bytes(&bytes).map_err(decoding_error)?;
+
+    let rgba = match &config.psd_layer {
+        None => psd.rgba(),
+        Some(name) => psd
+            .layer_by_name(name)
+            .ok_or_else(|| decoding_error(format!("no layer named {name:?}")))?
+            .rgba(),
+    };
+    RgbaImage::from_raw(psd.width(), psd.height(), rgba)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| decoding_error("pixel data doesn't match the canvas size"))
+}
+
+/// Reads the canvas size of a Photoshop document.
+pub fn read_psd_dimensions(path: &Path) -> image::ImageResult<(u32, u32)> {
+    let bytes = std::fs::read(path)?;
+    let psd = ::psd::Psd::from_bytes(&bytes).map_err(decoding_error)?;
+    Ok((psd.width(), psd.height()))
+}
+
+/// Wraps a failure to read a Photoshop document as an image decoding error, so it's reported like any other.
+fn decoding_error(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("PSD".to_string()),
+        err,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    /// Writes a Photoshop document with no layers, only the composite, stored raw.
+    fn write_psd(path: &Path, image: &RgbaImage) {
+        let mut bytes = b"8BPS".to_vec();
+        bytes.extend(1u16.to_be_bytes());
+        bytes.extend([0; 6]);
+        bytes.extend(4u16.to_be_bytes()); // RGBA
+        bytes.extend(image.height().to_be_bytes());
+        bytes.extend(image.width().to_be_bytes());
+        bytes.extend(8u16.to_be_bytes()); // bits per channel
+        bytes.extend(3u16.to_be_bytes()); // RGB color mode
+                                          // no color mode data, image resources or layers
+        bytes.extend([0; 12]);
+        bytes.extend(0u16.to_be_bytes()); // raw, uncompressed
+        for channel in 0..4 {
+            bytes.extend(image.pixels().map(|pixel| pixel[channel]));
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn documents_decode_as_their_composite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blobcat.PSD");
+        let image = RgbaImage::from_fn(4, 3, |x, y| {
+            Rgba([x as u8 * 60, y as u8 * 80, 200, 255 - x as u8 * 20])
+        });
+        write_psd(&path, &image);
+
+        assert!(is_psd(&path));
+        assert_eq!(read_psd_dimensions(&path).unwrap(), (4, 3));
+        let decoded = decode_psd(&path, &PackConfig::default()).unwrap();
+        assert_eq!(decoded.to_rgba8(), image);
+
+        let config = PackConfig {
+            psd_layer: Some("outline".to_string()),
+            ..PackConfig::default()
+        };
+        assert!(decode_psd(&path, &config).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+//! Slicing the glyphs back out of an existing atlas, for packing them again (e.g. at a different size).
+
+use std::collections::HashMap;
+
+use image::DynamicImage;
+use tracing::{debug, info, warn};
+
+use crate::{Pack, PackError};
+
+/// Glyphs sliced back out of an atlas.
+#[derive(Debug, Clone, Default)]
+pub struct SlicedAtlas {
+    /// The glyphs, by name, in atlas order.
+    pub glyphs: Vec<(String, DynamicImage)>,
+
+    /// Ascents of the glyphs whose provider has a different ascent from the first one, by name, to pass on as
+    /// [`crate::PackConfig::ascent_overrides`].
+    pub ascents: HashMap<String, i32>,
+}
+
+/// Slices the glyphs out of an atlas, laid out as described by its font provider definition (as in `emoji.json`),
+/// naming them from a name -> codepoint mapping (as in `fedimoji.json`).
+///
+/// Every bitmap provider over the same file as the first one is read, so glyphs split into providers of their own
+/// (for their ascent, their Unicode plane, or as aliases) are kept, along with their ascents. Providers over other
+/// files are skipped with a warning. Glyphs whose codepoint has no name are skipped with a warning, as is the padding
+/// (`\0`) at the end of the atlas.
+pub fn slice_atlas(
+    atlas: &DynamicImage,
+    font_provider: &serde_json::Value,
+    names: &HashMap<String, char>,
+) -> Result<SlicedAtlas, PackError> {
+    let bitmaps = font_provider["providers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|provider| provider["type"] == "bitmap")
+        .collect::<Vec<_>>();
+    let Some(first) = bitmaps.first() else {
+        return Err(PackError::InvalidProvider(
+            "no bitmap provider with chars".to_string(),
+        ));
+    };
+    let file = &first["file"];
+    let base_ascent = first["ascent"].as_i64();
+
+    let inverse: HashMap<char, &str> = names
+        .iter()
+        .map(|(name, codepoint)| (*codepoint, name.as_str()))
+        .collect();
+
+    // each glyph with its cell, to put them back in atlas order once every provider is read
+    let mut glyphs = Vec::new();
+    let mut ascents = HashMap::new();
+    for provider in bitmaps {
+        if provider["file"] != *file {
+            warn!(
+                kind = "skipped",
+                "a provider refers to {}, not the atlas {file} (skipping it)", provider["file"]
+            );
+            continue;
+        }
+        let rows = provider_rows(provider)?;
+
+        // every row has the same number of glyphs, which divide up the atlas
+        let columns = rows.first().map_or(0, Vec::len);
+        if columns == 0 || rows.iter().any(|row| row.len() != columns) {
+            return Err(PackError::InvalidProvider(
+                "chars rows are empty or differ in length".to_string(),
+            ));
+        }
+        let glyph_width = atlas.width() / columns as u32;
+        let glyph_height = atlas.height() / rows.len() as u32;
+        if glyph_width == 0 || glyph_height == 0 {
+            return Err(PackError::InvalidProvider(format!(
+                "{columns}x{} glyphs don't fit in a {}x{} atlas",
+                rows.len(),
+                atlas.width(),
+                atlas.height()
+            )));
+        }
+        debug!("slicing {glyph_width}x{glyph_height} glyphs from a provider");
+
+        let ascent = provider["ascent"]
+            .as_i64()
+            .filter(|ascent| Some(*ascent) != base_ascent)
+            .and_then(|ascent| i32::try_from(ascent).ok());
+        for (row, y) in rows.iter().zip(0u32..) {
+            for (codepoint, x) in row.iter().zip(0u32..) {
+                if *codepoint == '\0' {
+                    continue;
+                }
+                let Some(name) = inverse.get(codepoint) else {
+                    warn!(
+                        kind = "skipped",
+                        "glyph U+{:04X} has no name in the mapping (skipping it)",
+                        *codepoint as u32
+                    );
+                    continue;
+                };
+                let (x, y) = (x * glyph_width, y * glyph_height);
+                let glyph = atlas.crop_imm(x, y, glyph_width, glyph_height);
+                glyphs.push(((y, x), name.to_string(), glyph));
+                if let Some(ascent) = ascent {
+                    ascents.insert(name.to_string(), ascent);
+                }
+            }
+        }
+    }
+
+    // in atlas order, with glyphs sharing a cell (aliases) in name order
+    glyphs.sort_by(|(a_cell, a_name, _), (b_cell, b_name, _)| {
+        (a_cell, a_name).cmp(&(b_cell, b_name))
+    });
+    info!("sliced {} glyphs from the atlas", glyphs.len());
+    Ok(SlicedAtlas {
+        glyphs: glyphs
+            .into_iter()
+            .map(|(_, name, glyph)| (name, glyph))
+            .collect(),
+        ascents,
+    })
+}
+
+/// The rows of codepoints of a bitmap provider.
+fn provider_rows(provider: &serde_json::Value) -> Result<Vec<Vec<char>>, PackError> {
+    provider["chars"]
+        .as_array()
+        .ok_or_else(|| PackError::InvalidProvider("no bitmap provider with chars".to_string()))?
+        .iter()
+        .map(|row| {
+            row.as_str()
+                .map(|row| row.chars().collect::<Vec<_>>())
+                .ok_or_else(|| {
+                    PackError::InvalidProvider(format!("chars row {row} isn't a string"))
+                })
+        })
+        .collect()
+}
+
+/// Checks that a pack's atlas (or each of its tiles), once encoded, and font provider definition, once serialized, lay
+/// out each glyph just as it was placed: slicing every bitmap provider's cells out of the decoded image it refers to
+/// should give back what was placed in each emoji's cell (see [`Pack::cells`]) exactly.
+///
+/// Fails naming the emoji whose glyph is missing from the providers or doesn't match.
+pub fn round_trip_check(pack: &Pack) -> Result<(), PackError> {
+    let mut textures: HashMap<String, DynamicImage> = HashMap::new();
+    for tile in pack.tiles() {
+        let failed = |err: image::ImageError| {
+            PackError::RoundTripFailed(vec![format!("{} ({err})", tile.file_name())])
+        };
+        let png = pack.encode_tile(&tile).map_err(failed)?;
+        textures.insert(
+            tile.file.clone(),
+            image::load_from_memory(&png).map_err(failed)?,
+        );
+    }
+    let font_provider: serde_json::Value =
+        serde_json::from_str(&serde_json::to_string(&pack.font_provider()).unwrap())?;
+
+    // where each codepoint is in the providers, as the game would find it
+    let mut cells: HashMap<char, (&DynamicImage, u32, u32, u32, u32)> = HashMap::new();
+    let providers = font_provider["providers"].as_array().into_iter().flatten();
+    for provider in providers.filter(|provider| provider["type"] == "bitmap") {
+        let Some(texture) = provider["file"]
+            .as_str()
+            .and_then(|file| textures.get(file))
+        else {
+            continue;
+        };
+        let rows = provider["chars"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|row| row.as_str())
+            .map(|row| row.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let columns = rows.first().map_or(1, |row| row.len().max(1)) as u32;
+        let glyph_width = texture.width() / columns;
+        let glyph_height = texture.height() / (rows.len() as u32).max(1);
+        for (row, y) in rows.iter().zip(0u32..) {
+            for (codepoint, x) in row.iter().zip(0u32..) {
+                if *codepoint != '\0' {
+                    cells.insert(
+                        *codepoint,
+                        (
+                            texture,
+                            x * glyph_width,
+                            y * glyph_height,
+                            glyph_width,
+                            glyph_height,
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    // each emoji's cell in the providers should hold what was placed in its glyph's cell, aliases included
+    let mismatched = pack
+        .names
+        .iter()
+        .filter_map(|(name, codepoint)| {
+            let glyph = pack.aliases.get(codepoint).unwrap_or(codepoint);
+            // emoji kept from the imported mapping may have no glyph in this atlas
+            let &(placed_x, placed_y) = pack.cells.get(glyph)?;
+            let matches = cells
+                .get(codepoint)
+                .is_some_and(|&(texture, x, y, width, height)| {
+                    texture.crop_imm(x, y, width, height).to_rgba16()
+                        == pack
+                            .atlas
+                            .crop_imm(placed_x, placed_y, width, height)
+                            .to_rgba16()
+                });
+            (!matches).then(|| name.clone())
+        })
+        .collect::<Vec<_>>();
+    if !mismatched.is_empty() {
+        return Err(PackError::RoundTripFailed(mismatched));
+    }
+    debug!("every glyph matches its place in the font providers");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    use super::*;
+    use crate::{generate_pack_from_iter, PackConfig};
+
+    fn pack(
+        images: Vec<(String, DynamicImage)>,
+        glyph_size: u32,
+        existing_mappings: HashMap<String, char>,
+    ) -> Pack {
+        let config = PackConfig {
+            glyph_width: glyph_size,
+            glyph_height: glyph_size,
+            atlas_columns: 2,
+            existing_mappings,
+            ..PackConfig::default()
+        };
+        generate_pack_from_iter(images.into_iter(), &config).unwrap()
+    }
+
+    #[test]
+    fn sliced_glyphs_repack_at_a_new_size_keeping_their_codepoints() {
+        let images = ["a", "b", "c"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let image = RgbaImage::from_fn(16, 16, |x, y| {
+                    Rgba([i as u8 * 80, x as u8 * 16, y as u8 * 16, 255])
+                });
+                (name.to_string(), DynamicImage::ImageRgba8(image))
+            })
+            .collect::<Vec<_>>();
+        let original = pack(images.clone(), 16, HashMap::new());
+        let names = original
+            .names
+            .clone()
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        let sliced = slice_atlas(&original.atlas, &original.font_provider(), &names).unwrap();
+        assert!(sliced.ascents.is_empty());
+        let glyphs = sliced.glyphs;
+        assert_eq!(glyphs.len(), 3);
+        for ((name, glyph), (original_name, image)) in glyphs.iter().zip(&images) {
+            assert_eq!(name, original_name);
+            assert_eq!(glyph.to_rgba8(), image.to_rgba8());
+        }
+
+        let repacked = pack(glyphs, 8, names);
+        assert_eq!(repacked.atlas.dimensions(), (16, 16));
+        assert_eq!(repacked.names, original.names);
+    }
+
+    #[test]
+    fn round_trip_check_catches_corrupted_placements() {
+        let images = ["a", "b", "c"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let image = RgbaImage::from_fn(16, 16, |x, y| {
+                    Rgba([i as u8 * 80, x as u8 * 16, y as u8 * 16, 255])
+                });
+                (name.to_string(), DynamicImage::ImageRgba8(image))
+            })
+            .collect::<Vec<_>>();
+        let mut pack = pack(images, 16, HashMap::new());
+        round_trip_check(&pack).unwrap();
+
+        // "a" claims to be placed in "b"'s cell
+        let a = pack.names["a"];
+        let b = pack.names["b"];
+        let b_cell = pack.cells[&b];
+        pack.cells.insert(a, b_cell);
+        match round_trip_check(&pack) {
+            Err(PackError::RoundTripFailed(names)) => assert_eq!(names, ["a"]),
+            other => panic!("expected a round trip failure, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn glyphs_in_every_provider_are_sliced_with_their_ascents() {
+        let images = ["a", "b", "c"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let image = RgbaImage::from_fn(16, 16, |x, y| {
+                    Rgba([i as u8 * 80, x as u8 * 16, y as u8 * 16, 255])
+                });
+                (name.to_string(), DynamicImage::ImageRgba8(image))
+            })
+            .collect::<Vec<_>>();
+        let config = PackConfig {
+            glyph_width: 16,
+            glyph_height: 16,
+            atlas_columns: 2,
+            ascent_overrides: HashMap::from([("b".to_string(), 3)]),
+            ..PackConfig::default()
+        };
+        let original = generate_pack_from_iter(images.clone().into_iter(), &config).unwrap();
+        assert_eq!(original.providers().len(), 2);
+        let names = original
+            .names
+            .clone()
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        let sliced = slice_atlas(&original.atlas, &original.font_provider(), &names).unwrap();
+        assert_eq!(sliced.ascents, HashMap::from([("b".to_string(), 3)]));
+        let sliced_names = sliced
+            .glyphs
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(sliced_names, ["a", "b", "c"]);
+        assert_eq!(sliced.glyphs[1].1.to_rgba8(), images[1].1.to_rgba8());
+
+        let config = PackConfig {
+            existing_mappings: names,
+            ascent_overrides: sliced.ascents,
+            ..config
+        };
+        let repacked = generate_pack_from_iter(sliced.glyphs.into_iter(), &config).unwrap();
+        assert_eq!(repacked.names, original.names);
+        assert_eq!(repacked.glyph_ascents, original.glyph_ascents);
+    }
+}
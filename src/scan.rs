@@ -0,0 +1,1634 @@
+//! Finding emoji images in a directory.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use image::{DynamicImage, GenericImageView, ImageDecoder, ImageError, ImageFormat};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::{name_key, PackConfig, PackError};
+
+/// An emoji image found in the emoji directory.
+#[derive(Debug, Clone)]
+pub struct EmojiSource {
+    /// The normalized emoji name, derived from the file name.
+    pub name: String,
+
+    /// Path to the image file, or to the color layer for an emoji with a separate tint mask.
+    pub path: PathBuf,
+
+    /// Path to the tint mask whose alpha is applied to the color layer, for an emoji authored as a pair of files.
+    pub mask: Option<PathBuf>,
+
+    /// Path to a grayscale image whose luminance replaces the image's alpha, for an emoji with separate alpha.
+    pub alpha: Option<PathBuf>,
+
+    /// Options for this emoji, from the JSON sidecar file next to the image.
+    pub metadata: EmojiMetadata,
+}
+
+/// Per-emoji options, read from a JSON file with the same name as the image (`foo.json` for `foo.png`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EmojiMetadata {
+    /// Whether to pack the emoji at all, so work in progress can stay in the emoji directory.
+    pub enabled: bool,
+
+    /// Place the image at its native size, without resizing it.
+    pub no_resize: bool,
+
+    /// How far the top of the glyph sits above the text baseline, out of 8, rather than the pack's ascent.
+    pub ascent: Option<i32>,
+
+    /// Search keywords for emoji pickers, exported with the shortcodes.
+    #[serde(alias = "tags")]
+    pub keywords: Vec<String>,
+
+    /// Rotation to apply to the image, clockwise, to fix its orientation.
+    pub rotate: Option<Rotation>,
+
+    /// Flip to apply to the image (after rotating it), to fix its orientation.
+    pub flip: Option<Flip>,
+
+    /// Category to group the emoji under in emoji pickers, exported with the shortcodes.
+    pub category: Option<String>,
+
+    /// Position to pack the emoji at, before the emoji without one, with ties broken by name.
+    pub order: Option<i64>,
+
+    /// Group of emoji to keep this one next to in the atlas.
+    pub group: Option<String>,
+
+    /// Who made the emoji, for crediting them.
+    pub author: Option<String>,
+
+    /// License the emoji is distributed under, like `CC-BY-4.0`.
+    pub license: Option<String>,
+
+    /// Where the emoji came from.
+    pub source_url: Option<String>,
+
+    /// Sound for chat clients to play when the emoji is used, like a file name or URL, exported with the shortcodes.
+    pub sound: Option<String>,
+}
+
+impl Default for EmojiMetadata {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            no_resize: false,
+            ascent: None,
+            keywords: Vec::new(),
+            rotate: None,
+            flip: None,
+            category: None,
+            order: None,
+            group: None,
+            author: None,
+            license: None,
+            source_url: None,
+            sound: None,
+        }
+    }
+}
+
+/// An emoji in a CSV listing, which picks the emoji to pack from the emoji directory, and names and orders them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListedEmoji {
+    /// File name of the image in the emoji directory, with or without its extension.
+    pub file: String,
+
+    /// Name to give the emoji, rather than the one from its file name.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Category to group the emoji under, as [`EmojiMetadata::category`].
+    #[serde(default)]
+    pub category: Option<String>,
+
+    /// Position to pack the emoji at. Emoji without one follow those with one, in the order they're listed.
+    #[serde(default)]
+    pub order: Option<i64>,
+}
+
+/// A clockwise rotation by a multiple of 90 degrees, written as the number of degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "u16")]
+pub enum Rotation {
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+impl TryFrom<u16> for Rotation {
+    type Error = String;
+
+    fn try_from(degrees: u16) -> Result<Self, Self::Error> {
+        match degrees {
+            90 => Ok(Self::Quarter),
+            180 => Ok(Self::Half),
+            270 => Ok(Self::ThreeQuarters),
+            _ => Err(format!("can't rotate by {degrees}, only 90, 180 or 270")),
+        }
+    }
+}
+
+/// Direction to flip an image in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Flip {
+    /// Mirror left to right.
+    Horizontal,
+
+    /// Mirror top to bottom.
+    Vertical,
+}
+
+impl EmojiMetadata {
+    /// Applies the rotation and flip to an image.
+    pub fn orient(&self, image: DynamicImage) -> DynamicImage {
+        let image = match self.rotate {
+            None => image,
+            Some(Rotation::Quarter) => image.rotate90(),
+            Some(Rotation::Half) => image.rotate180(),
+            Some(Rotation::ThreeQuarters) => image.rotate270(),
+        };
+        match self.flip {
+            None => image,
+            Some(Flip::Horizontal) => image.fliph(),
+            Some(Flip::Vertical) => image.flipv(),
+        }
+    }
+
+    /// Reads the sidecar file for an image, if it has one, warning and using the defaults if it's invalid.
+    fn read_sidecar(image_path: &Path) -> Self {
+        let sidecar = image_path.with_extension("json");
+        if !sidecar.is_file() {
+            return Self::default();
+        }
+
+        let metadata = std::fs::read_to_string(&sidecar)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| serde_json::from_str(&contents).map_err(|err| err.to_string()));
+        match metadata {
+            Ok(metadata) => {
+                debug!("read metadata from \"{}\"", sidecar.display());
+                metadata
+            }
+            Err(err) => {
+                warn!(
+                    "failed to read metadata \"{}\" (ignoring it): {}",
+                    sidecar.display(),
+                    err
+                );
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Order in which to pack emoji found in the emoji directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortOrder {
+    /// Alphabetically by name.
+    Name,
+
+    /// By file size, smallest first.
+    Size,
+
+    /// By modification time, oldest first.
+    Mtime,
+}
+
+/// Name of the file in the emoji directory listing glob patterns of files to skip.
+pub const IGNORE_FILE_NAME: &str = ".fedimojiignore";
+
+/// Parses an image format from its file extension, as given to `--format-priority`.
+pub fn parse_format(extension: &str) -> Result<ImageFormat, String> {
+    match ImageFormat::from_extension(extension.trim()) {
+        Some(format) if format.reading_enabled() => Ok(format),
+        _ => Err(format!(
+            "can't read images with the extension {extension:?}"
+        )),
+    }
+}
+
+/// Position of an image's format in the configured priority, lower being preferred, or [`None`] if it isn't one of
+/// the formats to read.
+fn format_rank(path: &Path, config: &PackConfig) -> Option<usize> {
+    // Photoshop documents are the sources of exports, so any export with the same name is preferred
+    #[cfg(feature = "psd")]
+    if crate::psd::is_psd(path) {
+        return Some(config.format_priority.len());
+    }
+
+    let format = ImageFormat::from_path(path).ok()?;
+    config
+        .format_priority
+        .iter()
+        .position(|&priority| priority == format)
+}
+
+/// Builds the set of patterns for files to skip, from the configured excludes and the emoji directory's ignore file.
+fn exclude_patterns(emoji_dir: &Path, config: &PackConfig) -> Result<GlobSet, PackError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in &config.exclude {
+        builder.add(Glob::new(pattern)?);
+    }
+
+    let ignore_file = emoji_dir.join(IGNORE_FILE_NAME);
+    if ignore_file.is_file() {
+        let contents = std::fs::read_to_string(&ignore_file)?;
+        let patterns = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for pattern in patterns {
+            builder.add(Glob::new(pattern)?);
+        }
+        debug!("read ignore file `{}`", ignore_file.display());
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Reads a list of emoji names, one per line, composed and lowercased (see [`name_key`]). Blank lines and lines
+/// starting with `#` are ignored.
+pub fn read_name_list(path: &Path) -> Result<Vec<String>, PackError> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(name_key)
+        .collect())
+}
+
+/// Reads a CSV listing of emoji, with a header row naming its columns: `file`, and optionally `name`, `category` and
+/// `order`. Header names are compared case-insensitively, and any other columns are ignored.
+pub fn read_listing(path: &Path) -> Result<Vec<ListedEmoji>, PackError> {
+    if !path.exists() {
+        return Err(PackError::ListingMissing(path.to_path_buf()));
+    }
+    let csv_error = |source| PackError::ListingRead {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(csv_error)?;
+    let headers = reader
+        .headers()
+        .map_err(csv_error)?
+        .iter()
+        .map(str::to_lowercase)
+        .collect::<csv::StringRecord>();
+    reader.set_headers(headers);
+    reader
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .map_err(csv_error)
+}
+
+/// Checks that the emoji names are exactly the expected ones, failing if any are missing, or if there are extra
+/// ones and they aren't allowed.
+pub fn check_expected_names<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    expected: &[String],
+    allow_extra: bool,
+) -> Result<(), PackError> {
+    let names: BTreeSet<String> = names.into_iter().map(name_key).collect();
+    let expected: BTreeSet<String> = expected.iter().map(|name| name_key(name)).collect();
+
+    let missing = expected
+        .difference(&names)
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    for name in &missing {
+        warn!("expected emoji \"{name}\" is missing");
+    }
+    let extra = names
+        .difference(&expected)
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    for name in &extra {
+        if allow_extra {
+            info!("emoji \"{name}\" isn't in the expected names");
+        } else {
+            warn!("unexpected emoji \"{name}\"");
+        }
+    }
+
+    if missing.is_empty() && (allow_extra || extra.is_empty()) {
+        return Ok(());
+    }
+    Err(PackError::UnexpectedNames {
+        missing,
+        extra: if allow_extra { Vec::new() } else { extra },
+    })
+}
+
+/// Checks that every emoji in the imported mapping is among the emoji names, warning about (or, if `strict`, failing
+/// on) stale entries whose image has gone. Their codepoints stay reserved, but they're left out of the new mapping.
+pub fn check_stale_imports<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    existing_mappings: &HashMap<String, char>,
+    strict: bool,
+) -> Result<(), PackError> {
+    let names: HashSet<String> = names.into_iter().map(name_key).collect();
+    let stale = existing_mappings
+        .keys()
+        .filter(|name| !names.contains(&name_key(name)))
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    for name in &stale {
+        warn!(
+            kind = "stale_import",
+            "imported emoji \"{name}\" (U+{:04X}) has no image, so the imported mapping is stale",
+            existing_mappings[name] as u32
+        );
+    }
+
+    if strict && !stale.is_empty() {
+        return Err(PackError::StaleImports(stale.into_iter().collect()));
+    }
+    Ok(())
+}
+
+/// Checks that none of the emoji's files are larger than `max_bytes`, by their file metadata, warning about (or, if
+/// `strict`, failing on) those which are.
+pub fn check_file_sizes(
+    sources: &[EmojiSource],
+    max_bytes: u64,
+    strict: bool,
+) -> Result<(), PackError> {
+    let mut oversized = Vec::new();
+    for path in sources
+        .iter()
+        .flat_map(|source| std::iter::once(&source.path).chain(&source.mask))
+    {
+        let bytes = std::fs::metadata(path)?.len();
+        if bytes > max_bytes {
+            warn!(
+                kind = "oversized_file",
+                "\"{}\" is {bytes} bytes, over the limit of {max_bytes} bytes",
+                path.display()
+            );
+            oversized.push(path.display().to_string());
+        }
+    }
+
+    if strict && !oversized.is_empty() {
+        return Err(PackError::OversizedFiles(oversized));
+    }
+    Ok(())
+}
+
+/// Checks that every emoji's metadata credits its author and gives its license, warning about (or, if `strict`,
+/// failing on) those which don't.
+pub fn check_attribution(sources: &[EmojiSource], strict: bool) -> Result<(), PackError> {
+    let unattributed = sources
+        .iter()
+        .filter(|source| source.metadata.author.is_none() || source.metadata.license.is_none())
+        .map(|source| source.name.clone())
+        .collect::<Vec<_>>();
+    for name in &unattributed {
+        warn!(
+            kind = "unattributed",
+            "emoji \"{name}\" has no author or license in its metadata"
+        );
+    }
+
+    if strict && !unattributed.is_empty() {
+        return Err(PackError::MissingAttribution(unattributed));
+    }
+    Ok(())
+}
+
+/// Finds all the images in the emoji directory, in the formats to read, deriving emoji names from their file names.
+///
+/// Files matching an exclude pattern are skipped. Where files in different formats have the same name, the one whose
+/// format comes first in [`PackConfig::format_priority`] is used, and other files whose name duplicates an earlier
+/// file's are skipped.
+pub fn scan_emoji_dir(
+    emoji_dir: &Path,
+    config: &PackConfig,
+) -> Result<Vec<EmojiSource>, PackError> {
+    sources_from_files(dir_files(emoji_dir, config)?, config)
+}
+
+/// Finds all the images in several emoji directories, as [`scan_emoji_dir`] does for one, packing them together.
+///
+/// Where several directories have an emoji with the same name, the one in the last of them is used, with a warning,
+/// so add-on directories can replace emoji from a base one.
+pub fn scan_emoji_dirs(
+    emoji_dirs: &[PathBuf],
+    config: &PackConfig,
+) -> Result<Vec<EmojiSource>, PackError> {
+    let mut files: Vec<(PathBuf, String)> = Vec::new();
+    for emoji_dir in emoji_dirs {
+        let dir_files = dir_files(emoji_dir, config)?;
+        let names = dir_files
+            .iter()
+            .map(|(_, name)| name_key(&config.normalize_name(name)))
+            .collect::<HashSet<_>>();
+        files.retain(|(path, name)| {
+            let overridden = names.contains(&name_key(&config.normalize_name(name)));
+            if overridden {
+                warn!(
+                    kind = "duplicate",
+                    "\"{}\" is replaced by an emoji of the same name in `{}`",
+                    path.display(),
+                    emoji_dir.display()
+                );
+            }
+            !overridden
+        });
+        files.extend(dir_files);
+    }
+    sources_from_files(files, config)
+}
+
+/// Finds the image files in an emoji directory, in the formats to read, with the names they give their emoji.
+fn dir_files(emoji_dir: &Path, config: &PackConfig) -> Result<Vec<(PathBuf, String)>, PackError> {
+    if !emoji_dir.is_dir() {
+        return Err(PackError::EmojiDirMissing(emoji_dir.to_path_buf()));
+    }
+
+    let excludes = exclude_patterns(emoji_dir, config)?;
+
+    let files = emoji_dir
+        .read_dir()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            format_rank(&path, config)?;
+
+            if excludes.is_match(entry.file_name()) {
+                debug!("excluding \"{}\"", path.display());
+                return None;
+            }
+
+            let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+            if is_symlink {
+                if !config.follow_symlinks {
+                    debug!("not following symlink \"{}\"", path.display());
+                    return None;
+                }
+
+                // resolve the whole chain of links, which fails for broken or looping links
+                match path.canonicalize() {
+                    Err(err) => {
+                        warn!(
+                            kind = "skipped",
+                            "failed to resolve symlink \"{}\" (skipping it): {}",
+                            path.display(),
+                            err
+                        );
+                        None
+                    }
+                    Ok(target) if target.is_file() => Some(path),
+                    Ok(_) => None,
+                }
+            } else if path.is_file() {
+                Some(path)
+            } else {
+                None
+            }
+        })
+        .map(|path| with_file_stem(path, config))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    Ok(files)
+}
+
+/// Reads a list of image paths, one per line, deriving emoji names from their file names, in place of scanning the
+/// emoji directory. Blank lines are ignored, and files which don't exist or aren't in a format to read are skipped
+/// with a warning.
+///
+/// Where files in different formats have the same name, the one whose format comes first in
+/// [`PackConfig::format_priority`] is used, and other files whose name duplicates an earlier file's are skipped.
+pub fn scan_file_list(
+    mut list: impl Read,
+    config: &PackConfig,
+) -> Result<Vec<EmojiSource>, PackError> {
+    let mut contents = String::new();
+    list.read_to_string(&mut contents)?;
+
+    let files = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .filter(|path| {
+            if !path.is_file() {
+                warn!(
+                    kind = "skipped",
+                    "\"{}\" isn't a file (skipping it)",
+                    path.display()
+                );
+                return false;
+            }
+            if format_rank(path, config).is_none() {
+                warn!(
+                    kind = "skipped",
+                    "\"{}\" isn't an image in a format to read (skipping it)",
+                    path.display()
+                );
+                return false;
+            }
+            true
+        })
+        .map(|path| with_file_stem(path, config))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    sources_from_files(files, config)
+}
+
+/// Pairs a file's path with its file name without the extension, which the emoji is named after, or skips it with a
+/// warning if the file name isn't valid UTF-8, as it couldn't be named without mangling it. That's an error for
+/// [`PackConfig::reproducible`] builds, as skipping files silently changes the output.
+fn with_file_stem(
+    path: PathBuf,
+    config: &PackConfig,
+) -> Result<Option<(PathBuf, String)>, PackError> {
+    let Some(stem) = path.file_stem() else {
+        return Ok(None);
+    };
+    match stem.to_str().map(str::to_string) {
+        Some(stem) => Ok(Some((path, stem))),
+        None if config.reproducible => Err(PackError::NotReproducible(format!(
+            "file name of \"{}\" isn't valid UTF-8",
+            path.display()
+        ))),
+        None => {
+            warn!(
+                kind = "invalid_name",
+                "file name of \"{}\" isn't valid UTF-8, so it can't be an emoji name (skipping it)",
+                path.display()
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// Turns image files and the names from their file names into emoji sources, pairing up tint masks, reading sidecar
+/// metadata, applying the listing, and skipping duplicates and disabled emoji.
+fn sources_from_files(
+    files: Vec<(PathBuf, String)>,
+    config: &PackConfig,
+) -> Result<Vec<EmojiSource>, PackError> {
+    // names of the emoji we've already seen, for skipping duplicates
+    let mut seen_names: HashSet<String> = HashSet::new();
+
+    // set aside the separate alpha files, if there are any, to combine with their images
+    let (files, mut alphas) = match &config.alpha_suffix {
+        Some(alpha_suffix) => split_alpha_files(files, alpha_suffix),
+        None => (files, BTreeMap::new()),
+    };
+
+    // combine each tint mask with its color layer, if they're separate files
+    let files = match (&config.mask_suffix, &config.color_suffix) {
+        (Some(mask_suffix), Some(color_suffix)) => {
+            pair_tint_masks(files, mask_suffix, color_suffix)
+        }
+        _ => files
+            .into_iter()
+            .map(|(path, name)| (path, name, None))
+            .collect(),
+    };
+
+    let sources = files
+        .into_iter()
+        .map(|(path, name, mask)| {
+            let alpha = alphas.remove(&name);
+            // the name is the file name without its extension, normalized
+            let name = config.normalize_name(&name);
+            let metadata = EmojiMetadata::read_sidecar(&path);
+            EmojiSource {
+                name,
+                path,
+                mask,
+                alpha,
+                metadata,
+            }
+        })
+        .collect::<Vec<_>>();
+    for (name, path) in alphas {
+        warn!(
+            kind = "skipped",
+            "alpha file \"{}\" has no image \"{name}\" (skipping it)",
+            path.display()
+        );
+    }
+
+    let mut sources = sources
+        .into_iter()
+        .filter(|source| {
+            if !source.metadata.enabled {
+                debug!("\"{}\" is disabled (skipping it)", source.path.display());
+            }
+            source.metadata.enabled
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(order) = config.sort {
+        sort_sources(&mut sources, order);
+    }
+
+    if let Some(listing) = &config.listing {
+        sources = apply_listing(sources, listing, config);
+    }
+
+    // the most preferred format each name has a file in, comparing names by their keys
+    let mut best_ranks: HashMap<String, usize> = HashMap::new();
+    for source in &sources {
+        let rank = format_rank(&source.path, config).unwrap_or(usize::MAX);
+        best_ranks
+            .entry(name_key(&source.name))
+            .and_modify(|best| *best = rank.min(*best))
+            .or_insert(rank);
+    }
+
+    let mut sources = sources
+        .into_iter()
+        .filter(|source| {
+            // skip files in a less preferred format than another file with the same name
+            let rank = format_rank(&source.path, config).unwrap_or(usize::MAX);
+            if rank > best_ranks[&name_key(&source.name)] {
+                info!(
+                    "skipping \"{}\", as \"{}\" is also in a preferred format",
+                    source.path.display(),
+                    source.name
+                );
+                return false;
+            }
+
+            // skip emoji whose name we've already seen
+            if seen_names.insert(name_key(&source.name)) {
+                true
+            } else {
+                warn!(
+                    kind = "duplicate",
+                    "duplicate emoji name \"{}\" from \"{}\" (skipping it)",
+                    source.name,
+                    source.path.display()
+                );
+                false
+            }
+        })
+        .collect::<Vec<_>>();
+
+    config.shorten_names(sources.iter_mut().map(|source| &mut source.name))?;
+    Ok(sources)
+}
+
+/// Picks the emoji listed in a CSV listing, naming and ordering them as it says, with the unlisted emoji after them if
+/// [`PackConfig::include_unlisted`] is set.
+///
+/// Listed files which aren't in the emoji directory are skipped with a warning.
+fn apply_listing(
+    sources: Vec<EmojiSource>,
+    listing: &[ListedEmoji],
+    config: &PackConfig,
+) -> Vec<EmojiSource> {
+    // sources by file name, with and without the extension
+    let mut files: HashMap<String, usize> = HashMap::new();
+    for (index, source) in sources.iter().enumerate() {
+        for name in [source.path.file_name(), source.path.file_stem()]
+            .into_iter()
+            .flatten()
+        {
+            files
+                .entry(name.to_string_lossy().into_owned())
+                .or_insert(index);
+        }
+    }
+
+    // emoji with an order first, then the rest in the order they're listed
+    let mut listing = listing.iter().collect::<Vec<_>>();
+    listing.sort_by_key(|listed| (listed.order.is_none(), listed.order));
+
+    let mut sources = sources.into_iter().map(Some).collect::<Vec<_>>();
+
+    let mut listed_sources = Vec::new();
+    for listed in listing {
+        let Some(index) = files.get(&listed.file).copied() else {
+            warn!(
+                kind = "skipped",
+                "\"{}\" is in the CSV listing, but not the emoji directory (skipping it)",
+                listed.file
+            );
+            continue;
+        };
+        let Some(mut source) = sources[index].take() else {
+            warn!(
+                kind = "duplicate",
+                "\"{}\" is in the CSV listing more than once (skipping it)", listed.file
+            );
+            continue;
+        };
+
+        if let Some(name) = &listed.name {
+            source.name = config.normalize_name(name);
+        }
+        if listed.category.is_some() {
+            source.metadata.category.clone_from(&listed.category);
+        }
+        source.metadata.order = Some(listed_sources.len() as i64);
+        listed_sources.push(source);
+    }
+
+    let unlisted = sources.into_iter().flatten();
+    if config.include_unlisted {
+        listed_sources.extend(unlisted);
+    } else {
+        for source in unlisted {
+            debug!(
+                "\"{}\" isn't in the CSV listing (skipping it)",
+                source.path.display()
+            );
+        }
+    }
+    listed_sources
+}
+
+/// Separates the files holding the alpha of another image (`foo_alpha.png` for `foo.png`) from the rest, returning
+/// the rest, and the alpha files by the name of the image they belong to.
+fn split_alpha_files(
+    files: Vec<(PathBuf, String)>,
+    alpha_suffix: &str,
+) -> (Vec<(PathBuf, String)>, BTreeMap<String, PathBuf>) {
+    let mut alphas = BTreeMap::new();
+    let mut rest = Vec::new();
+    for (path, name) in files {
+        match name.strip_suffix(alpha_suffix) {
+            Some(base) if !base.is_empty() => {
+                alphas.insert(base.to_string(), path);
+            }
+            _ => rest.push((path, name)),
+        }
+    }
+    (rest, alphas)
+}
+
+/// Pairs up tint masks (`foo_mask.png`) with their color layers (`foo_color.png`) as single emoji named without the
+/// suffix (`foo`), as (color layer, name, mask). Files with neither suffix are passed through as they are, and masks
+/// or color layers without the other half are skipped with a warning.
+fn pair_tint_masks(
+    files: Vec<(PathBuf, String)>,
+    mask_suffix: &str,
+    color_suffix: &str,
+) -> Vec<(PathBuf, String, Option<PathBuf>)> {
+    let mut masks: BTreeMap<String, PathBuf> = BTreeMap::new();
+    let mut colors = Vec::new();
+    let mut paired = Vec::new();
+    for (path, name) in files {
+        if let Some(base) = name.strip_suffix(mask_suffix) {
+            masks.insert(base.to_string(), path);
+        } else if let Some(base) = name.strip_suffix(color_suffix) {
+            colors.push((path, base.to_string()));
+        } else {
+            paired.push((path, name, None));
+        }
+    }
+
+    for (path, name) in colors {
+        match masks.remove(&name) {
+            Some(mask) => paired.push((path, name, Some(mask))),
+            None => warn!(
+                kind = "skipped",
+                "color layer \"{}\" has no tint mask \"{name}{mask_suffix}\" (skipping it)",
+                path.display()
+            ),
+        }
+    }
+    for (name, path) in masks {
+        warn!(
+            kind = "skipped",
+            "tint mask \"{}\" has no color layer \"{name}{color_suffix}\" (skipping it)",
+            path.display()
+        );
+    }
+    paired
+}
+
+/// Applies a tint mask to its color layer, multiplying the color layer's alpha by the mask's, or returns [`None`] if
+/// they aren't the same size.
+pub fn apply_tint_mask(color: DynamicImage, mask: &DynamicImage) -> Option<DynamicImage> {
+    if color.dimensions() != mask.dimensions() {
+        return None;
+    }
+    Some(match color {
+        DynamicImage::ImageRgba16(mut color) => {
+            let mask = mask.to_rgba16();
+            for (pixel, mask) in color.pixels_mut().zip(mask.pixels()) {
+                pixel[3] = (u32::from(pixel[3]) * u32::from(mask[3]) / u32::from(u16::MAX)) as u16;
+            }
+            DynamicImage::ImageRgba16(color)
+        }
+        color => {
+            let mut color = color.into_rgba8();
+            let mask = mask.to_rgba8();
+            for (pixel, mask) in color.pixels_mut().zip(mask.pixels()) {
+                pixel[3] = (u16::from(pixel[3]) * u16::from(mask[3]) / u16::from(u8::MAX)) as u8;
+            }
+            DynamicImage::ImageRgba8(color)
+        }
+    })
+}
+
+/// Sorts emoji sources, breaking ties by name so the order is always deterministic.
+fn sort_sources(sources: &mut [EmojiSource], order: SortOrder) {
+    match order {
+        SortOrder::Name => sources.sort_by(|a, b| a.name.cmp(&b.name)),
+        SortOrder::Size => sources.sort_by_cached_key(|source| {
+            let size = std::fs::metadata(&source.path).map_or(0, |metadata| metadata.len());
+            (size, source.name.clone())
+        }),
+        SortOrder::Mtime => sources.sort_by_cached_key(|source| {
+            let mtime = std::fs::metadata(&source.path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            (mtime, source.name.clone())
+        }),
+    }
+}
+
+/// Reads the dimensions of an image from its header, without decoding the whole image.
+pub fn read_dimensions(path: &Path) -> image::ImageResult<(u32, u32)> {
+    #[cfg(feature = "psd")]
+    if crate::psd::is_psd(path) {
+        return crate::psd::read_psd_dimensions(path);
+    }
+
+    image::ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_dimensions()
+}
+
+/// Decodes an image, warning if it has an embedded color profile.
+pub fn decode_image(path: &Path, config: &PackConfig) -> image::ImageResult<DynamicImage> {
+    #[cfg(feature = "psd")]
+    if crate::psd::is_psd(path) {
+        return crate::psd::decode_psd(path, config);
+    }
+
+    let mut decoder = image::ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+
+    // we don't apply color profiles, and neither does Minecraft, so the pixels are always treated as plain sRGB
+    if decoder.icc_profile()?.is_some() {
+        if config.strip_color_profile {
+            debug!("stripping color profile from \"{}\"", path.display());
+        } else {
+            warn!(
+                "\"{}\" has an embedded color profile, which will be ignored, so its colors may look off in-game",
+                path.display()
+            );
+        }
+    }
+
+    DynamicImage::from_decoder(decoder).map(expand_to_rgba)
+}
+
+/// Expands a decoded image (e.g. palette or grayscale) to RGBA, so later stages always have an alpha channel to
+/// work with. The PNG decoder has already turned `tRNS` transparency into alpha by this point.
+pub(crate) fn expand_to_rgba(image: DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageRgba8(_) | DynamicImage::ImageRgba16(_) => image,
+        DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_) => DynamicImage::ImageRgba16(image.into_rgba16()),
+        _ => DynamicImage::ImageRgba8(image.into_rgba8()),
+    }
+}
+
+/// Why an image failed to decode, for telling a bad export apart from a file that isn't an image at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DecodeFailure {
+    /// The file is zero bytes long.
+    Empty,
+
+    /// The file ends before the image does.
+    Truncated,
+
+    /// The file isn't in an image format we can read.
+    UnsupportedFormat,
+
+    /// Anything else, such as corrupt image data.
+    Unknown,
+}
+
+impl DecodeFailure {
+    /// Works out why the image at `path` failed to decode with the given error.
+    pub fn classify(path: &Path, err: &ImageError) -> Self {
+        if std::fs::metadata(path).is_ok_and(|metadata| metadata.len() == 0) {
+            return Self::Empty;
+        }
+
+        let is_eof = |err: &std::io::Error| err.kind() == std::io::ErrorKind::UnexpectedEof;
+        match err {
+            ImageError::IoError(err) if is_eof(err) => return Self::Truncated,
+            ImageError::Unsupported(_) => return Self::UnsupportedFormat,
+            _ => {}
+        }
+
+        // the format is otherwise guessed from the extension, so check the file's contents look like an image (the
+        // image crate doesn't know Photoshop documents' signature)
+        let mut header = Vec::new();
+        let read_header = std::fs::File::open(path)
+            .and_then(|file| file.take(64).read_to_end(&mut header))
+            .is_ok();
+        if read_header && image::guess_format(&header).is_err() && !header.starts_with(b"8BPS") {
+            return Self::UnsupportedFormat;
+        }
+
+        // decoders wrap the I/O error when they run out of data partway through
+        let mut source = std::error::Error::source(err);
+        while let Some(err) = source {
+            if err.downcast_ref::<std::io::Error>().is_some_and(is_eof) {
+                return Self::Truncated;
+            }
+            source = err.source();
+        }
+        Self::Unknown
+    }
+}
+
+impl fmt::Display for DecodeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Empty => "empty file",
+            Self::Truncated => "truncated",
+            Self::UnsupportedFormat => "unsupported format",
+            Self::Unknown => "unknown error",
+        })
+    }
+}
+
+/// Reads the emoji images, skipping any which fail to decode.
+///
+/// Once all have been read, a summary of the failures is logged, grouped by [`DecodeFailure`].
+pub fn read_emoji<'a>(sources: &'a [EmojiSource], config: &'a PackConfig) -> ReadEmoji<'a> {
+    ReadEmoji {
+        sources: sources.iter(),
+        config,
+        failures: BTreeMap::new(),
+    }
+}
+
+/// An iterator over decoded emoji images, from [`read_emoji`].
+pub struct ReadEmoji<'a> {
+    sources: std::slice::Iter<'a, EmojiSource>,
+    config: &'a PackConfig,
+
+    /// Paths of the images which failed to decode so far, by why they failed.
+    failures: BTreeMap<DecodeFailure, Vec<&'a Path>>,
+}
+
+/// Reads an emoji's tint mask and applies it to its color layer, warning (and noting any decode failure) and
+/// returning [`None`] if it can't be.
+fn read_tint_mask<'a>(
+    color: DynamicImage,
+    mask_path: &'a Path,
+    config: &PackConfig,
+    failures: &mut BTreeMap<DecodeFailure, Vec<&'a Path>>,
+) -> Option<DynamicImage> {
+    let mask = match decode_image(mask_path, config) {
+        Ok(mask) => mask,
+        Err(err) => {
+            let failure = DecodeFailure::classify(mask_path, &err);
+            warn!(
+                kind = "decode_failure",
+                "failed to read \"{}\" (skipping it): {}: {}",
+                mask_path.display(),
+                failure,
+                err
+            );
+            failures.entry(failure).or_default().push(mask_path);
+            return None;
+        }
+    };
+    let (width, height) = (color.width(), color.height());
+    let image = apply_tint_mask(color, &mask);
+    if image.is_none() {
+        warn!(
+            kind = "skipped",
+            "tint mask \"{}\" is {}x{}, but its color layer is {width}x{height} (skipping it)",
+            mask_path.display(),
+            mask.width(),
+            mask.height()
+        );
+    }
+    image
+}
+
+/// Reads an emoji's separate alpha file and uses its luminance as the image's alpha, warning (and noting any decode
+/// failure) and returning [`None`] if it can't be.
+fn read_alpha<'a>(
+    image: DynamicImage,
+    alpha_path: &'a Path,
+    config: &PackConfig,
+    failures: &mut BTreeMap<DecodeFailure, Vec<&'a Path>>,
+) -> Option<DynamicImage> {
+    let alpha = match decode_image(alpha_path, config) {
+        Ok(alpha) => alpha,
+        Err(err) => {
+            let failure = DecodeFailure::classify(alpha_path, &err);
+            warn!(
+                kind = "decode_failure",
+                "failed to read \"{}\" (skipping it): {}: {}",
+                alpha_path.display(),
+                failure,
+                err
+            );
+            failures.entry(failure).or_default().push(alpha_path);
+            return None;
+        }
+    };
+    if alpha.dimensions() != image.dimensions() {
+        warn!(
+            kind = "skipped",
+            "alpha file \"{}\" is {}x{}, but its image is {}x{} (skipping it)",
+            alpha_path.display(),
+            alpha.width(),
+            alpha.height(),
+            image.width(),
+            image.height()
+        );
+        return None;
+    }
+    Some(apply_alpha(image, &alpha))
+}
+
+/// Replaces an image's alpha with the luminance of another image of the same size.
+pub fn apply_alpha(image: DynamicImage, alpha: &DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageRgba16(mut image) => {
+            let alpha = alpha.to_luma16();
+            for (pixel, alpha) in image.pixels_mut().zip(alpha.pixels()) {
+                pixel[3] = alpha[0];
+            }
+            DynamicImage::ImageRgba16(image)
+        }
+        image => {
+            let mut image = image.into_rgba8();
+            let alpha = alpha.to_luma8();
+            for (pixel, alpha) in image.pixels_mut().zip(alpha.pixels()) {
+                pixel[3] = alpha[0];
+            }
+            DynamicImage::ImageRgba8(image)
+        }
+    }
+}
+
+impl Iterator for ReadEmoji<'_> {
+    type Item = (String, DynamicImage);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for source in self.sources.by_ref() {
+            match decode_image(&source.path, self.config) {
+                Err(err) => {
+                    let failure = DecodeFailure::classify(&source.path, &err);
+                    warn!(
+                        kind = "decode_failure",
+                        "failed to read \"{}\" (skipping it): {}: {}",
+                        source.path.display(),
+                        failure,
+                        err
+                    );
+                    self.failures.entry(failure).or_default().push(&source.path);
+                }
+                Ok(image) => {
+                    let image = match &source.mask {
+                        Some(mask_path) => {
+                            match read_tint_mask(image, mask_path, self.config, &mut self.failures)
+                            {
+                                Some(image) => image,
+                                None => continue,
+                            }
+                        }
+                        None => image,
+                    };
+                    let image = match &source.alpha {
+                        Some(alpha_path) => {
+                            match read_alpha(image, alpha_path, self.config, &mut self.failures) {
+                                Some(image) => image,
+                                None => continue,
+                            }
+                        }
+                        None => image,
+                    };
+                    return Some((source.name.clone(), source.metadata.orient(image)));
+                }
+            }
+        }
+
+        // summarize the failures (each was already warned about), only the first time we run out
+        for (failure, paths) in std::mem::take(&mut self.failures) {
+            let paths = paths
+                .iter()
+                .map(|path| format!("\"{}\"", path.display()))
+                .collect::<Vec<_>>();
+            info!(
+                "{} image(s) failed to read ({}): {}",
+                paths.len(),
+                failure,
+                paths.join(", ")
+            );
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    /// Writes an emoji image to a directory, as `<file_name>`.
+    fn write_emoji(dir: &Path, file_name: &str) {
+        RgbaImage::from_fn(8, 8, |x, y| Rgba([x as u8 * 32, y as u8 * 32, 0, 255]))
+            .save(dir.join(file_name))
+            .unwrap();
+    }
+
+    fn names(sources: &[EmojiSource]) -> Vec<&str> {
+        let mut names = sources
+            .iter()
+            .map(|source| source.name.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_followed_unless_disabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+        write_emoji(dir.path(), "blobcat.png");
+        write_emoji(elsewhere.path(), "neofox.png");
+        std::os::unix::fs::symlink(
+            elsewhere.path().join("neofox.png"),
+            dir.path().join("neofox.png"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(
+            elsewhere.path().join("gone.png"),
+            dir.path().join("broken.png"),
+        )
+        .unwrap();
+
+        let config = PackConfig::default();
+        let sources = scan_emoji_dir(dir.path(), &config).unwrap();
+        assert_eq!(names(&sources), ["blobcat", "neofox"]);
+
+        let config = PackConfig {
+            follow_symlinks: false,
+            ..PackConfig::default()
+        };
+        let sources = scan_emoji_dir(dir.path(), &config).unwrap();
+        assert_eq!(names(&sources), ["blobcat"]);
+    }
+
+    #[test]
+    fn ignore_file_and_excludes_skip_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for file_name in ["blobcat.png", "wip_fox.png", "draft.png", "neofox.png"] {
+            write_emoji(dir.path(), file_name);
+        }
+        std::fs::write(
+            dir.path().join(IGNORE_FILE_NAME),
+            "# work in progress\nwip_*\n\n",
+        )
+        .unwrap();
+
+        let config = PackConfig {
+            exclude: vec!["draft.*".to_string()],
+            ..PackConfig::default()
+        };
+        let sources = scan_emoji_dir(dir.path(), &config).unwrap();
+        assert_eq!(names(&sources), ["blobcat", "neofox"]);
+    }
+
+    #[test]
+    fn sorting_by_size_or_mtime_breaks_ties_by_name() {
+        let dir = tempfile::tempdir().unwrap();
+        for file_name in ["c.png", "a.png", "d.png", "b.png"] {
+            write_emoji(dir.path(), file_name);
+        }
+        // one larger file
+        RgbaImage::from_fn(64, 64, |x, y| Rgba([x as u8, y as u8, 0, 255]))
+            .save(dir.path().join("a.png"))
+            .unwrap();
+        let mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        for file_name in ["c.png", "a.png", "d.png", "b.png"] {
+            std::fs::File::options()
+                .write(true)
+                .open(dir.path().join(file_name))
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        }
+
+        let sorted = |order| {
+            let config = PackConfig {
+                sort: Some(order),
+                ..PackConfig::default()
+            };
+            scan_emoji_dir(dir.path(), &config)
+                .unwrap()
+                .into_iter()
+                .map(|source| source.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(SortOrder::Size), ["b", "c", "d", "a"]);
+        assert_eq!(sorted(SortOrder::Mtime), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn decoded_images_are_expanded_to_rgba() {
+        let gray = DynamicImage::ImageLumaA8(image::GrayAlphaImage::from_pixel(
+            2,
+            2,
+            image::LumaA([100, 50]),
+        ));
+        let expanded = expand_to_rgba(gray);
+        assert_eq!(
+            expanded.as_rgba8().unwrap().get_pixel(0, 0).0,
+            [100, 100, 100, 50]
+        );
+
+        let deep = DynamicImage::ImageLuma16(image::ImageBuffer::from_pixel(
+            2,
+            2,
+            image::Luma([0x1234u16]),
+        ));
+        let expanded = expand_to_rgba(deep);
+        assert_eq!(
+            expanded.as_rgba16().unwrap().get_pixel(0, 0).0,
+            [0x1234, 0x1234, 0x1234, u16::MAX]
+        );
+
+        // as read from a file
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gray.png");
+        image::GrayImage::from_pixel(2, 2, image::Luma([7]))
+            .save(&path)
+            .unwrap();
+        let decoded = decode_image(&path, &PackConfig::default()).unwrap();
+        assert_eq!(
+            decoded.as_rgba8().unwrap().get_pixel(1, 1).0,
+            [7, 7, 7, 255]
+        );
+    }
+
+    #[test]
+    fn expected_names_report_missing_and_extra_emoji() {
+        let expected = ["blobcat", "neofox"].map(String::from);
+        assert!(check_expected_names(["Blobcat", "neofox"], &expected, false).is_ok());
+
+        let result = check_expected_names(["blobcat", "extra"], &expected, false);
+        assert!(matches!(
+            result,
+            Err(PackError::UnexpectedNames { missing, extra }) if missing == ["neofox"] && extra == ["extra"]
+        ));
+
+        assert!(check_expected_names(["blobcat", "neofox", "extra"], &expected, true).is_ok());
+        let result = check_expected_names(["blobcat", "extra"], &expected, true);
+        assert!(matches!(
+            result,
+            Err(PackError::UnexpectedNames { missing, extra }) if missing == ["neofox"] && extra.is_empty()
+        ));
+    }
+
+    #[test]
+    fn decode_failures_are_classified_and_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        write_emoji(dir.path(), "good.png");
+        let png = std::fs::read(dir.path().join("good.png")).unwrap();
+        std::fs::write(dir.path().join("empty.png"), []).unwrap();
+        std::fs::write(dir.path().join("truncated.png"), &png[..png.len() / 2]).unwrap();
+        std::fs::write(dir.path().join("text.png"), "not an image").unwrap();
+
+        let config = PackConfig::default();
+        let classify = |file_name: &str| {
+            let path = dir.path().join(file_name);
+            let err = decode_image(&path, &config).unwrap_err();
+            DecodeFailure::classify(&path, &err)
+        };
+        assert_eq!(classify("empty.png"), DecodeFailure::Empty);
+        assert_eq!(classify("truncated.png"), DecodeFailure::Truncated);
+        assert_eq!(classify("text.png"), DecodeFailure::UnsupportedFormat);
+
+        let sources = scan_emoji_dir(dir.path(), &config).unwrap();
+        let read = read_emoji(&sources, &config)
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(read, ["good"]);
+    }
+
+    #[test]
+    fn the_format_first_in_the_priority_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        write_emoji(dir.path(), "blobcat.png");
+        write_emoji(dir.path(), "blobcat.bmp");
+        write_emoji(dir.path(), "neofox.png");
+
+        for (priority, extension) in [
+            (vec![ImageFormat::Png, ImageFormat::Bmp], "png"),
+            (vec![ImageFormat::Bmp, ImageFormat::Png], "bmp"),
+        ] {
+            let config = PackConfig {
+                format_priority: priority,
+                ..PackConfig::default()
+            };
+            let sources = scan_emoji_dir(dir.path(), &config).unwrap();
+            assert_eq!(names(&sources), ["blobcat", "neofox"]);
+            let blobcat = sources
+                .iter()
+                .find(|source| source.name == "blobcat")
+                .unwrap();
+            assert_eq!(blobcat.path.extension().unwrap(), extension);
+        }
+    }
+
+    #[test]
+    fn metadata_orientation_rotates_then_flips() {
+        // a 3x2 image with one red pixel in its top-left corner
+        let mut image = RgbaImage::new(3, 2);
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        let image = DynamicImage::ImageRgba8(image);
+        let red_pixel = |metadata: &str| {
+            let metadata = serde_json::from_str::<EmojiMetadata>(metadata).unwrap();
+            let oriented = metadata.orient(image.clone()).to_rgba8();
+            let (x, y, _) = oriented
+                .enumerate_pixels()
+                .find(|(_, _, pixel)| pixel.0[0] == 255)
+                .unwrap();
+            (oriented.dimensions(), (x, y))
+        };
+
+        assert_eq!(red_pixel(r#"{"rotate": 90}"#), ((2, 3), (1, 0)));
+        assert_eq!(red_pixel(r#"{"rotate": 180}"#), ((3, 2), (2, 1)));
+        assert_eq!(red_pixel(r#"{"rotate": 270}"#), ((2, 3), (0, 2)));
+        assert_eq!(red_pixel(r#"{"flip": "vertical"}"#), ((3, 2), (0, 1)));
+        assert_eq!(
+            red_pixel(r#"{"rotate": 90, "flip": "horizontal"}"#),
+            ((2, 3), (0, 0))
+        );
+        assert!(serde_json::from_str::<EmojiMetadata>(r#"{"rotate": 45}"#).is_err());
+    }
+
+    #[test]
+    fn tint_masks_are_combined_with_their_color_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        RgbaImage::from_fn(4, 4, |x, _| {
+            Rgba([255, 255, 255, if x < 2 { 255 } else { 0 }])
+        })
+        .save(dir.path().join("blob_mask.png"))
+        .unwrap();
+        RgbaImage::from_pixel(4, 4, Rgba([200, 0, 0, 255]))
+            .save(dir.path().join("blob_color.png"))
+            .unwrap();
+        // a color layer without a mask
+        write_emoji(dir.path(), "unpaired_color.png");
+
+        let config = PackConfig {
+            mask_suffix: Some("_mask".to_string()),
+            color_suffix: Some("_color".to_string()),
+            ..PackConfig::default()
+        };
+        let sources = scan_emoji_dir(dir.path(), &config).unwrap();
+        assert_eq!(names(&sources), ["blob"]);
+
+        let emoji = read_emoji(&sources, &config).collect::<Vec<_>>();
+        assert_eq!(emoji.len(), 1);
+        let glyph = emoji[0].1.to_rgba8();
+        for (x, _, pixel) in glyph.enumerate_pixels() {
+            assert_eq!(pixel.0, [200, 0, 0, if x < 2 { 255 } else { 0 }]);
+        }
+    }
+
+    #[test]
+    fn emoji_are_decoded_one_at_a_time() {
+        let dir = tempfile::tempdir().unwrap();
+        write_emoji(dir.path(), "a.png");
+        write_emoji(dir.path(), "b.png");
+        let config = PackConfig::default();
+        let sources = scan_emoji_dir(dir.path(), &config).unwrap();
+        let mut emoji = read_emoji(&sources, &config);
+
+        let (name, _) = emoji.next().unwrap();
+        assert_eq!(name, "a");
+
+        // the next image isn't read until it's asked for, so it can't be in memory yet
+        RgbaImage::from_pixel(2, 2, Rgba([0, 0, 255, 255]))
+            .save(dir.path().join("b.png"))
+            .unwrap();
+        let (name, image) = emoji.next().unwrap();
+        assert_eq!(name, "b");
+        assert_eq!(image.dimensions(), (2, 2));
+        assert!(emoji.next().is_none());
+    }
+
+    #[test]
+    fn animated_pngs_are_read_as_their_first_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = std::fs::File::create(dir.path().join("party.png")).unwrap();
+        let mut encoder = png::Encoder::new(file, 4, 4);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(2, 0).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        for color in [[255, 0, 0, 255], [0, 0, 255, 255]] {
+            writer.write_image_data(&color.repeat(16)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let config = PackConfig::default();
+        let sources = scan_emoji_dir(dir.path(), &config).unwrap();
+        let emoji = read_emoji(&sources, &config).collect::<Vec<_>>();
+        assert_eq!(emoji.len(), 1);
+        assert_eq!(emoji[0].0, "party");
+        assert_eq!(emoji[0].1.to_rgba8().get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn a_csv_listing_picks_names_and_orders_the_emoji() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["blobcat.png", "neofox.png", "unlisted.png"] {
+            write_emoji(dir.path(), file);
+        }
+        let csv = dir.path().join("emoji.csv");
+        std::fs::write(
+            &csv,
+            "File,Name,Category,Order,Notes\n\
+             blobcat,,cats,2,\n\
+             neofox.png,Fox,animals,1,a note\n\
+             missing.png,gone,,,\n",
+        )
+        .unwrap();
+
+        let listing = read_listing(&csv).unwrap();
+        let sources = |include_unlisted| {
+            let config = PackConfig {
+                listing: Some(listing.clone()),
+                include_unlisted,
+                ..PackConfig::default()
+            };
+            scan_emoji_dir(dir.path(), &config).unwrap()
+        };
+
+        let listed = sources(false);
+        let described = listed
+            .iter()
+            .map(|source| (source.name.as_str(), source.metadata.category.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            described,
+            [("fox", Some("animals")), ("blobcat", Some("cats"))]
+        );
+
+        let all = sources(true);
+        let names = all
+            .iter()
+            .map(|source| source.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["fox", "blobcat", "unlisted"]);
+    }
+
+    #[test]
+    fn oversized_files_warn_or_fail_when_strict() {
+        let dir = tempfile::tempdir().unwrap();
+        write_emoji(dir.path(), "blobcat.png");
+        RgbaImage::from_fn(64, 64, |x, y| Rgba([(x * y) as u8, x as u8, y as u8, 255]))
+            .save(dir.path().join("neofox.png"))
+            .unwrap();
+        let sources = scan_emoji_dir(dir.path(), &PackConfig::default()).unwrap();
+        let limit = std::fs::metadata(dir.path().join("blobcat.png"))
+            .unwrap()
+            .len();
+
+        assert!(check_file_sizes(&sources, limit, false).is_ok());
+        let result = check_file_sizes(&sources, limit, true);
+        assert!(matches!(
+            result,
+            Err(PackError::OversizedFiles(paths)) if paths.len() == 1 && paths[0].ends_with("neofox.png")
+        ));
+        assert!(check_file_sizes(&sources, u64::MAX, true).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_file_names_are_skipped() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        write_emoji(dir.path(), "blobcat.png");
+        let file_name = std::ffi::OsStr::from_bytes(b"neo\xFFfox.png");
+        std::fs::copy(dir.path().join("blobcat.png"), dir.path().join(file_name)).unwrap();
+
+        let mut sources = Vec::new();
+        let warnings = crate::tests::count_warnings(|| {
+            sources = scan_emoji_dir(dir.path(), &PackConfig::default()).unwrap();
+        });
+        assert_eq!(names(&sources), ["blobcat"]);
+        assert_eq!(warnings.get("invalid_name"), Some(&1));
+
+        let config = PackConfig {
+            reproducible: true,
+            ..PackConfig::default()
+        };
+        assert!(matches!(
+            scan_emoji_dir(dir.path(), &config),
+            Err(PackError::NotReproducible(_))
+        ));
+    }
+
+    #[test]
+    fn alpha_files_give_their_luminance_as_alpha() {
+        let dir = tempfile::tempdir().unwrap();
+        RgbaImage::from_pixel(4, 4, Rgba([0, 200, 0, 255]))
+            .save(dir.path().join("blob.png"))
+            .unwrap();
+        image::GrayImage::from_fn(4, 4, |x, _| image::Luma([if x < 2 { 255 } else { 0 }]))
+            .save(dir.path().join("blob_alpha.png"))
+            .unwrap();
+        // an image without an alpha file keeps its own alpha
+        RgbaImage::from_pixel(4, 4, Rgba([0, 0, 200, 128]))
+            .save(dir.path().join("plain.png"))
+            .unwrap();
+
+        let config = PackConfig {
+            alpha_suffix: Some("_alpha".to_string()),
+            ..PackConfig::default()
+        };
+        let sources = scan_emoji_dir(dir.path(), &config).unwrap();
+        assert_eq!(names(&sources), ["blob", "plain"]);
+
+        let emoji = read_emoji(&sources, &config).collect::<BTreeMap<_, _>>();
+        for (x, _, pixel) in emoji["blob"].to_rgba8().enumerate_pixels() {
+            assert_eq!(pixel.0, [0, 200, 0, if x < 2 { 255 } else { 0 }]);
+        }
+        assert!(emoji["plain"]
+            .to_rgba8()
+            .pixels()
+            .all(|pixel| pixel.0 == [0, 0, 200, 128]));
+    }
+
+    #[test]
+    fn later_emoji_dirs_override_earlier_ones() {
+        let base = tempfile::tempdir().unwrap();
+        let add_on = tempfile::tempdir().unwrap();
+        write_emoji(base.path(), "blobcat.png");
+        write_emoji(base.path(), "neofox.png");
+        write_emoji(add_on.path(), "Neofox.png");
+        write_emoji(add_on.path(), "blobfox.png");
+
+        let mut sources = Vec::new();
+        let warnings = crate::tests::count_warnings(|| {
+            let dirs = [base.path().to_path_buf(), add_on.path().to_path_buf()];
+            sources = scan_emoji_dirs(&dirs, &PackConfig::default()).unwrap();
+        });
+        assert_eq!(names(&sources), ["blobcat", "blobfox", "neofox"]);
+        let neofox = sources.iter().find(|source| source.name == "neofox");
+        assert_eq!(neofox.unwrap().path, add_on.path().join("Neofox.png"));
+        assert_eq!(warnings.get("duplicate"), Some(&1));
+    }
+
+    #[test]
+    fn composed_and_decomposed_names_are_the_same_emoji() {
+        let dir = tempfile::tempdir().unwrap();
+        write_emoji(dir.path(), "caf\u{e9}.png");
+        write_emoji(dir.path(), "Cafe\u{301}.png");
+
+        let mut sources = Vec::new();
+        let warnings = crate::tests::count_warnings(|| {
+            sources = scan_emoji_dir(dir.path(), &PackConfig::default()).unwrap();
+        });
+        assert_eq!(names(&sources), ["caf\u{e9}"]);
+        assert_eq!(warnings.get("duplicate"), Some(&1));
+
+        let expected = ["Cafe\u{301}".to_string()];
+        assert!(check_expected_names(["caf\u{e9}"], &expected, false).is_ok());
+        let imported = HashMap::from([("cafe\u{301}".to_string(), '\u{F0000}')]);
+        assert!(check_stale_imports(["CAF\u{c9}"], &imported, true).is_ok());
+    }
+}
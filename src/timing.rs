@@ -0,0 +1,125 @@
+//! Measuring how long each stage of generating a pack takes.
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use tracing::info;
+
+/// Time spent in each stage of generating a pack.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timings {
+    /// Finding the emoji.
+    pub scan: Duration,
+
+    /// Decoding the emoji images.
+    pub decode: Duration,
+
+    /// Preparing each glyph: resizing it, and any other per-glyph processing.
+    pub resize: Duration,
+
+    /// Allocating codepoints, and placing the glyphs in the atlas.
+    pub allocate: Duration,
+
+    /// Encoding the atlas as a PNG.
+    pub encode: Duration,
+
+    /// Writing the output files.
+    pub write: Duration,
+}
+
+impl Timings {
+    /// Logs the breakdown, as one event with a field (in milliseconds) per stage.
+    pub fn report(&self) {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        info!(
+            scan_ms = ms(self.scan),
+            decode_ms = ms(self.decode),
+            resize_ms = ms(self.resize),
+            allocate_ms = ms(self.allocate),
+            encode_ms = ms(self.encode),
+            write_ms = ms(self.write),
+            total_ms = ms(self.total()),
+            "timing breakdown"
+        );
+    }
+
+    /// The time spent in each stage, and in total, in milliseconds.
+    pub fn as_millis(&self) -> serde_json::Value {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        serde_json::json!({
+            "scan": ms(self.scan),
+            "decode": ms(self.decode),
+            "resize": ms(self.resize),
+            "allocate": ms(self.allocate),
+            "encode": ms(self.encode),
+            "write": ms(self.write),
+            "total": ms(self.total()),
+        })
+    }
+
+    /// Total time spent across all stages.
+    pub fn total(&self) -> Duration {
+        self.scan + self.decode + self.resize + self.allocate + self.encode + self.write
+    }
+}
+
+/// Wraps an iterator, adding up the time spent producing its items.
+pub fn timed<I: Iterator>(iter: I) -> (Timed<I>, Rc<Cell<Duration>>) {
+    let elapsed = Rc::new(Cell::new(Duration::ZERO));
+    (
+        Timed {
+            iter,
+            elapsed: elapsed.clone(),
+        },
+        elapsed,
+    )
+}
+
+/// An iterator which adds up the time spent producing its items, from [`timed`].
+pub struct Timed<I> {
+    iter: I,
+    elapsed: Rc<Cell<Duration>>,
+}
+
+impl<I: Iterator> Iterator for Timed<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = Instant::now();
+        let item = self.iter.next();
+        self.elapsed.set(self.elapsed.get() + start.elapsed());
+        item
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timed_iterators_add_up_the_time_producing_items() {
+        let items = (0..3).inspect(|_| std::thread::sleep(Duration::from_millis(5)));
+        let (timed, elapsed) = timed(items);
+        assert_eq!(elapsed.get(), Duration::ZERO);
+        assert_eq!(timed.count(), 3);
+        assert!(elapsed.get() >= Duration::from_millis(15));
+    }
+
+    #[test]
+    fn totals_add_up_every_stage() {
+        let timings = Timings {
+            scan: Duration::from_millis(1),
+            decode: Duration::from_millis(2),
+            resize: Duration::from_millis(3),
+            allocate: Duration::from_millis(4),
+            encode: Duration::from_millis(5),
+            write: Duration::from_millis(6),
+        };
+        assert_eq!(timings.total(), Duration::from_millis(21));
+        assert_eq!(timings.as_millis()["total"], 21.0);
+        assert_eq!(timings.as_millis()["resize"], 3.0);
+    }
+}
@@ -0,0 +1,132 @@
+//! An interactive terminal interface for curating a pack: reviewing the emoji and their imported codepoints, and
+//! pinning, renaming or excluding them before the pack is generated.
+
+use std::io;
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style, Stylize},
+    text::Line,
+    widgets::{Block, Row, Table, TableState},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    curate::{Curation, CurationState},
+    PackConfig,
+};
+
+/// What the keys are doing.
+enum Mode {
+    /// Moving between emoji and toggling decisions.
+    Browse,
+
+    /// Typing a codepoint to pin to the selected emoji.
+    Pin(String),
+
+    /// Typing a new name for the selected emoji.
+    Rename(String),
+}
+
+/// Runs the curation interface until the decisions are saved, returning them, or it's quit, returning [`None`].
+pub fn run(state: CurationState, config: &PackConfig) -> io::Result<Option<Curation>> {
+    let mut terminal = ratatui::init();
+    let result = run_loop(&mut terminal, state, config);
+    ratatui::restore();
+    result
+}
+
+fn run_loop(
+    terminal: &mut DefaultTerminal,
+    mut state: CurationState,
+    config: &PackConfig,
+) -> io::Result<Option<Curation>> {
+    let mut mode = Mode::Browse;
+    let mut status = String::new();
+    loop {
+        terminal.draw(|frame| draw(frame, &state, &mode, &status))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        status.clear();
+        match &mut mode {
+            Mode::Browse => match key.code {
+                KeyCode::Down | KeyCode::Char('j') => state.select_next(),
+                KeyCode::Up | KeyCode::Char('k') => state.select_previous(),
+                KeyCode::Char('x') => state.toggle_excluded(),
+                KeyCode::Char('p') => mode = Mode::Pin(String::new()),
+                KeyCode::Char('u') => state.unpin(),
+                KeyCode::Char('r') => mode = Mode::Rename(String::new()),
+                KeyCode::Char('s') => return Ok(Some(state.curation)),
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+                _ => {}
+            },
+            Mode::Pin(input) | Mode::Rename(input) => match key.code {
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Esc => mode = Mode::Browse,
+                KeyCode::Enter => {
+                    let result = match &mode {
+                        Mode::Pin(codepoint) => state.pin(codepoint),
+                        Mode::Rename(name) => state.rename(name, config),
+                        Mode::Browse => Ok(()),
+                    };
+                    if let Err(err) = result {
+                        status = err;
+                    }
+                    mode = Mode::Browse;
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, state: &CurationState, mode: &Mode, status: &str) {
+    let [table_area, prompt_area] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+
+    let rows = state.emoji.iter().map(|emoji| {
+        let name = state.name(emoji);
+        let name = if name == emoji.name {
+            name.to_string()
+        } else {
+            format!("{} -> {name}", emoji.name)
+        };
+        let codepoint = match (state.curation.pins.get(&emoji.name), emoji.imported) {
+            (Some(pinned), _) => format!("U+{:04X} (pinned)", *pinned as u32),
+            (None, Some(imported)) => format!("U+{:04X} (imported)", imported as u32),
+            (None, None) => "new".to_string(),
+        };
+        let row = Row::new([name, codepoint]);
+        if state.is_excluded(emoji) {
+            row.style(Style::new().dim().crossed_out())
+        } else {
+            row
+        }
+    });
+    let table = Table::new(rows, [Constraint::Fill(1), Constraint::Length(20)])
+        .header(Row::new(["Emoji", "Codepoint"]).bold())
+        .block(Block::bordered().title(format!(" Curating {} emoji ", state.emoji.len())))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    let mut table_state = TableState::new().with_selected(state.selected_index());
+    frame.render_stateful_widget(table, table_area, &mut table_state);
+
+    let prompt = match mode {
+        Mode::Browse if !status.is_empty() => Line::from(status).red(),
+        Mode::Browse => Line::from(
+            "up/down: select  x: exclude  p: pin  u: unpin  r: rename  s: save and generate  q: quit",
+        ),
+        Mode::Pin(input) => Line::from(format!("Pin codepoint (like U+F0010): {input}")),
+        Mode::Rename(input) => Line::from(format!("Rename to (empty to undo): {input}")),
+    };
+    frame.render_widget(prompt, prompt_area);
+}
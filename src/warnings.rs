@@ -0,0 +1,133 @@
+//! Collecting the warnings logged during a run, to summarize them at the end.
+//!
+//! Warnings are grouped by their `kind` field (like `warn!(kind = "duplicate", ...)`), with untagged ones counted as
+//! `other`.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use tracing::{field::Field, Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// A tracing layer counting the warnings logged, by kind.
+#[derive(Debug, Clone, Default)]
+pub struct WarningCounter {
+    counts: Arc<Mutex<BTreeMap<String, usize>>>,
+}
+
+impl WarningCounter {
+    /// Number of warnings logged so far of each kind.
+    pub fn counts(&self) -> BTreeMap<String, usize> {
+        self.counts.lock().unwrap().clone()
+    }
+
+    /// Describes the warnings for people to read, like `2 decode failures, 1 duplicate`, or [`None`] if there were
+    /// none.
+    pub fn summary(&self) -> Option<String> {
+        let counts = self.counts();
+        if counts.is_empty() {
+            return None;
+        }
+        let parts = counts
+            .iter()
+            .map(|(kind, count)| format!("{count} {}", describe_kind(kind, *count)))
+            .collect::<Vec<_>>();
+        Some(parts.join(", "))
+    }
+
+    /// The warnings as a JSON object of kind -> count, with the total.
+    pub fn as_json(&self) -> serde_json::Value {
+        let counts = self.counts();
+        serde_json::json!({
+            "total": counts.values().sum::<usize>(),
+            "warnings": counts,
+        })
+    }
+}
+
+impl<S: Subscriber> Layer<S> for WarningCounter {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::WARN {
+            return;
+        }
+        let mut kind = KindVisitor(None);
+        event.record(&mut kind);
+        let kind = kind.0.unwrap_or_else(|| "other".to_string());
+        *self.counts.lock().unwrap().entry(kind).or_default() += 1;
+    }
+}
+
+/// Finds the `kind` field of an event.
+struct KindVisitor(Option<String>);
+
+impl tracing::field::Visit for KindVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "kind" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "kind" {
+            self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+/// Name of a kind of warning, for a number of them.
+fn describe_kind(kind: &str, count: usize) -> String {
+    let (one, many) = match kind {
+        "decode_failure" => ("decode failure", "decode failures"),
+        "duplicate" => ("duplicate", "duplicates"),
+        "skipped" => ("skipped", "skipped"),
+        "stale_import" => ("stale import", "stale imports"),
+        "reserved_name" => ("reserved name", "reserved names"),
+        "invalid_name" => ("invalid name", "invalid names"),
+        "unknown_name" => ("unknown name", "unknown names"),
+        "faint" => ("nearly blank glyph", "nearly blank glyphs"),
+        "blank" => ("blank glyph", "blank glyphs"),
+        "invalid_ascent" => ("invalid ascent", "invalid ascents"),
+        "atlas_size" => ("oversized atlas", "oversized atlases"),
+        "unattributed" => ("unattributed emoji", "unattributed emoji"),
+        "oversized_file" => ("oversized file", "oversized files"),
+        "other" => ("other", "other"),
+        kind => return kind.replace('_', " "),
+    };
+    if count == 1 { one } else { many }.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::{info, warn};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[test]
+    fn warnings_are_counted_by_kind() {
+        let counter = WarningCounter::default();
+        let subscriber = tracing_subscriber::registry().with(counter.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            warn!(kind = "decode_failure", "a");
+            warn!(kind = "decode_failure", "b");
+            warn!(kind = "duplicate", "c");
+            warn!("d");
+            info!(kind = "duplicate", "not a warning");
+        });
+
+        assert_eq!(
+            counter.summary().unwrap(),
+            "2 decode failures, 1 duplicate, 1 other"
+        );
+        assert_eq!(
+            counter.as_json(),
+            serde_json::json!({
+                "total": 4,
+                "warnings": {"decode_failure": 2, "duplicate": 1, "other": 1},
+            })
+        );
+        assert_eq!(WarningCounter::default().summary(), None);
+    }
+}
@@ -0,0 +1,1230 @@
+//! Tests of the command line interface, running the `fedimoji` binary on emoji written to temporary directories.
+
+use std::{
+    path::Path,
+    process::{Command, Output},
+};
+
+use image::{Rgba, RgbaImage};
+use serde_json::json;
+use tempfile::TempDir;
+
+/// An opaque gradient image, different for each seed, so it's neither blank nor a duplicate of another.
+fn emoji(width: u32, height: u32, seed: u8) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([seed, (x * 255 / width) as u8, (y * 255 / height) as u8, 255])
+    })
+}
+
+/// Writes a square emoji image to a directory, as `<name>.png`.
+fn write_emoji(dir: &Path, name: &str, seed: u8) {
+    emoji(32, 32, seed)
+        .save(dir.join(format!("{name}.png")))
+        .unwrap();
+}
+
+/// Writes emoji images named after the given names to a new directory.
+fn emoji_dir(names: &[&str]) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (i, name) in names.iter().enumerate() {
+        write_emoji(dir.path(), name, i as u8 * 8);
+    }
+    dir
+}
+
+/// Runs fedimoji with some arguments, without remembering codepoints between runs.
+fn fedimoji(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fedimoji"))
+        .arg("--no-persist")
+        .args(args)
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap()
+}
+
+/// Runs fedimoji on an emoji directory, writing to an output directory, and checks it succeeds.
+fn generate(emoji_dir: &Path, output_dir: &Path, args: &[&str]) -> Output {
+    let output = fedimoji(
+        &[
+            &[
+                "--emoji-dir",
+                path(emoji_dir),
+                "--output-dir",
+                path(output_dir),
+            ],
+            args,
+        ]
+        .concat(),
+    );
+    assert!(
+        output.status.success(),
+        "fedimoji failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+fn read_json(path: &Path) -> serde_json::Value {
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+/// Names of the files in a directory, sorted.
+fn file_names(dir: &Path) -> Vec<String> {
+    let mut names = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+#[test]
+fn copy_originals_keeps_sliced_and_fixed_emoji() {
+    let dir = emoji_dir(&["blobcat", "neo-fox"]);
+    emoji(96, 32, 64)
+        .save(dir.path().join("banner.png"))
+        .unwrap();
+    let output = tempfile::tempdir().unwrap();
+    generate(
+        dir.path(),
+        output.path(),
+        &["--copy-originals", "--slice-wide", "2", "--fix-names"],
+    );
+
+    assert_eq!(
+        file_names(&output.path().join("originals")),
+        ["banner.png", "blobcat.png", "neo-fox.png"]
+    );
+}
+
+#[test]
+fn pixel_perfect_disables_texture_blur() {
+    let dir = emoji_dir(&["blobcat"]);
+    let output = tempfile::tempdir().unwrap();
+    generate(dir.path(), output.path(), &[]);
+    assert!(!output.path().join("emoji.png.mcmeta").exists());
+
+    generate(dir.path(), output.path(), &["--pixel-perfect"]);
+    let mcmeta = read_json(&output.path().join("emoji.png.mcmeta"));
+    assert_eq!(mcmeta["texture"]["blur"], false);
+}
+
+#[test]
+fn list_prints_what_would_be_packed() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let import = dir.path().join("import.json");
+    std::fs::write(&import, r#"{"neofox": "U+F0005"}"#).unwrap();
+    let output = fedimoji(&[
+        "--emoji-dir",
+        path(dir.path()),
+        "--import",
+        path(&import),
+        "list",
+    ]);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines().collect::<Vec<_>>();
+    lines.sort();
+    let blobcat = dir.path().join("blobcat.png");
+    let neofox = dir.path().join("neofox.png");
+    assert_eq!(
+        lines,
+        [
+            format!("blobcat\t{}\tnew", path(&blobcat)),
+            format!("neofox\t{}\tU+F0005", path(&neofox)),
+        ]
+    );
+}
+
+#[test]
+fn unchanged_outputs_are_not_rewritten() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let output = tempfile::tempdir().unwrap();
+    let atlas = output.path().join("emoji.png");
+    let modified = || std::fs::metadata(&atlas).unwrap().modified().unwrap();
+
+    generate(dir.path(), output.path(), &[]);
+    let first = modified();
+    let first_atlas = std::fs::read(&atlas).unwrap();
+    generate(dir.path(), output.path(), &[]);
+    assert_eq!(modified(), first);
+
+    write_emoji(dir.path(), "neofox", 200);
+    generate(dir.path(), output.path(), &[]);
+    assert_ne!(std::fs::read(&atlas).unwrap(), first_atlas);
+}
+
+#[test]
+fn color_profiles_are_warned_about_unless_stripped() {
+    use image::{codecs::png::PngEncoder, ImageEncoder};
+
+    let dir = emoji_dir(&["blobcat"]);
+    let image = emoji(32, 32, 0);
+    let mut encoder =
+        PngEncoder::new(std::fs::File::create(dir.path().join("neofox.png")).unwrap());
+    encoder.set_icc_profile(vec![0; 128]).unwrap();
+    encoder
+        .write_image(&image, 32, 32, image::ExtendedColorType::Rgba8)
+        .unwrap();
+    let output = tempfile::tempdir().unwrap();
+
+    let warned = generate(dir.path(), output.path(), &[]);
+    let stderr = String::from_utf8_lossy(&warned.stderr);
+    assert!(stderr.contains("neofox.png\" has an embedded color profile"));
+    assert!(!stderr.contains("blobcat.png\" has an embedded color profile"));
+
+    let stripped = generate(dir.path(), output.path(), &["--strip-color-profile"]);
+    assert!(!String::from_utf8_lossy(&stripped.stderr).contains("color profile"));
+}
+
+#[test]
+fn provider_fragment_matches_the_font_providers() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let output = tempfile::tempdir().unwrap();
+    let fragment_path = output.path().join("fragment.json");
+    generate(
+        dir.path(),
+        output.path(),
+        &["--emit-provider-fragment", path(&fragment_path)],
+    );
+
+    let fragment = read_json(&fragment_path);
+    let font = read_json(&output.path().join("emoji.json"));
+    assert_eq!(fragment, font["providers"]);
+    assert_eq!(fragment[0]["type"], "bitmap");
+    assert_eq!(fragment[0]["chars"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn output_dir_dash_streams_a_tar_to_stdout() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let output = fedimoji(&["--emoji-dir", path(dir.path()), "--output-dir", "-"]);
+    assert!(output.status.success());
+
+    let mut archive = tar::Archive::new(&output.stdout[..]);
+    let mut entries = archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            entry
+                .unwrap()
+                .path()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert!(entries.contains(&"emoji.png".to_string()));
+    assert!(entries.contains(&"emoji.json".to_string()));
+    assert!(entries.contains(&"fedimoji.json".to_string()));
+}
+
+#[test]
+fn codepoints_continue_after_the_given_one() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let output = tempfile::tempdir().unwrap();
+    let base = output.path().join("base-last-codepoint.txt");
+    std::fs::write(&base, "U+F0041\n").unwrap();
+
+    generate(
+        dir.path(),
+        output.path(),
+        &["--codepoint-start-after", path(&base)],
+    );
+    let mapping = read_json(&output.path().join("fedimoji.json"));
+    assert_eq!(mapping["blobcat"], "\u{F0042}");
+    assert_eq!(mapping["neofox"], "\u{F0043}");
+
+    generate(
+        dir.path(),
+        output.path(),
+        &["--codepoint-start-after", "U+F00FF"],
+    );
+    let mapping = read_json(&output.path().join("fedimoji.json"));
+    assert_eq!(mapping["blobcat"], "\u{F0100}");
+}
+
+#[cfg(unix)]
+#[test]
+fn post_build_hook_runs_in_the_output_dir_and_can_fail_the_run() {
+    let dir = emoji_dir(&["blobcat"]);
+    let output = tempfile::tempdir().unwrap();
+    generate(
+        dir.path(),
+        output.path(),
+        &[
+            "--post-build",
+            "ls \"$1\" > \"$FEDIMOJI_OUTPUT_DIR/hook.txt\"",
+        ],
+    );
+    let listing = std::fs::read_to_string(output.path().join("hook.txt")).unwrap();
+    assert!(listing.lines().any(|line| line == "emoji.png"));
+
+    let failed = fedimoji(&[
+        "--emoji-dir",
+        path(dir.path()),
+        "--output-dir",
+        path(output.path()),
+        "--post-build",
+        "exit 3",
+    ]);
+    assert_eq!(failed.status.code(), Some(8));
+}
+
+#[test]
+fn explain_logs_why_each_emoji_got_its_codepoint() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let output = tempfile::tempdir().unwrap();
+    let import = output.path().join("import.json");
+    std::fs::write(&import, r#"{"neofox": "U+F0000"}"#).unwrap();
+
+    let quiet = generate(dir.path(), output.path(), &["--import", path(&import)]);
+    assert!(!String::from_utf8_lossy(&quiet.stderr).contains("using existing mapping"));
+
+    let explained = generate(
+        dir.path(),
+        output.path(),
+        &["--import", path(&import), "--explain"],
+    );
+    let stderr = String::from_utf8_lossy(&explained.stderr);
+    assert!(stderr
+        .contains("using existing mapping for \"neofox\", U+F0000, from the imported mapping"));
+    assert!(stderr.contains("using new mapping for \"blobcat\", U+F0001"));
+}
+
+#[test]
+fn unwritable_output_dir_is_an_error_not_a_panic() {
+    let dir = emoji_dir(&["blobcat"]);
+    let not_a_dir = dir.path().join("blobcat.png");
+    let output = fedimoji(&[
+        "--emoji-dir",
+        path(dir.path()),
+        "--output-dir",
+        path(&not_a_dir.join("out")),
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ERROR"), "{stderr}");
+    assert!(!stderr.contains("panicked"), "{stderr}");
+}
+
+#[test]
+fn merge_conflicts_are_written_with_the_pack() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let output = tempfile::tempdir().unwrap();
+    let base = output.path().join("base.json");
+    let addon = output.path().join("addon.json");
+    std::fs::write(&base, r#"{"blobcat": "U+F0000"}"#).unwrap();
+    std::fs::write(&addon, r#"{"blobcat": "U+F0005", "neofox": "U+F0000"}"#).unwrap();
+    let conflicts_path = output.path().join("conflicts.json");
+    let args = [
+        "--import",
+        path(&base),
+        "--import",
+        path(&addon),
+        "--emit-conflicts",
+        path(&conflicts_path),
+    ];
+
+    // nothing is written when only validating
+    generate(
+        dir.path(),
+        output.path(),
+        &[&args[..], &["--validate-only"]].concat(),
+    );
+    assert!(!conflicts_path.exists());
+
+    generate(dir.path(), output.path(), &args);
+    let conflicts = read_json(&conflicts_path);
+    assert_eq!(
+        conflicts["names"],
+        json!([{ "name": "blobcat", "codepoints": ["U+F0000", "U+F0005"], "winner": "U+F0000" }])
+    );
+    assert_eq!(
+        conflicts["codepoints"],
+        json!([{ "codepoint": "U+F0000", "names": ["blobcat", "neofox"] }])
+    );
+}
+
+#[test]
+fn count_prints_only_the_number_of_emoji() {
+    let dir = emoji_dir(&["blobcat", "neofox", "Blobcat"]);
+    std::fs::write(dir.path().join("notes.txt"), "not an emoji").unwrap();
+    let output = fedimoji(&["--emoji-dir", path(dir.path()), "count"]);
+    assert!(output.status.success());
+    // the duplicate name and the text file aren't counted
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+}
+
+#[test]
+fn web_bundle_refers_to_its_files_relatively() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let out = tempfile::tempdir().unwrap();
+    let web = out.path().join("web");
+    generate(dir.path(), out.path(), &["--emit-web", path(&web)]);
+
+    assert_eq!(
+        file_names(&web),
+        ["emoji.png", "fedimoji.js", "index.html", "manifest.json"]
+    );
+    let html = std::fs::read_to_string(web.join("index.html")).unwrap();
+    assert!(html.contains(r#"url("emoji.png")"#));
+    assert!(!html.contains(path(out.path())));
+    assert_eq!(read_json(&web.join("manifest.json"))["atlas"], "emoji.png");
+}
+
+#[test]
+fn metadata_keywords_are_exported_with_the_shortcodes() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    std::fs::write(
+        dir.path().join("blobcat.json"),
+        r#"{"tags": ["cat", "blob"]}"#,
+    )
+    .unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let shortcodes = out.path().join("shortcodes.json");
+    generate(
+        dir.path(),
+        out.path(),
+        &["--export-shortcodes", path(&shortcodes)],
+    );
+
+    let shortcodes = read_json(&shortcodes);
+    let keywords = |shortcode: &str| {
+        shortcodes
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|entry| entry["shortcode"] == shortcode)
+            .unwrap()["keywords"]
+            .clone()
+    };
+    assert_eq!(keywords("blobcat"), json!(["cat", "blob"]));
+    assert_eq!(keywords("neofox"), json!([]));
+}
+
+#[test]
+fn run_summary_describes_the_outcome() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let import = dir.path().join("import.json");
+    std::fs::write(&import, r#"{"neofox": "U+F0005"}"#).unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let summary = out.path().join("summary.json");
+    generate(
+        dir.path(),
+        out.path(),
+        &["--import", path(&import), "--emit-summary", path(&summary)],
+    );
+
+    let summary = read_json(&summary);
+    let counts = &summary["counts"];
+    assert_eq!(
+        (
+            &counts["total"],
+            &counts["new"],
+            &counts["imported"],
+            &counts["skipped"]
+        ),
+        (&json!(2), &json!(1), &json!(1), &json!(0))
+    );
+    assert_eq!(summary["glyphs"], 2);
+    let atlas = image::open(out.path().join("emoji.png")).unwrap();
+    assert_eq!(summary["atlas"]["width"], atlas.width());
+    assert_eq!(summary["atlas"]["height"], atlas.height());
+    assert_eq!(
+        summary["codepoints"],
+        json!({"first": "U+F0000", "last": "U+F0005"})
+    );
+    let files = summary["files"].as_array().unwrap();
+    assert!(files
+        .iter()
+        .any(|file| file.as_str().unwrap().ends_with("emoji.png")));
+    assert!(summary["timings_ms"].is_object());
+}
+
+#[test]
+fn imported_emoji_without_images_are_reported() {
+    let dir = emoji_dir(&["blobcat"]);
+    let import = dir.path().join("import.json");
+    std::fs::write(&import, r#"{"blobcat": "U+F0000", "neofox": "U+F0001"}"#).unwrap();
+    let out = tempfile::tempdir().unwrap();
+
+    let output = generate(dir.path(), out.path(), &["--import", path(&import)]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(r#"imported emoji "neofox" (U+F0001) has no image"#));
+    assert_eq!(
+        read_json(&out.path().join("fedimoji.json"))["neofox"],
+        serde_json::Value::Null
+    );
+
+    let output = fedimoji(&[
+        "--emoji-dir",
+        path(dir.path()),
+        "--output-dir",
+        path(out.path()),
+        "--import",
+        path(&import),
+        "--strict-import",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn renamed_emoji_keep_their_old_names_as_aliases() {
+    let dir = emoji_dir(&["thumbsup", "neofox"]);
+    let import = dir.path().join("import.json");
+    std::fs::write(&import, r#"{"thumbs_up": "U+F0003"}"#).unwrap();
+    let renames = dir.path().join("renames.json");
+    std::fs::write(&renames, r#"{"thumbs_up": "thumbsup"}"#).unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let args = ["--import", path(&import), "--rename", path(&renames)];
+
+    generate(dir.path(), out.path(), &args);
+    let mapping = read_json(&out.path().join("fedimoji.json"));
+    assert_eq!(mapping["thumbsup"], "\u{F0003}");
+    assert_eq!(mapping["thumbs_up"], serde_json::Value::Null);
+
+    generate(
+        dir.path(),
+        out.path(),
+        &[&args[..], &["--keep-aliases"]].concat(),
+    );
+    let mapping = read_json(&out.path().join("fedimoji.json"));
+    assert_eq!(mapping["thumbsup"], "\u{F0003}");
+    assert_eq!(mapping["thumbs_up"], mapping["thumbsup"]);
+}
+
+#[test]
+fn generic_output_has_rectangles_and_no_provider() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let out = tempfile::tempdir().unwrap();
+    generate(dir.path(), out.path(), &["--output-format", "generic"]);
+
+    let files = file_names(out.path());
+    assert!(files.contains(&"glyphs.json".to_string()));
+    assert!(files.contains(&"emoji.png".to_string()));
+    assert!(!files.contains(&"emoji.json".to_string()));
+
+    let glyphs = read_json(&out.path().join("glyphs.json"));
+    assert_eq!(glyphs["atlas"], "emoji.png");
+    let rect = |glyph: &serde_json::Value| {
+        (
+            glyph["name"].as_str().unwrap().to_string(),
+            glyph["x"].as_u64().unwrap(),
+            glyph["y"].as_u64().unwrap(),
+        )
+    };
+    let mut rects = glyphs["glyphs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(rect)
+        .collect::<Vec<_>>();
+    rects.sort();
+    let height = glyphs["glyphs"][0]["height"].as_u64().unwrap();
+    assert_eq!(
+        rects,
+        [
+            ("blobcat".to_string(), 0, 0),
+            ("neofox".to_string(), 0, height)
+        ]
+    );
+}
+
+#[test]
+fn output_dir_inside_the_emoji_dir_is_refused() {
+    let dir = emoji_dir(&["blobcat"]);
+    for output_dir in [dir.path().to_path_buf(), dir.path().join("out/pack")] {
+        let output = fedimoji(&[
+            "--emoji-dir",
+            path(dir.path()),
+            "--output-dir",
+            path(&output_dir),
+        ]);
+        assert_eq!(output.status.code(), Some(7));
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("is inside the emoji directory"));
+    }
+    assert_eq!(file_names(dir.path()), ["blobcat.png"]);
+}
+
+#[test]
+fn metadata_ascents_get_providers_of_their_own() {
+    let dir = emoji_dir(&["blobcat", "neofox", "underscore"]);
+    std::fs::write(dir.path().join("underscore.json"), r#"{"ascent": 2}"#).unwrap();
+    let out = tempfile::tempdir().unwrap();
+    generate(dir.path(), out.path(), &["--ascent", "7"]);
+
+    let mapping = read_json(&out.path().join("fedimoji.json"));
+    let font = read_json(&out.path().join("emoji.json"));
+    let providers = font["providers"].as_array().unwrap();
+    assert_eq!(providers.len(), 2);
+    let glyphs = |provider: &serde_json::Value| {
+        provider["chars"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|row| row.as_str().unwrap().chars())
+            .filter(|&c| c != '\0')
+            .collect::<String>()
+    };
+    assert_eq!(providers[0]["ascent"], 7);
+    assert_eq!(
+        glyphs(&providers[0]),
+        format!(
+            "{}{}",
+            mapping["blobcat"].as_str().unwrap(),
+            mapping["neofox"].as_str().unwrap()
+        )
+    );
+    assert_eq!(providers[1]["ascent"], 2);
+    assert_eq!(
+        glyphs(&providers[1]),
+        mapping["underscore"].as_str().unwrap()
+    );
+}
+
+#[test]
+fn validate_only_writes_nothing_and_fails_on_errors() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let out = tempfile::tempdir().unwrap();
+    generate(dir.path(), out.path(), &["--validate-only"]);
+    assert!(file_names(out.path()).is_empty());
+
+    write_emoji(dir.path(), "bold", 64);
+    let output = fedimoji(&[
+        "--emoji-dir",
+        path(dir.path()),
+        "--output-dir",
+        path(out.path()),
+        "--validate-only",
+        "--reserved-names",
+        "bold",
+        "--strict-names",
+    ]);
+    assert!(!output.status.success());
+    assert!(file_names(out.path()).is_empty());
+}
+
+#[test]
+fn the_version_is_recorded_in_the_outputs() {
+    let dir = emoji_dir(&["blobcat"]);
+    let out = tempfile::tempdir().unwrap();
+    generate(dir.path(), out.path(), &[]);
+
+    let version = env!("CARGO_PKG_VERSION");
+    let build_info = read_json(&out.path().join("build-info.json"));
+    assert_eq!(build_info["generator"], "fedimoji");
+    assert_eq!(build_info["version"], version);
+
+    let meta = read_json(&out.path().join("fedimoji.json"))["_meta"].clone();
+    assert!(meta
+        .as_str()
+        .unwrap()
+        .starts_with(&format!("fedimoji {version}")));
+}
+
+#[test]
+fn preview_single_writes_one_glyph() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let out = tempfile::tempdir().unwrap();
+    let preview = out.path().join("preview.png");
+    let args = |name| {
+        fedimoji(&[
+            "--emoji-dir",
+            path(dir.path()),
+            "--output-dir",
+            path(out.path()),
+            "--glyph-size",
+            "24",
+            "--preview-single",
+            name,
+            "--preview-out",
+            path(&preview),
+        ])
+    };
+
+    assert!(args("NeoFox").status.success());
+    let glyph = image::open(&preview).unwrap();
+    assert_eq!((glyph.width(), glyph.height()), (24, 24));
+    assert_eq!(file_names(out.path()), ["preview.png"]);
+
+    assert_eq!(args("missing").status.code(), Some(4));
+}
+
+#[test]
+fn profiles_share_codepoints_but_not_glyph_sizes() {
+    let dir = emoji_dir(&["CatJam", "neofox"]);
+    let out = tempfile::tempdir().unwrap();
+    let profiles = dir.path().join("profiles.json");
+    std::fs::write(
+        &profiles,
+        r#"{"chat": ["--glyph-size", "16"], "hud": ["--glyph-size", "32", "--tintable"]}"#,
+    )
+    .unwrap();
+    generate(
+        dir.path(),
+        out.path(),
+        &["--preserve-case", "--profiles", path(&profiles)],
+    );
+
+    let chat = out.path().join("chat");
+    let hud = out.path().join("hud");
+    let chat_map = read_json(&chat.join("fedimoji.json"));
+    assert_eq!(chat_map, read_json(&hud.join("fedimoji.json")));
+    assert!(chat_map["CatJam"].is_string());
+
+    let height = |dir: &Path| {
+        read_json(&dir.join("emoji.json"))["providers"][0]["chars"]
+            .as_array()
+            .unwrap()
+            .len() as u32
+    };
+    let atlas = |dir: &Path| image::open(dir.join("emoji.png")).unwrap();
+    assert_eq!(atlas(&chat).height(), 16 * height(&chat));
+    assert_eq!(atlas(&hud).height(), 32 * height(&hud));
+
+    std::fs::write(&profiles, r#"{"bad": ["--no-such-flag"]}"#).unwrap();
+    let output = fedimoji(&[
+        "--emoji-dir",
+        path(dir.path()),
+        "--output-dir",
+        path(out.path()),
+        "--profiles",
+        path(&profiles),
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains(r#"profile "bad": unexpected argument '--no-such-flag'"#));
+}
+
+#[test]
+fn attribution_is_written_and_can_be_required() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    std::fs::write(
+        dir.path().join("blobcat.json"),
+        r#"{"author": "Volpeon", "license": "Apache-2.0", "source_url": "https://volpeon.ink/emojis/blobcat/"}"#,
+    )
+    .unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let attribution = out.path().join("attribution.json");
+    let output = generate(
+        dir.path(),
+        out.path(),
+        &["--emit-attribution", path(&attribution)],
+    );
+    assert!(String::from_utf8(output.stderr).unwrap().contains("neofox"));
+    assert_eq!(
+        read_json(&attribution),
+        json!({
+            "blobcat": {
+                "author": "Volpeon",
+                "license": "Apache-2.0",
+                "source_url": "https://volpeon.ink/emojis/blobcat/",
+            },
+            "neofox": {"author": null, "license": null, "source_url": null},
+        })
+    );
+
+    let output = fedimoji(&[
+        "--emoji-dir",
+        path(dir.path()),
+        "--output-dir",
+        path(out.path()),
+        "--require-attribution",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn target_bytes_picks_a_smaller_glyph_size_within_budget() {
+    // noise, which doesn't compress, so the atlas's size follows the glyph size
+    let dir = tempfile::tempdir().unwrap();
+    let mut state = 1u32;
+    for name in ["blobcat", "neofox"] {
+        RgbaImage::from_fn(64, 64, |_, _| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let [r, g, b, _] = state.to_le_bytes();
+            Rgba([r, g, b, 255])
+        })
+        .save(dir.path().join(format!("{name}.png")))
+        .unwrap();
+    }
+    let out = tempfile::tempdir().unwrap();
+    let atlas = out.path().join("emoji.png");
+
+    generate(dir.path(), out.path(), &["--glyph-size", "64"]);
+    let full_size = std::fs::metadata(&atlas).unwrap().len();
+
+    let budget = full_size / 2;
+    generate(
+        dir.path(),
+        out.path(),
+        &["--glyph-size", "64", "--target-bytes", &budget.to_string()],
+    );
+    assert!(std::fs::metadata(&atlas).unwrap().len() <= budget);
+    let glyph_width = image::open(&atlas).unwrap().width();
+    assert!(glyph_width < 64 && glyph_width > 16, "{glyph_width}");
+
+    let output = fedimoji(&[
+        "--emoji-dir",
+        path(dir.path()),
+        "--output-dir",
+        path(out.path()),
+        "--target-bytes",
+        "1",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn disabled_emoji_are_left_out_without_a_codepoint() {
+    let dir = emoji_dir(&["blobcat", "catjam", "neofox"]);
+    std::fs::write(dir.path().join("catjam.json"), r#"{"enabled": false}"#).unwrap();
+    let out = tempfile::tempdir().unwrap();
+
+    generate(dir.path(), out.path(), &[]);
+    let mapping = read_json(&out.path().join("fedimoji.json"));
+    assert_eq!(mapping["blobcat"], "\u{F0000}");
+    assert_eq!(mapping["neofox"], "\u{F0001}");
+    assert!(mapping.get("catjam").is_none());
+}
+
+#[test]
+fn stdin_list_packs_the_same_as_the_directory() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let from_dir = tempfile::tempdir().unwrap();
+    generate(dir.path(), from_dir.path(), &[]);
+
+    let from_list = tempfile::tempdir().unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fedimoji"))
+        .args([
+            "--no-persist",
+            "--stdin-list",
+            "--emoji-dir",
+            "/nonexistent",
+        ])
+        .args(["--output-dir", path(from_list.path())])
+        .stdin(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let list = format!(
+        "{}\n{}\n",
+        path(&dir.path().join("blobcat.png")),
+        path(&dir.path().join("neofox.png"))
+    );
+    std::io::Write::write_all(&mut child.stdin.take().unwrap(), list.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "fedimoji failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    for file_name in ["emoji.png", "fedimoji.json"] {
+        assert_eq!(
+            std::fs::read(from_list.path().join(file_name)).unwrap(),
+            std::fs::read(from_dir.path().join(file_name)).unwrap(),
+            "{file_name}"
+        );
+    }
+}
+
+#[test]
+fn the_map_writes_codepoints_in_each_format() {
+    let dir = emoji_dir(&["blobcat"]);
+    let out = tempfile::tempdir().unwrap();
+    for (format, codepoint) in [
+        ("char", json!("\u{F0000}")),
+        ("decimal", json!(983040)),
+        ("hex", json!("U+F0000")),
+    ] {
+        generate(dir.path(), out.path(), &["--map-codepoint-format", format]);
+        let mapping = read_json(&out.path().join("fedimoji.json"));
+        assert_eq!(mapping["blobcat"], codepoint, "{format}");
+
+        // and it reads back in, keeping the codepoint
+        let imported = tempfile::tempdir().unwrap();
+        generate(
+            dir.path(),
+            imported.path(),
+            &[
+                "--import",
+                path(&out.path().join("fedimoji.json")),
+                "--codepoint-start-after",
+                "U+F00FF",
+            ],
+        );
+        let mapping = read_json(&imported.path().join("fedimoji.json"));
+        assert_eq!(mapping["blobcat"], "\u{F0000}", "{format}");
+    }
+}
+
+#[test]
+fn reproducible_builds_are_byte_identical() {
+    let dir = emoji_dir(&["neofox", "blobcat", "catjam"]);
+    let (first, second) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+    for out in [&first, &second] {
+        generate(dir.path(), out.path(), &["--reproducible"]);
+    }
+
+    let files = file_names(first.path());
+    assert!(files.contains(&"build-info.json".to_string()));
+    assert_eq!(files, file_names(second.path()));
+    for file_name in &files {
+        assert_eq!(
+            std::fs::read(first.path().join(file_name)).unwrap(),
+            std::fs::read(second.path().join(file_name)).unwrap(),
+            "{file_name}"
+        );
+    }
+}
+
+#[test]
+fn a_generated_test_pack_packs_cleanly() {
+    let dir = tempfile::tempdir().unwrap();
+    let emoji = dir.path().join("emoji");
+    let output = fedimoji(&[
+        "gen-test-pack",
+        "--count",
+        "5",
+        "--out",
+        path(&emoji),
+        "--glyph-size",
+        "16",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        file_names(&emoji),
+        [
+            "test_0.png",
+            "test_1.png",
+            "test_2.png",
+            "test_3.png",
+            "test_4.png"
+        ]
+    );
+
+    let out = dir.path().join("out");
+    let output = generate(&emoji, &out, &["--glyph-size", "16", "--warnings-as-json"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let warnings = stderr.lines().last().unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(warnings).unwrap()["total"],
+        0,
+        "{warnings}"
+    );
+    assert_eq!(
+        read_json(&out.join("fedimoji.json"))
+            .as_object()
+            .unwrap()
+            .len(),
+        6
+    );
+}
+
+#[test]
+fn frame_times_never_animate_the_atlas() {
+    // font textures can't animate, so frame times in the metadata are ignored rather than applied to the whole atlas
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    std::fs::write(
+        dir.path().join("neofox.json"),
+        r#"{"frametime": 2, "frames": [0, 1]}"#,
+    )
+    .unwrap();
+    let out = tempfile::tempdir().unwrap();
+
+    generate(dir.path(), out.path(), &["--pixel-perfect"]);
+    assert_eq!(
+        read_json(&out.path().join("emoji.png.mcmeta")),
+        json!({"texture": {"blur": false, "clamp": false}})
+    );
+    assert!(read_json(&out.path().join("fedimoji.json"))["neofox"].is_string());
+}
+
+#[test]
+fn build_info_is_dated_by_source_date_epoch() {
+    let dir = emoji_dir(&["blobcat"]);
+    let out = tempfile::tempdir().unwrap();
+    let run = |epoch: Option<&str>| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_fedimoji"));
+        command
+            .args([
+                "--no-persist",
+                "--reproducible",
+                "--emoji-dir",
+                path(dir.path()),
+            ])
+            .args(["--output-dir", path(out.path())]);
+        match epoch {
+            Some(epoch) => command.env("SOURCE_DATE_EPOCH", epoch),
+            None => command.env_remove("SOURCE_DATE_EPOCH"),
+        };
+        command.output().unwrap()
+    };
+    let built_at = || read_json(&out.path().join("build-info.json"))["built_at"].clone();
+
+    assert!(run(Some("1700000000")).status.success());
+    assert_eq!(built_at(), 1_700_000_000);
+
+    // a reproducible build without a date has none
+    assert!(run(None).status.success());
+    assert!(built_at().is_null());
+
+    assert!(!run(Some("yesterday")).status.success());
+}
+
+#[test]
+fn codepoints_lists_those_used_and_the_gaps() {
+    let dir = tempfile::tempdir().unwrap();
+    let map = dir.path().join("fedimoji.json");
+    std::fs::write(
+        &map,
+        r#"{"a": "U+F0000", "b": "U+F0001", "c": "U+F0003", "d": "U+F0007", "e": 983048}"#,
+    )
+    .unwrap();
+
+    let output = fedimoji(&["codepoints", path(&map)]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "U+F0000\nU+F0001\nU+F0003\nU+F0007\nU+F0008\n\
+         \ngaps:\nU+F0002\nU+F0004-U+F0006 (3)\n"
+    );
+}
+
+#[test]
+fn allocations_persist_between_runs() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let out = tempfile::tempdir().unwrap();
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_fedimoji"))
+            .args([
+                "--emoji-dir",
+                path(dir.path()),
+                "--output-dir",
+                path(out.path()),
+            ])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        read_json(&out.path().join("fedimoji.json"))
+    };
+
+    assert_eq!(run(&[])["neofox"], "\u{F0001}");
+    assert!(out.path().join("allocation.json").is_file());
+
+    // without blobcat, neofox would move down, but it keeps the codepoint it was given
+    std::fs::remove_file(dir.path().join("blobcat.png")).unwrap();
+    assert_eq!(run(&[])["neofox"], "\u{F0001}");
+    assert_eq!(run(&["--no-persist"])["neofox"], "\u{F0000}");
+}
+
+#[test]
+fn atlas_subpath_moves_the_atlas_and_its_provider_file() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let out = tempfile::tempdir().unwrap();
+    generate(dir.path(), out.path(), &["--atlas-subpath", "font/v3"]);
+
+    assert!(out.path().join("font/v3/emoji.png").is_file());
+    assert!(!out.path().join("emoji.png").exists());
+    let font = read_json(&out.path().join("emoji.json"));
+    assert_eq!(font["providers"][0]["file"], "fedimoji:font/v3/emoji.png");
+
+    let output = fedimoji(&[
+        "--emoji-dir",
+        path(dir.path()),
+        "--output-dir",
+        path(out.path()),
+        "--atlas-subpath",
+        "Font/v3",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[cfg(feature = "profile-memory")]
+#[test]
+fn profile_memory_reports_the_peak() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    let out = tempfile::tempdir().unwrap();
+    let output = generate(dir.path(), out.path(), &["--profile-memory"]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let peaks = stderr
+        .lines()
+        .filter(|line| line.contains("peak memory use"))
+        .map(|line| {
+            let (_, peak) = line.split_once("peak_bytes=").unwrap();
+            peak.trim().parse::<u64>().unwrap()
+        })
+        .collect::<Vec<_>>();
+    // once the atlas is built, and at the end
+    assert_eq!(peaks.len(), 2);
+    assert!(peaks.iter().all(|peak| *peak > 0));
+    assert!(peaks[1] >= peaks[0]);
+}
+
+#[test]
+fn layout_plan_matches_the_built_atlas() {
+    let dir = emoji_dir(&["a", "b", "c", "d", "e"]);
+    let plan_dir = tempfile::tempdir().unwrap();
+    let plan_path = plan_dir.path().join("plan.json");
+    let out = tempfile::tempdir().unwrap();
+    generate(
+        dir.path(),
+        out.path(),
+        &["--auto-atlas", "--plan-only", path(&plan_path)],
+    );
+    // only the plan is written
+    assert!(file_names(out.path()).is_empty());
+
+    generate(
+        dir.path(),
+        out.path(),
+        &["--auto-atlas", "--output-format", "generic"],
+    );
+    let mut plan = read_json(&plan_path);
+    assert_eq!(plan["columns"], 2);
+    plan.as_object_mut().unwrap().remove("columns");
+    assert_eq!(plan, read_json(&out.path().join("glyphs.json")));
+}
+
+#[test]
+fn sounds_from_sidecars_flow_into_the_exports() {
+    let dir = emoji_dir(&["blobcat", "neofox"]);
+    std::fs::write(dir.path().join("blobcat.json"), r#"{"sound": "meow.ogg"}"#).unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let exports = tempfile::tempdir().unwrap();
+    let shortcodes_path = exports.path().join("shortcodes.json");
+    let manifest_path = exports.path().join("manifest.json");
+    generate(
+        dir.path(),
+        out.path(),
+        &[
+            "--export-shortcodes",
+            path(&shortcodes_path),
+            "--emit-fedi-manifest",
+            path(&manifest_path),
+        ],
+    );
+
+    for export in [read_json(&shortcodes_path), read_json(&manifest_path)] {
+        let emoji = export.as_array().unwrap();
+        assert_eq!(emoji[0]["shortcode"], "blobcat");
+        assert_eq!(emoji[0]["sound"], "meow.ogg");
+        assert_eq!(emoji[1]["shortcode"], "neofox");
+        assert!(emoji[1].get("sound").is_none());
+    }
+}
+
+#[test]
+fn only_tiles_the_providers_refer_to_are_written() {
+    let dir = emoji_dir(&["a", "b", "c"]);
+    let out = tempfile::tempdir().unwrap();
+    generate(
+        dir.path(),
+        out.path(),
+        &[
+            "--glyph-size",
+            "16",
+            "--mc-version",
+            "1.19",
+            "--tile-size",
+            "16",
+        ],
+    );
+
+    // one row of 16 cells, with only the first 3 holding emoji
+
+    let written = file_names(out.path())
+        .into_iter()
+        .filter(|name| name.starts_with("emoji_tile_"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        written,
+        [
+            "emoji_tile_0_0.png",
+            "emoji_tile_0_1.png",
+            "emoji_tile_0_2.png"
+        ]
+    );
+    let font = read_json(&out.path().join("emoji.json"));
+    let mut referenced = font["providers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|provider| {
+            let file = provider["file"].as_str().unwrap();
+            file.strip_prefix("fedimoji:font/").unwrap().to_string()
+        })
+        .collect::<Vec<_>>();
+    referenced.sort();
+    referenced.dedup();
+    assert_eq!(written, referenced);
+
+    let index = read_json(&out.path().join("tiles.json"));
+    let mut indexed = index["tiles"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tile| tile["file"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    indexed.sort();
+    assert_eq!(indexed, written);
+}
+
+#[test]
+fn preview_dpi_is_bounded() {
+    let dir = emoji_dir(&["blobcat"]);
+    let output = tempfile::tempdir().unwrap();
+    let preview = output.path().join("preview.png");
+    let preview_at = |dpi: &str| {
+        fedimoji(&[
+            "--emoji-dir",
+            path(dir.path()),
+            "--output-dir",
+            path(output.path()),
+            "--emit-preview",
+            path(&preview),
+            "--preview-dpi",
+            dpi,
+        ])
+    };
+    for dpi in ["0", "71", "2401", "4000000000"] {
+        let failed = preview_at(dpi);
+        assert_eq!(failed.status.code(), Some(2), "{dpi} DPI");
+        assert!(!preview.exists());
+    }
+
+    assert!(preview_at("72").status.success());
+    let page = image::open(&preview).unwrap();
+    assert_eq!((page.width(), page.height()), (595, 842));
+}
+
+#[test]
+fn max_name_length_leaves_room_for_a_suffix() {
+    let dir = emoji_dir(&["blobcat"]);
+    let output = tempfile::tempdir().unwrap();
+    for length in ["0", "2"] {
+        let failed = fedimoji(&[
+            "--emoji-dir",
+            path(dir.path()),
+            "--output-dir",
+            path(output.path()),
+            "--max-name-length",
+            length,
+        ]);
+        assert_eq!(failed.status.code(), Some(2), "length {length}");
+    }
+    generate(dir.path(), output.path(), &["--max-name-length", "3"]);
+}